@@ -0,0 +1,4 @@
+DROP TABLE notification_queue;
+DROP TABLE notification_preferences;
+DROP TYPE notification_preference_type;
+DROP TYPE notification_category_type;
@@ -0,0 +1,29 @@
+CREATE TYPE notification_category_type AS ENUM (
+    'invitations',
+    'requests',
+    'expirations'
+);
+
+CREATE TYPE notification_preference_type AS ENUM (
+    'immediate',
+    'daily',
+    'weekly',
+    'off'
+);
+
+CREATE TABLE notification_preferences (
+    user_uuid UUID NOT NULL,
+    category notification_category_type NOT NULL,
+    preference notification_preference_type NOT NULL DEFAULT 'immediate',
+    PRIMARY KEY (user_uuid, category)
+);
+
+CREATE TABLE notification_queue (
+    id SERIAL PRIMARY KEY,
+    user_uuid UUID NOT NULL,
+    preference notification_preference_type NOT NULL,
+    template JSONB NOT NULL,
+    created TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX notification_queue_preference_idx ON notification_queue (preference);
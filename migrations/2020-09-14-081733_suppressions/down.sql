@@ -0,0 +1,2 @@
+DROP TABLE suppressed_emails;
+DROP TYPE suppression_type;
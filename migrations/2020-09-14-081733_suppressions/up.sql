@@ -0,0 +1,11 @@
+CREATE TYPE suppression_type AS ENUM (
+    'bounce',
+    'complaint'
+);
+
+CREATE TABLE suppressed_emails (
+    email VARCHAR PRIMARY KEY,
+    typ suppression_type NOT NULL,
+    created TIMESTAMP NOT NULL DEFAULT NOW(),
+    body JSONB
+);
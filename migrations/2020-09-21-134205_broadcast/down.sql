@@ -0,0 +1,32 @@
+DELETE FROM logs WHERE target = 'email';
+ALTER TYPE log_target_type RENAME TO log_target_type__;
+CREATE TYPE log_target_type AS ENUM (
+    'group',
+    'terms',
+    'membership',
+    'role',
+    'invitation',
+    'request'
+);
+ALTER TABLE logs
+    ALTER COLUMN target type log_target_type using target::text::log_target_type;
+DROP TYPE log_target_type__;
+
+UPDATE roles SET permissions = array_remove(permissions, 'email_members');
+ALTER TYPE permission_type RENAME TO permission_type__;
+CREATE TYPE permission_type AS ENUM (
+    'invite_member',
+    'edit_description',
+    'add_curator',
+    'remove_curator',
+    'delete_group',
+    'remove_member',
+    'edit_terms'
+);
+ALTER TABLE roles
+    ALTER COLUMN permissions DROP DEFAULT;
+ALTER TABLE roles
+    ALTER COLUMN permissions type permission_type[] using permissions::text[]::permission_type[];
+ALTER TABLE roles
+    ALTER COLUMN permissions SET DEFAULT array[]::permission_type[];
+DROP TYPE permission_type__;
@@ -0,0 +1,8 @@
+CREATE TABLE group_newsletters (
+    group_id SERIAL REFERENCES groups,
+    newsletter VARCHAR NOT NULL,
+    PRIMARY KEY (group_id, newsletter)
+);
+
+INSERT INTO group_newsletters (group_id, newsletter)
+    SELECT group_id, 'mozillians-nda' FROM groups WHERE name = 'nda';
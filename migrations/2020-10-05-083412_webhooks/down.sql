@@ -0,0 +1,4 @@
+DROP TABLE webhook_deliveries;
+DROP TABLE webhooks;
+DROP TYPE delivery_status_type;
+DROP TYPE webhook_event_type;
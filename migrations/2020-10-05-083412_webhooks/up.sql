@@ -0,0 +1,37 @@
+CREATE TYPE webhook_event_type AS ENUM (
+    'membership_created',
+    'membership_deleted',
+    'role_changed',
+    'group_deleted',
+    'trust_changed'
+);
+
+CREATE TYPE delivery_status_type AS ENUM (
+    'pending',
+    'delivered',
+    'failed'
+);
+
+CREATE TABLE webhooks (
+    id SERIAL PRIMARY KEY,
+    url VARCHAR NOT NULL,
+    secret VARCHAR NOT NULL,
+    group_id INTEGER REFERENCES groups,
+    events webhook_event_type[] NOT NULL,
+    created TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE webhook_deliveries (
+    id SERIAL PRIMARY KEY,
+    webhook_id INTEGER NOT NULL REFERENCES webhooks ON DELETE CASCADE,
+    event webhook_event_type NOT NULL,
+    payload JSONB NOT NULL,
+    status delivery_status_type NOT NULL DEFAULT 'pending',
+    attempts INTEGER NOT NULL DEFAULT 0,
+    last_error TEXT,
+    next_attempt TIMESTAMP NOT NULL DEFAULT NOW(),
+    created TIMESTAMP NOT NULL DEFAULT NOW(),
+    updated TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX webhook_deliveries_pending_idx ON webhook_deliveries (status, next_attempt);
@@ -0,0 +1,2 @@
+DROP TABLE cis_outbox;
+DROP TYPE outbox_operation_type;
@@ -0,0 +1,20 @@
+CREATE TYPE outbox_operation_type AS ENUM (
+    'add_groups',
+    'remove_groups'
+);
+
+CREATE TABLE cis_outbox (
+    id SERIAL PRIMARY KEY,
+    user_uuid UUID NOT NULL,
+    operation outbox_operation_type NOT NULL,
+    group_names VARCHAR[] NOT NULL,
+    status delivery_status_type NOT NULL DEFAULT 'pending',
+    attempts INTEGER NOT NULL DEFAULT 0,
+    last_error TEXT,
+    next_attempt TIMESTAMP NOT NULL DEFAULT NOW(),
+    created TIMESTAMP NOT NULL DEFAULT NOW(),
+    updated TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX cis_outbox_pending_idx ON cis_outbox (status, next_attempt);
+CREATE INDEX cis_outbox_user_uuid_idx ON cis_outbox (user_uuid);
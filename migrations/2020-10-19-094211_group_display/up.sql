@@ -0,0 +1,4 @@
+CREATE TABLE group_display (
+    group_id SERIAL PRIMARY KEY REFERENCES groups,
+    display trust_type NOT NULL DEFAULT 'staff'
+);
@@ -0,0 +1,10 @@
+CREATE TABLE resync_runs (
+    id SERIAL PRIMARY KEY,
+    cursor UUID,
+    total INTEGER NOT NULL,
+    processed INTEGER NOT NULL DEFAULT 0,
+    failed INTEGER NOT NULL DEFAULT 0,
+    started TIMESTAMP NOT NULL DEFAULT NOW(),
+    updated TIMESTAMP NOT NULL DEFAULT NOW(),
+    finished TIMESTAMP
+);
@@ -0,0 +1,2 @@
+DROP TABLE suspended_memberships;
+DROP TABLE inactive_users;
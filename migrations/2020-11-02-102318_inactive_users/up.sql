@@ -0,0 +1,14 @@
+CREATE TABLE inactive_users (
+    user_uuid UUID PRIMARY KEY,
+    since TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE suspended_memberships (
+    user_uuid UUID NOT NULL REFERENCES inactive_users ON DELETE CASCADE,
+    group_id INTEGER NOT NULL REFERENCES groups,
+    role_id INTEGER NOT NULL REFERENCES roles,
+    expiration TIMESTAMP,
+    added_by UUID NOT NULL,
+    added_ts TIMESTAMP NOT NULL,
+    PRIMARY KEY (user_uuid, group_id)
+);
@@ -0,0 +1,2 @@
+DROP TABLE job_runs;
+DROP TYPE job_type;
@@ -0,0 +1,10 @@
+CREATE TYPE job_type AS ENUM ('expire_requests', 'expire_invitations', 'expire_memberships', 'expiration_notification', 'pending_requests_notification', 'notify_anonymous_members');
+
+CREATE TABLE job_runs (
+    job job_type PRIMARY KEY,
+    tick TIMESTAMP NOT NULL,
+    started TIMESTAMP NOT NULL DEFAULT NOW(),
+    finished TIMESTAMP,
+    success BOOLEAN,
+    message TEXT
+);
@@ -0,0 +1,2 @@
+DROP TABLE expiration_notifications;
+DROP TYPE expiration_notification_type;
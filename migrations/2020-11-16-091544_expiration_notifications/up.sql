@@ -0,0 +1,10 @@
+CREATE TYPE expiration_notification_type AS ENUM ('first', 'second');
+
+CREATE TABLE expiration_notifications (
+    user_uuid UUID NOT NULL,
+    group_id INTEGER NOT NULL REFERENCES groups,
+    kind expiration_notification_type NOT NULL,
+    expiration TIMESTAMP NOT NULL,
+    sent TIMESTAMP NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (user_uuid, group_id, kind, expiration)
+);
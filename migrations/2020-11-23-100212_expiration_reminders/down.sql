@@ -0,0 +1,11 @@
+CREATE TYPE expiration_notification_type AS ENUM ('first', 'second');
+
+DELETE FROM expiration_notifications WHERE days NOT IN (7, 14);
+ALTER TABLE expiration_notifications ADD COLUMN kind expiration_notification_type;
+UPDATE expiration_notifications SET kind = CASE days WHEN 14 THEN 'first'::expiration_notification_type ELSE 'second'::expiration_notification_type END;
+ALTER TABLE expiration_notifications ALTER COLUMN kind SET NOT NULL;
+ALTER TABLE expiration_notifications DROP CONSTRAINT expiration_notifications_pkey;
+ALTER TABLE expiration_notifications DROP COLUMN days;
+ALTER TABLE expiration_notifications ADD PRIMARY KEY (user_uuid, group_id, kind, expiration);
+
+DROP TABLE expiration_reminders;
@@ -0,0 +1,16 @@
+CREATE TABLE expiration_reminders (
+    group_id INTEGER NOT NULL REFERENCES groups,
+    days INTEGER NOT NULL CHECK (days > 0),
+    member BOOLEAN NOT NULL DEFAULT FALSE,
+    host BOOLEAN NOT NULL DEFAULT FALSE,
+    curators BOOLEAN NOT NULL DEFAULT FALSE,
+    PRIMARY KEY (group_id, days)
+);
+
+ALTER TABLE expiration_notifications ADD COLUMN days INTEGER;
+UPDATE expiration_notifications SET days = CASE kind WHEN 'first' THEN 14 ELSE 7 END;
+ALTER TABLE expiration_notifications ALTER COLUMN days SET NOT NULL;
+ALTER TABLE expiration_notifications DROP CONSTRAINT expiration_notifications_pkey;
+ALTER TABLE expiration_notifications DROP COLUMN kind;
+ALTER TABLE expiration_notifications ADD PRIMARY KEY (user_uuid, group_id, days, expiration);
+DROP TYPE expiration_notification_type;
@@ -0,0 +1,2 @@
+DROP TABLE self_renewals;
+DROP TABLE self_renewal_groups;
@@ -0,0 +1,11 @@
+CREATE TABLE self_renewal_groups (
+    group_id INTEGER PRIMARY KEY REFERENCES groups
+);
+
+CREATE TABLE self_renewals (
+    user_uuid UUID NOT NULL,
+    group_id INTEGER NOT NULL REFERENCES groups,
+    expiration TIMESTAMP NOT NULL,
+    renewed TIMESTAMP NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (user_uuid, group_id, expiration)
+);
@@ -0,0 +1,2 @@
+DROP TABLE expired_memberships;
+DROP TABLE expiration_grace;
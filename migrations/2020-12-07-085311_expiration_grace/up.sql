@@ -0,0 +1,14 @@
+CREATE TABLE expiration_grace (
+    group_id INTEGER PRIMARY KEY REFERENCES groups,
+    days INTEGER NOT NULL CHECK (days > 0),
+    keep_access BOOLEAN NOT NULL DEFAULT FALSE
+);
+
+CREATE TABLE expired_memberships (
+    user_uuid UUID NOT NULL,
+    group_id INTEGER NOT NULL REFERENCES groups,
+    expired TIMESTAMP NOT NULL DEFAULT NOW(),
+    access_removed BOOLEAN NOT NULL,
+    PRIMARY KEY (user_uuid, group_id),
+    FOREIGN KEY (user_uuid, group_id) REFERENCES memberships ON DELETE CASCADE ON UPDATE CASCADE
+);
@@ -0,0 +1,7 @@
+CREATE TABLE calendar_tokens (
+    group_id INTEGER NOT NULL REFERENCES groups,
+    user_uuid UUID NOT NULL,
+    token_hash VARCHAR NOT NULL UNIQUE,
+    created TIMESTAMP NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (group_id, user_uuid)
+);
@@ -0,0 +1,7 @@
+CREATE TABLE trust_changes (
+    group_id INTEGER PRIMARY KEY REFERENCES groups,
+    trust trust_type NOT NULL,
+    effective TIMESTAMP NOT NULL,
+    created_by UUID NOT NULL,
+    created TIMESTAMP NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,2 @@
+DROP TABLE at_risk_memberships;
+DROP TABLE trust_grace;
@@ -0,0 +1,13 @@
+CREATE TABLE trust_grace (
+    id BOOLEAN PRIMARY KEY DEFAULT TRUE CHECK (id),
+    days INTEGER NOT NULL CHECK (days > 0)
+);
+
+CREATE TABLE at_risk_memberships (
+    user_uuid UUID NOT NULL,
+    group_id INTEGER NOT NULL REFERENCES groups,
+    flagged TIMESTAMP NOT NULL DEFAULT NOW(),
+    revoke_after TIMESTAMP NOT NULL,
+    PRIMARY KEY (user_uuid, group_id),
+    FOREIGN KEY (user_uuid, group_id) REFERENCES memberships ON DELETE CASCADE ON UPDATE CASCADE
+);
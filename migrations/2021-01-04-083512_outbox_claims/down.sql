@@ -0,0 +1 @@
+ALTER TABLE cis_outbox DROP COLUMN claimed_until;
@@ -0,0 +1 @@
+ALTER TABLE cis_outbox ADD COLUMN claimed_until TIMESTAMP;
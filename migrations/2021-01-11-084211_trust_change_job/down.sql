@@ -0,0 +1,13 @@
+DELETE FROM job_runs WHERE job = 'apply_trust_changes';
+ALTER TYPE job_type RENAME TO job_type__;
+CREATE TYPE job_type AS ENUM (
+    'expire_requests',
+    'expire_invitations',
+    'expire_memberships',
+    'expiration_notification',
+    'pending_requests_notification',
+    'notify_anonymous_members'
+);
+ALTER TABLE job_runs
+    ALTER COLUMN job type job_type using job::text::job_type;
+DROP TYPE job_type__;
@@ -0,0 +1,14 @@
+DELETE FROM job_runs WHERE job = 'revoke_at_risk_memberships';
+ALTER TYPE job_type RENAME TO job_type__;
+CREATE TYPE job_type AS ENUM (
+    'expire_requests',
+    'expire_invitations',
+    'expire_memberships',
+    'expiration_notification',
+    'pending_requests_notification',
+    'notify_anonymous_members',
+    'apply_trust_changes'
+);
+ALTER TABLE job_runs
+    ALTER COLUMN job type job_type using job::text::job_type;
+DROP TYPE job_type__;
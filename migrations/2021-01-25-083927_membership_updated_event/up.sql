@@ -0,0 +1,14 @@
+ALTER TYPE webhook_event_type RENAME TO webhook_event_type__;
+CREATE TYPE webhook_event_type AS ENUM (
+    'membership_created',
+    'membership_deleted',
+    'role_changed',
+    'group_deleted',
+    'trust_changed',
+    'membership_updated'
+);
+ALTER TABLE webhooks
+    ALTER COLUMN events type webhook_event_type[] using events::text[]::webhook_event_type[];
+ALTER TABLE webhook_deliveries
+    ALTER COLUMN event type webhook_event_type using event::text::webhook_event_type;
+DROP TYPE webhook_event_type__;
@@ -1,11 +1,11 @@
 use crate::api::error::ApiError;
+use crate::cis::operations::ProfilePublisher;
 use crate::db::operations;
 use crate::db::Pool;
 use crate::user::User;
 use actix_web::dev::HttpServiceFactory;
 use actix_web::web;
 use actix_web::HttpResponse;
-use cis_client::AsyncCisClientTrait;
 use dino_park_gate::scope::ScopeAndUser;
 use serde::Deserialize;
 use std::sync::Arc;
@@ -22,12 +22,12 @@ pub struct DowngradeAdmin {
 }
 
 #[guard(Ndaed, None, Medium)]
-async fn add_admin<T: AsyncCisClientTrait>(
+async fn add_admin<T: ProfilePublisher>(
     pool: web::Data<Pool>,
     group_name: web::Path<String>,
     scope_and_user: ScopeAndUser,
     add_admin: web::Json<AddAdmin>,
-    cis_client: web::Data<T>,
+    publisher: web::Data<T>,
 ) -> Result<HttpResponse, ApiError> {
     let pool_f = pool.clone();
     let user_uuid = add_admin.member_uuid;
@@ -38,7 +38,7 @@ async fn add_admin<T: AsyncCisClientTrait>(
         &group_name,
         &host,
         &User { user_uuid },
-        Arc::clone(&*cis_client),
+        Arc::clone(&*publisher),
     )
     .await?;
     Ok(HttpResponse::Ok().json(""))
@@ -67,7 +67,7 @@ pub async fn downgrade(
     .map_err(ApiError::GenericBadRequest)
 }
 
-pub fn admins_app<T: AsyncCisClientTrait + 'static>() -> impl HttpServiceFactory {
+pub fn admins_app<T: ProfilePublisher + 'static>() -> impl HttpServiceFactory {
     web::scope("/curators")
         .service(web::resource("/{group_name}").route(web::post().to(add_admin::<T>)))
         .service(
@@ -0,0 +1,122 @@
+use crate::api::error::ApiError;
+use crate::db::operations;
+use crate::db::operations::models::CalendarFeed;
+use crate::db::Pool;
+use actix_web::dev::HttpServiceFactory;
+use actix_web::web;
+use actix_web::HttpResponse;
+use chrono::Duration;
+use chrono::Utc;
+
+/// Maximum length of a content line in octets (RFC 5545 3.1).
+const LINE_MAX_LEN: usize = 75;
+
+struct Domain(String);
+
+fn escape_text(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+fn fold_line(line: &str) -> String {
+    let mut folded = String::with_capacity(line.len() + 2);
+    let mut len = 0;
+    for c in line.chars() {
+        if len + c.len_utf8() > LINE_MAX_LEN {
+            folded.push_str("\r\n ");
+            len = 1;
+        }
+        folded.push(c);
+        len += c.len_utf8();
+    }
+    folded.push_str("\r\n");
+    folded
+}
+
+fn render(feed: &CalendarFeed, domain: &str) -> String {
+    let stamp = Utc::now().format("%Y%m%dT%H%M%SZ");
+    let renew = format!(
+        "https://{}/a/{}/edit?section=members",
+        domain, feed.group_name
+    );
+    let mut lines = vec![
+        String::from("BEGIN:VCALENDAR"),
+        String::from("VERSION:2.0"),
+        format!("PRODID:-//{}//dino-park-packs//EN", domain),
+        format!(
+            "X-WR-CALNAME:{}",
+            escape_text(&format!("{} expirations", feed.group_name))
+        ),
+    ];
+    for entry in &feed.entries {
+        lines.extend(vec![
+            String::from("BEGIN:VEVENT"),
+            format!("UID:{}-{}@{}", entry.user_uuid, feed.group_id, domain),
+            format!("DTSTAMP:{}", stamp),
+            format!("DTSTART;VALUE=DATE:{}", entry.expiration.format("%Y%m%d")),
+            format!(
+                "DTEND;VALUE=DATE:{}",
+                (entry.expiration + Duration::days(1)).format("%Y%m%d")
+            ),
+            format!(
+                "SUMMARY:{}",
+                escape_text(&format!(
+                    "{} expires from {}",
+                    entry.username, feed.group_name
+                ))
+            ),
+            format!(
+                "DESCRIPTION:{}",
+                escape_text(&format!("Renew at {}", renew))
+            ),
+            format!("URL:{}", renew),
+            String::from("END:VEVENT"),
+        ]);
+    }
+    lines.push(String::from("END:VCALENDAR"));
+    lines.iter().map(|line| fold_line(line)).collect()
+}
+
+async fn calendar_feed(
+    pool: web::Data<Pool>,
+    domain: web::Data<Domain>,
+    token: web::Path<String>,
+) -> Result<HttpResponse, ApiError> {
+    match operations::calendar::calendar_feed(&pool, &token)? {
+        Some(feed) => Ok(HttpResponse::Ok()
+            .content_type("text/calendar; charset=utf-8")
+            .body(render(&feed, &domain.0))),
+        None => Ok(HttpResponse::NotFound().finish()),
+    }
+}
+
+/// Token authenticated and therefore not behind the scope middleware so calendar clients can
+/// subscribe to it.
+pub fn calendar_app(domain: String) -> impl HttpServiceFactory {
+    web::scope("/groups/calendar")
+        .data(Domain(domain))
+        .service(web::resource("/{token}").route(web::get().to(calendar_feed)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_escape_text() {
+        assert_eq!(escape_text("a,b;c\\d\ne"), "a\\,b\\;c\\\\d\\ne");
+    }
+
+    #[test]
+    fn test_fold_line() {
+        assert_eq!(fold_line("short"), "short\r\n");
+        let long = "x".repeat(100);
+        let folded = fold_line(&long);
+        let lines: Vec<&str> = folded.trim_end().split("\r\n").collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].len(), LINE_MAX_LEN);
+        assert_eq!(lines[1], format!(" {}", "x".repeat(25)));
+    }
+}
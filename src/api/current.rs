@@ -1,12 +1,13 @@
 use crate::api::error::ApiError;
+use crate::cis::operations::ProfilePublisher;
 use crate::db::operations;
+use crate::db::operations::models::DisplayNotificationPreference;
 use crate::db::Pool;
 use actix_web::dev::HttpServiceFactory;
 use actix_web::web;
 use actix_web::HttpRequest;
 use actix_web::HttpResponse;
 use actix_web::Responder;
-use cis_client::AsyncCisClientTrait;
 use dino_park_gate::scope::ScopeAndUser;
 use serde::Deserialize;
 use std::sync::Arc;
@@ -17,12 +18,12 @@ struct ForceLeave {
 }
 
 #[guard(Authenticated)]
-async fn join<T: AsyncCisClientTrait>(
+async fn join<T: ProfilePublisher>(
     _: HttpRequest,
     pool: web::Data<Pool>,
     group_name: web::Path<String>,
     scope_and_user: ScopeAndUser,
-    cis_client: web::Data<T>,
+    publisher: web::Data<T>,
 ) -> Result<HttpResponse, ApiError> {
     let user = operations::users::user_by_id(&pool, &scope_and_user.user_id)?;
     operations::invitations::accept_invitation(
@@ -30,27 +31,27 @@ async fn join<T: AsyncCisClientTrait>(
         &scope_and_user,
         &group_name,
         &user,
-        Arc::clone(&*cis_client),
+        Arc::clone(&*publisher),
     )
     .await?;
     Ok(HttpResponse::Ok().json(""))
 }
 
 #[guard(Authenticated)]
-async fn leave<T: AsyncCisClientTrait>(
+async fn leave<T: ProfilePublisher>(
     _: HttpRequest,
     pool: web::Data<Pool>,
     group_name: web::Path<String>,
     scope_and_user: ScopeAndUser,
     force: web::Query<ForceLeave>,
-    cis_client: web::Data<T>,
+    publisher: web::Data<T>,
 ) -> Result<HttpResponse, ApiError> {
     operations::members::leave(
         &pool,
         &scope_and_user,
         &group_name,
         force.force.unwrap_or_default(),
-        Arc::clone(&*cis_client),
+        Arc::clone(&*publisher),
     )
     .await?;
     Ok(HttpResponse::Ok().json(""))
@@ -95,6 +96,19 @@ async fn reject_invitation(
     }
 }
 
+#[guard(Authenticated)]
+async fn renew<T: ProfilePublisher>(
+    _: HttpRequest,
+    pool: web::Data<Pool>,
+    group_name: web::Path<String>,
+    scope_and_user: ScopeAndUser,
+    publisher: web::Data<T>,
+) -> Result<HttpResponse, ApiError> {
+    operations::members::self_renew(&pool, &scope_and_user, &group_name, Arc::clone(&*publisher))
+        .await?;
+    Ok(HttpResponse::Ok().json(""))
+}
+
 #[guard(Authenticated)]
 async fn invitations(pool: web::Data<Pool>, scope_and_user: ScopeAndUser) -> impl Responder {
     match operations::invitations::pending_invitations_for_user(&pool, &scope_and_user) {
@@ -111,7 +125,34 @@ async fn requests(pool: web::Data<Pool>, scope_and_user: ScopeAndUser) -> impl R
     }
 }
 
-pub fn current_app<T: AsyncCisClientTrait + 'static>() -> impl HttpServiceFactory {
+#[guard(Authenticated)]
+async fn notification_preferences(
+    pool: web::Data<Pool>,
+    scope_and_user: ScopeAndUser,
+) -> impl Responder {
+    match operations::notifications::get_preferences(&pool, &scope_and_user) {
+        Ok(preferences) => Ok(HttpResponse::Ok().json(preferences)),
+        Err(e) => Err(ApiError::GenericBadRequest(e)),
+    }
+}
+
+#[guard(Authenticated)]
+async fn update_notification_preference(
+    pool: web::Data<Pool>,
+    scope_and_user: ScopeAndUser,
+    preference: web::Json<DisplayNotificationPreference>,
+) -> impl Responder {
+    match operations::notifications::update_preference(
+        &pool,
+        &scope_and_user,
+        preference.into_inner(),
+    ) {
+        Ok(_) => Ok(HttpResponse::Ok().json("")),
+        Err(e) => Err(ApiError::GenericBadRequest(e)),
+    }
+}
+
+pub fn current_app<T: ProfilePublisher + 'static>() -> impl HttpServiceFactory {
     web::scope("/self")
         .service(
             web::resource("/invitations/{group_name}")
@@ -125,5 +166,11 @@ pub fn current_app<T: AsyncCisClientTrait + 'static>() -> impl HttpServiceFactor
                 .route(web::delete().to(cancel_request)),
         )
         .service(web::resource("/requests").route(web::get().to(requests)))
+        .service(
+            web::resource("/notifications")
+                .route(web::get().to(notification_preferences))
+                .route(web::put().to(update_notification_preference)),
+        )
+        .service(web::resource("/{group_name}/renew").route(web::post().to(renew::<T>)))
         .service(web::resource("/{group_name}").route(web::delete().to(leave::<T>)))
 }
@@ -1,4 +1,5 @@
 use crate::api::error::ApiError;
+use crate::cis::operations::ProfilePublisher;
 use crate::db::operations;
 use crate::db::Pool;
 use crate::mail::manager::send_email_raw;
@@ -7,7 +8,6 @@ use crate::mail::Message;
 use actix_web::dev::HttpServiceFactory;
 use actix_web::web;
 use actix_web::HttpResponse;
-use cis_client::AsyncCisClientTrait;
 use dino_park_gate::scope::ScopeAndUser;
 use serde::Deserialize;
 
@@ -80,7 +80,7 @@ async fn form() -> Result<HttpResponse, ApiError> {
         .body(include_str!("../../static/form.html")))
 }
 
-pub fn forms_app<T: AsyncCisClientTrait + 'static>() -> impl HttpServiceFactory {
+pub fn forms_app<T: ProfilePublisher + 'static>() -> impl HttpServiceFactory {
     web::scope("/forms")
         .service(web::resource("/email/").route(web::get().to(form)))
         .service(web::resource("/email/bcc").route(web::post().to(email_bcc)))
@@ -2,6 +2,7 @@ use crate::api::error::ApiError;
 use crate::api::models::DisplayGroup;
 use crate::api::models::DisplayGroupDetails;
 use crate::api::models::GroupInfo;
+use crate::cis::operations::ProfilePublisher;
 use crate::db::operations;
 use crate::db::operations::models::GroupUpdate;
 use crate::db::operations::models::NewGroup;
@@ -12,7 +13,6 @@ use actix_web::dev::HttpServiceFactory;
 use actix_web::web;
 use actix_web::HttpResponse;
 use actix_web::Responder;
-use cis_client::AsyncCisClientTrait;
 use dino_park_gate::scope::ScopeAndUser;
 use dino_park_trust::GroupsTrust;
 use log::info;
@@ -30,6 +30,12 @@ struct ListGroupsQuery {
     by: SortGroupsBy,
 }
 
+#[derive(Deserialize)]
+struct UpdateGroupQuery {
+    #[serde(default)]
+    preview: bool,
+}
+
 fn default_groups_list_size() -> i64 {
     20
 }
@@ -54,6 +60,7 @@ async fn update_group(
     scope_and_user: ScopeAndUser,
     group_update: web::Json<GroupUpdate>,
     group_name: web::Path<String>,
+    query: web::Query<UpdateGroupQuery>,
 ) -> impl Responder {
     let group_update = group_update.into_inner().checked()?;
     operations::groups::update_group(
@@ -61,33 +68,34 @@ async fn update_group(
         &scope_and_user,
         group_name.into_inner(),
         group_update,
+        query.preview,
     )
-    .map(|_| HttpResponse::Created().json(""))
+    .map(|status| HttpResponse::Created().json(status))
     .map_err(ApiError::GenericBadRequest)
 }
 
 #[guard(Staff, Creator, Medium)]
-async fn add_group<T: AsyncCisClientTrait>(
-    cis_client: web::Data<T>,
+async fn add_group<T: ProfilePublisher>(
+    publisher: web::Data<T>,
     pool: web::Data<Pool>,
     scope_and_user: ScopeAndUser,
     new_group: web::Json<NewGroup>,
 ) -> Result<HttpResponse, ApiError> {
     let new_group = new_group.into_inner().checked()?;
     info!("trying to create new group: {}", new_group.name);
-    operations::groups::add_new_group(&pool, &scope_and_user, new_group, Arc::clone(&*cis_client))
+    operations::groups::add_new_group(&pool, &scope_and_user, new_group, Arc::clone(&*publisher))
         .await?;
     Ok(HttpResponse::Created().json(""))
 }
 
 #[guard(Staff, Creator, Medium)]
-async fn delete_group<T: AsyncCisClientTrait>(
-    cis_client: web::Data<T>,
+async fn delete_group<T: ProfilePublisher>(
+    publisher: web::Data<T>,
     pool: web::Data<Pool>,
     group_name: web::Path<String>,
     scope_and_user: ScopeAndUser,
 ) -> Result<HttpResponse, ApiError> {
-    operations::groups::delete_group(&pool, &scope_and_user, &group_name, Arc::clone(&cis_client))
+    operations::groups::delete_group(&pool, &scope_and_user, &group_name, Arc::clone(&publisher))
         .await?;
     Ok(HttpResponse::Created().json(""))
 }
@@ -139,6 +147,15 @@ async fn group_details(
     } else {
         None
     };
+    let (reminders, expiration_grace) = if curator {
+        (
+            Some(operations::groups::get_reminders(&pool, group.group.id)?),
+            operations::groups::get_grace(&pool, group.group.id)?,
+        )
+    } else {
+        (None, None)
+    };
+    let self_renewal = operations::groups::self_renewal_enabled(&pool, group.group.id)?;
     let result = DisplayGroupDetails {
         membership,
         super_user,
@@ -156,6 +173,9 @@ async fn group_details(
             created: group.group.created,
             terms: group.terms,
             trust: group.group.trust,
+            reminders,
+            self_renewal,
+            expiration_grace,
         },
         member_count,
         invitation_count,
@@ -165,7 +185,7 @@ async fn group_details(
     Ok(HttpResponse::Ok().json(result))
 }
 
-pub fn groups_app<T: AsyncCisClientTrait + 'static>() -> impl HttpServiceFactory {
+pub fn groups_app<T: ProfilePublisher + 'static>() -> impl HttpServiceFactory {
     web::scope("/groups")
         .service(
             web::resource("")
@@ -1,6 +1,13 @@
 use crate::api::error::ApiError;
+use crate::cis::operations::ProfilePublisher;
 use crate::db::operations;
+use crate::db::types::JobType;
+use crate::db::types::NotificationPreferenceType;
 use crate::db::Pool;
+use crate::error::PacksError;
+use crate::mail::sns::SesNotification;
+use crate::mail::sns::SnsMessage;
+use crate::scheduler;
 use crate::user::User;
 use actix_multipart::Multipart;
 use actix_web::dev::HttpServiceFactory;
@@ -8,11 +15,14 @@ use actix_web::web;
 use actix_web::web::Bytes;
 use actix_web::HttpResponse;
 use actix_web::Responder;
-use cis_client::AsyncCisClientTrait;
 use cis_profile::schema::Profile;
+use failure::Error;
 use futures::StreamExt;
 use futures::TryFutureExt;
 use futures::TryStreamExt;
+use log::info;
+use log::warn;
+use serde::Deserialize;
 use serde::Serialize;
 use std::sync::Arc;
 use uuid::Uuid;
@@ -23,17 +33,57 @@ pub struct UpdatedProfiles {
 }
 
 #[derive(Serialize)]
-pub struct NotificationStatus {
-    expire_first: usize,
-    expire_second: usize,
+pub struct DigestStatus {
+    sent: usize,
 }
 
-async fn update_user<T: AsyncCisClientTrait>(
+#[derive(Serialize)]
+pub struct SuppressionStatus {
+    suppressed: usize,
+}
+
+#[derive(Deserialize)]
+pub struct ReconcileOptions {
+    #[serde(default)]
+    dry_run: bool,
+}
+
+#[derive(Serialize)]
+pub struct WebhookStatus {
+    delivered: usize,
+}
+
+#[derive(Deserialize)]
+pub struct ResyncOptions {
+    #[serde(default = "default_resync_batch_size")]
+    batch_size: i64,
+}
+
+fn default_resync_batch_size() -> i64 {
+    500
+}
+
+struct SuspensionGrace(i64);
+
+/// ARN of the SNS topic SES notifications are accepted from.
+struct SnsTopic(Option<String>);
+
+#[derive(Serialize)]
+pub struct SuspensionStatus {
+    revoked: usize,
+}
+
+#[derive(Serialize)]
+pub struct OutboxStatus {
+    delivered: usize,
+}
+
+async fn update_user<T: ProfilePublisher>(
     pool: web::Data<Pool>,
     profile: web::Json<Profile>,
-    cis_client: web::Data<T>,
+    publisher: web::Data<T>,
 ) -> Result<HttpResponse, ApiError> {
-    operations::users::update_user_cache(&pool, &profile, Arc::clone(&*cis_client)).await?;
+    operations::users::update_user_cache(&pool, &profile, Arc::clone(&*publisher)).await?;
     Ok(HttpResponse::Ok().json(""))
 }
 
@@ -44,42 +94,167 @@ async fn delete_user(pool: web::Data<Pool>, user_uuid: web::Path<Uuid>) -> impl
     operations::users::delete_user(&pool, &user).map(|_| HttpResponse::Ok().json(""))
 }
 
-async fn expire_all<T: AsyncCisClientTrait>(
+async fn expire_all<T: ProfilePublisher>(
     pool: web::Data<Pool>,
-    cis_client: web::Data<T>,
+    publisher: web::Data<T>,
 ) -> Result<HttpResponse, ApiError> {
-    operations::expirations::expire_requests(&pool)?;
-    operations::expirations::expire_invitations(&pool)?;
-    operations::expirations::expire_memberships(&pool, Arc::clone(&*cis_client)).await?;
+    for job in &[
+        JobType::ExpireRequests,
+        JobType::ExpireInvitations,
+        JobType::ExpireMemberships,
+    ] {
+        scheduler::run_now(&pool, Arc::clone(&*publisher), *job).await?;
+    }
     Ok(HttpResponse::Ok().json(""))
 }
 
-async fn expiration_notifications(pool: web::Data<Pool>) -> Result<HttpResponse, ApiError> {
-    let expire_first = operations::expirations::expiration_notification(&pool, true)?;
-    let expire_second = operations::expirations::expiration_notification(&pool, false)?;
-    Ok(HttpResponse::Ok().json(NotificationStatus {
-        expire_first,
-        expire_second,
-    }))
+async fn run_job<T: ProfilePublisher>(
+    pool: &Pool,
+    publisher: web::Data<T>,
+    job: JobType,
+) -> Result<HttpResponse, ApiError> {
+    let run = scheduler::run_now(pool, Arc::clone(&*publisher), job).await?;
+    Ok(HttpResponse::Ok().json(run))
 }
 
-async fn requests_notifications(pool: web::Data<Pool>) -> Result<HttpResponse, ApiError> {
-    operations::requests::pending_requests_notification(&pool)?;
-    Ok(HttpResponse::Ok().json(""))
+async fn apply_trust_changes<T: ProfilePublisher>(
+    pool: web::Data<Pool>,
+    publisher: web::Data<T>,
+) -> Result<HttpResponse, ApiError> {
+    run_job(&pool, publisher, JobType::ApplyTrustChanges).await
 }
 
-async fn all_notifications(pool: web::Data<Pool>) -> Result<HttpResponse, ApiError> {
-    operations::requests::pending_requests_notification(&pool)?;
-    operations::expirations::expiration_notification(&pool, true)?;
-    operations::expirations::expiration_notification(&pool, false)?;
-    Ok(HttpResponse::Ok().json(""))
+async fn revoke_at_risk<T: ProfilePublisher>(
+    pool: web::Data<Pool>,
+    publisher: web::Data<T>,
+) -> Result<HttpResponse, ApiError> {
+    run_job(&pool, publisher, JobType::RevokeAtRiskMemberships).await
+}
+
+async fn expiration_notifications<T: ProfilePublisher>(
+    pool: web::Data<Pool>,
+    publisher: web::Data<T>,
+) -> Result<HttpResponse, ApiError> {
+    run_job(&pool, publisher, JobType::ExpirationNotification).await
+}
+
+async fn requests_notifications<T: ProfilePublisher>(
+    pool: web::Data<Pool>,
+    publisher: web::Data<T>,
+) -> Result<HttpResponse, ApiError> {
+    run_job(&pool, publisher, JobType::PendingRequestsNotification).await
 }
 
-async fn anonymous_notifications(pool: web::Data<Pool>) -> Result<HttpResponse, ApiError> {
-    operations::members::notify_anonymous_members(&pool)?;
+async fn all_notifications<T: ProfilePublisher>(
+    pool: web::Data<Pool>,
+    publisher: web::Data<T>,
+) -> Result<HttpResponse, ApiError> {
+    for job in &[
+        JobType::PendingRequestsNotification,
+        JobType::ExpirationNotification,
+    ] {
+        scheduler::run_now(&pool, Arc::clone(&*publisher), *job).await?;
+    }
+    operations::notifications::send_digests(&pool, NotificationPreferenceType::Daily).await?;
     Ok(HttpResponse::Ok().json(""))
 }
 
+async fn daily_digest(pool: web::Data<Pool>) -> Result<HttpResponse, ApiError> {
+    let sent =
+        operations::notifications::send_digests(&pool, NotificationPreferenceType::Daily).await?;
+    Ok(HttpResponse::Ok().json(DigestStatus { sent }))
+}
+
+async fn weekly_digest(pool: web::Data<Pool>) -> Result<HttpResponse, ApiError> {
+    let sent =
+        operations::notifications::send_digests(&pool, NotificationPreferenceType::Weekly).await?;
+    Ok(HttpResponse::Ok().json(DigestStatus { sent }))
+}
+
+async fn anonymous_notifications<T: ProfilePublisher>(
+    pool: web::Data<Pool>,
+    publisher: web::Data<T>,
+) -> Result<HttpResponse, ApiError> {
+    run_job(&pool, publisher, JobType::NotifyAnonymousMembers).await
+}
+
+async fn sns_notification(
+    pool: web::Data<Pool>,
+    topic: web::Data<SnsTopic>,
+    body: Bytes,
+) -> Result<HttpResponse, ApiError> {
+    let sns = serde_json::from_slice::<SnsMessage>(&body).map_err(Error::from)?;
+    // only messages of our own topic are trusted
+    if topic.0.as_deref() != Some(sns.topic_arn()) {
+        warn!("rejecting SNS message of topic {}", sns.topic_arn());
+        return Err(PacksError::InvalidSnsTopic.into());
+    }
+    let suppressed = match sns {
+        SnsMessage::Notification { message, .. } => {
+            let notification =
+                serde_json::from_str::<SesNotification>(&message).map_err(Error::from)?;
+            operations::suppressions::record_ses_notification(&pool, notification)?
+        }
+        SnsMessage::SubscriptionConfirmation { subscribe_url, .. } => {
+            info!("confirm SNS subscription via: {}", subscribe_url);
+            0
+        }
+        SnsMessage::UnsubscribeConfirmation { .. } => 0,
+    };
+    Ok(HttpResponse::Ok().json(SuppressionStatus { suppressed }))
+}
+
+async fn reconcile_groups<T: ProfilePublisher>(
+    pool: web::Data<Pool>,
+    publisher: web::Data<T>,
+    options: web::Query<ReconcileOptions>,
+) -> Result<HttpResponse, ApiError> {
+    let report =
+        operations::drift::reconcile(&pool, Arc::clone(&*publisher), options.dry_run).await?;
+    Ok(HttpResponse::Ok().json(report))
+}
+
+async fn deliver_webhooks(pool: web::Data<Pool>) -> Result<HttpResponse, ApiError> {
+    let delivered = operations::webhooks::deliver_pending(&pool).await?;
+    Ok(HttpResponse::Ok().json(WebhookStatus { delivered }))
+}
+
+async fn deliver_outbox<T: ProfilePublisher>(
+    pool: web::Data<Pool>,
+    publisher: web::Data<T>,
+) -> Result<HttpResponse, ApiError> {
+    let delivered = operations::outbox::deliver_pending(&pool, Arc::clone(&*publisher)).await?;
+    Ok(HttpResponse::Ok().json(OutboxStatus { delivered }))
+}
+
+async fn resync_users<T: ProfilePublisher>(
+    pool: web::Data<Pool>,
+    publisher: web::Data<T>,
+    options: web::Query<ResyncOptions>,
+) -> Result<HttpResponse, ApiError> {
+    let report =
+        operations::resync::resync(&pool, Arc::clone(&*publisher), options.batch_size).await?;
+    Ok(HttpResponse::Ok().json(report))
+}
+
+async fn revoke_suspensions(
+    pool: web::Data<Pool>,
+    grace: web::Data<SuspensionGrace>,
+) -> Result<HttpResponse, ApiError> {
+    let revoked = operations::inactive::revoke_suspended(&pool, grace.0)?;
+    Ok(HttpResponse::Ok().json(SuspensionStatus { revoked }))
+}
+
+async fn job_runs(pool: web::Data<Pool>) -> Result<HttpResponse, ApiError> {
+    let runs = operations::jobs::job_runs(&pool)?;
+    Ok(HttpResponse::Ok().json(runs))
+}
+
+async fn resync_status(pool: web::Data<Pool>) -> Result<HttpResponse, ApiError> {
+    let report = operations::resync::resync_status(&pool)?;
+    Ok(HttpResponse::Ok().json(report))
+}
+
 async fn bulk_update_users(
     pool: web::Data<Pool>,
     mut multipart: Multipart,
@@ -104,17 +279,42 @@ async fn bulk_update_users(
     Ok(HttpResponse::Ok().json(UpdatedProfiles { updated }))
 }
 
-pub fn internal_app<T: AsyncCisClientTrait + 'static>() -> impl HttpServiceFactory {
+pub fn internal_app<T: ProfilePublisher + 'static>(
+    suspension_grace_days: i64,
+    sns_topic_arn: Option<String>,
+) -> impl HttpServiceFactory {
     web::scope("/internal")
         .app_data(web::JsonConfig::default().limit(1_048_576))
+        .data(SuspensionGrace(suspension_grace_days))
+        .data(SnsTopic(sns_topic_arn))
         .service(web::resource("/update/bulk").route(web::post().to(bulk_update_users)))
         .service(web::resource("/update/user").route(web::post().to(update_user::<T>)))
         .service(web::resource("/delete/{user_uuid}").route(web::delete().to(delete_user)))
         .service(web::resource("/expire/all").route(web::post().to(expire_all::<T>)))
+        .service(web::resource("/trust/apply").route(web::post().to(apply_trust_changes::<T>)))
+        .service(web::resource("/trust/revoke").route(web::post().to(revoke_at_risk::<T>)))
+        .service(
+            web::resource("/notify/expiration")
+                .route(web::post().to(expiration_notifications::<T>)),
+        )
+        .service(
+            web::resource("/notify/requests").route(web::post().to(requests_notifications::<T>)),
+        )
+        .service(web::resource("/notify/all").route(web::post().to(all_notifications::<T>)))
+        .service(
+            web::resource("/notify/anonymous").route(web::post().to(anonymous_notifications::<T>)),
+        )
+        .service(web::resource("/notify/digest/daily").route(web::post().to(daily_digest)))
+        .service(web::resource("/notify/digest/weekly").route(web::post().to(weekly_digest)))
+        .service(web::resource("/mail/sns").route(web::post().to(sns_notification)))
+        .service(web::resource("/webhooks/deliver").route(web::post().to(deliver_webhooks)))
+        .service(web::resource("/outbox/deliver").route(web::post().to(deliver_outbox::<T>)))
         .service(
-            web::resource("/notify/expiration").route(web::post().to(expiration_notifications)),
+            web::resource("/resync/users")
+                .route(web::get().to(resync_status))
+                .route(web::post().to(resync_users::<T>)),
         )
-        .service(web::resource("/notify/requests").route(web::post().to(requests_notifications)))
-        .service(web::resource("/notify/all").route(web::post().to(all_notifications)))
-        .service(web::resource("/notify/anonymous").route(web::post().to(anonymous_notifications)))
+        .service(web::resource("/suspensions/revoke").route(web::post().to(revoke_suspensions)))
+        .service(web::resource("/jobs").route(web::get().to(job_runs)))
+        .service(web::resource("/reconcile/groups").route(web::post().to(reconcile_groups::<T>)))
 }
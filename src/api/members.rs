@@ -1,5 +1,7 @@
 use crate::api::error::ApiError;
+use crate::cis::operations::ProfilePublisher;
 use crate::db::operations;
+use crate::db::operations::models::Broadcast;
 use crate::db::operations::models::MembersQueryOptions;
 use crate::db::operations::models::SortMembersBy;
 use crate::db::types::RoleType;
@@ -10,9 +12,9 @@ use actix_web::web;
 use actix_web::HttpRequest;
 use actix_web::HttpResponse;
 use actix_web::Responder;
-use cis_client::AsyncCisClientTrait;
 use dino_park_gate::scope::ScopeAndUser;
 use serde::Deserialize;
+use serde::Serialize;
 use std::sync::Arc;
 use uuid::Uuid;
 
@@ -21,6 +23,11 @@ pub struct RenewMember {
     group_expiration: Option<i32>,
 }
 
+#[derive(Serialize)]
+pub struct BroadcastStatus {
+    recipients: usize,
+}
+
 #[derive(Clone, Deserialize)]
 pub enum MemberRoles {
     Any,
@@ -82,11 +89,11 @@ async fn get_members(
 }
 
 #[guard(Ndaed, None, Medium)]
-async fn remove_member<T: AsyncCisClientTrait>(
+async fn remove_member<T: ProfilePublisher>(
     pool: web::Data<Pool>,
     path: web::Path<(String, Uuid)>,
     scope_and_user: ScopeAndUser,
-    cis_client: web::Data<T>,
+    publisher: web::Data<T>,
 ) -> Result<HttpResponse, ApiError> {
     let (group_name, user_uuid) = path.into_inner();
     let user = User { user_uuid };
@@ -97,18 +104,19 @@ async fn remove_member<T: AsyncCisClientTrait>(
         &group_name,
         &host,
         &user,
-        Arc::clone(&*cis_client),
+        Arc::clone(&*publisher),
     )
     .await?;
     Ok(HttpResponse::Ok().json(""))
 }
 
 #[guard(Ndaed, None, Medium)]
-async fn renew_member(
+async fn renew_member<T: ProfilePublisher>(
     pool: web::Data<Pool>,
     path: web::Path<(String, Uuid)>,
     scope_and_user: ScopeAndUser,
     renew_member: web::Json<RenewMember>,
+    publisher: web::Data<T>,
 ) -> impl Responder {
     let (group_name, user_uuid) = path.into_inner();
     let user = User { user_uuid };
@@ -120,19 +128,62 @@ async fn renew_member(
         &host,
         &user,
         renew_member.group_expiration,
-    ) {
+        Arc::clone(&*publisher),
+    )
+    .await
+    {
         Ok(_) => Ok(HttpResponse::Created().json("")),
         Err(e) => Err(ApiError::GenericBadRequest(e)),
     }
 }
 
-pub fn members_app<T: AsyncCisClientTrait + 'static>() -> impl HttpServiceFactory {
+#[guard(Ndaed, None, Medium)]
+async fn email_members(
+    pool: web::Data<Pool>,
+    group_name: web::Path<String>,
+    scope_and_user: ScopeAndUser,
+    broadcast: web::Json<Broadcast>,
+) -> Result<HttpResponse, ApiError> {
+    let broadcast = broadcast.into_inner().checked()?;
+    let recipients =
+        operations::broadcasts::broadcast(&pool, &scope_and_user, &group_name, broadcast)?;
+    Ok(HttpResponse::Ok().json(BroadcastStatus { recipients }))
+}
+
+#[guard(Ndaed, None, Medium)]
+async fn create_calendar_token(
+    pool: web::Data<Pool>,
+    group_name: web::Path<String>,
+    scope_and_user: ScopeAndUser,
+) -> Result<HttpResponse, ApiError> {
+    let token = operations::calendar::create_calendar_token(&pool, &scope_and_user, &group_name)?;
+    Ok(HttpResponse::Created().json(token))
+}
+
+#[guard(Ndaed, None, Medium)]
+async fn revoke_calendar_token(
+    pool: web::Data<Pool>,
+    group_name: web::Path<String>,
+    scope_and_user: ScopeAndUser,
+) -> Result<HttpResponse, ApiError> {
+    operations::calendar::revoke_calendar_token(&pool, &scope_and_user, &group_name)?;
+    Ok(HttpResponse::Ok().json(""))
+}
+
+pub fn members_app<T: ProfilePublisher + 'static>() -> impl HttpServiceFactory {
     web::scope("/members")
         .service(web::resource("/{group_name}").route(web::get().to(get_members)))
+        .service(web::resource("/{group_name}/email").route(web::post().to(email_members)))
+        .service(
+            web::resource("/{group_name}/calendar")
+                .route(web::post().to(create_calendar_token))
+                .route(web::delete().to(revoke_calendar_token)),
+        )
         .service(
             web::resource("/{group_name}/{user_uuid}").route(web::delete().to(remove_member::<T>)),
         )
         .service(
-            web::resource("/{group_name}/{user_uuid}/renew").route(web::post().to(renew_member)),
+            web::resource("/{group_name}/{user_uuid}/renew")
+                .route(web::post().to(renew_member::<T>)),
         )
 }
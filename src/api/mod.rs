@@ -1,4 +1,5 @@
 pub mod admins;
+pub mod calendar;
 pub mod current;
 pub mod error;
 pub mod forms;
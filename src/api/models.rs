@@ -1,5 +1,7 @@
 use crate::db::model::Group;
 use crate::db::operations::models::DisplayMembershipAndHost;
+use crate::db::operations::models::Grace;
+use crate::db::operations::models::Reminder;
 use crate::db::types::GroupType;
 use crate::db::types::TrustType;
 use crate::utils::to_utc;
@@ -38,6 +40,9 @@ pub struct GroupInfo {
     pub created: NaiveDateTime,
     pub terms: bool,
     pub trust: TrustType,
+    pub reminders: Option<Vec<Reminder>>,
+    pub self_renewal: bool,
+    pub expiration_grace: Option<Grace>,
 }
 
 #[derive(Serialize)]
@@ -1,23 +1,41 @@
 use crate::api::error::ApiError;
+use crate::cis::operations::ProfilePublisher;
 use crate::db::operations;
+use crate::db::operations::models::GroupDisplaySetting;
+use crate::db::operations::models::GroupNewsletters;
+use crate::db::operations::models::MergeUsers;
+use crate::db::operations::models::NewWebhook;
+use crate::db::operations::models::TrustGrace;
+use crate::db::types::DeliveryStatusType;
 use crate::db::types::TrustType;
 use crate::db::Pool;
-use crate::mail::manager::subscribe_nda;
-use crate::mail::manager::unsubscribe_nda;
 use crate::user::User;
 use actix_web::dev::HttpServiceFactory;
 use actix_web::web;
 use actix_web::HttpResponse;
 use actix_web::Responder;
-use cis_client::AsyncCisClientTrait;
+use chrono::DateTime;
+use chrono::Utc;
 use dino_park_gate::scope::ScopeAndUser;
 use serde::Deserialize;
+use serde::Serialize;
 use std::sync::Arc;
 use uuid::Uuid;
 
+#[derive(Serialize)]
+pub struct ReconcileStatus {
+    subscribed: usize,
+}
+
+#[derive(Serialize)]
+pub struct RepublishStatus {
+    queued: usize,
+}
+
 #[derive(Clone, Deserialize)]
 pub struct ChangeTrust {
     trust: TrustType,
+    stage_days: Option<i32>,
 }
 
 #[derive(Clone, Deserialize)]
@@ -28,6 +46,27 @@ pub struct AddUser {
     no_host: bool,
 }
 
+#[derive(Deserialize)]
+struct OutboxQuery {
+    #[serde(default = "default_outbox_status")]
+    status: DeliveryStatusType,
+}
+
+fn default_outbox_status() -> DeliveryStatusType {
+    DeliveryStatusType::Failed
+}
+
+#[derive(Deserialize)]
+struct ExpirationPreviewQuery {
+    before: Option<DateTime<Utc>>,
+}
+
+#[derive(Deserialize)]
+struct ChangeTrustQuery {
+    #[serde(default)]
+    preview: bool,
+}
+
 #[derive(Deserialize)]
 struct LimitOffsetQuery {
     #[serde(default)]
@@ -41,12 +80,12 @@ fn default_groups_list_size() -> i64 {
 }
 
 #[guard(Staff, Admin, Medium)]
-async fn add_member<T: AsyncCisClientTrait>(
+async fn add_member<T: ProfilePublisher>(
     pool: web::Data<Pool>,
     group_name: web::Path<String>,
     scope_and_user: ScopeAndUser,
     add_member: web::Json<AddUser>,
-    cis_client: web::Data<T>,
+    publisher: web::Data<T>,
 ) -> Result<HttpResponse, ApiError> {
     let user_uuid = add_member.user_uuid;
     let host = if add_member.no_host {
@@ -61,19 +100,19 @@ async fn add_member<T: AsyncCisClientTrait>(
         &host,
         &User { user_uuid },
         add_member.group_expiration,
-        Arc::clone(&*cis_client),
+        Arc::clone(&*publisher),
     )
     .await?;
     Ok(HttpResponse::Ok().json(""))
 }
 
 #[guard(Staff, Admin, Medium)]
-async fn add_admin<T: AsyncCisClientTrait>(
+async fn add_admin<T: ProfilePublisher>(
     pool: web::Data<Pool>,
     group_name: web::Path<String>,
     scope_and_user: ScopeAndUser,
     add_admin: web::Json<AddUser>,
-    cis_client: web::Data<T>,
+    publisher: web::Data<T>,
 ) -> Result<HttpResponse, ApiError> {
     let user_uuid = add_admin.user_uuid;
     let host = if add_admin.no_host {
@@ -87,18 +126,18 @@ async fn add_admin<T: AsyncCisClientTrait>(
         &group_name,
         &host,
         &User { user_uuid },
-        Arc::clone(&*cis_client),
+        Arc::clone(&*publisher),
     )
     .await?;
     Ok(HttpResponse::Ok().json(""))
 }
 
 #[guard(Staff, Admin, Medium)]
-async fn remove_member<T: AsyncCisClientTrait>(
+async fn remove_member<T: ProfilePublisher>(
     pool: web::Data<Pool>,
     path: web::Path<(String, Uuid)>,
     scope_and_user: ScopeAndUser,
-    cis_client: web::Data<T>,
+    publisher: web::Data<T>,
 ) -> Result<HttpResponse, ApiError> {
     let (group_name, user_uuid) = path.into_inner();
     let host = operations::users::user_by_id(&pool.clone(), &scope_and_user.user_id)?;
@@ -108,7 +147,7 @@ async fn remove_member<T: AsyncCisClientTrait>(
         &group_name,
         &host,
         &User { user_uuid },
-        Arc::clone(&*cis_client),
+        Arc::clone(&*publisher),
     )
     .await?;
     Ok(HttpResponse::Ok().json(""))
@@ -123,6 +162,98 @@ async fn all_raw_logs(pool: web::Data<Pool>, scope_and_user: ScopeAndUser) -> im
     }
 }
 
+#[guard(Staff, Admin, Medium)]
+async fn suppressed_emails(pool: web::Data<Pool>, scope_and_user: ScopeAndUser) -> impl Responder {
+    let user = operations::users::user_by_id(&pool, &scope_and_user.user_id)?;
+    match operations::suppressions::suppressed_emails(&pool, &scope_and_user, &user) {
+        Ok(emails) => Ok(HttpResponse::Ok().json(emails)),
+        Err(e) => Err(ApiError::GenericBadRequest(e)),
+    }
+}
+
+#[guard(Staff, Admin, Medium)]
+async fn unsuppress_email(
+    pool: web::Data<Pool>,
+    scope_and_user: ScopeAndUser,
+    email: web::Path<String>,
+) -> impl Responder {
+    let user = operations::users::user_by_id(&pool, &scope_and_user.user_id)?;
+    operations::suppressions::unsuppress_email(&pool, &scope_and_user, &user, &email)
+        .map(|_| HttpResponse::Ok().json(""))
+        .map_err(ApiError::GenericBadRequest)
+}
+
+#[guard(Staff, Admin, Medium)]
+async fn webhooks(pool: web::Data<Pool>, scope_and_user: ScopeAndUser) -> impl Responder {
+    let user = operations::users::user_by_id(&pool, &scope_and_user.user_id)?;
+    match operations::webhooks::webhooks(&pool, &scope_and_user, &user) {
+        Ok(webhooks) => Ok(HttpResponse::Ok().json(webhooks)),
+        Err(e) => Err(ApiError::GenericBadRequest(e)),
+    }
+}
+
+#[guard(Staff, Admin, Medium)]
+async fn create_webhook(
+    pool: web::Data<Pool>,
+    scope_and_user: ScopeAndUser,
+    new_webhook: web::Json<NewWebhook>,
+) -> impl Responder {
+    let new_webhook = new_webhook.into_inner().checked()?;
+    let user = operations::users::user_by_id(&pool, &scope_and_user.user_id)?;
+    match operations::webhooks::create_webhook(&pool, &scope_and_user, &user, new_webhook) {
+        Ok(webhook) => Ok(HttpResponse::Created().json(webhook)),
+        Err(e) => Err(ApiError::GenericBadRequest(e)),
+    }
+}
+
+#[guard(Staff, Admin, Medium)]
+async fn delete_webhook(
+    pool: web::Data<Pool>,
+    scope_and_user: ScopeAndUser,
+    id: web::Path<i32>,
+) -> impl Responder {
+    let user = operations::users::user_by_id(&pool, &scope_and_user.user_id)?;
+    operations::webhooks::delete_webhook(&pool, &scope_and_user, &user, id.into_inner())
+        .map(|_| HttpResponse::Ok().json(""))
+        .map_err(ApiError::GenericBadRequest)
+}
+
+#[guard(Staff, Admin, Medium)]
+async fn webhook_deliveries(
+    pool: web::Data<Pool>,
+    scope_and_user: ScopeAndUser,
+    id: web::Path<i32>,
+) -> impl Responder {
+    let user = operations::users::user_by_id(&pool, &scope_and_user.user_id)?;
+    match operations::webhooks::webhook_deliveries(&pool, &scope_and_user, &user, id.into_inner()) {
+        Ok(deliveries) => Ok(HttpResponse::Ok().json(deliveries)),
+        Err(e) => Err(ApiError::GenericBadRequest(e)),
+    }
+}
+
+#[guard(Staff, Admin, Medium)]
+async fn drift_report(pool: web::Data<Pool>, scope_and_user: ScopeAndUser) -> impl Responder {
+    let user = operations::users::user_by_id(&pool, &scope_and_user.user_id)?;
+    match operations::drift::drift_report(&pool, &scope_and_user, &user) {
+        Ok(report) => Ok(HttpResponse::Ok().json(report)),
+        Err(e) => Err(ApiError::GenericBadRequest(e)),
+    }
+}
+
+#[guard(Staff, Admin, Medium)]
+async fn expiration_preview(
+    pool: web::Data<Pool>,
+    scope_and_user: ScopeAndUser,
+    query: web::Query<ExpirationPreviewQuery>,
+) -> impl Responder {
+    let user = operations::users::user_by_id(&pool, &scope_and_user.user_id)?;
+    let before = query.before.unwrap_or_else(Utc::now).naive_utc();
+    match operations::expirations::expiration_preview(&pool, &scope_and_user, &user, before) {
+        Ok(preview) => Ok(HttpResponse::Ok().json(preview)),
+        Err(e) => Err(ApiError::GenericBadRequest(e)),
+    }
+}
+
 #[guard(Staff, Admin, Medium)]
 async fn curator_emails(
     pool: web::Data<Pool>,
@@ -159,23 +290,115 @@ async fn delete_inactive_group(
 }
 
 #[guard(Staff, Admin, Medium)]
-async fn subscribe_nda_mailing_list(
+async fn newsletters(
     pool: web::Data<Pool>,
-    user_uuid: web::Path<Uuid>,
-) -> Result<HttpResponse, ApiError> {
-    let user_profile = operations::users::user_profile_by_uuid(&pool, &user_uuid)?;
-    subscribe_nda(user_profile.email);
-    Ok(HttpResponse::Ok().json(""))
+    scope_and_user: ScopeAndUser,
+    group_name: web::Path<String>,
+) -> impl Responder {
+    let user = operations::users::user_by_id(&pool, &scope_and_user.user_id)?;
+    match operations::newsletters::newsletters(&pool, &scope_and_user, &user, &group_name) {
+        Ok(newsletters) => Ok(HttpResponse::Ok().json(newsletters)),
+        Err(e) => Err(ApiError::GenericBadRequest(e)),
+    }
+}
+
+#[guard(Staff, Admin, Medium)]
+async fn update_newsletters(
+    pool: web::Data<Pool>,
+    scope_and_user: ScopeAndUser,
+    group_name: web::Path<String>,
+    newsletters: web::Json<GroupNewsletters>,
+) -> impl Responder {
+    let newsletters = newsletters.into_inner().checked()?;
+    let user = operations::users::user_by_id(&pool, &scope_and_user.user_id)?;
+    operations::newsletters::update_newsletters(
+        &pool,
+        &scope_and_user,
+        &user,
+        &group_name,
+        newsletters,
+    )
+    .map(|_| HttpResponse::Ok().json(""))
+    .map_err(ApiError::GenericBadRequest)
 }
 
 #[guard(Staff, Admin, Medium)]
-async fn unsubscribe_nda_mailing_list(
+async fn reconcile_newsletters(
     pool: web::Data<Pool>,
-    user_uuid: web::Path<Uuid>,
+    scope_and_user: ScopeAndUser,
+    group_name: web::Path<String>,
+) -> impl Responder {
+    let user = operations::users::user_by_id(&pool, &scope_and_user.user_id)?;
+    match operations::newsletters::reconcile_newsletters(&pool, &scope_and_user, &user, &group_name)
+    {
+        Ok(subscribed) => Ok(HttpResponse::Ok().json(ReconcileStatus { subscribed })),
+        Err(e) => Err(ApiError::GenericBadRequest(e)),
+    }
+}
+
+#[guard(Staff, Admin, Medium)]
+async fn group_display(
+    pool: web::Data<Pool>,
+    scope_and_user: ScopeAndUser,
+    group_name: web::Path<String>,
+) -> impl Responder {
+    let user = operations::users::user_by_id(&pool, &scope_and_user.user_id)?;
+    match operations::display::group_display(&pool, &scope_and_user, &user, &group_name) {
+        Ok(setting) => Ok(HttpResponse::Ok().json(setting)),
+        Err(e) => Err(ApiError::GenericBadRequest(e)),
+    }
+}
+
+#[guard(Staff, Admin, Medium)]
+async fn update_group_display(
+    pool: web::Data<Pool>,
+    scope_and_user: ScopeAndUser,
+    group_name: web::Path<String>,
+    setting: web::Json<GroupDisplaySetting>,
+) -> impl Responder {
+    let user = operations::users::user_by_id(&pool, &scope_and_user.user_id)?;
+    operations::display::update_group_display(
+        &pool,
+        &scope_and_user,
+        &user,
+        &group_name,
+        setting.into_inner(),
+    )
+    .map(|_| HttpResponse::Ok().json(""))
+    .map_err(ApiError::GenericBadRequest)
+}
+
+#[guard(Staff, Admin, Medium)]
+async fn republish_memberships(
+    pool: web::Data<Pool>,
+    scope_and_user: ScopeAndUser,
+    group_name: web::Path<String>,
+) -> impl Responder {
+    let user = operations::users::user_by_id(&pool, &scope_and_user.user_id)?;
+    match operations::display::republish_memberships(&pool, &scope_and_user, &user, &group_name) {
+        Ok(queued) => Ok(HttpResponse::Ok().json(RepublishStatus { queued })),
+        Err(e) => Err(ApiError::GenericBadRequest(e)),
+    }
+}
+
+#[guard(Staff, Admin, Medium)]
+async fn merge_users<T: ProfilePublisher>(
+    pool: web::Data<Pool>,
+    scope_and_user: ScopeAndUser,
+    merge: web::Json<MergeUsers>,
+    publisher: web::Data<T>,
 ) -> Result<HttpResponse, ApiError> {
-    let user_profile = operations::users::user_profile_by_uuid(&pool, &user_uuid)?;
-    unsubscribe_nda(user_profile.email);
-    Ok(HttpResponse::Ok().json(""))
+    let merge = merge.into_inner().checked()?;
+    let user = operations::users::user_by_id(&pool, &scope_and_user.user_id)?;
+    let report = operations::users::merge_users(
+        &pool,
+        &scope_and_user,
+        &user,
+        merge,
+        Arc::clone(&*publisher),
+    )
+    .await?;
+    Ok(HttpResponse::Ok().json(report))
 }
 
 #[guard(Staff, Admin, Medium)]
@@ -190,25 +413,120 @@ async fn reserve_group(
 }
 
 #[guard(Staff, Admin, Medium)]
-async fn change_trust<T: AsyncCisClientTrait>(
+async fn change_trust<T: ProfilePublisher>(
     pool: web::Data<Pool>,
     group_name: web::Path<String>,
     scope_and_user: ScopeAndUser,
     trust_change: web::Json<ChangeTrust>,
-    cis_client: web::Data<T>,
+    query: web::Query<ChangeTrustQuery>,
+    publisher: web::Data<T>,
+) -> Result<HttpResponse, ApiError> {
+    let ChangeTrust { trust, stage_days } = trust_change.into_inner();
+    if query.preview {
+        let status = operations::groups::trust_change_preview(
+            &pool,
+            &scope_and_user,
+            &group_name,
+            &trust,
+            stage_days,
+        )?;
+        return Ok(HttpResponse::Ok().json(status));
+    }
+    let status = match stage_days {
+        Some(days) => operations::groups::stage_group_trust(
+            &pool,
+            &scope_and_user,
+            &group_name,
+            &trust,
+            days,
+        )?,
+        None => {
+            operations::groups::update_group_trust(
+                &pool,
+                &scope_and_user,
+                &group_name,
+                &trust,
+                Arc::clone(&*publisher),
+            )
+            .await?
+        }
+    };
+    Ok(HttpResponse::Ok().json(status))
+}
+
+#[guard(Staff, Admin, Medium)]
+async fn staged_trust(
+    pool: web::Data<Pool>,
+    group_name: web::Path<String>,
+    scope_and_user: ScopeAndUser,
+) -> Result<HttpResponse, ApiError> {
+    let staged = operations::groups::staged_group_trust(&pool, &scope_and_user, &group_name)?;
+    Ok(HttpResponse::Ok().json(staged))
+}
+
+#[guard(Staff, Admin, Medium)]
+async fn cancel_trust(
+    pool: web::Data<Pool>,
+    group_name: web::Path<String>,
+    scope_and_user: ScopeAndUser,
+) -> Result<HttpResponse, ApiError> {
+    operations::groups::cancel_group_trust(&pool, &scope_and_user, &group_name)?;
+    Ok(HttpResponse::Ok().json(""))
+}
+
+#[guard(Staff, Admin, Medium)]
+async fn trust_grace(
+    pool: web::Data<Pool>,
+    scope_and_user: ScopeAndUser,
 ) -> Result<HttpResponse, ApiError> {
-    operations::groups::update_group_trust(
+    let trust_grace = operations::users::trust_grace(&pool, &scope_and_user)?;
+    Ok(HttpResponse::Ok().json(trust_grace))
+}
+
+#[guard(Staff, Admin, Medium)]
+async fn set_trust_grace(
+    pool: web::Data<Pool>,
+    scope_and_user: ScopeAndUser,
+    trust_grace: web::Json<TrustGrace>,
+) -> Result<HttpResponse, ApiError> {
+    let trust_grace = trust_grace.into_inner().checked()?;
+    operations::users::set_trust_grace(&pool, &scope_and_user, trust_grace)?;
+    Ok(HttpResponse::Ok().json(""))
+}
+
+#[guard(Staff, Admin, Medium)]
+async fn outbox(
+    pool: web::Data<Pool>,
+    scope_and_user: ScopeAndUser,
+    query: web::Query<OutboxQuery>,
+) -> impl Responder {
+    let user = operations::users::user_by_id(&pool, &scope_and_user.user_id)?;
+    match operations::outbox::outbox_entries(&pool, &scope_and_user, &user, query.status) {
+        Ok(entries) => Ok(HttpResponse::Ok().json(entries)),
+        Err(e) => Err(ApiError::GenericBadRequest(e)),
+    }
+}
+
+#[guard(Staff, Admin, Medium)]
+async fn retry_outbox_entry<T: ProfilePublisher>(
+    pool: web::Data<Pool>,
+    scope_and_user: ScopeAndUser,
+    id: web::Path<i32>,
+    publisher: web::Data<T>,
+) -> Result<HttpResponse, ApiError> {
+    let user = operations::users::user_by_id(&pool, &scope_and_user.user_id)?;
+    let entry = operations::outbox::retry_entry(
         &pool,
         &scope_and_user,
-        &group_name,
-        &trust_change.trust,
-        Arc::clone(&*cis_client),
+        &user,
+        id.into_inner(),
+        Arc::clone(&*publisher),
     )
     .await?;
-    Ok(HttpResponse::Ok().json(""))
+    Ok(HttpResponse::Ok().json(entry))
 }
 
-pub fn sudo_app<T: AsyncCisClientTrait + 'static>() -> impl HttpServiceFactory {
+pub fn sudo_app<T: ProfilePublisher + 'static>() -> impl HttpServiceFactory {
     web::scope("/sudo")
         .service(web::resource("/groups/reserve/{group_name}").route(web::post().to(reserve_group)))
         .service(web::resource("/groups/inactive").route(web::get().to(list_inactive_groups)))
@@ -217,7 +535,15 @@ pub fn sudo_app<T: AsyncCisClientTrait + 'static>() -> impl HttpServiceFactory {
                 .route(web::delete().to(delete_inactive_group)),
         )
         .service(
-            web::resource("/trust/groups/{group_name}").route(web::put().to(change_trust::<T>)),
+            web::resource("/trust/groups/{group_name}")
+                .route(web::get().to(staged_trust))
+                .route(web::put().to(change_trust::<T>))
+                .route(web::delete().to(cancel_trust)),
+        )
+        .service(
+            web::resource("/trust/grace")
+                .route(web::get().to(trust_grace))
+                .route(web::put().to(set_trust_grace)),
         )
         .service(web::resource("/member/{group_name}").route(web::post().to(add_member::<T>)))
         .service(
@@ -229,5 +555,41 @@ pub fn sudo_app<T: AsyncCisClientTrait + 'static>() -> impl HttpServiceFactory {
                 .route(web::get().to(curator_emails))
                 .route(web::post().to(add_admin::<T>)),
         )
+        .service(web::resource("/users/merge").route(web::post().to(merge_users::<T>)))
         .service(web::resource("/logs/all/raw").route(web::get().to(all_raw_logs)))
+        .service(web::resource("/drift").route(web::get().to(drift_report)))
+        .service(web::resource("/expirations/preview").route(web::get().to(expiration_preview)))
+        .service(web::resource("/mail/suppressed").route(web::get().to(suppressed_emails)))
+        .service(
+            web::resource("/mail/suppressed/{email}").route(web::delete().to(unsuppress_email)),
+        )
+        .service(
+            web::resource("/newsletters/{group_name}")
+                .route(web::get().to(newsletters))
+                .route(web::put().to(update_newsletters)),
+        )
+        .service(
+            web::resource("/newsletters/{group_name}/reconcile")
+                .route(web::post().to(reconcile_newsletters)),
+        )
+        .service(
+            web::resource("/display/groups/{group_name}")
+                .route(web::get().to(group_display))
+                .route(web::put().to(update_group_display)),
+        )
+        .service(
+            web::resource("/display/groups/{group_name}/republish")
+                .route(web::post().to(republish_memberships)),
+        )
+        .service(
+            web::resource("/webhooks")
+                .route(web::get().to(webhooks))
+                .route(web::post().to(create_webhook)),
+        )
+        .service(web::resource("/webhooks/{id}").route(web::delete().to(delete_webhook)))
+        .service(
+            web::resource("/webhooks/{id}/deliveries").route(web::get().to(webhook_deliveries)),
+        )
+        .service(web::resource("/outbox").route(web::get().to(outbox)))
+        .service(web::resource("/outbox/{id}/retry").route(web::post().to(retry_outbox_entry::<T>)))
 }
@@ -0,0 +1,156 @@
+//! In-memory stand-in for CIS to test services embedding packs. Only available with the
+//! `test-support` feature.
+
+use crate::db::operations::users::update_user_cache_unchecked;
+use crate::db::Pool;
+use cis_client::error::ProfileError;
+use cis_client::getby::GetBy;
+use cis_client::AsyncCisClientTrait;
+use cis_client::CisFut;
+use cis_profile::crypto::SecretStore;
+use cis_profile::schema::Profile;
+use failure::format_err;
+use futures::future::err;
+use futures::future::ok;
+use serde_json::json;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::RwLock;
+
+const FAKE_KEY: &str = include_str!("fake_key.json");
+
+/// Keeps profiles in memory and writes every update back to the packs profile cache like CIS
+/// would by notifying packs. Updates are signed with a fixed test key.
+#[derive(Clone)]
+pub struct CisFakeClient {
+    pub store: Arc<RwLock<HashMap<String, Profile>>>,
+    pub pool: Pool,
+    pub secret_store: Arc<SecretStore>,
+    updates: Arc<RwLock<Vec<(String, Profile)>>>,
+    failing_updates: Arc<RwLock<usize>>,
+    failing_fetches: Arc<RwLock<usize>>,
+}
+
+impl CisFakeClient {
+    pub fn new(pool: Pool) -> Self {
+        let secret_store = Arc::new(
+            SecretStore::default()
+                .with_sign_keys_from_inline_iter(vec![(
+                    String::from("mozilliansorg"),
+                    FAKE_KEY.to_owned(),
+                )])
+                .expect("invalid fake key"),
+        );
+        CisFakeClient {
+            store: Default::default(),
+            pool,
+            secret_store,
+            updates: Default::default(),
+            failing_updates: Default::default(),
+            failing_fetches: Default::default(),
+        }
+    }
+
+    /// Stores `profile` without updating the packs profile cache.
+    pub fn insert(&self, profile: Profile) {
+        if let Some(user_id) = profile.user_id.value.clone() {
+            self.store.write().unwrap().insert(user_id, profile);
+        }
+    }
+
+    pub fn profile(&self, user_id: &str) -> Option<Profile> {
+        self.store.read().unwrap().get(user_id).cloned()
+    }
+
+    /// All updates sent by packs in order as `(user_id, profile)`.
+    pub fn updates(&self) -> Vec<(String, Profile)> {
+        self.updates.read().unwrap().clone()
+    }
+
+    pub fn clear_updates(&self) {
+        self.updates.write().unwrap().clear()
+    }
+
+    /// Lets the next `n` profile updates fail.
+    pub fn fail_updates(&self, n: usize) {
+        *self.failing_updates.write().unwrap() = n;
+    }
+
+    /// Lets the next `n` profile fetches fail.
+    pub fn fail_fetches(&self, n: usize) {
+        *self.failing_fetches.write().unwrap() = n;
+    }
+
+    fn inject_failure(counter: &RwLock<usize>) -> bool {
+        let mut counter = counter.write().unwrap();
+        if *counter > 0 {
+            *counter -= 1;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+impl AsyncCisClientTrait for CisFakeClient {
+    fn get_user_by(&self, id: &str, _: &GetBy, _: Option<&str>) -> CisFut<Profile> {
+        if Self::inject_failure(&self.failing_fetches) {
+            return Box::pin(err(format_err!("injected failure")));
+        }
+        if let Some(p) = self.store.read().unwrap().get(id) {
+            Box::pin(ok(p.clone()))
+        } else {
+            Box::pin(err(ProfileError::ProfileDoesNotExist.into()))
+        }
+    }
+    fn get_any_user_by(&self, id: &str, by: &GetBy, filter: Option<&str>) -> CisFut<Profile> {
+        self.get_user_by(id, by, filter)
+    }
+    fn get_inactive_user_by(&self, id: &str, by: &GetBy, filter: Option<&str>) -> CisFut<Profile> {
+        self.get_user_by(id, by, filter)
+    }
+    fn update_user(&self, id: &str, profile: Profile) -> CisFut<Value> {
+        if Self::inject_failure(&self.failing_updates) {
+            return Box::pin(err(format_err!("injected failure")));
+        }
+        self.updates
+            .write()
+            .unwrap()
+            .push((id.to_owned(), profile.clone()));
+        let mut store = self.store.write().unwrap();
+        let p = if let Some(p) = store.get_mut(id) {
+            p.access_information.mozilliansorg = profile.access_information.mozilliansorg;
+            p.clone()
+        } else {
+            store.insert(id.to_owned(), profile.clone());
+            profile
+        };
+        match update_user_cache_unchecked(&self.pool, &p) {
+            Ok(_) => Box::pin(ok(json!({}))),
+            Err(e) => Box::pin(err(e)),
+        }
+    }
+    fn update_users(&self, profiles: &[Profile]) -> CisFut<Value> {
+        for profile in profiles {
+            if let Some(user_id) = profile.user_id.value.clone() {
+                self.insert(profile.clone());
+                if let Err(e) = update_user_cache_unchecked(&self.pool, profile) {
+                    return Box::pin(err(e));
+                }
+                self.updates
+                    .write()
+                    .unwrap()
+                    .push((user_id, profile.clone()));
+            }
+        }
+        Box::pin(ok(json!({})))
+    }
+    fn delete_user(&self, id: &str, _: Profile) -> CisFut<Value> {
+        self.store.write().unwrap().remove(id);
+        Box::pin(ok(json!({})))
+    }
+    fn get_secret_store(&self) -> &SecretStore {
+        &self.secret_store
+    }
+}
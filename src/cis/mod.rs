@@ -1 +1,3 @@
+#[cfg(feature = "test-support")]
+pub mod fake;
 pub mod operations;
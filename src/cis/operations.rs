@@ -1,5 +1,7 @@
+use crate::error::PacksError;
 use chrono::DateTime;
 use chrono::Utc;
+use cis_client::getby::GetBy;
 use cis_client::AsyncCisClientTrait;
 use cis_profile::crypto::SecretStore;
 use cis_profile::crypto::Signer;
@@ -10,118 +12,346 @@ use cis_profile::schema::Profile;
 use cis_profile::schema::PublisherAuthority;
 use failure::format_err;
 use failure::Error;
+use futures::future;
+use futures::Future;
 use futures::TryFutureExt;
+use log::info;
 use log::warn;
+use serde_json::json;
 use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+use std::pin::Pin;
 use std::sync::Arc;
+use url::Url;
 
-fn insert_kv_and_sign_values_field(
+/// Adds `add` and removes `remove` from the values and signs the field if anything changed.
+/// Returns whether the field changed.
+fn update_kv_and_sign_values_field(
     field: &mut AccessInformationProviderSubObject,
-    kv: (String, Option<String>),
+    add: &[String],
+    remove: &[String],
+    display: Display,
     store: &SecretStore,
     now: &DateTime<Utc>,
-) -> Result<(), Error> {
-    if let Some(KeyValue(ref mut values)) = &mut field.values {
-        values.insert(kv.0, kv.1);
-    } else {
-        field.metadata.created = *now;
-        field.values = Some(KeyValue({
-            let mut btm = BTreeMap::new();
-            btm.insert(kv.0, kv.1);
-            btm
-        }));
+) -> Result<bool, Error> {
+    let mut values = match field.values.take() {
+        Some(KeyValue(values)) => values,
+        None if add.is_empty() => BTreeMap::new(),
+        None => {
+            field.metadata.created = *now;
+            BTreeMap::new()
+        }
+    };
+    let mut changed = !add.is_empty();
+    for key in remove {
+        if values.remove(key).is_some() {
+            changed = true;
+        } else {
+            warn!("group {} was not present when trying to delete", key);
+        }
     }
-    if field.metadata.display.is_none() {
-        field.metadata.display = Some(Display::Staff);
+    values.extend(add.iter().map(|key| (key.clone(), Some(String::default()))));
+    field.values = Some(KeyValue(values));
+    if !changed {
+        return Ok(false);
     }
+    field.metadata.display = Some(display);
     field.metadata.last_modified = *now;
     field.signature.publisher.name = PublisherAuthority::Mozilliansorg;
-    store.sign_attribute(field)
+    store.sign_attribute(field)?;
+    Ok(true)
 }
 
-fn remove_kv_and_sign_values_field(
+fn replace_kv_and_sign_values_field(
     field: &mut AccessInformationProviderSubObject,
-    keys: &[&str],
+    keys: &[String],
+    display: Display,
     store: &SecretStore,
     now: &DateTime<Utc>,
 ) -> Result<(), Error> {
-    if let Some(KeyValue(ref mut values)) = &mut field.values {
-        let mut changed = false;
-        for key in keys {
-            if values.remove(*key).is_some() {
-                changed = true;
-            } else {
-                warn!("group {} was not present when trying to delete", key);
-            }
-        }
-        if changed {
-            field.metadata.last_modified = *now;
-            field.signature.publisher.name = PublisherAuthority::Mozilliansorg;
-            return store.sign_attribute(field);
+    let old = match field.values.take() {
+        Some(KeyValue(values)) => values,
+        None => {
+            field.metadata.created = *now;
+            BTreeMap::new()
         }
-    }
-    warn!("groups {:?} where not present when trying to delete", keys);
-    Ok(())
+    };
+    let values = keys
+        .iter()
+        .map(|key| {
+            let value = old
+                .get(key)
+                .cloned()
+                .unwrap_or_else(|| Some(String::default()));
+            (key.clone(), value)
+        })
+        .collect();
+    field.values = Some(KeyValue(values));
+    field.metadata.display = Some(display);
+    field.metadata.last_modified = *now;
+    field.signature.publisher.name = PublisherAuthority::Mozilliansorg;
+    store.sign_attribute(field)
 }
 
-pub async fn add_group_to_profile(
-    cis_client: Arc<impl AsyncCisClientTrait>,
-    group_name: String,
-    profile: Profile,
-) -> Result<(), Error> {
-    let now = &Utc::now();
-    let mut update_profile = Profile::default();
-    update_profile.access_information.mozilliansorg = profile.access_information.mozilliansorg;
-    update_profile.active = profile.active;
-    match insert_kv_and_sign_values_field(
-        &mut update_profile.access_information.mozilliansorg,
-        (group_name, Some(String::default())),
-        cis_client.get_secret_store(),
-        &now,
-    ) {
-        Ok(_) => {
-            if let Some(user_id) = profile.user_id.value.clone() {
-                cis_client
-                    .update_user(&user_id, update_profile)
-                    .map_ok(|_| ())
-                    .await
-            } else {
-                Err(format_err!("invalid user_id"))
+pub type PublishFut<T> = Pin<Box<dyn Future<Output = Result<T, Error>>>>;
+
+/// Publishes the group memberships managed by packs. CIS is the default, but packs can also run
+/// as a standalone group manager with `NoopPublisher` or `LocalPublisher`.
+pub trait ProfilePublisher {
+    /// Adds `add` and removes `remove` from the groups of the profile with a single update.
+    /// `display` is applied to the whole field.
+    fn update_groups(
+        self: Arc<Self>,
+        add: Vec<String>,
+        remove: Vec<String>,
+        display: Display,
+        profile: Profile,
+    ) -> PublishFut<()>;
+
+    /// Replaces all groups of the profile with `group_names`. `display` is applied to the whole
+    /// field.
+    fn set_groups(
+        self: Arc<Self>,
+        group_names: Vec<String>,
+        display: Display,
+        profile: Profile,
+    ) -> PublishFut<()>;
+
+    /// Fetches the current profile of `user_id` from the source of the profiles.
+    fn fetch_profile(self: Arc<Self>, user_id: String) -> PublishFut<Profile>;
+}
+
+fn user_id(profile: &Profile) -> Result<String, Error> {
+    profile
+        .user_id
+        .value
+        .clone()
+        .ok_or_else(|| format_err!("invalid user_id"))
+}
+
+/// Publishes to CIS by signing the mozilliansorg field and updating the profile.
+impl<C: AsyncCisClientTrait + 'static> ProfilePublisher for C {
+    fn update_groups(
+        self: Arc<Self>,
+        add: Vec<String>,
+        remove: Vec<String>,
+        display: Display,
+        profile: Profile,
+    ) -> PublishFut<()> {
+        Box::pin(async move {
+            let user_id = user_id(&profile)?;
+            let now = &Utc::now();
+            let mut update_profile = Profile::default();
+            update_profile.access_information.mozilliansorg =
+                profile.access_information.mozilliansorg;
+            update_profile.active = profile.active;
+            if !update_kv_and_sign_values_field(
+                &mut update_profile.access_information.mozilliansorg,
+                &add,
+                &remove,
+                display,
+                self.get_secret_store(),
+                &now,
+            )? {
+                return Ok(());
             }
-        }
-        Err(e) => Err(e),
+            self.update_user(&user_id, update_profile)
+                .map_ok(|_| ())
+                .await
+        })
+    }
+
+    fn set_groups(
+        self: Arc<Self>,
+        group_names: Vec<String>,
+        display: Display,
+        profile: Profile,
+    ) -> PublishFut<()> {
+        Box::pin(async move {
+            let user_id = user_id(&profile)?;
+            let now = &Utc::now();
+            let mut update_profile = Profile::default();
+            update_profile.access_information.mozilliansorg =
+                profile.access_information.mozilliansorg;
+            update_profile.active = profile.active;
+            replace_kv_and_sign_values_field(
+                &mut update_profile.access_information.mozilliansorg,
+                &group_names,
+                display,
+                self.get_secret_store(),
+                &now,
+            )?;
+            self.update_user(&user_id, update_profile)
+                .map_ok(|_| ())
+                .await
+        })
+    }
+
+    fn fetch_profile(self: Arc<Self>, user_id: String) -> PublishFut<Profile> {
+        Box::pin(async move { self.get_user_by(&user_id, &GetBy::UserId, None).await })
     }
 }
 
-pub async fn remove_group_from_profile(
-    cis_client: Arc<impl AsyncCisClientTrait>,
-    group_names: &[&str],
-    profile: Profile,
-) -> Result<(), Error> {
-    if group_names.is_empty() {
-        return Ok(());
+/// Drops all updates. Runs packs as a standalone group manager.
+#[derive(Clone, Debug, Default)]
+pub struct NoopPublisher;
+
+impl ProfilePublisher for NoopPublisher {
+    fn update_groups(
+        self: Arc<Self>,
+        _: Vec<String>,
+        _: Vec<String>,
+        _: Display,
+        _: Profile,
+    ) -> PublishFut<()> {
+        Box::pin(future::ok(()))
     }
-    let now = &Utc::now();
-    let mut update_profile = Profile::default();
-    update_profile.access_information.mozilliansorg = profile.access_information.mozilliansorg;
-    update_profile.active = profile.active;
-    match remove_kv_and_sign_values_field(
-        &mut update_profile.access_information.mozilliansorg,
-        group_names,
-        cis_client.get_secret_store(),
-        &now,
-    ) {
-        Ok(_) => {
-            if let Some(user_id) = profile.user_id.value.clone() {
-                log::debug!("updating profile");
-                cis_client
-                    .update_user(&user_id, update_profile)
-                    .map_ok(|_| ())
-                    .await
-            } else {
-                Err(format_err!("invalid user_id"))
+
+    fn set_groups(self: Arc<Self>, _: Vec<String>, _: Display, _: Profile) -> PublishFut<()> {
+        Box::pin(future::ok(()))
+    }
+
+    fn fetch_profile(self: Arc<Self>, _: String) -> PublishFut<Profile> {
+        Box::pin(future::err(PacksError::NoProfileSource.into()))
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum LocalTarget {
+    File(PathBuf),
+    Http(Url),
+}
+
+/// Stand-in for CIS which writes the resulting groups of every update as a JSON line to a file
+/// or posts them to an HTTP endpoint. Nothing is signed.
+#[derive(Clone, Debug)]
+pub struct LocalPublisher {
+    target: LocalTarget,
+    client: reqwest::Client,
+}
+
+impl LocalPublisher {
+    pub fn new(target: LocalTarget) -> Self {
+        LocalPublisher {
+            target,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    async fn publish(
+        &self,
+        group_names: Vec<String>,
+        display: Display,
+        profile: Profile,
+    ) -> Result<(), Error> {
+        let user_id = user_id(&profile)?;
+        let count = group_names.len();
+        let update = json!({
+            "user_id": user_id,
+            "user_uuid": profile.uuid.value,
+            "groups": group_names,
+            "display": display,
+        });
+        match self.target {
+            LocalTarget::File(ref path) => {
+                let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+                writeln!(file, "{}", serde_json::to_string(&update)?)?;
+            }
+            LocalTarget::Http(ref url) => {
+                let res = self
+                    .client
+                    .post(url.clone())
+                    .header(reqwest::header::CONTENT_TYPE, "application/json")
+                    .body(serde_json::to_vec(&update)?)
+                    .send()
+                    .await?;
+                if !res.status().is_success() {
+                    return Err(format_err!("unexpected status {}", res.status()));
+                }
             }
         }
-        Err(e) => Err(e),
+        info!("published {} groups for {}", count, user_id);
+        Ok(())
+    }
+}
+
+impl ProfilePublisher for LocalPublisher {
+    fn update_groups(
+        self: Arc<Self>,
+        add: Vec<String>,
+        remove: Vec<String>,
+        display: Display,
+        profile: Profile,
+    ) -> PublishFut<()> {
+        let mut group_names = match profile.access_information.mozilliansorg.values {
+            Some(KeyValue(ref values)) => values.keys().cloned().collect::<BTreeSet<_>>(),
+            None => BTreeSet::new(),
+        };
+        for group_name in &remove {
+            group_names.remove(group_name);
+        }
+        group_names.extend(add);
+        let group_names = group_names.into_iter().collect();
+        Box::pin(async move { self.publish(group_names, display, profile).await })
+    }
+
+    fn set_groups(
+        self: Arc<Self>,
+        group_names: Vec<String>,
+        display: Display,
+        profile: Profile,
+    ) -> PublishFut<()> {
+        Box::pin(async move { self.publish(group_names, display, profile).await })
+    }
+
+    fn fetch_profile(self: Arc<Self>, _: String) -> PublishFut<Profile> {
+        Box::pin(future::err(PacksError::NoProfileSource.into()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs;
+
+    #[actix_rt::test]
+    async fn test_local_publisher_file() -> Result<(), Error> {
+        let path = std::env::temp_dir().join("packs-local-publisher-test.jsonl");
+        let _ = fs::remove_file(&path);
+        let publisher = Arc::new(LocalPublisher::new(LocalTarget::File(path.clone())));
+        let mut profile = Profile::default();
+        profile.user_id.value = Some(String::from("fire1"));
+        profile.access_information.mozilliansorg.values = Some(KeyValue(
+            vec![
+                (String::from("a"), Some(String::default())),
+                (String::from("b"), Some(String::default())),
+            ]
+            .into_iter()
+            .collect(),
+        ));
+        Arc::clone(&publisher)
+            .update_groups(
+                vec![String::from("c")],
+                vec![String::from("a")],
+                Display::Staff,
+                profile.clone(),
+            )
+            .await?;
+        publisher
+            .set_groups(vec![String::from("d")], Display::Ndaed, profile)
+            .await?;
+        let lines = fs::read_to_string(&path)?
+            .lines()
+            .map(serde_json::from_str)
+            .collect::<Result<Vec<serde_json::Value>, _>>()?;
+        fs::remove_file(&path)?;
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["user_id"], "fire1");
+        assert_eq!(lines[0]["groups"], json!(["b", "c"]));
+        assert_eq!(lines[1]["groups"], json!(["d"]));
+        Ok(())
     }
 }
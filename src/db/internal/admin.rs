@@ -6,8 +6,10 @@ use crate::db::schema;
 use crate::db::types::*;
 
 use crate::user::User;
+use diesel::dsl::exists;
 use diesel::prelude::*;
 use failure::Error;
+use serde_json::json;
 use uuid::Uuid;
 
 const ROLE_ADMIN: &str = "admin";
@@ -21,7 +23,7 @@ pub fn add_admin_role(
         group_id,
         typ: RoleType::Admin,
         name: ROLE_ADMIN.to_owned(),
-        permissions: vec![],
+        permissions: vec![PermissionType::EmailMembers],
     };
     diesel::insert_into(schema::roles::table)
         .values(admin)
@@ -73,6 +75,12 @@ pub fn demote_to_member(
     .get_result(connection)
     .map_err(Into::into)
     .map(|membership| {
+        internal::webhook::emit(
+            connection,
+            &log_ctx,
+            WebhookEventType::RoleChanged,
+            Some(json!({ "role": RoleType::Member })),
+        );
         internal::log::db_log(
             connection,
             &log_ctx,
@@ -100,6 +108,12 @@ pub fn add_admin(
         added_by: host.user_uuid,
     };
     let log_ctx = LogContext::with(group.id, host.user_uuid).with_user(user.user_uuid);
+    let existed = diesel::select(exists(
+        schema::memberships::table
+            .filter(schema::memberships::group_id.eq(group.id))
+            .filter(schema::memberships::user_uuid.eq(user.user_uuid)),
+    ))
+    .get_result::<bool>(connection)?;
     let membership = diesel::insert_into(schema::memberships::table)
         .values(&admin_membership)
         .on_conflict((
@@ -110,6 +124,16 @@ pub fn add_admin(
         .set(&admin_membership)
         .get_result(connection)
         .map(|membership| {
+            internal::webhook::emit(
+                connection,
+                &log_ctx,
+                if existed {
+                    WebhookEventType::RoleChanged
+                } else {
+                    WebhookEventType::MembershipCreated
+                },
+                Some(json!({ "role": RoleType::Admin })),
+            );
             internal::log::db_log(
                 connection,
                 &log_ctx,
@@ -0,0 +1,101 @@
+use crate::db::model::Group;
+use crate::db::schema;
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use failure::Error;
+use uuid::Uuid;
+
+/// Stores the hash of a new calendar token for the curator, replacing any previous one.
+pub fn set_token(
+    connection: &PgConnection,
+    group_id: i32,
+    user_uuid: &Uuid,
+    token_hash: &str,
+) -> Result<(), Error> {
+    use schema::calendar_tokens as c;
+    diesel::insert_into(c::table)
+        .values((
+            c::group_id.eq(group_id),
+            c::user_uuid.eq(user_uuid),
+            c::token_hash.eq(token_hash),
+        ))
+        .on_conflict((c::group_id, c::user_uuid))
+        .do_update()
+        .set((
+            c::token_hash.eq(token_hash),
+            c::created.eq(diesel::dsl::now),
+        ))
+        .execute(connection)
+        .map(|_| ())
+        .map_err(Into::into)
+}
+
+pub fn revoke_token(
+    connection: &PgConnection,
+    group_id: i32,
+    user_uuid: &Uuid,
+) -> Result<(), Error> {
+    diesel::delete(schema::calendar_tokens::table)
+        .filter(schema::calendar_tokens::group_id.eq(group_id))
+        .filter(schema::calendar_tokens::user_uuid.eq(user_uuid))
+        .execute(connection)
+        .map(|_| ())
+        .map_err(Into::into)
+}
+
+/// Returns the curator and group a calendar token was issued for.
+pub fn token_owner(
+    connection: &PgConnection,
+    token_hash: &str,
+) -> Result<Option<(Uuid, Group)>, Error> {
+    schema::calendar_tokens::table
+        .filter(schema::calendar_tokens::token_hash.eq(token_hash))
+        .inner_join(schema::groups::table)
+        .select((
+            schema::calendar_tokens::user_uuid,
+            schema::groups::all_columns,
+        ))
+        .first(connection)
+        .optional()
+        .map_err(Into::into)
+}
+
+/// Returns user uuid, username and expiration of all memberships in the group expiring after
+/// `after`, soonest first.
+pub fn expiring_memberships(
+    connection: &PgConnection,
+    group_id: i32,
+    after: NaiveDateTime,
+) -> Result<Vec<(Uuid, String, Option<NaiveDateTime>)>, Error> {
+    schema::memberships::table
+        .filter(schema::memberships::group_id.eq(group_id))
+        .filter(schema::memberships::expiration.gt(after))
+        .inner_join(
+            schema::profiles::table
+                .on(schema::profiles::user_uuid.eq(schema::memberships::user_uuid)),
+        )
+        .select((
+            schema::memberships::user_uuid,
+            schema::profiles::username,
+            schema::memberships::expiration,
+        ))
+        .order(schema::memberships::expiration)
+        .get_results(connection)
+        .map_err(Into::into)
+}
+
+pub fn delete_for_group(connection: &PgConnection, group_id: i32) -> Result<(), Error> {
+    diesel::delete(schema::calendar_tokens::table)
+        .filter(schema::calendar_tokens::group_id.eq(group_id))
+        .execute(connection)
+        .map(|_| ())
+        .map_err(Into::into)
+}
+
+pub fn delete_for_user(connection: &PgConnection, user_uuid: &Uuid) -> Result<(), Error> {
+    diesel::delete(schema::calendar_tokens::table)
+        .filter(schema::calendar_tokens::user_uuid.eq(user_uuid))
+        .execute(connection)
+        .map(|_| ())
+        .map_err(Into::into)
+}
@@ -0,0 +1,63 @@
+use crate::db::model::*;
+use crate::db::schema;
+use crate::db::types::*;
+use diesel::prelude::*;
+use failure::Error;
+
+/// Groups without an explicit setting are only visible to staff.
+pub const DEFAULT_DISPLAY: TrustType = TrustType::Staff;
+
+pub fn display_for_group(connection: &PgConnection, group_id: i32) -> Result<TrustType, Error> {
+    schema::group_display::table
+        .filter(schema::group_display::group_id.eq(group_id))
+        .select(schema::group_display::display)
+        .first(connection)
+        .optional()
+        .map(|display| display.unwrap_or(DEFAULT_DISPLAY))
+        .map_err(Into::into)
+}
+
+/// The display level for a profile field containing all `group_names`. Since the display level
+/// applies to the whole field this is the most restrictive level of all groups. Unknown groups
+/// fall back to `DEFAULT_DISPLAY`.
+pub fn display_for_groups(
+    connection: &PgConnection,
+    group_names: &[String],
+) -> Result<TrustType, Error> {
+    use schema::group_display as d;
+    use schema::groups as g;
+    let displays = g::table
+        .inner_join(d::table)
+        .filter(g::name.eq_any(group_names))
+        .select(d::display)
+        .get_results::<TrustType>(connection)?;
+    if displays.len() < group_names.len() {
+        return Ok(DEFAULT_DISPLAY);
+    }
+    Ok(displays
+        .into_iter()
+        .fold(TrustType::Public, |a, b| if b > a { b } else { a }))
+}
+
+pub fn set_display(
+    connection: &PgConnection,
+    group_id: i32,
+    display: TrustType,
+) -> Result<(), Error> {
+    diesel::insert_into(schema::group_display::table)
+        .values(GroupDisplay { group_id, display })
+        .on_conflict(schema::group_display::group_id)
+        .do_update()
+        .set(schema::group_display::display.eq(display))
+        .execute(connection)
+        .map(|_| ())
+        .map_err(Into::into)
+}
+
+pub fn delete_for_group(connection: &PgConnection, group_id: i32) -> Result<(), Error> {
+    diesel::delete(schema::group_display::table)
+        .filter(schema::group_display::group_id.eq(group_id))
+        .execute(connection)
+        .map(|_| ())
+        .map_err(Into::into)
+}
@@ -1,5 +1,12 @@
+use crate::db::model::ExpirationReminder;
+use crate::db::model::Membership;
+use crate::db::operations::models::Reminder;
+use crate::db::schema;
 use crate::utils::to_expiration_ts;
 use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use failure::Error;
+use uuid::Uuid;
 
 pub fn map_expiration(expiration: Option<i32>, fallback: Option<i32>) -> Option<NaiveDateTime> {
     match expiration {
@@ -9,3 +16,125 @@ pub fn map_expiration(expiration: Option<i32>, fallback: Option<i32>) -> Option<
     }
     .map(to_expiration_ts)
 }
+
+/// Returns the configured reminder schedule of a group, most distant reminder first. Falls back
+/// to the default schedule if the group has none.
+pub fn reminders_for_group(
+    connection: &PgConnection,
+    group_id: i32,
+) -> Result<Vec<Reminder>, Error> {
+    use schema::expiration_reminders as r;
+    let reminders = r::table
+        .filter(r::group_id.eq(group_id))
+        .order_by(r::days.desc())
+        .get_results::<ExpirationReminder>(connection)?;
+    if reminders.is_empty() {
+        return Ok(Reminder::default_schedule());
+    }
+    Ok(reminders.into_iter().map(Reminder::from).collect())
+}
+
+/// Returns the most distant reminder of all groups in days.
+pub fn max_reminder_days(connection: &PgConnection) -> Result<i32, Error> {
+    use schema::expiration_reminders as r;
+    let configured = r::table
+        .select(diesel::dsl::max(r::days))
+        .first::<Option<i32>>(connection)?;
+    let default = Reminder::default_schedule()
+        .iter()
+        .map(|r| r.days)
+        .max()
+        .unwrap_or_default();
+    Ok(configured.unwrap_or_default().max(default))
+}
+
+/// Replaces the reminder schedule of a group. An empty schedule restores the default one.
+pub fn set_reminders(
+    connection: &PgConnection,
+    group_id: i32,
+    reminders: Vec<Reminder>,
+) -> Result<(), Error> {
+    use schema::expiration_reminders as r;
+    delete_reminders_for_group(connection, group_id)?;
+    let reminders = reminders
+        .into_iter()
+        .map(|reminder| ExpirationReminder {
+            group_id,
+            days: reminder.days,
+            member: reminder.member,
+            host: reminder.host,
+            curators: reminder.curators,
+        })
+        .collect::<Vec<_>>();
+    diesel::insert_into(r::table)
+        .values(reminders)
+        .execute(connection)
+        .map(|_| ())
+        .map_err(Into::into)
+}
+
+pub fn delete_reminders_for_group(connection: &PgConnection, group_id: i32) -> Result<(), Error> {
+    use schema::expiration_reminders as r;
+    diesel::delete(r::table.filter(r::group_id.eq(group_id)))
+        .execute(connection)
+        .map(|_| ())
+        .map_err(Into::into)
+}
+
+/// Records a sent expiration reminder for the membership. Returns `false` if the reminder for
+/// this expiration date has already been recorded.
+pub fn record_notification(
+    connection: &PgConnection,
+    membership: &Membership,
+    days: i32,
+) -> Result<bool, Error> {
+    use schema::expiration_notifications as n;
+    let expiration = match membership.expiration {
+        Some(expiration) => expiration,
+        None => return Ok(false),
+    };
+    diesel::insert_into(n::table)
+        .values((
+            n::user_uuid.eq(membership.user_uuid),
+            n::group_id.eq(membership.group_id),
+            n::days.eq(days),
+            n::expiration.eq(expiration),
+        ))
+        .on_conflict_do_nothing()
+        .execute(connection)
+        .map(|inserted| inserted == 1)
+        .map_err(Into::into)
+}
+
+/// Drops recorded notifications for expirations which already passed.
+pub fn prune_notifications(
+    connection: &PgConnection,
+    before: NaiveDateTime,
+) -> Result<usize, Error> {
+    use schema::expiration_notifications as n;
+    diesel::delete(n::table.filter(n::expiration.lt(before)))
+        .execute(connection)
+        .map_err(Into::into)
+}
+
+pub fn delete_notifications_for_group(
+    connection: &PgConnection,
+    group_id: i32,
+) -> Result<(), Error> {
+    use schema::expiration_notifications as n;
+    diesel::delete(n::table.filter(n::group_id.eq(group_id)))
+        .execute(connection)
+        .map(|_| ())
+        .map_err(Into::into)
+}
+
+pub fn delete_notifications_for_user(
+    connection: &PgConnection,
+    user_uuid: &Uuid,
+) -> Result<(), Error> {
+    use schema::expiration_notifications as n;
+    diesel::delete(n::table.filter(n::user_uuid.eq(user_uuid)))
+        .execute(connection)
+        .map(|_| ())
+        .map_err(Into::into)
+}
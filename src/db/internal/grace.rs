@@ -0,0 +1,126 @@
+use crate::db::internal;
+use crate::db::logs::log_comment_body;
+use crate::db::logs::LogContext;
+use crate::db::model::*;
+use crate::db::operations::models::Grace;
+use crate::db::schema;
+use crate::db::types::*;
+use diesel::prelude::*;
+use failure::Error;
+use uuid::Uuid;
+
+pub fn grace_for_group(connection: &PgConnection, group_id: i32) -> Result<Option<Grace>, Error> {
+    schema::expiration_grace::table
+        .filter(schema::expiration_grace::group_id.eq(group_id))
+        .first::<ExpirationGrace>(connection)
+        .optional()
+        .map(|grace| grace.map(Grace::from))
+        .map_err(Into::into)
+}
+
+/// Sets or clears the grace period of expired memberships of a group.
+pub fn set_grace(
+    connection: &PgConnection,
+    group_id: i32,
+    grace: Option<Grace>,
+) -> Result<(), Error> {
+    use schema::expiration_grace as g;
+    match grace {
+        Some(grace) => diesel::insert_into(g::table)
+            .values(ExpirationGrace {
+                group_id,
+                days: grace.days,
+                keep_access: grace.keep_access,
+            })
+            .on_conflict(g::group_id)
+            .do_update()
+            .set((g::days.eq(grace.days), g::keep_access.eq(grace.keep_access)))
+            .execute(connection),
+        None => diesel::delete(g::table.filter(g::group_id.eq(group_id))).execute(connection),
+    }
+    .map(|_| ())
+    .map_err(Into::into)
+}
+
+/// Marks the membership as expired and within the grace period. Returns `false` if it has
+/// already been marked.
+pub fn mark_expired(
+    connection: &PgConnection,
+    host_uuid: &Uuid,
+    membership: &Membership,
+    access_removed: bool,
+) -> Result<bool, Error> {
+    use schema::expired_memberships as e;
+    let marked = diesel::insert_into(e::table)
+        .values((
+            e::user_uuid.eq(membership.user_uuid),
+            e::group_id.eq(membership.group_id),
+            e::access_removed.eq(access_removed),
+        ))
+        .on_conflict_do_nothing()
+        .execute(connection)?;
+    if marked == 0 {
+        return Ok(false);
+    }
+    let log_ctx = LogContext::with(membership.group_id, *host_uuid).with_user(membership.user_uuid);
+    let comment = log_comment_body("expired, in grace period");
+    internal::webhook::emit(
+        connection,
+        &log_ctx,
+        WebhookEventType::MembershipUpdated,
+        comment.clone(),
+    );
+    internal::log::db_log(
+        connection,
+        &log_ctx,
+        LogTargetType::Membership,
+        LogOperationType::Updated,
+        comment,
+    );
+    Ok(true)
+}
+
+/// Clears the expired mark of a membership. Returns whether access had been removed or `None`
+/// if the membership was not marked.
+pub fn clear_expired(
+    connection: &PgConnection,
+    group_id: i32,
+    user_uuid: &Uuid,
+) -> Result<Option<bool>, Error> {
+    use schema::expired_memberships as e;
+    diesel::delete(
+        e::table
+            .filter(e::group_id.eq(group_id))
+            .filter(e::user_uuid.eq(user_uuid)),
+    )
+    .returning(e::access_removed)
+    .get_result(connection)
+    .optional()
+    .map_err(Into::into)
+}
+
+pub fn expired_memberships(connection: &PgConnection) -> Result<Vec<ExpiredMembership>, Error> {
+    schema::expired_memberships::table
+        .get_results(connection)
+        .map_err(Into::into)
+}
+
+/// Returns all expired memberships without access as pairs of user uuid and group name.
+pub fn without_access_by_name(connection: &PgConnection) -> Result<Vec<(Uuid, String)>, Error> {
+    use schema::expired_memberships as e;
+    use schema::groups as g;
+    e::table
+        .filter(e::access_removed)
+        .inner_join(g::table)
+        .select((e::user_uuid, g::name))
+        .get_results(connection)
+        .map_err(Into::into)
+}
+
+pub fn delete_for_group(connection: &PgConnection, group_id: i32) -> Result<(), Error> {
+    diesel::delete(schema::expiration_grace::table)
+        .filter(schema::expiration_grace::group_id.eq(group_id))
+        .execute(connection)
+        .map(|_| ())
+        .map_err(Into::into)
+}
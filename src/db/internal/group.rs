@@ -14,6 +14,7 @@ use diesel::dsl::exists;
 use diesel::dsl::select;
 use diesel::prelude::*;
 use failure::Error;
+use serde_json::json;
 use serde_json::Value;
 use std::convert::TryFrom;
 use uuid::Uuid;
@@ -41,6 +42,17 @@ pub fn get_group(connection: &PgConnection, group_name: &str) -> Result<Group, E
         .map_err(Into::into)
 }
 
+/// Locks the row of the group until the end of the surrounding transaction.
+pub fn lock_group(connection: &PgConnection, group_id: i32) -> Result<(), Error> {
+    schema::groups::table
+        .filter(schema::groups::group_id.eq(group_id))
+        .select(schema::groups::group_id)
+        .for_update()
+        .first::<i32>(connection)
+        .map(|_| ())
+        .map_err(Into::into)
+}
+
 pub fn get_group_by_id(connection: &PgConnection, group_id: i32) -> Result<Option<Group>, Error> {
     schema::groups::table
         .filter(schema::groups::group_id.eq(group_id))
@@ -109,6 +121,12 @@ pub fn update_group_trust(
         .map_err(Into::into)
         .map(move |group| {
             let log_ctx = LogContext::with(group.id, *host_uuid);
+            internal::webhook::emit(
+                connection,
+                &log_ctx,
+                WebhookEventType::TrustChanged,
+                Some(json!({ "trust": group.trust })),
+            );
             internal::log::db_log(
                 connection,
                 &log_ctx,
@@ -127,33 +145,54 @@ pub fn update_group(
     group_update: GroupUpdate,
 ) -> Result<Group, Error> {
     let log_comment = group_update.log_comment();
-    diesel::update(schema::groups::table.filter(schema::groups::name.eq(&name)))
-        .set((
-            group_update
-                .description
-                .map(|d| schema::groups::description.eq(d)),
-            group_update
-                .capabilities
-                .map(|c| schema::groups::capabilities.eq(c)),
-            group_update.typ.map(|t| schema::groups::typ.eq(t)),
-            group_update
-                .group_expiration
-                .map(|e| e.and_then(|i| if i < 1 { None } else { Some(i) }))
-                .map(|e| schema::groups::group_expiration.eq(e)),
-        ))
-        .get_result::<Group>(connection)
-        .map_err(Into::into)
-        .map(move |group| {
-            let log_ctx = LogContext::with(group.id, *host_uuid);
-            internal::log::db_log(
-                connection,
-                &log_ctx,
-                LogTargetType::Group,
-                LogOperationType::Updated,
-                log_comment_body(&log_comment),
-            );
-            group
-        })
+    let reminders = group_update.reminders;
+    let self_renewal = group_update.self_renewal;
+    let expiration_grace = group_update.expiration_grace;
+    let group = if (reminders.is_some()
+        || self_renewal.is_some()
+        || expiration_grace.is_some()
+        || group_update.apply_expiration.is_some())
+        && group_update.description.is_none()
+        && group_update.capabilities.is_none()
+        && group_update.typ.is_none()
+        && group_update.group_expiration.is_none()
+    {
+        get_group(connection, &name)?
+    } else {
+        diesel::update(schema::groups::table.filter(schema::groups::name.eq(&name)))
+            .set((
+                group_update
+                    .description
+                    .map(|d| schema::groups::description.eq(d)),
+                group_update
+                    .capabilities
+                    .map(|c| schema::groups::capabilities.eq(c)),
+                group_update.typ.map(|t| schema::groups::typ.eq(t)),
+                group_update
+                    .group_expiration
+                    .map(|e| e.and_then(|i| if i < 1 { None } else { Some(i) }))
+                    .map(|e| schema::groups::group_expiration.eq(e)),
+            ))
+            .get_result::<Group>(connection)?
+    };
+    if let Some(reminders) = reminders {
+        internal::expiration::set_reminders(connection, group.id, reminders)?;
+    }
+    if let Some(self_renewal) = self_renewal {
+        internal::renewal::set_self_renewal(connection, group.id, self_renewal)?;
+    }
+    if let Some(expiration_grace) = expiration_grace {
+        internal::grace::set_grace(connection, group.id, expiration_grace)?;
+    }
+    let log_ctx = LogContext::with(group.id, *host_uuid);
+    internal::log::db_log(
+        connection,
+        &log_ctx,
+        LogTargetType::Group,
+        LogOperationType::Updated,
+        log_comment_body(&log_comment),
+    );
+    Ok(group)
 }
 
 fn log_delete(
@@ -180,6 +219,13 @@ pub fn delete_group(host_uuid: &Uuid, connection: &PgConnection, name: &str) ->
                 log_comment_body("all outstanding invitations"),
             )
         })?;
+    internal::inactive::delete_for_group(connection, group.id)?;
+    internal::expiration::delete_notifications_for_group(connection, group.id)?;
+    internal::expiration::delete_reminders_for_group(connection, group.id)?;
+    internal::renewal::delete_for_group(connection, group.id)?;
+    internal::grace::delete_for_group(connection, group.id)?;
+    internal::calendar::delete_for_group(connection, group.id)?;
+    internal::trust::delete_for_group(connection, group.id)?;
     diesel::delete(schema::roles::table)
         .filter(schema::roles::group_id.eq(group.id))
         .execute(connection)
@@ -208,6 +254,8 @@ pub fn delete_group(host_uuid: &Uuid, connection: &PgConnection, name: &str) ->
         .execute(connection)
         .optional()
         .map(|_| log_delete(connection, &log_ctx, LogTargetType::Terms, None))?;
+    internal::newsletter::delete_for_group(connection, group.id)?;
+    internal::display::delete_for_group(connection, group.id)?;
     diesel::update(schema::groups::table)
         .filter(schema::groups::name.eq(name))
         .set((
@@ -215,7 +263,10 @@ pub fn delete_group(host_uuid: &Uuid, connection: &PgConnection, name: &str) ->
             schema::groups::active.eq(false),
         ))
         .execute(connection)
-        .map(|_| log_delete(connection, &log_ctx, LogTargetType::Group, None))
+        .map(|_| {
+            internal::webhook::emit(connection, &log_ctx, WebhookEventType::GroupDeleted, None);
+            log_delete(connection, &log_ctx, LogTargetType::Group, None)
+        })
         .map_err(Into::into)
 }
 
@@ -0,0 +1,157 @@
+use crate::db::internal;
+use crate::db::logs::log_comment_body;
+use crate::db::logs::LogContext;
+use crate::db::model::*;
+use crate::db::schema;
+use crate::db::types::*;
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use failure::Error;
+use uuid::Uuid;
+
+pub fn is_inactive(connection: &PgConnection, user_uuid: &Uuid) -> Result<bool, Error> {
+    diesel::select(diesel::dsl::exists(
+        schema::inactive_users::table.filter(schema::inactive_users::user_uuid.eq(user_uuid)),
+    ))
+    .get_result(connection)
+    .map_err(Into::into)
+}
+
+fn group_names(connection: &PgConnection, group_ids: Vec<i32>) -> Result<Vec<String>, Error> {
+    schema::groups::table
+        .filter(schema::groups::group_id.eq_any(group_ids))
+        .select(schema::groups::name)
+        .get_results(connection)
+        .map_err(Into::into)
+}
+
+/// Marks the user as inactive and moves all memberships aside. Returns the names of the
+/// suspended groups. Must be called within a transaction.
+pub fn suspend_memberships(
+    connection: &PgConnection,
+    host_uuid: &Uuid,
+    user_uuid: &Uuid,
+) -> Result<Vec<String>, Error> {
+    diesel::insert_into(schema::inactive_users::table)
+        .values(schema::inactive_users::user_uuid.eq(user_uuid))
+        .on_conflict_do_nothing()
+        .execute(connection)?;
+    let memberships = diesel::delete(schema::memberships::table)
+        .filter(schema::memberships::user_uuid.eq(user_uuid))
+        .get_results::<Membership>(connection)?;
+    let mut group_ids = Vec::with_capacity(memberships.len());
+    for membership in memberships {
+        let log_ctx = LogContext::with(membership.group_id, *host_uuid).with_user(*user_uuid);
+        group_ids.push(membership.group_id);
+        diesel::insert_into(schema::suspended_memberships::table)
+            .values(SuspendedMembership::from(membership))
+            .on_conflict_do_nothing()
+            .execute(connection)?;
+        internal::webhook::emit(
+            connection,
+            &log_ctx,
+            WebhookEventType::MembershipDeleted,
+            log_comment_body("profile deactivated"),
+        );
+        internal::log::db_log(
+            connection,
+            &log_ctx,
+            LogTargetType::Membership,
+            LogOperationType::Deleted,
+            log_comment_body("profile deactivated"),
+        );
+    }
+    group_names(connection, group_ids)
+}
+
+/// Clears the inactive mark of the user and restores all suspended memberships. Returns the
+/// names of the restored groups. Must be called within a transaction.
+pub fn restore_memberships(
+    connection: &PgConnection,
+    host_uuid: &Uuid,
+    user_uuid: &Uuid,
+) -> Result<Vec<String>, Error> {
+    let suspended = diesel::delete(schema::suspended_memberships::table)
+        .filter(schema::suspended_memberships::user_uuid.eq(user_uuid))
+        .get_results::<SuspendedMembership>(connection)?;
+    diesel::delete(schema::inactive_users::table)
+        .filter(schema::inactive_users::user_uuid.eq(user_uuid))
+        .execute(connection)?;
+    let mut group_ids = Vec::with_capacity(suspended.len());
+    for membership in suspended {
+        let log_ctx = LogContext::with(membership.group_id, *host_uuid).with_user(*user_uuid);
+        let group_id = membership.group_id;
+        let restored = diesel::insert_into(schema::memberships::table)
+            .values(Membership::from(membership))
+            .on_conflict_do_nothing()
+            .execute(connection)?;
+        if restored == 0 {
+            continue;
+        }
+        group_ids.push(group_id);
+        internal::webhook::emit(
+            connection,
+            &log_ctx,
+            WebhookEventType::MembershipCreated,
+            log_comment_body("profile reactivated"),
+        );
+        internal::log::db_log(
+            connection,
+            &log_ctx,
+            LogTargetType::Membership,
+            LogOperationType::Created,
+            log_comment_body("profile reactivated"),
+        );
+    }
+    group_names(connection, group_ids)
+}
+
+/// Drops the suspended memberships of users which became inactive before `before`. Returns the
+/// affected users and group names. Must be called within a transaction.
+pub fn revoke_suspended(
+    connection: &PgConnection,
+    host_uuid: &Uuid,
+    before: NaiveDateTime,
+) -> Result<Vec<(Uuid, String)>, Error> {
+    let user_uuids = schema::inactive_users::table
+        .filter(schema::inactive_users::since.lt(before))
+        .select(schema::inactive_users::user_uuid)
+        .get_results::<Uuid>(connection)?;
+    let revoked = diesel::delete(schema::suspended_memberships::table)
+        .filter(schema::suspended_memberships::user_uuid.eq_any(user_uuids))
+        .get_results::<SuspendedMembership>(connection)?;
+    let mut memberships = Vec::with_capacity(revoked.len());
+    for membership in revoked {
+        let log_ctx =
+            LogContext::with(membership.group_id, *host_uuid).with_user(membership.user_uuid);
+        internal::log::db_log(
+            connection,
+            &log_ctx,
+            LogTargetType::Membership,
+            LogOperationType::Deleted,
+            log_comment_body("revoked after deactivation grace period"),
+        );
+        let group_name = schema::groups::table
+            .filter(schema::groups::group_id.eq(membership.group_id))
+            .select(schema::groups::name)
+            .first::<String>(connection)?;
+        memberships.push((membership.user_uuid, group_name));
+    }
+    Ok(memberships)
+}
+
+pub fn delete_for_group(connection: &PgConnection, group_id: i32) -> Result<(), Error> {
+    diesel::delete(schema::suspended_memberships::table)
+        .filter(schema::suspended_memberships::group_id.eq(group_id))
+        .execute(connection)
+        .map(|_| ())
+        .map_err(Into::into)
+}
+
+pub fn delete_for_user(connection: &PgConnection, user_uuid: &Uuid) -> Result<(), Error> {
+    diesel::delete(schema::inactive_users::table)
+        .filter(schema::inactive_users::user_uuid.eq(user_uuid))
+        .execute(connection)
+        .map(|_| ())
+        .map_err(Into::into)
+}
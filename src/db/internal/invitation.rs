@@ -11,6 +11,7 @@ use crate::db::schema::groups::dsl as groups;
 use crate::db::types::LogOperationType;
 use crate::db::types::LogTargetType;
 use crate::db::types::TrustType;
+use crate::db::types::WebhookEventType;
 use crate::db::views;
 use crate::user::User;
 use chrono::NaiveDateTime;
@@ -253,6 +254,12 @@ pub fn accept(connection: &PgConnection, group_name: &str, member: &User) -> Res
         .set(&membership)
         .execute(&*connection)
         .map(|_| {
+            internal::webhook::emit(
+                connection,
+                &log_ctx,
+                WebhookEventType::MembershipCreated,
+                None,
+            );
             internal::log::db_log(
                 connection,
                 &log_ctx,
@@ -271,6 +278,16 @@ pub fn accept(connection: &PgConnection, group_name: &str, member: &User) -> Res
     Ok(())
 }
 
+pub fn expiring_before(
+    connection: &PgConnection,
+    before: NaiveDateTime,
+) -> Result<Vec<Invitation>, Error> {
+    schema::invitations::table
+        .filter(schema::invitations::invitation_expiration.le(before))
+        .get_results(connection)
+        .map_err(Into::into)
+}
+
 pub fn expire_before(connection: &PgConnection, before: NaiveDateTime) -> Result<(), Error> {
     let deleted = diesel::delete(schema::invitations::table)
         .filter(schema::invitations::invitation_expiration.le(before))
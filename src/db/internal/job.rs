@@ -0,0 +1,68 @@
+use crate::db::internal::lock::AdvisoryLock;
+use crate::db::model::*;
+use crate::db::schema;
+use crate::db::types::*;
+use crate::db::Pool;
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use failure::Error;
+
+/// Namespace of the advisory locks held by the job scheduler.
+const LOCK_NAMESPACE: i32 = 0x7061_636b;
+
+/// Tries to take the lock for `job`. Only one connection across all replicas can hold it. The
+/// lock is released once the returned guard is dropped.
+pub fn try_lock(pool: &Pool, job: JobType) -> Result<Option<AdvisoryLock>, Error> {
+    AdvisoryLock::try_acquire(pool, LOCK_NAMESPACE, job as i32)
+}
+
+pub fn last_run(connection: &PgConnection, job: JobType) -> Result<Option<JobRun>, Error> {
+    schema::job_runs::table
+        .filter(schema::job_runs::job.eq(job))
+        .first(connection)
+        .optional()
+        .map_err(Into::into)
+}
+
+pub fn runs(connection: &PgConnection) -> Result<Vec<JobRun>, Error> {
+    schema::job_runs::table
+        .order(schema::job_runs::job)
+        .get_results(connection)
+        .map_err(Into::into)
+}
+
+pub fn start(connection: &PgConnection, job: JobType, tick: NaiveDateTime) -> Result<(), Error> {
+    use schema::job_runs as j;
+    diesel::insert_into(j::table)
+        .values((j::job.eq(job), j::tick.eq(tick)))
+        .on_conflict(j::job)
+        .do_update()
+        .set((
+            j::tick.eq(tick),
+            j::started.eq(diesel::dsl::now),
+            j::finished.eq(None::<NaiveDateTime>),
+            j::success.eq(None::<bool>),
+            j::message.eq(None::<String>),
+        ))
+        .execute(connection)
+        .map(|_| ())
+        .map_err(Into::into)
+}
+
+pub fn finish(
+    connection: &PgConnection,
+    job: JobType,
+    success: bool,
+    message: Option<String>,
+) -> Result<(), Error> {
+    use schema::job_runs as j;
+    diesel::update(j::table.filter(j::job.eq(job)))
+        .set((
+            j::finished.eq(diesel::dsl::now),
+            j::success.eq(success),
+            j::message.eq(message),
+        ))
+        .execute(connection)
+        .map(|_| ())
+        .map_err(Into::into)
+}
@@ -0,0 +1,60 @@
+use crate::db::Pool;
+use diesel::prelude::*;
+use diesel::r2d2::ConnectionManager;
+use diesel::sql_types::Bool;
+use diesel::sql_types::Integer;
+use failure::Error;
+use log::error;
+use r2d2::PooledConnection;
+use std::ops::Deref;
+
+sql_function!(fn pg_try_advisory_lock(namespace: Integer, key: Integer) -> Bool);
+sql_function!(fn pg_advisory_unlock(namespace: Integer, key: Integer) -> Bool);
+
+/// A session advisory lock held on a connection of the pool. The lock is released when this
+/// guard is dropped, also when unwinding from a panic.
+pub struct AdvisoryLock {
+    connection: PooledConnection<ConnectionManager<PgConnection>>,
+    namespace: i32,
+    key: i32,
+}
+
+impl AdvisoryLock {
+    /// Tries to take the lock. Only one connection across all replicas can hold it.
+    pub fn try_acquire(pool: &Pool, namespace: i32, key: i32) -> Result<Option<Self>, Error> {
+        let connection = pool.get()?;
+        let locked =
+            diesel::select(pg_try_advisory_lock(namespace, key)).get_result::<bool>(&connection)?;
+        if locked {
+            Ok(Some(AdvisoryLock {
+                connection,
+                namespace,
+                key,
+            }))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+impl Deref for AdvisoryLock {
+    type Target = PgConnection;
+
+    fn deref(&self) -> &PgConnection {
+        &self.connection
+    }
+}
+
+impl Drop for AdvisoryLock {
+    fn drop(&mut self) {
+        // an unlock can only fail on a broken connection which also ends the session's locks
+        if let Err(e) = diesel::select(pg_advisory_unlock(self.namespace, self.key))
+            .get_result::<bool>(&*self.connection)
+        {
+            error!(
+                "unable to release advisory lock ({}, {}): {}",
+                self.namespace, self.key, e
+            );
+        }
+    }
+}
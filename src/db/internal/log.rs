@@ -4,6 +4,7 @@ use crate::db::logs::LogContext;
 use crate::db::schema;
 use crate::db::types::LogOperationType;
 use crate::db::types::LogTargetType;
+use chrono::NaiveDateTime;
 use diesel::prelude::*;
 use diesel::PgConnection;
 use failure::Error;
@@ -35,6 +36,21 @@ pub fn db_log(
     }
 }
 
+pub fn count_since(
+    connection: &PgConnection,
+    group_id: i32,
+    target: LogTargetType,
+    since: NaiveDateTime,
+) -> Result<i64, Error> {
+    schema::logs::table
+        .filter(schema::logs::group_id.eq(group_id))
+        .filter(schema::logs::target.eq(target))
+        .filter(schema::logs::ts.ge(since))
+        .count()
+        .get_result(connection)
+        .map_err(Into::into)
+}
+
 /*
 pub fn paginated_raw_logs(connection: &PgConnection, limit: i64, offset: Option<i64>) -> Result<Vec<Log>, Error> {
         schema::logs::table.limit(limit).offset(offset.unwrap_or_default()).get_results(connection).map_err(Into::into)
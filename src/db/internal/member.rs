@@ -384,6 +384,12 @@ pub fn remove_from_group(
         .filter(schema::memberships::group_id.eq(group.id))
         .execute(connection)
         .map(|_| {
+            internal::webhook::emit(
+                connection,
+                &log_ctx,
+                WebhookEventType::MembershipDeleted,
+                comment.clone(),
+            );
             internal::log::db_log(
                 connection,
                 &log_ctx,
@@ -423,6 +429,12 @@ pub fn add_to_group(
         .set(&membership)
         .execute(connection)
         .map(|_| {
+            internal::webhook::emit(
+                connection,
+                &log_ctx,
+                WebhookEventType::MembershipCreated,
+                None,
+            );
             internal::log::db_log(
                 connection,
                 &log_ctx,
@@ -454,51 +466,133 @@ pub fn renew(
     .set(schema::memberships::expiration.eq(expiration))
     .execute(connection)
     .map(|_| {
+        let comment = log_comment_body("renewed");
+        internal::webhook::emit(
+            connection,
+            &log_ctx,
+            WebhookEventType::MembershipUpdated,
+            comment.clone(),
+        );
         internal::log::db_log(
             connection,
             &log_ctx,
             LogTargetType::Membership,
             LogOperationType::Updated,
-            log_comment_body("renewed"),
+            comment,
         );
     })
     .map_err(Into::into)
 }
 
-pub fn get_members_not_current(
+/// Returns the members (without curators or admins) of a group whose expiration changes when
+/// applying `expiration` according to `apply`.
+pub fn expiration_affected(
     connection: &PgConnection,
-    group_name: &str,
-    current: &User,
-) -> Result<Vec<User>, Error> {
+    group_id: i32,
+    expiration: NaiveDateTime,
+    apply: ApplyExpiration,
+) -> Result<Vec<Uuid>, Error> {
+    use schema::memberships as m;
+    use schema::roles as r;
+    let mut query = m::table
+        .inner_join(r::table)
+        .filter(m::group_id.eq(group_id))
+        .filter(r::typ.eq(RoleType::Member))
+        .select(m::user_uuid)
+        .into_boxed();
+    query = match (apply.missing(), apply.longer()) {
+        (true, true) => query.filter(m::expiration.is_null().or(m::expiration.gt(expiration))),
+        (true, false) => query.filter(m::expiration.is_null()),
+        (false, _) => query.filter(m::expiration.gt(expiration)),
+    };
+    query.get_results(connection).map_err(Into::into)
+}
+
+/// Applies `expiration` to the members of a group according to `apply`. Returns the number of
+/// changed memberships. Must be called within a transaction.
+pub fn apply_expiration(
+    connection: &PgConnection,
+    host_uuid: &Uuid,
+    group_id: i32,
+    expiration: NaiveDateTime,
+    apply: ApplyExpiration,
+) -> Result<usize, Error> {
+    use schema::memberships as m;
+    let user_uuids = expiration_affected(connection, group_id, expiration, apply)?;
+    let updated = diesel::update(
+        m::table
+            .filter(m::group_id.eq(group_id))
+            .filter(m::user_uuid.eq_any(&user_uuids)),
+    )
+    .set(m::expiration.eq(expiration))
+    .execute(connection)?;
+    for user_uuid in user_uuids {
+        let log_ctx = LogContext::with(group_id, *host_uuid).with_user(user_uuid);
+        let comment = log_comment_body("group expiration applied");
+        internal::webhook::emit(
+            connection,
+            &log_ctx,
+            WebhookEventType::MembershipUpdated,
+            comment.clone(),
+        );
+        internal::log::db_log(
+            connection,
+            &log_ctx,
+            LogTargetType::Membership,
+            LogOperationType::Updated,
+            comment,
+        );
+    }
+    Ok(updated)
+}
+
+pub fn get_membership(
+    connection: &PgConnection,
+    group_id: i32,
+    user_uuid: &Uuid,
+) -> Result<Option<Membership>, Error> {
+    schema::memberships::table
+        .filter(schema::memberships::group_id.eq(group_id))
+        .filter(schema::memberships::user_uuid.eq(user_uuid))
+        .first(connection)
+        .optional()
+        .map_err(Into::into)
+}
+
+pub fn get_members(connection: &PgConnection, group_name: &str) -> Result<Vec<User>, Error> {
     let group = internal::group::get_group(connection, group_name)?;
     schema::memberships::table
         .filter(schema::memberships::group_id.eq(group.id))
-        .filter(schema::memberships::user_uuid.ne(current.user_uuid))
         .select(schema::memberships::user_uuid)
         .get_results(connection)
         .map(|r| r.into_iter().map(|user_uuid| User { user_uuid }).collect())
         .map_err(Into::into)
 }
 
-pub fn get_members_by_trust_less_than(
+pub fn get_members_not_current(
     connection: &PgConnection,
     group_name: &str,
-    trust: &TrustType,
+    current: &User,
 ) -> Result<Vec<User>, Error> {
     let group = internal::group::get_group(connection, group_name)?;
     schema::memberships::table
         .filter(schema::memberships::group_id.eq(group.id))
-        .left_join(
-            schema::profiles::table
-                .on(schema::profiles::user_uuid.eq(schema::memberships::user_uuid)),
-        )
-        .filter(schema::profiles::trust.lt(trust))
+        .filter(schema::memberships::user_uuid.ne(current.user_uuid))
         .select(schema::memberships::user_uuid)
         .get_results(connection)
         .map(|r| r.into_iter().map(|user_uuid| User { user_uuid }).collect())
         .map_err(Into::into)
 }
 
+/// Returns all memberships as pairs of user uuid and group name.
+pub fn all_memberships_by_name(connection: &PgConnection) -> Result<Vec<(Uuid, String)>, Error> {
+    schema::memberships::table
+        .inner_join(schema::groups::table)
+        .select((schema::memberships::user_uuid, schema::groups::name))
+        .get_results(connection)
+        .map_err(Into::into)
+}
+
 pub fn get_memberships_expired_before(
     connection: &PgConnection,
     before: NaiveDateTime,
@@ -536,6 +630,24 @@ pub fn get_member_emails_by_group_name(
         .map_err(Into::into)
 }
 
+pub fn get_member_emails_by_roles(
+    connection: &PgConnection,
+    group_id: i32,
+    roles: &[RoleType],
+) -> Result<Vec<String>, Error> {
+    use schema::memberships as m;
+    use schema::profiles as p;
+    use schema::roles as r;
+    m::table
+        .filter(m::group_id.eq(group_id))
+        .inner_join(r::table.on(r::role_id.eq(m::role_id)))
+        .filter(r::typ.eq_any(roles))
+        .inner_join(p::table.on(m::user_uuid.eq(p::user_uuid)))
+        .select(p::email)
+        .get_results::<String>(connection)
+        .map_err(Into::into)
+}
+
 pub fn get_curator_emails(connection: &PgConnection, group_id: i32) -> Result<Vec<String>, Error> {
     use schema::memberships as m;
     use schema::profiles as p;
@@ -0,0 +1,365 @@
+use crate::db::internal;
+use crate::db::logs::LogContext;
+use crate::db::model::*;
+use crate::db::operations::models::MergeReport;
+use crate::db::schema;
+use crate::db::types::*;
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use failure::Error;
+use serde_json::json;
+use serde_json::Value;
+use uuid::Uuid;
+
+fn merge_body(from: &Uuid) -> Option<Value> {
+    Some(json!({ "comment": "account merge", "merged_from": from }))
+}
+
+/// Later of two membership expirations where `None` (never expires) wins.
+fn later_expiration(a: Option<NaiveDateTime>, b: Option<NaiveDateTime>) -> Option<NaiveDateTime> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.max(b)),
+        _ => None,
+    }
+}
+
+fn merge_memberships(
+    connection: &PgConnection,
+    host_uuid: &Uuid,
+    from: &Uuid,
+    to: &Uuid,
+) -> Result<Vec<String>, Error> {
+    use schema::memberships as m;
+    use schema::roles as r;
+    let memberships = m::table
+        .inner_join(r::table)
+        .filter(m::user_uuid.eq(from))
+        .select((m::all_columns, r::typ))
+        .get_results::<(Membership, RoleType)>(connection)?;
+    let mut group_ids = Vec::with_capacity(memberships.len());
+    for (membership, role) in memberships {
+        let log_ctx = LogContext::with(membership.group_id, *host_uuid).with_user(*to);
+        let existing = m::table
+            .inner_join(r::table)
+            .filter(m::user_uuid.eq(to))
+            .filter(m::group_id.eq(membership.group_id))
+            .select((m::all_columns, r::typ))
+            .first::<(Membership, RoleType)>(connection)
+            .optional()?;
+        let event = match existing {
+            Some((existing, existing_role)) => {
+                let role_id = if role > existing_role {
+                    membership.role_id
+                } else {
+                    existing.role_id
+                };
+                let expiration = later_expiration(membership.expiration, existing.expiration);
+                diesel::update(
+                    m::table
+                        .filter(m::user_uuid.eq(to))
+                        .filter(m::group_id.eq(membership.group_id)),
+                )
+                .set((m::role_id.eq(role_id), m::expiration.eq(expiration)))
+                .execute(connection)?;
+                diesel::delete(
+                    m::table
+                        .filter(m::user_uuid.eq(from))
+                        .filter(m::group_id.eq(membership.group_id)),
+                )
+                .execute(connection)?;
+                WebhookEventType::MembershipUpdated
+            }
+            None => {
+                diesel::update(
+                    m::table
+                        .filter(m::user_uuid.eq(from))
+                        .filter(m::group_id.eq(membership.group_id)),
+                )
+                .set(m::user_uuid.eq(to))
+                .execute(connection)?;
+                WebhookEventType::MembershipCreated
+            }
+        };
+        internal::webhook::emit(
+            connection,
+            &LogContext::with(membership.group_id, *host_uuid).with_user(*from),
+            WebhookEventType::MembershipDeleted,
+            merge_body(from),
+        );
+        internal::webhook::emit(connection, &log_ctx, event, merge_body(from));
+        internal::log::db_log(
+            connection,
+            &log_ctx,
+            LogTargetType::Membership,
+            LogOperationType::Updated,
+            merge_body(from),
+        );
+        group_ids.push(membership.group_id);
+    }
+    diesel::update(m::table.filter(m::added_by.eq(from)))
+        .set(m::added_by.eq(to))
+        .execute(connection)?;
+    schema::groups::table
+        .filter(schema::groups::group_id.eq_any(group_ids))
+        .select(schema::groups::name)
+        .get_results(connection)
+        .map_err(Into::into)
+}
+
+fn merge_invitations(
+    connection: &PgConnection,
+    host_uuid: &Uuid,
+    from: &Uuid,
+    to: &Uuid,
+) -> Result<usize, Error> {
+    use schema::invitations as i;
+    use schema::memberships as m;
+    let invitations = i::table
+        .filter(i::user_uuid.eq(from))
+        .get_results::<Invitation>(connection)?;
+    let mut moved = 0;
+    for invitation in invitations {
+        let log_ctx = LogContext::with(invitation.group_id, *host_uuid).with_user(*to);
+        let obsolete = diesel::select(diesel::dsl::exists(
+            m::table
+                .filter(m::user_uuid.eq(to))
+                .filter(m::group_id.eq(invitation.group_id)),
+        ))
+        .get_result::<bool>(connection)?
+            || diesel::select(diesel::dsl::exists(
+                i::table
+                    .filter(i::user_uuid.eq(to))
+                    .filter(i::group_id.eq(invitation.group_id)),
+            ))
+            .get_result::<bool>(connection)?;
+        let query = i::table
+            .filter(i::user_uuid.eq(from))
+            .filter(i::group_id.eq(invitation.group_id));
+        let operation = if obsolete {
+            diesel::delete(query).execute(connection)?;
+            LogOperationType::Deleted
+        } else {
+            diesel::update(query)
+                .set(i::user_uuid.eq(to))
+                .execute(connection)?;
+            moved += 1;
+            LogOperationType::Updated
+        };
+        internal::log::db_log(
+            connection,
+            &log_ctx,
+            LogTargetType::Invitation,
+            operation,
+            merge_body(from),
+        );
+    }
+    diesel::update(i::table.filter(i::added_by.eq(from)))
+        .set(i::added_by.eq(to))
+        .execute(connection)?;
+    Ok(moved)
+}
+
+fn merge_requests(
+    connection: &PgConnection,
+    host_uuid: &Uuid,
+    from: &Uuid,
+    to: &Uuid,
+) -> Result<usize, Error> {
+    use schema::memberships as m;
+    use schema::requests as r;
+    let requests = r::table
+        .filter(r::user_uuid.eq(from))
+        .get_results::<Request>(connection)?;
+    let mut moved = 0;
+    for request in requests {
+        let log_ctx = LogContext::with(request.group_id, *host_uuid).with_user(*to);
+        let obsolete = diesel::select(diesel::dsl::exists(
+            m::table
+                .filter(m::user_uuid.eq(to))
+                .filter(m::group_id.eq(request.group_id)),
+        ))
+        .get_result::<bool>(connection)?
+            || diesel::select(diesel::dsl::exists(
+                r::table
+                    .filter(r::user_uuid.eq(to))
+                    .filter(r::group_id.eq(request.group_id)),
+            ))
+            .get_result::<bool>(connection)?;
+        let query = r::table
+            .filter(r::user_uuid.eq(from))
+            .filter(r::group_id.eq(request.group_id));
+        let operation = if obsolete {
+            diesel::delete(query).execute(connection)?;
+            LogOperationType::Deleted
+        } else {
+            diesel::update(query)
+                .set(r::user_uuid.eq(to))
+                .execute(connection)?;
+            moved += 1;
+            LogOperationType::Updated
+        };
+        internal::log::db_log(
+            connection,
+            &log_ctx,
+            LogTargetType::Request,
+            operation,
+            merge_body(from),
+        );
+    }
+    Ok(moved)
+}
+
+/// Moves the per user records of memberships which don't cascade with them: sent expiration
+/// reminders, self-renewals, grace and at risk flags and calendar tokens. Records `to` already
+/// has are kept.
+fn merge_membership_records(
+    connection: &PgConnection,
+    from: &Uuid,
+    to: &Uuid,
+) -> Result<(), Error> {
+    {
+        use schema::expiration_notifications as e;
+        let records = diesel::delete(e::table.filter(e::user_uuid.eq(from)))
+            .returning((e::group_id, e::expiration, e::sent, e::days))
+            .get_results::<(i32, NaiveDateTime, NaiveDateTime, i32)>(connection)?;
+        let records = records
+            .into_iter()
+            .map(|(group_id, expiration, sent, days)| {
+                (
+                    e::user_uuid.eq(to),
+                    e::group_id.eq(group_id),
+                    e::expiration.eq(expiration),
+                    e::sent.eq(sent),
+                    e::days.eq(days),
+                )
+            })
+            .collect::<Vec<_>>();
+        diesel::insert_into(e::table)
+            .values(&records)
+            .on_conflict_do_nothing()
+            .execute(connection)?;
+    }
+    {
+        use schema::self_renewals as r;
+        let records = diesel::delete(r::table.filter(r::user_uuid.eq(from)))
+            .returning((r::group_id, r::expiration, r::renewed))
+            .get_results::<(i32, NaiveDateTime, NaiveDateTime)>(connection)?;
+        let records = records
+            .into_iter()
+            .map(|(group_id, expiration, renewed)| {
+                (
+                    r::user_uuid.eq(to),
+                    r::group_id.eq(group_id),
+                    r::expiration.eq(expiration),
+                    r::renewed.eq(renewed),
+                )
+            })
+            .collect::<Vec<_>>();
+        diesel::insert_into(r::table)
+            .values(&records)
+            .on_conflict_do_nothing()
+            .execute(connection)?;
+    }
+    {
+        use schema::expired_memberships as e;
+        let records = diesel::delete(e::table.filter(e::user_uuid.eq(from)))
+            .returning((e::group_id, e::expired, e::access_removed))
+            .get_results::<(i32, NaiveDateTime, bool)>(connection)?;
+        let records = records
+            .into_iter()
+            .map(|(group_id, expired, access_removed)| {
+                (
+                    e::user_uuid.eq(to),
+                    e::group_id.eq(group_id),
+                    e::expired.eq(expired),
+                    e::access_removed.eq(access_removed),
+                )
+            })
+            .collect::<Vec<_>>();
+        diesel::insert_into(e::table)
+            .values(&records)
+            .on_conflict_do_nothing()
+            .execute(connection)?;
+    }
+    {
+        use schema::at_risk_memberships as a;
+        let records = diesel::delete(a::table.filter(a::user_uuid.eq(from)))
+            .returning((a::group_id, a::flagged, a::revoke_after))
+            .get_results::<(i32, NaiveDateTime, NaiveDateTime)>(connection)?;
+        let records = records
+            .into_iter()
+            .map(|(group_id, flagged, revoke_after)| {
+                (
+                    a::user_uuid.eq(to),
+                    a::group_id.eq(group_id),
+                    a::flagged.eq(flagged),
+                    a::revoke_after.eq(revoke_after),
+                )
+            })
+            .collect::<Vec<_>>();
+        diesel::insert_into(a::table)
+            .values(&records)
+            .on_conflict_do_nothing()
+            .execute(connection)?;
+    }
+    {
+        // the token hash is unique, so the old tokens must be gone before inserting them again
+        use schema::calendar_tokens as c;
+        let records = diesel::delete(c::table.filter(c::user_uuid.eq(from)))
+            .returning((c::group_id, c::token_hash, c::created))
+            .get_results::<(i32, String, NaiveDateTime)>(connection)?;
+        let records = records
+            .into_iter()
+            .map(|(group_id, token_hash, created)| {
+                (
+                    c::user_uuid.eq(to),
+                    c::group_id.eq(group_id),
+                    c::token_hash.eq(token_hash),
+                    c::created.eq(created),
+                )
+            })
+            .collect::<Vec<_>>();
+        diesel::insert_into(c::table)
+            .values(&records)
+            .on_conflict_do_nothing()
+            .execute(connection)?;
+    }
+    Ok(())
+}
+
+/// Moves memberships with their records, invitations, requests and `added_by` references from
+/// `from` to `to`. Conflicting memberships keep the higher role and the later expiration. Invitations and
+/// requests which became obsolete are dropped. Must be called within a transaction.
+pub fn merge_users(
+    connection: &PgConnection,
+    host_uuid: &Uuid,
+    from: &Uuid,
+    to: &Uuid,
+) -> Result<MergeReport, Error> {
+    let group_names = merge_memberships(connection, host_uuid, from, to)?;
+    merge_membership_records(connection, from, to)?;
+    let invitations = merge_invitations(connection, host_uuid, from, to)?;
+    let requests = merge_requests(connection, host_uuid, from, to)?;
+    Ok(MergeReport {
+        group_names,
+        invitations,
+        requests,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::Duration;
+    use chrono::Utc;
+
+    #[test]
+    fn test_later_expiration() {
+        let early = Utc::now().naive_utc();
+        let late = early + Duration::days(1);
+        assert_eq!(later_expiration(Some(early), Some(late)), Some(late));
+        assert_eq!(later_expiration(Some(late), Some(early)), Some(late));
+        assert_eq!(later_expiration(None, Some(late)), None);
+        assert_eq!(later_expiration(Some(early), None), None);
+    }
+}
@@ -1,9 +1,24 @@
 pub mod admin;
+pub mod calendar;
+pub mod display;
 pub mod expiration;
+pub mod grace;
 pub mod group;
+pub mod inactive;
 pub mod invitation;
+pub mod job;
+pub mod lock;
 pub mod log;
 pub mod member;
+pub mod merge;
+pub mod newsletter;
+pub mod notification;
+pub mod outbox;
+pub mod renewal;
 pub mod request;
+pub mod resync;
+pub mod suppression;
 pub mod terms;
+pub mod trust;
 pub mod user;
+pub mod webhook;
@@ -0,0 +1,63 @@
+use crate::db::model::*;
+use crate::db::schema;
+use diesel::prelude::*;
+use failure::Error;
+use uuid::Uuid;
+
+pub fn newsletters_for_group(
+    connection: &PgConnection,
+    group_id: i32,
+) -> Result<Vec<String>, Error> {
+    schema::group_newsletters::table
+        .filter(schema::group_newsletters::group_id.eq(group_id))
+        .select(schema::group_newsletters::newsletter)
+        .order(schema::group_newsletters::newsletter)
+        .get_results(connection)
+        .map_err(Into::into)
+}
+
+pub fn newsletters_for_user(
+    connection: &PgConnection,
+    user_uuid: &Uuid,
+) -> Result<Vec<String>, Error> {
+    use schema::group_newsletters as n;
+    use schema::memberships as m;
+    m::table
+        .filter(m::user_uuid.eq(user_uuid))
+        .inner_join(n::table.on(n::group_id.eq(m::group_id)))
+        .select(n::newsletter)
+        .distinct()
+        .get_results(connection)
+        .map_err(Into::into)
+}
+
+pub fn set_newsletters(
+    connection: &PgConnection,
+    group_id: i32,
+    newsletters: &[String],
+) -> Result<(), Error> {
+    connection.transaction(|| {
+        delete_for_group(connection, group_id)?;
+        let newsletters = newsletters
+            .iter()
+            .map(|newsletter| GroupNewsletter {
+                group_id,
+                newsletter: newsletter.clone(),
+            })
+            .collect::<Vec<_>>();
+        diesel::insert_into(schema::group_newsletters::table)
+            .values(&newsletters)
+            .on_conflict_do_nothing()
+            .execute(connection)
+            .map(|_| ())
+            .map_err(Into::into)
+    })
+}
+
+pub fn delete_for_group(connection: &PgConnection, group_id: i32) -> Result<(), Error> {
+    diesel::delete(schema::group_newsletters::table)
+        .filter(schema::group_newsletters::group_id.eq(group_id))
+        .execute(connection)
+        .map(|_| ())
+        .map_err(Into::into)
+}
@@ -0,0 +1,97 @@
+use crate::db::model::*;
+use crate::db::schema;
+use crate::db::types::*;
+use crate::mail::templates::Template;
+use diesel::prelude::*;
+use failure::Error;
+use uuid::Uuid;
+
+pub fn get_preferences(
+    connection: &PgConnection,
+    user_uuid: &Uuid,
+) -> Result<Vec<NotificationPreference>, Error> {
+    schema::notification_preferences::table
+        .filter(schema::notification_preferences::user_uuid.eq(user_uuid))
+        .get_results(connection)
+        .map_err(Into::into)
+}
+
+pub fn set_preference(
+    connection: &PgConnection,
+    preference: &NotificationPreference,
+) -> Result<(), Error> {
+    diesel::insert_into(schema::notification_preferences::table)
+        .values(preference)
+        .on_conflict((
+            schema::notification_preferences::user_uuid,
+            schema::notification_preferences::category,
+        ))
+        .do_update()
+        .set(preference)
+        .execute(connection)
+        .map(|_| ())
+        .map_err(Into::into)
+}
+
+pub fn preferences_by_emails(
+    connection: &PgConnection,
+    category: NotificationCategoryType,
+    emails: &[String],
+) -> Result<Vec<(String, Uuid, NotificationPreferenceType)>, Error> {
+    use schema::notification_preferences as n;
+    use schema::profiles as p;
+    p::table
+        .filter(p::email.eq_any(emails))
+        .inner_join(n::table.on(n::user_uuid.eq(p::user_uuid)))
+        .filter(n::category.eq(category))
+        .select((p::email, p::user_uuid, n::preference))
+        .get_results(connection)
+        .map_err(Into::into)
+}
+
+pub fn enqueue(
+    connection: &PgConnection,
+    user_uuid: &Uuid,
+    preference: NotificationPreferenceType,
+    template: &Template,
+) -> Result<(), Error> {
+    let queued = InsertQueuedNotification {
+        user_uuid: *user_uuid,
+        preference,
+        template: serde_json::to_value(template)?,
+    };
+    diesel::insert_into(schema::notification_queue::table)
+        .values(queued)
+        .execute(connection)
+        .map(|_| ())
+        .map_err(Into::into)
+}
+
+pub fn queued(
+    connection: &PgConnection,
+    preference: NotificationPreferenceType,
+) -> Result<Vec<QueuedNotification>, Error> {
+    schema::notification_queue::table
+        .filter(schema::notification_queue::preference.eq(preference))
+        .order_by(schema::notification_queue::id)
+        .get_results::<QueuedNotification>(connection)
+        .map_err(Into::into)
+}
+
+pub fn delete_queued(connection: &PgConnection, ids: &[i32]) -> Result<(), Error> {
+    diesel::delete(schema::notification_queue::table)
+        .filter(schema::notification_queue::id.eq_any(ids))
+        .execute(connection)
+        .map(|_| ())
+        .map_err(Into::into)
+}
+
+pub fn delete_for_user(connection: &PgConnection, user_uuid: &Uuid) -> Result<(), Error> {
+    diesel::delete(schema::notification_queue::table)
+        .filter(schema::notification_queue::user_uuid.eq(user_uuid))
+        .execute(connection)?;
+    diesel::delete(schema::notification_preferences::table)
+        .filter(schema::notification_preferences::user_uuid.eq(user_uuid))
+        .execute(connection)?;
+    Ok(())
+}
@@ -0,0 +1,185 @@
+use crate::db::model::*;
+use crate::db::schema;
+use crate::db::types::*;
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use failure::Error;
+use uuid::Uuid;
+
+pub fn enqueue(
+    connection: &PgConnection,
+    user_uuid: &Uuid,
+    operation: OutboxOperationType,
+    group_names: Vec<String>,
+    next_attempt: NaiveDateTime,
+) -> Result<CisOutboxEntry, Error> {
+    diesel::insert_into(schema::cis_outbox::table)
+        .values(InsertCisOutboxEntry {
+            user_uuid: *user_uuid,
+            operation,
+            group_names,
+            next_attempt,
+        })
+        .get_result(connection)
+        .map_err(Into::into)
+}
+
+pub fn entry(connection: &PgConnection, id: i32) -> Result<CisOutboxEntry, Error> {
+    schema::cis_outbox::table
+        .filter(schema::cis_outbox::id.eq(id))
+        .first(connection)
+        .map_err(Into::into)
+}
+
+pub fn entries(
+    connection: &PgConnection,
+    status: DeliveryStatusType,
+    limit: i64,
+) -> Result<Vec<CisOutboxEntry>, Error> {
+    schema::cis_outbox::table
+        .filter(schema::cis_outbox::status.eq(status))
+        .order(schema::cis_outbox::id.desc())
+        .limit(limit)
+        .get_results(connection)
+        .map_err(Into::into)
+}
+
+pub fn pending(
+    connection: &PgConnection,
+    now: NaiveDateTime,
+    limit: i64,
+) -> Result<Vec<CisOutboxEntry>, Error> {
+    schema::cis_outbox::table
+        .filter(schema::cis_outbox::status.eq(DeliveryStatusType::Pending))
+        .filter(schema::cis_outbox::next_attempt.le(now))
+        .filter(
+            schema::cis_outbox::claimed_until
+                .is_null()
+                .or(schema::cis_outbox::claimed_until.le(now)),
+        )
+        .order(schema::cis_outbox::id)
+        .limit(limit)
+        .get_results(connection)
+        .map_err(Into::into)
+}
+
+pub fn pending_for_user(
+    connection: &PgConnection,
+    user_uuid: &Uuid,
+) -> Result<Vec<CisOutboxEntry>, Error> {
+    schema::cis_outbox::table
+        .filter(schema::cis_outbox::user_uuid.eq(user_uuid))
+        .filter(schema::cis_outbox::status.eq(DeliveryStatusType::Pending))
+        .order(schema::cis_outbox::id)
+        .get_results(connection)
+        .map_err(Into::into)
+}
+
+/// Whether there are pending or failed entries for the user which were queued before `id`.
+/// Entries for one user must be applied in order.
+pub fn has_undelivered_before(
+    connection: &PgConnection,
+    user_uuid: &Uuid,
+    id: i32,
+) -> Result<bool, Error> {
+    diesel::select(diesel::dsl::exists(
+        schema::cis_outbox::table
+            .filter(schema::cis_outbox::user_uuid.eq(user_uuid))
+            .filter(schema::cis_outbox::id.lt(id))
+            .filter(schema::cis_outbox::status.eq_any(vec![
+                DeliveryStatusType::Pending,
+                DeliveryStatusType::Failed,
+            ])),
+    ))
+    .get_result(connection)
+    .map_err(Into::into)
+}
+
+/// Whether an entry for the user which was queued after `id` has already been delivered.
+pub fn has_delivered_after(
+    connection: &PgConnection,
+    user_uuid: &Uuid,
+    id: i32,
+) -> Result<bool, Error> {
+    diesel::select(diesel::dsl::exists(
+        schema::cis_outbox::table
+            .filter(schema::cis_outbox::user_uuid.eq(user_uuid))
+            .filter(schema::cis_outbox::id.gt(id))
+            .filter(schema::cis_outbox::status.eq(DeliveryStatusType::Delivered)),
+    ))
+    .get_result(connection)
+    .map_err(Into::into)
+}
+
+/// Claims the pending entries `ids` until `lease_until` so no concurrent delivery picks them up.
+/// Either all entries get claimed or, if one is already claimed by someone else, none.
+pub fn claim(
+    connection: &PgConnection,
+    ids: &[i32],
+    now: NaiveDateTime,
+    lease_until: NaiveDateTime,
+) -> Result<Vec<CisOutboxEntry>, Error> {
+    let claimed = connection.transaction::<_, diesel::result::Error, _>(|| {
+        let claimed = diesel::update(schema::cis_outbox::table)
+            .filter(schema::cis_outbox::id.eq_any(ids))
+            .filter(schema::cis_outbox::status.eq(DeliveryStatusType::Pending))
+            .filter(
+                schema::cis_outbox::claimed_until
+                    .is_null()
+                    .or(schema::cis_outbox::claimed_until.le(now)),
+            )
+            .set(schema::cis_outbox::claimed_until.eq(lease_until))
+            .get_results::<CisOutboxEntry>(connection)?;
+        if claimed.len() < ids.len() {
+            Err(diesel::result::Error::RollbackTransaction)
+        } else {
+            Ok(claimed)
+        }
+    });
+    match claimed {
+        Ok(mut claimed) => {
+            claimed.sort_by_key(|entry| entry.id);
+            Ok(claimed)
+        }
+        Err(diesel::result::Error::RollbackTransaction) => Ok(vec![]),
+        Err(e) => Err(e.into()),
+    }
+}
+
+pub fn update(
+    connection: &PgConnection,
+    entry: &CisOutboxEntry,
+    status: DeliveryStatusType,
+    last_error: Option<String>,
+    next_attempt: NaiveDateTime,
+) -> Result<(), Error> {
+    diesel::update(schema::cis_outbox::table)
+        .filter(schema::cis_outbox::id.eq(entry.id))
+        .set((
+            schema::cis_outbox::status.eq(status),
+            schema::cis_outbox::attempts.eq(entry.attempts + 1),
+            schema::cis_outbox::last_error.eq(last_error),
+            schema::cis_outbox::next_attempt.eq(next_attempt),
+            schema::cis_outbox::updated.eq(diesel::dsl::now),
+            schema::cis_outbox::claimed_until.eq(None::<NaiveDateTime>),
+        ))
+        .execute(connection)
+        .map(|_| ())
+        .map_err(Into::into)
+}
+
+/// Moves a failed entry back into the queue with a fresh set of attempts.
+pub fn retry(connection: &PgConnection, id: i32) -> Result<CisOutboxEntry, Error> {
+    diesel::update(schema::cis_outbox::table)
+        .filter(schema::cis_outbox::id.eq(id))
+        .filter(schema::cis_outbox::status.eq(DeliveryStatusType::Failed))
+        .set((
+            schema::cis_outbox::status.eq(DeliveryStatusType::Pending),
+            schema::cis_outbox::attempts.eq(0),
+            schema::cis_outbox::next_attempt.eq(diesel::dsl::now),
+            schema::cis_outbox::updated.eq(diesel::dsl::now),
+            schema::cis_outbox::claimed_until.eq(None::<NaiveDateTime>),
+        ))
+        .get_result(connection)
+        .map_err(Into::into)
+}
@@ -0,0 +1,109 @@
+use crate::db::internal;
+use crate::db::logs::log_comment_body;
+use crate::db::logs::LogContext;
+use crate::db::schema;
+use crate::db::types::*;
+use crate::error::PacksError;
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use failure::Error;
+use uuid::Uuid;
+
+pub fn self_renewal_enabled(connection: &PgConnection, group_id: i32) -> Result<bool, Error> {
+    diesel::select(diesel::dsl::exists(
+        schema::self_renewal_groups::table
+            .filter(schema::self_renewal_groups::group_id.eq(group_id)),
+    ))
+    .get_result(connection)
+    .map_err(Into::into)
+}
+
+pub fn set_self_renewal(
+    connection: &PgConnection,
+    group_id: i32,
+    enabled: bool,
+) -> Result<(), Error> {
+    if enabled {
+        diesel::insert_into(schema::self_renewal_groups::table)
+            .values(schema::self_renewal_groups::group_id.eq(group_id))
+            .on_conflict_do_nothing()
+            .execute(connection)
+    } else {
+        diesel::delete(schema::self_renewal_groups::table)
+            .filter(schema::self_renewal_groups::group_id.eq(group_id))
+            .execute(connection)
+    }
+    .map(|_| ())
+    .map_err(Into::into)
+}
+
+/// Extends the membership from `current` to `expiration`. Fails if the membership has already
+/// been self-renewed for the `current` expiration or changed in the meantime. Must be called
+/// within a transaction.
+pub fn self_renew(
+    connection: &PgConnection,
+    group_id: i32,
+    user_uuid: &Uuid,
+    current: NaiveDateTime,
+    expiration: NaiveDateTime,
+) -> Result<(), Error> {
+    use schema::memberships as m;
+    use schema::self_renewals as r;
+    let recorded = diesel::insert_into(r::table)
+        .values((
+            r::user_uuid.eq(user_uuid),
+            r::group_id.eq(group_id),
+            r::expiration.eq(current),
+        ))
+        .on_conflict_do_nothing()
+        .execute(connection)?;
+    if recorded == 0 {
+        return Err(PacksError::SelfRenewalNotAllowed.into());
+    }
+    let updated = diesel::update(
+        m::table
+            .filter(m::group_id.eq(group_id))
+            .filter(m::user_uuid.eq(user_uuid))
+            .filter(m::expiration.eq(current)),
+    )
+    .set(m::expiration.eq(expiration))
+    .execute(connection)?;
+    if updated == 0 {
+        return Err(PacksError::SelfRenewalNotAllowed.into());
+    }
+    let log_ctx = LogContext::with(group_id, *user_uuid).with_user(*user_uuid);
+    let comment = log_comment_body("self-renewed");
+    internal::webhook::emit(
+        connection,
+        &log_ctx,
+        WebhookEventType::MembershipUpdated,
+        comment.clone(),
+    );
+    internal::log::db_log(
+        connection,
+        &log_ctx,
+        LogTargetType::Membership,
+        LogOperationType::Updated,
+        comment,
+    );
+    Ok(())
+}
+
+pub fn delete_for_group(connection: &PgConnection, group_id: i32) -> Result<(), Error> {
+    diesel::delete(schema::self_renewals::table)
+        .filter(schema::self_renewals::group_id.eq(group_id))
+        .execute(connection)?;
+    diesel::delete(schema::self_renewal_groups::table)
+        .filter(schema::self_renewal_groups::group_id.eq(group_id))
+        .execute(connection)
+        .map(|_| ())
+        .map_err(Into::into)
+}
+
+pub fn delete_for_user(connection: &PgConnection, user_uuid: &Uuid) -> Result<(), Error> {
+    diesel::delete(schema::self_renewals::table)
+        .filter(schema::self_renewals::user_uuid.eq(user_uuid))
+        .execute(connection)
+        .map(|_| ())
+        .map_err(Into::into)
+}
@@ -218,6 +218,16 @@ pub fn new_pending(
     Ok(pending)
 }
 
+pub fn expiring_before(
+    connection: &PgConnection,
+    before: NaiveDateTime,
+) -> Result<Vec<Request>, Error> {
+    schema::requests::table
+        .filter(schema::requests::request_expiration.le(before))
+        .get_results(connection)
+        .map_err(Into::into)
+}
+
 pub fn expire_before(connection: &PgConnection, before: NaiveDateTime) -> Result<(), Error> {
     let deleted = diesel::delete(schema::requests::table)
         .filter(schema::requests::request_expiration.le(before))
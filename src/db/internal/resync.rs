@@ -0,0 +1,79 @@
+use crate::db::model::*;
+use crate::db::schema;
+use diesel::dsl::count_star;
+use diesel::prelude::*;
+use failure::Error;
+use uuid::Uuid;
+
+/// The latest run, finished or not.
+pub fn latest_run(connection: &PgConnection) -> Result<Option<ResyncRun>, Error> {
+    schema::resync_runs::table
+        .order(schema::resync_runs::id.desc())
+        .first(connection)
+        .optional()
+        .map_err(Into::into)
+}
+
+pub fn start_run(connection: &PgConnection) -> Result<ResyncRun, Error> {
+    let total = schema::profiles::table
+        .select(count_star())
+        .first::<i64>(connection)?;
+    diesel::insert_into(schema::resync_runs::table)
+        .values(schema::resync_runs::total.eq(total as i32))
+        .get_result(connection)
+        .map_err(Into::into)
+}
+
+/// Returns the next `limit` users (uuid and user id) after `cursor` in uuid order.
+pub fn users_after(
+    connection: &PgConnection,
+    cursor: Option<Uuid>,
+    limit: i64,
+) -> Result<Vec<(Uuid, String)>, Error> {
+    let mut query = schema::profiles::table
+        .select((schema::profiles::user_uuid, schema::profiles::user_id))
+        .order(schema::profiles::user_uuid)
+        .limit(limit)
+        .into_boxed();
+    if let Some(cursor) = cursor {
+        query = query.filter(schema::profiles::user_uuid.gt(cursor));
+    }
+    query.get_results(connection).map_err(Into::into)
+}
+
+pub fn advance(
+    connection: &PgConnection,
+    id: i32,
+    cursor: &Uuid,
+    failed: bool,
+) -> Result<(), Error> {
+    use schema::resync_runs as r;
+    diesel::update(r::table.filter(r::id.eq(id)))
+        .set((
+            r::cursor.eq(cursor),
+            r::processed.eq(r::processed + 1),
+            r::failed.eq(r::failed + if failed { 1 } else { 0 }),
+            r::updated.eq(diesel::dsl::now),
+        ))
+        .execute(connection)
+        .map(|_| ())
+        .map_err(Into::into)
+}
+
+pub fn finish(connection: &PgConnection, id: i32) -> Result<ResyncRun, Error> {
+    use schema::resync_runs as r;
+    diesel::update(r::table.filter(r::id.eq(id)))
+        .set((
+            r::finished.eq(diesel::dsl::now.nullable()),
+            r::updated.eq(diesel::dsl::now),
+        ))
+        .get_result(connection)
+        .map_err(Into::into)
+}
+
+pub fn run(connection: &PgConnection, id: i32) -> Result<ResyncRun, Error> {
+    schema::resync_runs::table
+        .filter(schema::resync_runs::id.eq(id))
+        .first(connection)
+        .map_err(Into::into)
+}
@@ -0,0 +1,53 @@
+use crate::db::model::*;
+use crate::db::schema;
+use diesel::dsl::exists;
+use diesel::prelude::*;
+use failure::Error;
+
+pub fn suppress(
+    connection: &PgConnection,
+    suppression: &InsertSuppressedEmail,
+) -> Result<(), Error> {
+    diesel::insert_into(schema::suppressed_emails::table)
+        .values(suppression)
+        .on_conflict(schema::suppressed_emails::email)
+        .do_update()
+        .set(suppression)
+        .execute(connection)
+        .map(|_| ())
+        .map_err(Into::into)
+}
+
+pub fn unsuppress(connection: &PgConnection, email: &str) -> Result<(), Error> {
+    diesel::delete(schema::suppressed_emails::table)
+        .filter(schema::suppressed_emails::email.eq(email))
+        .execute(connection)
+        .map(|_| ())
+        .map_err(Into::into)
+}
+
+pub fn is_suppressed(connection: &PgConnection, email: &str) -> Result<bool, Error> {
+    diesel::select(exists(
+        schema::suppressed_emails::table.filter(schema::suppressed_emails::email.eq(email)),
+    ))
+    .get_result(connection)
+    .map_err(Into::into)
+}
+
+pub fn suppressed_among(
+    connection: &PgConnection,
+    emails: &[String],
+) -> Result<Vec<String>, Error> {
+    schema::suppressed_emails::table
+        .filter(schema::suppressed_emails::email.eq_any(emails))
+        .select(schema::suppressed_emails::email)
+        .get_results(connection)
+        .map_err(Into::into)
+}
+
+pub fn suppressed_emails(connection: &PgConnection) -> Result<Vec<SuppressedEmail>, Error> {
+    schema::suppressed_emails::table
+        .order_by(schema::suppressed_emails::created.desc())
+        .get_results(connection)
+        .map_err(Into::into)
+}
@@ -0,0 +1,228 @@
+use crate::db::internal;
+use crate::db::logs::log_comment_body;
+use crate::db::logs::LogContext;
+use crate::db::model::Group;
+use crate::db::model::TrustChange;
+use crate::db::schema;
+use crate::db::types::*;
+use crate::db::users::UserProfileSlim;
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use failure::Error;
+use uuid::Uuid;
+
+/// Profiles of all members of the group with a trust level below `trust`.
+pub fn members_below(
+    connection: &PgConnection,
+    group_id: i32,
+    trust: &TrustType,
+) -> Result<Vec<UserProfileSlim>, Error> {
+    use schema::memberships as m;
+    use schema::profiles as p;
+    m::table
+        .filter(m::group_id.eq(group_id))
+        .inner_join(p::table.on(p::user_uuid.eq(m::user_uuid)))
+        .filter(p::trust.lt(trust))
+        .select((p::user_uuid, p::user_id, p::email, p::username, p::trust))
+        .order(p::username)
+        .get_results(connection)
+        .map_err(Into::into)
+}
+
+/// Stages a trust change for the group replacing a previously staged one.
+pub fn stage(
+    connection: &PgConnection,
+    host_uuid: &Uuid,
+    group_id: i32,
+    trust: &TrustType,
+    effective: NaiveDateTime,
+) -> Result<TrustChange, Error> {
+    use schema::trust_changes as t;
+    let change = diesel::insert_into(t::table)
+        .values((
+            t::group_id.eq(group_id),
+            t::trust.eq(trust),
+            t::effective.eq(effective),
+            t::created_by.eq(host_uuid),
+        ))
+        .on_conflict(t::group_id)
+        .do_update()
+        .set((
+            t::trust.eq(trust),
+            t::effective.eq(effective),
+            t::created_by.eq(host_uuid),
+            t::created.eq(diesel::dsl::now),
+        ))
+        .get_result(connection)?;
+    let log_ctx = LogContext::with(group_id, *host_uuid);
+    internal::log::db_log(
+        connection,
+        &log_ctx,
+        LogTargetType::Group,
+        LogOperationType::Updated,
+        log_comment_body("trust change staged"),
+    );
+    Ok(change)
+}
+
+pub fn staged(connection: &PgConnection, group_id: i32) -> Result<Option<TrustChange>, Error> {
+    schema::trust_changes::table
+        .filter(schema::trust_changes::group_id.eq(group_id))
+        .first(connection)
+        .optional()
+        .map_err(Into::into)
+}
+
+/// Staged trust changes which are effective at `now` with their groups.
+pub fn due(
+    connection: &PgConnection,
+    now: NaiveDateTime,
+) -> Result<Vec<(TrustChange, Group)>, Error> {
+    schema::trust_changes::table
+        .filter(schema::trust_changes::effective.le(now))
+        .inner_join(schema::groups::table)
+        .order(schema::trust_changes::effective)
+        .get_results(connection)
+        .map_err(Into::into)
+}
+
+/// Drops the staged trust change of the group. Returns whether there was one.
+pub fn cancel(connection: &PgConnection, host_uuid: &Uuid, group_id: i32) -> Result<bool, Error> {
+    let deleted = delete_for_group(connection, group_id)?;
+    if deleted {
+        let log_ctx = LogContext::with(group_id, *host_uuid);
+        internal::log::db_log(
+            connection,
+            &log_ctx,
+            LogTargetType::Group,
+            LogOperationType::Updated,
+            log_comment_body("trust change cancelled"),
+        );
+    }
+    Ok(deleted)
+}
+
+pub fn delete_for_group(connection: &PgConnection, group_id: i32) -> Result<bool, Error> {
+    diesel::delete(schema::trust_changes::table)
+        .filter(schema::trust_changes::group_id.eq(group_id))
+        .execute(connection)
+        .map(|deleted| deleted > 0)
+        .map_err(Into::into)
+}
+
+/// Days memberships are kept after the trust of a member dropped. `None` revokes them
+/// immediately.
+pub fn grace_days(connection: &PgConnection) -> Result<Option<i32>, Error> {
+    schema::trust_grace::table
+        .select(schema::trust_grace::days)
+        .first(connection)
+        .optional()
+        .map_err(Into::into)
+}
+
+pub fn set_grace_days(connection: &PgConnection, days: Option<i32>) -> Result<(), Error> {
+    use schema::trust_grace as g;
+    match days {
+        Some(days) => diesel::insert_into(g::table)
+            .values((g::id.eq(true), g::days.eq(days)))
+            .on_conflict(g::id)
+            .do_update()
+            .set(g::days.eq(days))
+            .execute(connection),
+        None => diesel::delete(g::table).execute(connection),
+    }
+    .map(|_| ())
+    .map_err(Into::into)
+}
+
+/// Flags the membership as at risk of being revoked after `revoke_after`. Returns `false` if
+/// the membership was already flagged.
+pub fn flag_at_risk(
+    connection: &PgConnection,
+    user_uuid: &Uuid,
+    group_id: i32,
+    revoke_after: NaiveDateTime,
+) -> Result<bool, Error> {
+    use schema::at_risk_memberships as a;
+    let flagged = diesel::insert_into(a::table)
+        .values((
+            a::user_uuid.eq(user_uuid),
+            a::group_id.eq(group_id),
+            a::revoke_after.eq(revoke_after),
+        ))
+        .on_conflict_do_nothing()
+        .execute(connection)?;
+    if flagged == 0 {
+        return Ok(false);
+    }
+    let log_ctx = LogContext::with(group_id, Uuid::default()).with_user(*user_uuid);
+    let comment = log_comment_body("at risk, trust dropped");
+    internal::webhook::emit(
+        connection,
+        &log_ctx,
+        WebhookEventType::MembershipUpdated,
+        comment.clone(),
+    );
+    internal::log::db_log(
+        connection,
+        &log_ctx,
+        LogTargetType::Membership,
+        LogOperationType::Updated,
+        comment,
+    );
+    Ok(true)
+}
+
+/// Clears the at risk flags of all memberships of the user in groups which `trust` is
+/// sufficient for. Returns the number of cleared flags.
+pub fn clear_at_risk(
+    connection: &PgConnection,
+    user_uuid: &Uuid,
+    trust: &TrustType,
+) -> Result<usize, Error> {
+    use schema::at_risk_memberships as a;
+    let groups = schema::groups::table
+        .filter(schema::groups::trust.le(trust))
+        .select(schema::groups::group_id);
+    let cleared = diesel::delete(
+        a::table
+            .filter(a::user_uuid.eq(user_uuid))
+            .filter(a::group_id.eq_any(groups)),
+    )
+    .returning(a::group_id)
+    .get_results::<i32>(connection)?;
+    for group_id in &cleared {
+        let log_ctx = LogContext::with(*group_id, Uuid::default()).with_user(*user_uuid);
+        let comment = log_comment_body("trust restored");
+        internal::webhook::emit(
+            connection,
+            &log_ctx,
+            WebhookEventType::MembershipUpdated,
+            comment.clone(),
+        );
+        internal::log::db_log(
+            connection,
+            &log_ctx,
+            LogTargetType::Membership,
+            LogOperationType::Updated,
+            comment,
+        );
+    }
+    Ok(cleared.len())
+}
+
+/// At risk memberships whose grace period ended at `now` as pairs of user uuid and group.
+pub fn due_at_risk(
+    connection: &PgConnection,
+    now: NaiveDateTime,
+) -> Result<Vec<(Uuid, Group)>, Error> {
+    schema::at_risk_memberships::table
+        .filter(schema::at_risk_memberships::revoke_after.le(now))
+        .inner_join(schema::groups::table)
+        .select((
+            schema::at_risk_memberships::user_uuid,
+            schema::groups::all_columns,
+        ))
+        .get_results(connection)
+        .map_err(Into::into)
+}
@@ -6,11 +6,15 @@ use crate::db::users::*;
 use crate::error::PacksError;
 use crate::user::User;
 use cis_profile::schema::Profile;
+use diesel::dsl::sql;
 use diesel::prelude::*;
 use diesel::sql_types::Bool;
+use diesel::sql_types::Jsonb;
+use diesel::sql_types::Nullable;
 use failure::Error;
 use log::error;
 use log::info;
+use serde_json::Value;
 use std::convert::TryFrom;
 use uuid::Uuid;
 
@@ -68,6 +72,21 @@ pub fn slim_user_profile_by_uuid(
         .map_err(|_| PacksError::ProfileNotFound.into())
 }
 
+/// Returns the cached `access_information.mozilliansorg` values of every profile.
+pub fn all_mozilliansorg_values(
+    connection: &PgConnection,
+) -> Result<Vec<(Uuid, String, Option<Value>)>, Error> {
+    use schema::profiles as p;
+    p::table
+        .select((
+            p::user_uuid,
+            p::username,
+            sql::<Nullable<Jsonb>>("profile->'access_information'->'mozilliansorg'->'values'"),
+        ))
+        .get_results(connection)
+        .map_err(Into::into)
+}
+
 pub fn user_profile_by_user_id(
     connection: &PgConnection,
     user_id: &str,
@@ -87,6 +106,11 @@ pub fn delete_user(connection: &PgConnection, user: &User) -> Result<(), Error>
     diesel::delete(schema::memberships::table)
         .filter(schema::memberships::user_uuid.eq(user.user_uuid))
         .execute(connection)?;
+    internal::notification::delete_for_user(connection, &user.user_uuid)?;
+    internal::inactive::delete_for_user(connection, &user.user_uuid)?;
+    internal::expiration::delete_notifications_for_user(connection, &user.user_uuid)?;
+    internal::renewal::delete_for_user(connection, &user.user_uuid)?;
+    internal::calendar::delete_for_user(connection, &user.user_uuid)?;
     diesel::delete(schema::users_staff::table)
         .filter(schema::users_staff::user_uuid.eq(user.user_uuid))
         .execute(connection)?;
@@ -209,6 +233,10 @@ macro_rules! scoped_search_users_for_group {
                     .or(u::email.ilike($q)),
             )
             .filter(u::trust.ge($trust))
+            .filter(diesel::dsl::not(diesel::dsl::exists(
+                schema::inactive_users::table
+                    .filter(schema::inactive_users::user_uuid.eq(u::user_uuid)),
+            )))
             .left_outer_join(
                 schema::memberships::table.on(schema::memberships::user_uuid
                     .eq(u::user_uuid)
@@ -299,6 +327,10 @@ macro_rules! scoped_search_users {
                     .or(u::email.ilike($q)),
             )
             .filter(u::trust.ge($trust))
+            .filter(diesel::dsl::not(diesel::dsl::exists(
+                schema::inactive_users::table
+                    .filter(schema::inactive_users::user_uuid.eq(u::user_uuid)),
+            )))
             .limit($limit)
             .get_results::<$typ>($connection)
             .map(|users| users.into_iter().map(|u| u.into()).collect())
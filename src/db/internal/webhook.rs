@@ -0,0 +1,158 @@
+use crate::db::logs::LogContext;
+use crate::db::model::*;
+use crate::db::schema;
+use crate::db::types::*;
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use failure::Error;
+use log::error;
+use serde_json::json;
+use serde_json::Value;
+
+pub fn create(connection: &PgConnection, webhook: &InsertWebhook) -> Result<Webhook, Error> {
+    diesel::insert_into(schema::webhooks::table)
+        .values(webhook)
+        .get_result(connection)
+        .map_err(Into::into)
+}
+
+pub fn webhooks(connection: &PgConnection) -> Result<Vec<(Webhook, Option<String>)>, Error> {
+    schema::webhooks::table
+        .left_join(schema::groups::table)
+        .select((
+            schema::webhooks::all_columns,
+            schema::groups::name.nullable(),
+        ))
+        .order(schema::webhooks::id)
+        .get_results(connection)
+        .map_err(Into::into)
+}
+
+pub fn delete(connection: &PgConnection, id: i32) -> Result<(), Error> {
+    diesel::delete(schema::webhooks::table)
+        .filter(schema::webhooks::id.eq(id))
+        .execute(connection)
+        .map(|_| ())
+        .map_err(Into::into)
+}
+
+pub fn deliveries(
+    connection: &PgConnection,
+    webhook_id: i32,
+    limit: i64,
+) -> Result<Vec<WebhookDelivery>, Error> {
+    schema::webhook_deliveries::table
+        .filter(schema::webhook_deliveries::webhook_id.eq(webhook_id))
+        .order(schema::webhook_deliveries::id.desc())
+        .limit(limit)
+        .get_results(connection)
+        .map_err(Into::into)
+}
+
+/// Claims up to `limit` due deliveries by moving their next attempt to `lease_until`. Rows
+/// locked by a concurrent claim are skipped so every delivery is only handed out once.
+pub fn claim_pending_deliveries(
+    connection: &PgConnection,
+    now: NaiveDateTime,
+    lease_until: NaiveDateTime,
+    limit: i64,
+) -> Result<Vec<(WebhookDelivery, Webhook)>, Error> {
+    connection.transaction(|| {
+        let ids = schema::webhook_deliveries::table
+            .filter(schema::webhook_deliveries::status.eq(DeliveryStatusType::Pending))
+            .filter(schema::webhook_deliveries::next_attempt.le(now))
+            .order(schema::webhook_deliveries::id)
+            .limit(limit)
+            .select(schema::webhook_deliveries::id)
+            .for_update()
+            .skip_locked()
+            .get_results::<i32>(connection)?;
+        diesel::update(schema::webhook_deliveries::table)
+            .filter(schema::webhook_deliveries::id.eq_any(&ids))
+            .set(schema::webhook_deliveries::next_attempt.eq(lease_until))
+            .execute(connection)?;
+        schema::webhook_deliveries::table
+            .inner_join(schema::webhooks::table)
+            .filter(schema::webhook_deliveries::id.eq_any(&ids))
+            .order(schema::webhook_deliveries::id)
+            .get_results(connection)
+            .map_err(Into::into)
+    })
+}
+
+pub fn update_delivery(
+    connection: &PgConnection,
+    delivery: &WebhookDelivery,
+    status: DeliveryStatusType,
+    last_error: Option<String>,
+    next_attempt: NaiveDateTime,
+) -> Result<(), Error> {
+    diesel::update(schema::webhook_deliveries::table)
+        .filter(schema::webhook_deliveries::id.eq(delivery.id))
+        .set((
+            schema::webhook_deliveries::status.eq(status),
+            schema::webhook_deliveries::attempts.eq(delivery.attempts + 1),
+            schema::webhook_deliveries::last_error.eq(last_error),
+            schema::webhook_deliveries::next_attempt.eq(next_attempt),
+            schema::webhook_deliveries::updated.eq(diesel::dsl::now),
+        ))
+        .execute(connection)
+        .map(|_| ())
+        .map_err(Into::into)
+}
+
+fn try_emit(
+    connection: &PgConnection,
+    ctx: &LogContext,
+    event: WebhookEventType,
+    body: Option<Value>,
+) -> Result<(), Error> {
+    let webhooks = schema::webhooks::table
+        .filter(
+            schema::webhooks::group_id
+                .eq(ctx.group_id)
+                .or(schema::webhooks::group_id.is_null()),
+        )
+        .filter(schema::webhooks::events.contains(vec![event]))
+        .get_results::<Webhook>(connection)?;
+    if webhooks.is_empty() {
+        return Ok(());
+    }
+    // deleted groups are inactive, so we can't use `get_group_by_id`
+    let group_name = schema::groups::table
+        .filter(schema::groups::group_id.eq(ctx.group_id))
+        .select(schema::groups::name)
+        .first::<String>(connection)?;
+    let payload = json!({
+        "event": event,
+        "group_name": group_name,
+        "host_uuid": ctx.host_uuid,
+        "user_uuid": ctx.user_uuid,
+        "body": body,
+    });
+    let deliveries = webhooks
+        .into_iter()
+        .map(|webhook| InsertWebhookDelivery {
+            webhook_id: webhook.id,
+            event,
+            payload: payload.clone(),
+        })
+        .collect::<Vec<_>>();
+    diesel::insert_into(schema::webhook_deliveries::table)
+        .values(&deliveries)
+        .execute(connection)?;
+    Ok(())
+}
+
+/// Queues a delivery for every webhook subscribed to `event` for the group in `ctx`. Like
+/// `db_log` this never fails the surrounding operation.
+pub fn emit(
+    connection: &PgConnection,
+    ctx: &LogContext,
+    event: WebhookEventType,
+    body: Option<Value>,
+) {
+    if let Err(e) = try_emit(connection, ctx, event, body) {
+        error!("Failed to emit webhook event {:?}: {}", event, e);
+    }
+}
@@ -2,6 +2,7 @@ use crate::db::schema::*;
 use crate::db::types::*;
 use chrono::NaiveDateTime;
 use serde::Serialize;
+use serde_json::Value;
 use uuid::Uuid;
 
 #[derive(Identifiable, Queryable, PartialEq, Debug, AsChangeset, Serialize)]
@@ -126,3 +127,218 @@ pub struct InsertRequest {
     pub user_uuid: Uuid,
     pub request_expiration: Option<NaiveDateTime>,
 }
+
+#[derive(Queryable, PartialEq, Debug, Insertable, AsChangeset)]
+#[table_name = "notification_preferences"]
+pub struct NotificationPreference {
+    pub user_uuid: Uuid,
+    pub category: NotificationCategoryType,
+    pub preference: NotificationPreferenceType,
+}
+
+#[derive(Queryable, PartialEq, Debug)]
+pub struct QueuedNotification {
+    pub id: i32,
+    pub user_uuid: Uuid,
+    pub preference: NotificationPreferenceType,
+    pub template: Value,
+    pub created: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[table_name = "notification_queue"]
+pub struct InsertQueuedNotification {
+    pub user_uuid: Uuid,
+    pub preference: NotificationPreferenceType,
+    pub template: Value,
+}
+
+#[derive(Queryable, PartialEq, Debug, Insertable, AsChangeset)]
+#[table_name = "suppressed_emails"]
+pub struct SuppressedEmail {
+    pub email: String,
+    pub typ: SuppressionType,
+    pub created: NaiveDateTime,
+    pub body: Option<Value>,
+}
+
+#[derive(Insertable, AsChangeset)]
+#[table_name = "suppressed_emails"]
+pub struct InsertSuppressedEmail {
+    pub email: String,
+    pub typ: SuppressionType,
+    pub body: Option<Value>,
+}
+
+#[derive(Queryable, PartialEq, Debug, Insertable)]
+#[table_name = "group_display"]
+pub struct GroupDisplay {
+    pub group_id: i32,
+    pub display: TrustType,
+}
+
+#[derive(Queryable, PartialEq, Debug, Insertable)]
+#[table_name = "group_newsletters"]
+pub struct GroupNewsletter {
+    pub group_id: i32,
+    pub newsletter: String,
+}
+
+#[derive(Queryable, PartialEq, Debug)]
+pub struct Webhook {
+    pub id: i32,
+    pub url: String,
+    pub secret: String,
+    pub group_id: Option<i32>,
+    pub events: Vec<WebhookEventType>,
+    pub created: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[table_name = "webhooks"]
+pub struct InsertWebhook {
+    pub url: String,
+    pub secret: String,
+    pub group_id: Option<i32>,
+    pub events: Vec<WebhookEventType>,
+}
+
+#[derive(Queryable, PartialEq, Debug)]
+pub struct WebhookDelivery {
+    pub id: i32,
+    pub webhook_id: i32,
+    pub event: WebhookEventType,
+    pub payload: Value,
+    pub status: DeliveryStatusType,
+    pub attempts: i32,
+    pub last_error: Option<String>,
+    pub next_attempt: NaiveDateTime,
+    pub created: NaiveDateTime,
+    pub updated: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[table_name = "webhook_deliveries"]
+pub struct InsertWebhookDelivery {
+    pub webhook_id: i32,
+    pub event: WebhookEventType,
+    pub payload: Value,
+}
+
+#[derive(Queryable, PartialEq, Debug)]
+pub struct CisOutboxEntry {
+    pub id: i32,
+    pub user_uuid: Uuid,
+    pub operation: OutboxOperationType,
+    pub group_names: Vec<String>,
+    pub status: DeliveryStatusType,
+    pub attempts: i32,
+    pub last_error: Option<String>,
+    pub next_attempt: NaiveDateTime,
+    pub created: NaiveDateTime,
+    pub updated: NaiveDateTime,
+    pub claimed_until: Option<NaiveDateTime>,
+}
+
+#[derive(Insertable)]
+#[table_name = "cis_outbox"]
+pub struct InsertCisOutboxEntry {
+    pub user_uuid: Uuid,
+    pub operation: OutboxOperationType,
+    pub group_names: Vec<String>,
+    pub next_attempt: NaiveDateTime,
+}
+
+#[derive(Queryable, PartialEq, Debug)]
+pub struct ResyncRun {
+    pub id: i32,
+    pub cursor: Option<Uuid>,
+    pub total: i32,
+    pub processed: i32,
+    pub failed: i32,
+    pub started: NaiveDateTime,
+    pub updated: NaiveDateTime,
+    pub finished: Option<NaiveDateTime>,
+}
+
+#[derive(Queryable, Insertable, PartialEq, Debug)]
+#[table_name = "expiration_reminders"]
+pub struct ExpirationReminder {
+    pub group_id: i32,
+    pub days: i32,
+    pub member: bool,
+    pub host: bool,
+    pub curators: bool,
+}
+
+#[derive(Queryable, Insertable, PartialEq, Debug)]
+#[table_name = "expiration_grace"]
+pub struct ExpirationGrace {
+    pub group_id: i32,
+    pub days: i32,
+    pub keep_access: bool,
+}
+
+#[derive(Queryable, PartialEq, Debug)]
+pub struct ExpiredMembership {
+    pub user_uuid: Uuid,
+    pub group_id: i32,
+    pub expired: NaiveDateTime,
+    pub access_removed: bool,
+}
+
+#[derive(Queryable, PartialEq, Debug)]
+pub struct TrustChange {
+    pub group_id: i32,
+    pub trust: TrustType,
+    pub effective: NaiveDateTime,
+    pub created_by: Uuid,
+    pub created: NaiveDateTime,
+}
+
+#[derive(Queryable, Insertable, PartialEq, Debug)]
+#[table_name = "suspended_memberships"]
+pub struct SuspendedMembership {
+    pub user_uuid: Uuid,
+    pub group_id: i32,
+    pub role_id: i32,
+    pub expiration: Option<NaiveDateTime>,
+    pub added_by: Uuid,
+    pub added_ts: NaiveDateTime,
+}
+
+impl From<Membership> for SuspendedMembership {
+    fn from(m: Membership) -> Self {
+        SuspendedMembership {
+            user_uuid: m.user_uuid,
+            group_id: m.group_id,
+            role_id: m.role_id,
+            expiration: m.expiration,
+            added_by: m.added_by,
+            added_ts: m.added_ts,
+        }
+    }
+}
+
+impl From<SuspendedMembership> for Membership {
+    fn from(m: SuspendedMembership) -> Self {
+        Membership {
+            user_uuid: m.user_uuid,
+            group_id: m.group_id,
+            role_id: m.role_id,
+            expiration: m.expiration,
+            added_by: m.added_by,
+            added_ts: m.added_ts,
+        }
+    }
+}
+
+#[derive(Queryable, PartialEq, Debug)]
+pub struct JobRun {
+    pub job: JobType,
+    pub tick: NaiveDateTime,
+    pub started: NaiveDateTime,
+    pub finished: Option<NaiveDateTime>,
+    pub success: Option<bool>,
+    pub message: Option<String>,
+}
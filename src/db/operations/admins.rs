@@ -1,5 +1,7 @@
-use crate::cis::operations::add_group_to_profile;
+use crate::cis::operations::ProfilePublisher;
 use crate::db::internal;
+use crate::db::operations;
+use crate::db::operations::newsletters::subscribe_member;
 use crate::db::Pool;
 use crate::error::PacksError;
 use crate::mail::manager::send_email;
@@ -7,7 +9,7 @@ use crate::mail::templates::Template;
 use crate::rules::engine::*;
 use crate::rules::RuleContext;
 use crate::user::User;
-use cis_client::AsyncCisClientTrait;
+use diesel::Connection;
 use dino_park_gate::scope::ScopeAndUser;
 use failure::Error;
 use std::sync::Arc;
@@ -18,9 +20,8 @@ pub async fn add_admin(
     group_name: &str,
     host: &User,
     user: &User,
-    cis_client: Arc<impl AsyncCisClientTrait>,
+    publisher: Arc<impl ProfilePublisher>,
 ) -> Result<(), Error> {
-    let group_name_f = group_name.to_owned();
     CAN_ADD_CURATOR.run(&RuleContext::minimal_with_member_uuid(
         pool,
         scope_and_user,
@@ -29,10 +30,14 @@ pub async fn add_admin(
         &user.user_uuid,
     ))?;
     let connection = pool.get()?;
-    let user_profile = internal::user::user_profile_by_uuid(&connection, &user.user_uuid)?;
-    internal::admin::add_admin(&connection, &group_name, host, user)?;
+    let entry = connection.transaction::<_, Error, _>(|| {
+        internal::admin::add_admin(&connection, &group_name, host, user)?;
+        operations::outbox::enqueue_add_group(&connection, &user.user_uuid, group_name)
+    })?;
+    subscribe_member(&connection, group_name, user);
     drop(connection);
-    add_group_to_profile(cis_client, group_name_f, user_profile.profile).await
+    operations::outbox::deliver(pool, publisher, entry).await;
+    Ok(())
 }
 
 pub fn demote(
@@ -0,0 +1,78 @@
+use crate::db::internal;
+use crate::db::logs::LogContext;
+use crate::db::operations::models::Broadcast;
+use crate::db::types::LogOperationType;
+use crate::db::types::LogTargetType;
+use crate::db::Pool;
+use crate::error::PacksError;
+use crate::mail::manager::send_emails_with_reply_to;
+use crate::mail::templates::Template;
+use crate::rules::engine::EMAIL_MEMBERS;
+use crate::rules::RuleContext;
+use chrono::Duration;
+use chrono::Utc;
+use diesel::Connection;
+use dino_park_gate::scope::ScopeAndUser;
+use failure::Error;
+use serde_json::json;
+
+/// Maximum number of broadcasts per group within `BROADCAST_PERIOD_HOURS`.
+const BROADCAST_LIMIT: i64 = 3;
+const BROADCAST_PERIOD_HOURS: i64 = 24;
+
+pub fn broadcast(
+    pool: &Pool,
+    scope_and_user: &ScopeAndUser,
+    group_name: &str,
+    broadcast: Broadcast,
+) -> Result<usize, Error> {
+    let connection = pool.get()?;
+    let host = internal::user::user_by_id(&connection, &scope_and_user.user_id)?;
+    EMAIL_MEMBERS.run(&RuleContext::minimal(
+        pool,
+        scope_and_user,
+        group_name,
+        &host.user_uuid,
+    ))?;
+    let group = internal::group::get_group(&connection, group_name)?;
+    let host_profile = internal::user::slim_user_profile_by_uuid(&connection, &host.user_uuid)?;
+    let emails =
+        internal::member::get_member_emails_by_roles(&connection, group.id, &broadcast.roles)?;
+    let recipients = emails.len();
+    // the group row serializes concurrent broadcasts so the limit holds
+    connection.transaction::<_, Error, _>(|| {
+        internal::group::lock_group(&connection, group.id)?;
+        let since = (Utc::now() - Duration::hours(BROADCAST_PERIOD_HOURS)).naive_utc();
+        if internal::log::count_since(&connection, group.id, LogTargetType::Email, since)?
+            >= BROADCAST_LIMIT
+        {
+            return Err(PacksError::BroadcastLimitReached.into());
+        }
+        let log_ctx = LogContext::with(group.id, host.user_uuid);
+        internal::log::db_log(
+            &connection,
+            &log_ctx,
+            LogTargetType::Email,
+            LogOperationType::Created,
+            Some(json!({
+                "subject": broadcast.subject,
+                "roles": broadcast.roles,
+                "recipients": recipients,
+            })),
+        );
+        Ok(())
+    })?;
+    if !emails.is_empty() {
+        send_emails_with_reply_to(
+            emails,
+            host_profile.email,
+            &Template::Broadcast(
+                group.name,
+                host_profile.username,
+                broadcast.subject,
+                broadcast.body,
+            ),
+        );
+    }
+    Ok(recipients)
+}
@@ -0,0 +1,86 @@
+use crate::db::internal;
+use crate::db::operations::models::CalendarEntry;
+use crate::db::operations::models::CalendarFeed;
+use crate::db::operations::models::CalendarToken;
+use crate::db::Pool;
+use crate::rules::engine::HOST_IS_CURATOR;
+use crate::rules::RuleContext;
+use chrono::Utc;
+use dino_park_gate::scope::ScopeAndUser;
+use failure::Error;
+use sha2::Digest;
+use sha2::Sha256;
+use uuid::Uuid;
+
+fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+/// Creates a new calendar token for the curator replacing (and thereby revoking) any previous
+/// one for this group.
+pub fn create_calendar_token(
+    pool: &Pool,
+    scope_and_user: &ScopeAndUser,
+    group_name: &str,
+) -> Result<CalendarToken, Error> {
+    let connection = pool.get()?;
+    let host = internal::user::user_by_id(&connection, &scope_and_user.user_id)?;
+    HOST_IS_CURATOR.run(&RuleContext::minimal(
+        pool,
+        scope_and_user,
+        group_name,
+        &host.user_uuid,
+    ))?;
+    let group = internal::group::get_group(&connection, group_name)?;
+    let token = format!(
+        "{}{}",
+        Uuid::new_v4().to_simple(),
+        Uuid::new_v4().to_simple()
+    );
+    internal::calendar::set_token(&connection, group.id, &host.user_uuid, &hash_token(&token))?;
+    Ok(CalendarToken { token })
+}
+
+pub fn revoke_calendar_token(
+    pool: &Pool,
+    scope_and_user: &ScopeAndUser,
+    group_name: &str,
+) -> Result<(), Error> {
+    let connection = pool.get()?;
+    let host = internal::user::user_by_id(&connection, &scope_and_user.user_id)?;
+    let group = internal::group::get_group(&connection, group_name)?;
+    internal::calendar::revoke_token(&connection, group.id, &host.user_uuid)
+}
+
+/// Upcoming membership expirations for the group the token was issued for. Returns `None` if
+/// the token is unknown or its owner is no longer a curator of the group.
+pub fn calendar_feed(pool: &Pool, token: &str) -> Result<Option<CalendarFeed>, Error> {
+    let connection = pool.get()?;
+    let (user_uuid, group) = match internal::calendar::token_owner(&connection, &hash_token(token))?
+    {
+        Some(owner) => owner,
+        None => return Ok(None),
+    };
+    let is_curator = internal::member::role_for(&connection, &user_uuid, &group.name)?
+        .map(|role| role.typ.is_curator())
+        .unwrap_or_default();
+    if !is_curator {
+        return Ok(None);
+    }
+    let entries =
+        internal::calendar::expiring_memberships(&connection, group.id, Utc::now().naive_utc())?
+            .into_iter()
+            .filter_map(|(user_uuid, username, expiration)| {
+                expiration.map(|expiration| CalendarEntry {
+                    user_uuid,
+                    username,
+                    expiration,
+                })
+            })
+            .collect();
+    Ok(Some(CalendarFeed {
+        group_id: group.id,
+        group_name: group.name,
+        entries,
+    }))
+}
@@ -0,0 +1,71 @@
+use crate::db::internal;
+use crate::db::operations;
+use crate::db::operations::models::GroupDisplaySetting;
+use crate::db::Pool;
+use crate::rules::engine::ONLY_ADMINS;
+use crate::rules::RuleContext;
+use crate::user::User;
+use diesel::Connection;
+use dino_park_gate::scope::ScopeAndUser;
+use failure::Error;
+
+pub fn group_display(
+    pool: &Pool,
+    scope_and_user: &ScopeAndUser,
+    host: &User,
+    group_name: &str,
+) -> Result<GroupDisplaySetting, Error> {
+    ONLY_ADMINS.run(&RuleContext::minimal(
+        &pool.clone(),
+        scope_and_user,
+        group_name,
+        &host.user_uuid,
+    ))?;
+    let connection = pool.get()?;
+    let group = internal::group::get_group(&connection, group_name)?;
+    let display = internal::display::display_for_group(&connection, group.id)?;
+    Ok(GroupDisplaySetting { display })
+}
+
+pub fn update_group_display(
+    pool: &Pool,
+    scope_and_user: &ScopeAndUser,
+    host: &User,
+    group_name: &str,
+    setting: GroupDisplaySetting,
+) -> Result<(), Error> {
+    ONLY_ADMINS.run(&RuleContext::minimal(
+        &pool.clone(),
+        scope_and_user,
+        group_name,
+        &host.user_uuid,
+    ))?;
+    let connection = pool.get()?;
+    let group = internal::group::get_group(&connection, group_name)?;
+    internal::display::set_display(&connection, group.id, setting.display)
+}
+
+/// Queues a profile update for every member of the group so the current display setting gets
+/// published. Returns the number of queued members.
+pub fn republish_memberships(
+    pool: &Pool,
+    scope_and_user: &ScopeAndUser,
+    host: &User,
+    group_name: &str,
+) -> Result<usize, Error> {
+    ONLY_ADMINS.run(&RuleContext::minimal(
+        &pool.clone(),
+        scope_and_user,
+        group_name,
+        &host.user_uuid,
+    ))?;
+    let connection = pool.get()?;
+    let members = internal::member::get_members(&connection, group_name)?;
+    connection.transaction::<_, Error, _>(|| {
+        for member in &members {
+            operations::outbox::batch_add_group(&connection, &member.user_uuid, group_name)?;
+        }
+        Ok(())
+    })?;
+    Ok(members.len())
+}
@@ -0,0 +1,172 @@
+use crate::cis::operations::ProfilePublisher;
+use crate::db::internal;
+use crate::db::operations::models::DriftReport;
+use crate::db::operations::models::GroupDrift;
+use crate::db::operations::models::ReconcileReport;
+use crate::db::operations::models::UserDrift;
+use crate::db::Pool;
+use crate::rules::engine::ONLY_ADMINS;
+use crate::rules::RuleContext;
+use crate::user::User;
+use actix_rt::time::delay_for;
+use diesel::PgConnection;
+use dino_park_gate::scope::ScopeAndUser;
+use failure::Error;
+use log::info;
+use log::warn;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Delay between two profile updates to not overload CIS.
+const RECONCILE_DELAY_MS: u64 = 200;
+
+fn profile_groups(values: Option<Value>) -> BTreeSet<String> {
+    match values {
+        Some(Value::Object(groups)) => groups.into_iter().map(|(k, _)| k).collect(),
+        _ => BTreeSet::new(),
+    }
+}
+
+/// Compares the `access_information.mozilliansorg` values of all cached profiles with the actual
+/// memberships and returns the drift together with the groups the user should have.
+fn user_drifts(connection: &PgConnection) -> Result<Vec<(UserDrift, Vec<String>)>, Error> {
+    let mut memberships: BTreeMap<Uuid, BTreeSet<String>> = BTreeMap::new();
+    for (user_uuid, group_name) in internal::member::all_memberships_by_name(connection)? {
+        memberships.entry(user_uuid).or_default().insert(group_name);
+    }
+    // expired memberships lose access during the grace period
+    for (user_uuid, group_name) in internal::grace::without_access_by_name(connection)? {
+        if let Some(groups) = memberships.get_mut(&user_uuid) {
+            groups.remove(&group_name);
+        }
+    }
+    let profiles = internal::user::all_mozilliansorg_values(connection)?;
+    let mut drifts = vec![];
+    for (user_uuid, username, values) in profiles {
+        let in_profile = profile_groups(values);
+        let in_db = memberships.remove(&user_uuid).unwrap_or_default();
+        let missing = in_db.difference(&in_profile).cloned().collect::<Vec<_>>();
+        let extra = in_profile.difference(&in_db).cloned().collect::<Vec<_>>();
+        if missing.is_empty() && extra.is_empty() {
+            continue;
+        }
+        let drift = UserDrift {
+            user_uuid,
+            username,
+            missing,
+            extra,
+        };
+        drifts.push((drift, in_db.into_iter().collect()));
+    }
+    Ok(drifts)
+}
+
+pub fn drift_report(
+    pool: &Pool,
+    scope_and_user: &ScopeAndUser,
+    host: &User,
+) -> Result<DriftReport, Error> {
+    ONLY_ADMINS.run(&RuleContext::minimal(
+        &pool.clone(),
+        scope_and_user,
+        "",
+        &host.user_uuid,
+    ))?;
+    let connection = pool.get()?;
+    let drifts = user_drifts(&connection)?;
+    drop(connection);
+
+    let mut users = vec![];
+    let mut groups: BTreeMap<String, GroupDrift> = BTreeMap::new();
+    for (drift, _) in drifts {
+        let user_uuid = drift.user_uuid;
+        for group_name in &drift.missing {
+            groups
+                .entry(group_name.clone())
+                .or_default()
+                .missing
+                .push(user_uuid);
+        }
+        for group_name in &drift.extra {
+            groups
+                .entry(group_name.clone())
+                .or_default()
+                .extra
+                .push(user_uuid);
+        }
+        users.push(drift);
+    }
+    let groups = groups
+        .into_iter()
+        .map(|(group_name, drift)| GroupDrift {
+            group_name,
+            ..drift
+        })
+        .collect();
+    Ok(DriftReport { users, groups })
+}
+
+/// Publishes the correct mozilliansorg groups for every user whose cached profile disagrees
+/// with the memberships. With `dry_run` only the report is generated.
+pub async fn reconcile(
+    pool: &Pool,
+    publisher: Arc<impl ProfilePublisher>,
+    dry_run: bool,
+) -> Result<ReconcileReport, Error> {
+    let connection = pool.get()?;
+    let drifts = user_drifts(&connection)?;
+    drop(connection);
+    let mut report = ReconcileReport {
+        dry_run,
+        users: vec![],
+        updated: 0,
+        failed: 0,
+    };
+    for (drift, groups) in drifts {
+        if !dry_run {
+            let connection = pool.get()?;
+            let user_profile = internal::user::user_profile_by_uuid(&connection, &drift.user_uuid)?;
+            let display = internal::display::display_for_groups(&connection, &groups)?;
+            drop(connection);
+            match Arc::clone(&publisher)
+                .set_groups(groups, display.into(), user_profile.profile)
+                .await
+            {
+                Ok(_) => report.updated += 1,
+                Err(e) => {
+                    warn!("failed to reconcile groups for {}: {}", drift.user_uuid, e);
+                    report.failed += 1;
+                }
+            }
+            delay_for(Duration::from_millis(RECONCILE_DELAY_MS)).await;
+        }
+        report.users.push(drift);
+    }
+    info!(
+        "reconciled {} profiles ({} failed, dry run: {})",
+        report.updated, report.failed, dry_run
+    );
+    Ok(report)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_profile_groups() {
+        assert!(profile_groups(None).is_empty());
+        assert!(profile_groups(Some(Value::Null)).is_empty());
+        assert_eq!(
+            profile_groups(Some(json!({ "nda": "", "foo": null }))),
+            vec![String::from("foo"), String::from("nda")]
+                .into_iter()
+                .collect()
+        );
+    }
+}
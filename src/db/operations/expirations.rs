@@ -1,65 +1,211 @@
+use crate::cis::operations::ProfilePublisher;
 use crate::db::internal;
 use crate::db::logs::log_comment_body;
 use crate::db::model::Membership;
+use crate::db::operations;
 use crate::db::operations::members::revoke_membership;
+use crate::db::operations::models::ExpirationAction;
+use crate::db::operations::models::ExpirationPreview;
+use crate::db::operations::models::ExpiringEntry;
+use crate::db::operations::models::ExpiringMembership;
+use crate::db::operations::models::Grace;
+use crate::db::operations::models::GroupExpirationPreview;
+use crate::db::operations::models::PreviewNotification;
+use crate::db::operations::models::Reminder;
 use crate::db::operations::models::RemoveGroups;
+use crate::db::operations::notifications::notify;
+use crate::db::operations::notifications::notify_many;
 use crate::db::types::RoleType;
+use crate::db::users::UserProfileSlim;
 use crate::db::Pool;
 use crate::error::PacksError;
-use crate::mail::manager::send_email;
-use crate::mail::manager::send_emails;
 use crate::mail::templates::Template;
+use crate::rules::engine::ONLY_ADMINS;
+use crate::rules::RuleContext;
 use crate::user::User;
 use chrono::Duration;
+use chrono::NaiveDateTime;
 use chrono::Utc;
-use cis_client::AsyncCisClientTrait;
+use diesel::Connection;
+use diesel::PgConnection;
+use dino_park_gate::scope::ScopeAndUser;
 use failure::Error;
 use futures::future::try_join_all;
 use futures::TryFutureExt;
 use log::info;
+use std::collections::hash_map::Entry;
 use std::collections::HashMap;
+use std::collections::HashSet;
 use std::sync::Arc;
 use uuid::Uuid;
 
+fn group_names(
+    connection: &PgConnection,
+    memberships: &[Membership],
+) -> Result<Vec<String>, Error> {
+    let groups = internal::group::get_groups_by_ids(
+        connection,
+        &memberships.iter().map(|m| m.group_id).collect::<Vec<i32>>(),
+    )?;
+    Ok(groups.into_iter().map(|g| g.name).collect())
+}
+
 async fn expire_membership(
     pool: &Pool,
-    cis_client: Arc<impl AsyncCisClientTrait>,
+    publisher: Arc<impl ProfilePublisher>,
     user: &User,
     memberships: Vec<Membership>,
 ) -> Result<(), Error> {
     let connection = pool.get()?;
-    let groups = internal::group::get_groups_by_ids(
-        &connection,
-        &memberships.iter().map(|m| m.group_id).collect::<Vec<i32>>(),
-    )?;
-    let group_names = groups.iter().map(|g| g.name.as_str()).collect::<Vec<_>>();
+    let group_names = group_names(&connection, &memberships)?;
+    let group_names = group_names.iter().map(String::as_str).collect::<Vec<_>>();
+    drop(connection);
     let host = User::default();
     let remove_groups = RemoveGroups {
         user: *user,
         group_names: group_names.as_slice(),
         force: true,
         notify: true,
+        batch: false,
     };
     revoke_membership(
         pool,
         remove_groups,
         &host,
-        cis_client,
+        publisher,
         log_comment_body("expired"),
     )
     .await
 }
 
-pub async fn expire_memberships(
+/// Clears the expired mark of a renewed membership and gives back access if it had been removed
+/// during the grace period.
+pub async fn restore_access(
     pool: &Pool,
-    cis_client: Arc<impl AsyncCisClientTrait>,
+    publisher: Arc<impl ProfilePublisher>,
+    user_uuid: &Uuid,
+    group_id: i32,
 ) -> Result<(), Error> {
-    let expires_before = Utc::now().naive_utc();
     let connection = pool.get()?;
-    let memberships =
-        internal::member::get_memberships_expired_before(&connection, expires_before)?;
+    let entry =
+        connection.transaction::<_, Error, _>(|| {
+            match internal::grace::clear_expired(&connection, group_id, user_uuid)? {
+                Some(true) => {
+                    let group = internal::group::get_group_by_id(&connection, group_id)?
+                        .ok_or(PacksError::InvalidGroupData)?;
+                    operations::outbox::enqueue_add_groups(&connection, user_uuid, vec![group.name])
+                        .map(Some)
+                }
+                _ => Ok(None),
+            }
+        })?;
+    drop(connection);
+    if let Some(entry) = entry {
+        operations::outbox::deliver(pool, publisher, entry).await;
+    }
+    Ok(())
+}
+
+/// Restores expired memberships which have been renewed in the meantime.
+async fn restore_renewed(
+    pool: &Pool,
+    publisher: Arc<impl ProfilePublisher>,
+    now: NaiveDateTime,
+) -> Result<(), Error> {
+    let connection = pool.get()?;
+    let mut renewed = vec![];
+    for expired in internal::grace::expired_memberships(&connection)? {
+        let membership =
+            internal::member::get_membership(&connection, expired.group_id, &expired.user_uuid)?;
+        if membership
+            .and_then(|m| m.expiration)
+            .map(|e| e > now)
+            .unwrap_or(true)
+        {
+            renewed.push(expired);
+        }
+    }
+    drop(connection);
+    for expired in renewed {
+        restore_access(
+            pool,
+            Arc::clone(&publisher),
+            &expired.user_uuid,
+            expired.group_id,
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+/// Marks the membership as expired and removes it from the profile unless the group keeps access
+/// during the grace period.
+async fn enter_grace(
+    pool: &Pool,
+    publisher: Arc<impl ProfilePublisher>,
+    membership: &Membership,
+    keep_access: bool,
+) -> Result<(), Error> {
+    let host = User::default();
+    let connection = pool.get()?;
+    let entry = connection.transaction::<_, Error, _>(|| {
+        if !internal::grace::mark_expired(&connection, &host.user_uuid, membership, !keep_access)?
+            || keep_access
+        {
+            return Ok(None);
+        }
+        let group = internal::group::get_group_by_id(&connection, membership.group_id)?
+            .ok_or(PacksError::InvalidGroupData)?;
+        operations::outbox::enqueue_remove_groups(
+            &connection,
+            &membership.user_uuid,
+            vec![group.name],
+        )
+        .map(Some)
+    })?;
     drop(connection);
-    let memberships = memberships.into_iter().fold(
+    if let Some(entry) = entry {
+        operations::outbox::deliver(pool, publisher, entry).await;
+    }
+    Ok(())
+}
+
+/// Memberships within the grace period with whether their group keeps access and memberships to
+/// revoke.
+type ExpiredMemberships = (Vec<(Membership, bool)>, Vec<Membership>);
+
+/// Splits the memberships expired before `now` into those within the grace period of their
+/// group and those to revoke.
+fn expired_memberships(
+    connection: &PgConnection,
+    now: NaiveDateTime,
+) -> Result<ExpiredMemberships, Error> {
+    let memberships = internal::member::get_memberships_expired_before(connection, now)?;
+    let mut graces: HashMap<i32, Option<Grace>> = HashMap::new();
+    let mut expired = vec![];
+    let mut in_grace = vec![];
+    for membership in memberships {
+        let grace = match graces.entry(membership.group_id) {
+            Entry::Occupied(e) => e.into_mut(),
+            Entry::Vacant(e) => e.insert(internal::grace::grace_for_group(
+                connection,
+                membership.group_id,
+            )?),
+        };
+        match (grace, membership.expiration) {
+            (Some(grace), Some(expiration))
+                if expiration + Duration::days(grace.days.into()) > now =>
+            {
+                in_grace.push((membership, grace.keep_access))
+            }
+            _ => expired.push(membership),
+        }
+    }
+    Ok((in_grace, expired))
+}
+
+fn by_user(memberships: Vec<Membership>) -> HashMap<Uuid, Vec<Membership>> {
+    memberships.into_iter().fold(
         HashMap::new(),
         |mut h: HashMap<Uuid, Vec<Membership>>, m| {
             if let Some(v) = h.get_mut(&m.user_uuid) {
@@ -69,86 +215,280 @@ pub async fn expire_memberships(
             }
             h
         },
-    );
+    )
+}
+
+pub async fn expire_memberships(
+    pool: &Pool,
+    publisher: Arc<impl ProfilePublisher>,
+) -> Result<(), Error> {
+    let now = Utc::now().naive_utc();
+    restore_renewed(pool, Arc::clone(&publisher), now).await?;
+    let connection = pool.get()?;
+    let (in_grace, expired) = expired_memberships(&connection, now)?;
+    drop(connection);
+    for (membership, keep_access) in in_grace {
+        enter_grace(pool, Arc::clone(&publisher), &membership, keep_access).await?;
+    }
+    let memberships = by_user(expired);
     try_join_all(memberships.into_iter().map(|(user_uuid, memberships)| {
         let user = User { user_uuid };
-        let cis_client = Arc::clone(&cis_client);
+        let publisher = Arc::clone(&publisher);
         async move {
             let pool = pool.clone();
-            expire_membership(&pool, cis_client, &user, memberships).await
+            expire_membership(&pool, publisher, &user, memberships).await
         }
     }))
     .map_ok(|_| ())
     .await
 }
 
-pub fn expiration_notification(pool: &Pool, first: bool) -> Result<usize, Error> {
-    let days = if first { 14 } else { 7 };
-    let lower = Utc::now()
-        .checked_add_signed(Duration::days(days))
-        .unwrap()
-        .date()
-        .and_hms(0, 0, 0)
-        .naive_utc();
-    let upper = Utc::now()
-        .checked_add_signed(Duration::days(days))
-        .unwrap()
+fn end_of_day_in(days: i64) -> NaiveDateTime {
+    (Utc::now() + Duration::days(days))
         .date()
         .and_hms_nano(23, 59, 59, 999_999_999)
-        .naive_utc();
+        .naive_utc()
+}
+
+fn send_reminder(
+    connection: &PgConnection,
+    membership: &Membership,
+    reminder: &Reminder,
+) -> Result<(), Error> {
+    let group = internal::group::get_group_by_id(connection, membership.group_id)?
+        .ok_or(PacksError::InvalidGroupData)?;
+    let user = internal::user::slim_user_profile_by_uuid(connection, &membership.user_uuid)?;
+    let template = Template::HostExpiration(group.name.clone(), user.username, reminder.days);
+    let mut notify_curators = reminder.curators;
+    if reminder.host {
+        let host = internal::user::slim_user_profile_by_uuid(connection, &membership.added_by)?;
+        // fall back to all curators if the host is no curator or has a bouncing email
+        let host_valid = !internal::suppression::is_suppressed(connection, &host.email)?
+            && match internal::member::role_for(connection, &host.user_uuid, &group.name)? {
+                Some(r) => r.typ != RoleType::Member,
+                None => false,
+            };
+        if host_valid && !reminder.curators {
+            notify(connection, host.email, template.clone())?;
+        } else {
+            notify_curators = true;
+        }
+    }
+    if notify_curators {
+        let bcc = internal::member::get_curator_emails(connection, group.id)?;
+        notify_many(connection, bcc, template)?;
+    }
+    if reminder.member {
+        let template = if internal::renewal::self_renewal_enabled(connection, group.id)? {
+            Template::MemberSelfRenewal(group.name, reminder.days)
+        } else {
+            Template::MemberExpiration(group.name, reminder.days)
+        };
+        notify(connection, user.email, template)?;
+    }
+    Ok(())
+}
+
+/// Sends expiration reminders according to the reminder schedule of each group. A membership
+/// is due for the closest reminder whose window it fell into, so missed runs are caught up and
+/// repeated runs never remind twice.
+pub fn expiration_notification(pool: &Pool) -> Result<usize, Error> {
     let connection = pool.get()?;
-    let memberships = internal::member::get_memberships_expire_between(&connection, lower, upper)?;
+    let now = Utc::now().naive_utc();
+    internal::expiration::prune_notifications(&connection, now)?;
+    let upper = end_of_day_in(internal::expiration::max_reminder_days(&connection)?.into());
+    let memberships = internal::member::get_memberships_expire_between(&connection, now, upper)?;
     info!(
-        "{} memberships expiring in {} days ({}-{})",
+        "{} memberships expiring between {} and {}",
         memberships.len(),
-        days,
-        lower,
+        now,
         upper
     );
+    let mut schedules: HashMap<i32, Vec<Reminder>> = HashMap::new();
     let mut count = 0;
     for membership in memberships {
-        let group = internal::group::get_group_by_id(&connection, membership.group_id)?
-            .ok_or(PacksError::InvalidGroupData)?;
-        let host = internal::user::slim_user_profile_by_uuid(&connection, &membership.added_by)?;
-        let host_valid =
-            match internal::member::role_for(&connection, &host.user_uuid, &group.name)? {
-                Some(r) => r.typ != RoleType::Member,
-                None => false,
-            };
-        let user = internal::user::slim_user_profile_by_uuid(&connection, &membership.added_by)?;
-        if first {
-            if host_valid {
-                send_email(
-                    host.email,
-                    &Template::FirstHostExpiration(group.name, user.username),
-                );
-            } else {
-                let bcc = internal::member::get_curator_emails(&connection, group.id)?;
-                send_emails(
-                    bcc,
-                    &Template::FirstHostExpiration(group.name, user.username),
-                );
-            };
-        } else {
-            if host_valid {
-                send_email(
-                    host.email,
-                    &Template::SecondHostExpiration(group.name.clone(), user.username),
-                );
-            } else {
-                let bcc = internal::member::get_curator_emails(&connection, group.id)?;
-                send_emails(
-                    bcc,
-                    &Template::SecondHostExpiration(group.name.clone(), user.username),
-                );
+        let expiration = match membership.expiration {
+            Some(expiration) => expiration,
+            None => continue,
+        };
+        let reminders = match schedules.entry(membership.group_id) {
+            Entry::Occupied(e) => e.into_mut(),
+            Entry::Vacant(e) => e.insert(internal::expiration::reminders_for_group(
+                &connection,
+                membership.group_id,
+            )?),
+        };
+        let reminder = reminders
+            .iter()
+            .filter(|r| expiration <= end_of_day_in(r.days.into()))
+            .min_by_key(|r| r.days);
+        let reminder = match reminder {
+            Some(reminder) => reminder,
+            None => continue,
+        };
+        let sent = connection.transaction::<_, Error, _>(|| {
+            if !internal::expiration::record_notification(&connection, &membership, reminder.days)?
+            {
+                return Ok(false);
             }
-            send_email(user.email, &Template::MemberExpiration(group.name));
+            send_reminder(&connection, &membership, reminder)?;
+            Ok(true)
+        })?;
+        if sent {
+            count += 1;
         }
-        count += 1;
     }
+    info!("sent {} expiration reminders", count);
     Ok(count)
 }
 
+fn group_preview<'a>(
+    connection: &PgConnection,
+    groups: &'a mut HashMap<i32, GroupExpirationPreview>,
+    group_id: i32,
+) -> Result<&'a mut GroupExpirationPreview, Error> {
+    match groups.entry(group_id) {
+        Entry::Occupied(e) => Ok(e.into_mut()),
+        Entry::Vacant(e) => {
+            let group = internal::group::get_group_by_id(connection, group_id)?
+                .ok_or(PacksError::InvalidGroupData)?;
+            Ok(e.insert(GroupExpirationPreview {
+                group_name: group.name,
+                ..Default::default()
+            }))
+        }
+    }
+}
+
+fn preview_membership(
+    connection: &PgConnection,
+    groups: &mut HashMap<i32, GroupExpirationPreview>,
+    group_id: i32,
+    user: &UserProfileSlim,
+    action: ExpirationAction,
+) -> Result<(), Error> {
+    let membership = internal::member::get_membership(connection, group_id, &user.user_uuid)?;
+    group_preview(connection, groups, group_id)?
+        .memberships
+        .push(ExpiringMembership {
+            user_uuid: user.user_uuid,
+            username: user.username.clone(),
+            expiration: membership.and_then(|m| m.expiration),
+            action,
+            keep_access: false,
+        });
+    Ok(())
+}
+
+/// Adds the memberships revoked along with `group_names` and the mails `revoke_membership` sends
+/// to the preview.
+fn preview_revocation(
+    connection: &PgConnection,
+    groups: &mut HashMap<i32, GroupExpirationPreview>,
+    user: &UserProfileSlim,
+    group_names: &[&str],
+) -> Result<(), Error> {
+    let revoked = operations::members::revoked_groups(
+        connection,
+        &User {
+            user_uuid: user.user_uuid,
+        },
+        group_names,
+    )?;
+    for group_name in revoked {
+        let group = internal::group::get_group(connection, &group_name)?;
+        if !group_names.contains(&group_name.as_str()) {
+            // revoked along with a nda membership
+            preview_membership(connection, groups, group.id, user, ExpirationAction::Revoke)?;
+        }
+        let preview = group_preview(connection, groups, group.id)?;
+        for (email, template) in operations::members::revocation_notifications(user, &group_name) {
+            preview
+                .notifications
+                .push(PreviewNotification { email, template });
+        }
+    }
+    Ok(())
+}
+
+/// Reports the requests, invitations and memberships expiring up to `before` together with the
+/// memberships revoked along with them and the resulting notifications. Nothing is changed.
+pub fn expiration_preview(
+    pool: &Pool,
+    scope_and_user: &ScopeAndUser,
+    host: &User,
+    before: NaiveDateTime,
+) -> Result<ExpirationPreview, Error> {
+    ONLY_ADMINS.run(&RuleContext::minimal(
+        &pool.clone(),
+        scope_and_user,
+        "",
+        &host.user_uuid,
+    ))?;
+    let connection = pool.get()?;
+    let mut groups: HashMap<i32, GroupExpirationPreview> = HashMap::new();
+    for request in internal::request::expiring_before(&connection, before)? {
+        let user = internal::user::slim_user_profile_by_uuid(&connection, &request.user_uuid)?;
+        group_preview(&connection, &mut groups, request.group_id)?
+            .requests
+            .push(ExpiringEntry {
+                user_uuid: request.user_uuid,
+                username: user.username,
+                expiration: request.request_expiration,
+            });
+    }
+    for invitation in internal::invitation::expiring_before(&connection, before)? {
+        let user = internal::user::slim_user_profile_by_uuid(&connection, &invitation.user_uuid)?;
+        group_preview(&connection, &mut groups, invitation.group_id)?
+            .invitations
+            .push(ExpiringEntry {
+                user_uuid: invitation.user_uuid,
+                username: user.username,
+                expiration: invitation.invitation_expiration,
+            });
+    }
+    let marked = internal::grace::expired_memberships(&connection)?
+        .into_iter()
+        .map(|e| (e.user_uuid, e.group_id))
+        .collect::<HashSet<_>>();
+    let (in_grace, expired) = expired_memberships(&connection, before)?;
+    for (membership, keep_access) in in_grace {
+        if marked.contains(&(membership.user_uuid, membership.group_id)) {
+            continue;
+        }
+        let user = internal::user::slim_user_profile_by_uuid(&connection, &membership.user_uuid)?;
+        group_preview(&connection, &mut groups, membership.group_id)?
+            .memberships
+            .push(ExpiringMembership {
+                user_uuid: membership.user_uuid,
+                username: user.username,
+                expiration: membership.expiration,
+                action: ExpirationAction::Grace,
+                keep_access,
+            });
+    }
+    for (user_uuid, memberships) in by_user(expired) {
+        let group_names = group_names(&connection, &memberships)?;
+        let group_names = group_names.iter().map(String::as_str).collect::<Vec<_>>();
+        let user = internal::user::slim_user_profile_by_uuid(&connection, &user_uuid)?;
+        for membership in memberships {
+            group_preview(&connection, &mut groups, membership.group_id)?
+                .memberships
+                .push(ExpiringMembership {
+                    user_uuid,
+                    username: user.username.clone(),
+                    expiration: membership.expiration,
+                    action: ExpirationAction::Revoke,
+                    keep_access: false,
+                });
+        }
+        preview_revocation(&connection, &mut groups, &user, &group_names)?;
+    }
+    let mut groups = groups.drain().map(|(_, g)| g).collect::<Vec<_>>();
+    groups.sort_by(|a, b| a.group_name.cmp(&b.group_name));
+    Ok(ExpirationPreview { before, groups })
+}
+
 pub fn expire_invitations(pool: &Pool) -> Result<(), Error> {
     let connection = pool.get()?;
     let expires_before = Utc::now().naive_utc();
@@ -1,16 +1,22 @@
-use crate::cis::operations::add_group_to_profile;
+use crate::cis::operations::ProfilePublisher;
 use crate::db::internal;
 use crate::db::logs::LogContext;
 use crate::db::model::Group;
 use crate::db::operations;
+use crate::db::operations::models::Grace;
 use crate::db::operations::models::GroupUpdate;
+use crate::db::operations::models::GroupUpdateStatus;
 use crate::db::operations::models::GroupWithTermsFlag;
 use crate::db::operations::models::NewGroup;
 use crate::db::operations::models::PaginatedGroupsLists;
+use crate::db::operations::models::Reminder;
 use crate::db::operations::models::SortGroupsBy;
+use crate::db::operations::models::TrustChangeMember;
+use crate::db::operations::models::TrustChangeStatus;
 use crate::db::types::TrustType;
 use crate::db::Pool;
 use crate::error::PacksError;
+use crate::mail::manager::send_email;
 use crate::mail::manager::send_emails;
 use crate::mail::templates::Template;
 use crate::rules::engine::CREATE_GROUP;
@@ -18,13 +24,18 @@ use crate::rules::engine::HOST_IS_GROUP_ADMIN;
 use crate::rules::engine::ONLY_ADMINS;
 use crate::rules::RuleContext;
 use crate::user::User;
-use cis_client::AsyncCisClientTrait;
+use crate::utils::to_expiration_ts;
+use chrono::Utc;
 use diesel::pg::PgConnection;
+use diesel::Connection;
 use dino_park_gate::scope::ScopeAndUser;
 use failure::Error;
+use log::error;
 use std::convert::TryFrom;
 use std::sync::Arc;
 
+const TRUST_CHANGE_MAX_DAYS: i32 = 90;
+
 fn add_new_group_db(
     connection: &PgConnection,
     new_group: NewGroup,
@@ -42,7 +53,7 @@ pub async fn add_new_group(
     pool: &Pool,
     scope_and_user: &ScopeAndUser,
     new_group: NewGroup,
-    cis_client: Arc<impl AsyncCisClientTrait>,
+    publisher: Arc<impl ProfilePublisher>,
 ) -> Result<(), Error> {
     let connection = pool.get()?;
     let user_profile =
@@ -54,17 +65,22 @@ pub async fn add_new_group(
         &new_group.name,
         &user.user_uuid,
     ))?;
+    let user_uuid = user.user_uuid;
     let new_group_name = new_group.name.clone();
-    add_new_group_db(&connection, new_group, user).map_err(|_| PacksError::GroupNameExists)?;
+    let entry = connection.transaction::<_, Error, _>(|| {
+        add_new_group_db(&connection, new_group, user).map_err(|_| PacksError::GroupNameExists)?;
+        operations::outbox::enqueue_add_group(&connection, &user_uuid, &new_group_name)
+    })?;
     drop(connection);
-    add_group_to_profile(cis_client, new_group_name, user_profile.profile).await
+    operations::outbox::deliver(pool, publisher, entry).await;
+    Ok(())
 }
 
 pub async fn delete_group(
     pool: &Pool,
     scope_and_user: &ScopeAndUser,
     group_name: &str,
-    cis_client: Arc<impl AsyncCisClientTrait>,
+    publisher: Arc<impl ProfilePublisher>,
 ) -> Result<(), Error> {
     let connection = pool.get()?;
     let host = internal::user::user_by_id(&connection, &scope_and_user.user_id)?;
@@ -82,18 +98,11 @@ pub async fn delete_group(
         scope_and_user,
         group_name,
         &members,
-        Arc::clone(&cis_client),
-    )
-    .await?;
-    operations::members::remove(
-        &pool,
-        &scope_and_user,
-        &group_name,
-        &host,
-        &host,
-        cis_client,
+        Arc::clone(&publisher),
     )
     .await?;
+    operations::members::remove(&pool, &scope_and_user, &group_name, &host, &host, publisher)
+        .await?;
     let connection = pool.get()?;
     internal::group::delete_group(&host.user_uuid, &connection, &group_name)?;
     let host_profile = internal::user::slim_user_profile_by_uuid(&connection, &host.user_uuid)?;
@@ -104,37 +113,241 @@ pub async fn delete_group(
     Ok(())
 }
 
+fn check_stage_days(stage_days: Option<i32>) -> Result<(), PacksError> {
+    match stage_days {
+        Some(days) if !(1..=TRUST_CHANGE_MAX_DAYS).contains(&days) => {
+            Err(PacksError::InvalidTrustChange)
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Removes all members below `trust` and changes the trust of the group. Any staged trust
+/// change for the group is dropped.
+async fn change_trust(
+    pool: &Pool,
+    host: &User,
+    group_name: &str,
+    trust: &TrustType,
+    publisher: Arc<impl ProfilePublisher>,
+) -> Result<Vec<TrustChangeMember>, Error> {
+    let connection = pool.get()?;
+    let group = internal::group::get_group(&connection, group_name)?;
+    let to_delete = internal::trust::members_below(&connection, group.id, trust)?;
+    drop(connection);
+    let users = to_delete
+        .iter()
+        .map(|p| User {
+            user_uuid: p.user_uuid,
+        })
+        .collect::<Vec<_>>();
+    operations::members::remove_members_unchecked(pool, host, group_name, &users, publisher)
+        .await?;
+    let connection = pool.get()?;
+    connection.transaction::<_, Error, _>(|| {
+        internal::group::update_group_trust(&host.user_uuid, &connection, group_name, trust)?;
+        internal::trust::delete_for_group(&connection, group.id)
+    })?;
+    Ok(to_delete.into_iter().map(TrustChangeMember::from).collect())
+}
+
 pub async fn update_group_trust(
     pool: &Pool,
     scope_and_user: &ScopeAndUser,
     group_name: &str,
     trust: &TrustType,
-    cis_client: Arc<impl AsyncCisClientTrait>,
-) -> Result<(), Error> {
+    publisher: Arc<impl ProfilePublisher>,
+) -> Result<TrustChangeStatus, Error> {
     let connection = pool.get()?;
-    let to_delete =
-        internal::member::get_members_by_trust_less_than(&connection, group_name, trust)?;
+    let host = internal::user::user_by_id(&connection, &scope_and_user.user_id)?;
+    ONLY_ADMINS.run(&RuleContext::minimal(
+        pool,
+        scope_and_user,
+        group_name,
+        &host.user_uuid,
+    ))?;
     drop(connection);
-    operations::members::remove_members_silent(
+    let members = change_trust(pool, &host, group_name, trust, publisher).await?;
+    Ok(TrustChangeStatus {
+        trust: *trust,
+        effective: None,
+        members,
+    })
+}
+
+/// Lists the members who would be removed by changing the trust of the group without changing
+/// anything.
+pub fn trust_change_preview(
+    pool: &Pool,
+    scope_and_user: &ScopeAndUser,
+    group_name: &str,
+    trust: &TrustType,
+    stage_days: Option<i32>,
+) -> Result<TrustChangeStatus, Error> {
+    check_stage_days(stage_days)?;
+    let connection = pool.get()?;
+    let host = internal::user::user_by_id(&connection, &scope_and_user.user_id)?;
+    ONLY_ADMINS.run(&RuleContext::minimal(
         pool,
         scope_and_user,
         group_name,
-        &to_delete,
-        cis_client,
-    )
-    .await?;
+        &host.user_uuid,
+    ))?;
+    let group = internal::group::get_group(&connection, group_name)?;
+    let members = internal::trust::members_below(&connection, group.id, trust)?;
+    Ok(TrustChangeStatus {
+        trust: *trust,
+        effective: stage_days.map(to_expiration_ts),
+        members: members.into_iter().map(TrustChangeMember::from).collect(),
+    })
+}
+
+/// Stages a trust change which is applied after `stage_days` unless it gets cancelled. Members
+/// who would be removed and the curators of the group are notified.
+pub fn stage_group_trust(
+    pool: &Pool,
+    scope_and_user: &ScopeAndUser,
+    group_name: &str,
+    trust: &TrustType,
+    stage_days: i32,
+) -> Result<TrustChangeStatus, Error> {
+    check_stage_days(Some(stage_days))?;
+    let connection = pool.get()?;
+    let host = internal::user::user_by_id(&connection, &scope_and_user.user_id)?;
+    ONLY_ADMINS.run(&RuleContext::minimal(
+        pool,
+        scope_and_user,
+        group_name,
+        &host.user_uuid,
+    ))?;
+    let group = internal::group::get_group(&connection, group_name)?;
+    let change = internal::trust::stage(
+        &connection,
+        &host.user_uuid,
+        group.id,
+        trust,
+        to_expiration_ts(stage_days),
+    )?;
+    let members = internal::trust::members_below(&connection, group.id, trust)?;
+    if !members.is_empty() {
+        let template = Template::MemberTrustChange(group.name.clone(), stage_days);
+        for member in &members {
+            send_email(member.email.clone(), &template);
+        }
+        let bcc = internal::member::get_curator_emails(&connection, group.id)?;
+        send_emails(
+            bcc,
+            &Template::HostTrustChange(group.name.clone(), members.len(), stage_days),
+        );
+    }
+    Ok(TrustChangeStatus {
+        trust: change.trust,
+        effective: Some(change.effective),
+        members: members.into_iter().map(TrustChangeMember::from).collect(),
+    })
+}
+
+pub fn staged_group_trust(
+    pool: &Pool,
+    scope_and_user: &ScopeAndUser,
+    group_name: &str,
+) -> Result<Option<TrustChangeStatus>, Error> {
+    let connection = pool.get()?;
+    let host = internal::user::user_by_id(&connection, &scope_and_user.user_id)?;
+    ONLY_ADMINS.run(&RuleContext::minimal(
+        pool,
+        scope_and_user,
+        group_name,
+        &host.user_uuid,
+    ))?;
+    let group = internal::group::get_group(&connection, group_name)?;
+    match internal::trust::staged(&connection, group.id)? {
+        Some(change) => {
+            let members = internal::trust::members_below(&connection, group.id, &change.trust)?;
+            Ok(Some(TrustChangeStatus {
+                trust: change.trust,
+                effective: Some(change.effective),
+                members: members.into_iter().map(TrustChangeMember::from).collect(),
+            }))
+        }
+        None => Ok(None),
+    }
+}
+
+pub fn cancel_group_trust(
+    pool: &Pool,
+    scope_and_user: &ScopeAndUser,
+    group_name: &str,
+) -> Result<(), Error> {
     let connection = pool.get()?;
     let host = internal::user::user_by_id(&connection, &scope_and_user.user_id)?;
-    internal::group::update_group_trust(&host.user_uuid, &connection, group_name, trust)?;
+    ONLY_ADMINS.run(&RuleContext::minimal(
+        pool,
+        scope_and_user,
+        group_name,
+        &host.user_uuid,
+    ))?;
+    let group = internal::group::get_group(&connection, group_name)?;
+    let change = match internal::trust::staged(&connection, group.id)? {
+        Some(change) => change,
+        None => return Ok(()),
+    };
+    // the same members were notified when the change was staged
+    let members = internal::trust::members_below(&connection, group.id, &change.trust)?;
+    if internal::trust::cancel(&connection, &host.user_uuid, group.id)? && !members.is_empty() {
+        let template = Template::MemberTrustChangeCancelled(group.name.clone());
+        for member in &members {
+            send_email(member.email.clone(), &template);
+        }
+        let bcc = internal::member::get_curator_emails(&connection, group.id)?;
+        send_emails(bcc, &Template::HostTrustChangeCancelled(group.name.clone()));
+    }
     Ok(())
 }
 
+/// Applies all staged trust changes which became effective on behalf of the admin who staged
+/// them. A failing change is logged and retried on the next run. Returns the number of applied
+/// changes.
+pub async fn apply_staged_trust_changes(
+    pool: &Pool,
+    publisher: Arc<impl ProfilePublisher>,
+) -> Result<usize, Error> {
+    let connection = pool.get()?;
+    let due = internal::trust::due(&connection, Utc::now().naive_utc())?;
+    drop(connection);
+    let mut applied = 0;
+    for (change, group) in due {
+        let host = User {
+            user_uuid: change.created_by,
+        };
+        match change_trust(
+            pool,
+            &host,
+            &group.name,
+            &change.trust,
+            Arc::clone(&publisher),
+        )
+        .await
+        {
+            Ok(_) => applied += 1,
+            Err(e) => error!(
+                "unable to apply staged trust change of {}: {}",
+                group.name, e
+            ),
+        }
+    }
+    Ok(applied)
+}
+
+/// Updates the group and optionally applies its expiration to the current members. With
+/// `preview` only the number of affected members is returned.
 pub fn update_group(
     pool: &Pool,
     scope_and_user: &ScopeAndUser,
     group_name: String,
     group_update: GroupUpdate,
-) -> Result<(), Error> {
+    preview: bool,
+) -> Result<GroupUpdateStatus, Error> {
     let connection = pool.get()?;
     let host = internal::user::user_by_id(&connection, &scope_and_user.user_id)?;
     HOST_IS_GROUP_ADMIN.run(&RuleContext::minimal(
@@ -143,9 +356,40 @@ pub fn update_group(
         &group_name,
         &host.user_uuid,
     ))?;
-    internal::group::update_group(&host.user_uuid, &connection, group_name, group_update)
-        .map(|_| ())
-        .map_err(Into::into)
+    let apply = group_update.apply_expiration;
+    if preview {
+        let group = internal::group::get_group(&connection, &group_name)?;
+        let group_expiration = match group_update.group_expiration {
+            Some(group_expiration) => group_expiration,
+            None => group.group_expiration,
+        };
+        let affected = match (apply, group_expiration.filter(|days| *days > 0)) {
+            (Some(apply), Some(days)) => internal::member::expiration_affected(
+                &connection,
+                group.id,
+                to_expiration_ts(days),
+                apply,
+            )?
+            .len(),
+            _ => 0,
+        };
+        return Ok(GroupUpdateStatus { affected });
+    }
+    connection.transaction::<_, Error, _>(|| {
+        let group =
+            internal::group::update_group(&host.user_uuid, &connection, group_name, group_update)?;
+        let affected = match (apply, group.group_expiration.filter(|days| *days > 0)) {
+            (Some(apply), Some(days)) => internal::member::apply_expiration(
+                &connection,
+                &host.user_uuid,
+                group.id,
+                to_expiration_ts(days),
+                apply,
+            )?,
+            _ => 0,
+        };
+        Ok(GroupUpdateStatus { affected })
+    })
 }
 
 pub fn get_group(pool: &Pool, group_name: &str) -> Result<Group, Error> {
@@ -161,6 +405,21 @@ pub fn get_group_with_terms_flag(
     internal::group::get_group_with_terms_flag(&connection, group_name)
 }
 
+pub fn get_reminders(pool: &Pool, group_id: i32) -> Result<Vec<Reminder>, Error> {
+    let connection = pool.get()?;
+    internal::expiration::reminders_for_group(&connection, group_id)
+}
+
+pub fn get_grace(pool: &Pool, group_id: i32) -> Result<Option<Grace>, Error> {
+    let connection = pool.get()?;
+    internal::grace::grace_for_group(&connection, group_id)
+}
+
+pub fn self_renewal_enabled(pool: &Pool, group_id: i32) -> Result<bool, Error> {
+    let connection = pool.get()?;
+    internal::renewal::self_renewal_enabled(&connection, group_id)
+}
+
 pub fn list_groups(
     pool: &Pool,
     filter: Option<String>,
@@ -0,0 +1,87 @@
+use crate::cis::operations::ProfilePublisher;
+use crate::db::internal;
+use crate::db::operations;
+use crate::db::Pool;
+use crate::user::User;
+use chrono::Duration;
+use chrono::Utc;
+use cis_profile::schema::Profile;
+use diesel::Connection;
+use failure::Error;
+use log::info;
+use std::sync::Arc;
+use uuid::Uuid;
+
+fn is_inactive_profile(profile: &Profile) -> bool {
+    profile.active.value == Some(false)
+}
+
+/// Suspends all memberships of a profile which became inactive and restores them once it gets
+/// reactivated. Suspended memberships are removed from the profile in CIS.
+pub async fn update_activity(
+    pool: &Pool,
+    user_uuid: &Uuid,
+    profile: &Profile,
+    publisher: Arc<impl ProfilePublisher>,
+) -> Result<(), Error> {
+    let host_uuid = User::default().user_uuid;
+    let inactive = is_inactive_profile(profile);
+    let connection = pool.get()?;
+    if inactive == internal::inactive::is_inactive(&connection, user_uuid)? {
+        return Ok(());
+    }
+    let entry = connection.transaction::<_, Error, _>(|| {
+        if inactive {
+            let group_names =
+                internal::inactive::suspend_memberships(&connection, &host_uuid, user_uuid)?;
+            info!(
+                "suspended {} memberships of inactive user {}",
+                group_names.len(),
+                user_uuid
+            );
+            if group_names.is_empty() {
+                return Ok(None);
+            }
+            operations::outbox::enqueue_remove_groups(&connection, user_uuid, group_names).map(Some)
+        } else {
+            let group_names =
+                internal::inactive::restore_memberships(&connection, &host_uuid, user_uuid)?;
+            info!(
+                "restored {} memberships of reactivated user {}",
+                group_names.len(),
+                user_uuid
+            );
+            if group_names.is_empty() {
+                return Ok(None);
+            }
+            operations::outbox::enqueue_add_groups(&connection, user_uuid, group_names).map(Some)
+        }
+    })?;
+    drop(connection);
+    if let Some(entry) = entry {
+        operations::outbox::deliver(pool, publisher, entry).await;
+    }
+    Ok(())
+}
+
+/// Revokes the suspended memberships of users which have been inactive for more than
+/// `grace_days`. Returns the number of revoked memberships.
+pub fn revoke_suspended(pool: &Pool, grace_days: i64) -> Result<usize, Error> {
+    let host = User::default();
+    let before = (Utc::now() - Duration::days(grace_days)).naive_utc();
+    let connection = pool.get()?;
+    let revoked = connection.transaction::<_, Error, _>(|| {
+        internal::inactive::revoke_suspended(&connection, &host.user_uuid, before)
+    })?;
+    for (user_uuid, group_name) in &revoked {
+        operations::newsletters::unsubscribe_member(
+            &connection,
+            group_name,
+            &User {
+                user_uuid: *user_uuid,
+            },
+        );
+    }
+    info!("revoked {} suspended memberships", revoked.len());
+    Ok(revoked.len())
+}
@@ -1,8 +1,11 @@
-use crate::cis::operations::add_group_to_profile;
+use crate::cis::operations::ProfilePublisher;
 use crate::db::internal;
 use crate::db::internal::invitation::*;
 use crate::db::logs::log_comment_body;
+use crate::db::operations;
 use crate::db::operations::models::*;
+use crate::db::operations::newsletters::subscribe_member;
+use crate::db::operations::notifications::notify;
 use crate::db::Pool;
 use crate::mail::manager::send_email;
 use crate::mail::templates::Template;
@@ -10,7 +13,7 @@ use crate::rules::engine::*;
 use crate::rules::RuleContext;
 use crate::user::User;
 use chrono::NaiveDateTime;
-use cis_client::AsyncCisClientTrait;
+use diesel::Connection;
 use dino_park_gate::scope::ScopeAndUser;
 use dino_park_trust::Trust;
 use failure::Error;
@@ -116,14 +119,18 @@ pub fn invite_member(
     if let Ok(Some(invitation_text)) =
         internal::invitation::get_invitation_text(&connection, group_name)
     {
-        send_email(
+        notify(
+            &connection,
             p.email,
-            &Template::CustomInvitation(group_name.to_owned(), invitation_text.body),
-        );
+            Template::CustomInvitation(group_name.to_owned(), invitation_text.body),
+        )
     } else {
-        send_email(p.email, &Template::Invitation(group_name.to_owned()));
+        notify(
+            &connection,
+            p.email,
+            Template::Invitation(group_name.to_owned()),
+        )
     }
-    Ok(())
 }
 
 pub fn pending_invitations_count(
@@ -186,7 +193,7 @@ pub async fn accept_invitation(
     scope_and_user: &ScopeAndUser,
     group_name: &str,
     user: &User,
-    cis_client: Arc<impl AsyncCisClientTrait>,
+    publisher: Arc<impl ProfilePublisher>,
 ) -> Result<(), Error> {
     CURRENT_USER_CAN_JOIN.run(&RuleContext::minimal(
         pool,
@@ -195,10 +202,14 @@ pub async fn accept_invitation(
         &Uuid::default(),
     ))?;
     let connection = pool.get()?;
-    let user_profile = internal::user::user_profile_by_uuid(&connection, &user.user_uuid)?;
-    accept(&connection, group_name, user)?;
+    let entry = connection.transaction::<_, Error, _>(|| {
+        accept(&connection, group_name, user)?;
+        operations::outbox::enqueue_add_group(&connection, &user.user_uuid, group_name)
+    })?;
+    subscribe_member(&connection, group_name, user);
     drop(connection);
-    add_group_to_profile(cis_client, group_name.to_owned(), user_profile.profile).await
+    operations::outbox::deliver(pool, publisher, entry).await;
+    Ok(())
 }
 
 pub fn set_invitation_email(
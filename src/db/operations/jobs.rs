@@ -0,0 +1,10 @@
+use crate::db::internal;
+use crate::db::operations::models::DisplayJobRun;
+use crate::db::Pool;
+use failure::Error;
+
+/// The latest run of every job which ran at least once.
+pub fn job_runs(pool: &Pool) -> Result<Vec<DisplayJobRun>, Error> {
+    let connection = pool.get()?;
+    internal::job::runs(&connection).map(|runs| runs.into_iter().map(Into::into).collect())
+}
@@ -1,5 +1,4 @@
-use crate::cis::operations::add_group_to_profile;
-use crate::cis::operations::remove_group_from_profile;
+use crate::cis::operations::ProfilePublisher;
 use crate::db::internal;
 use crate::db::logs::add_to_comment_body;
 use crate::db::operations;
@@ -7,6 +6,7 @@ use crate::db::operations::models::*;
 use crate::db::schema;
 use crate::db::schema::groups::dsl as groups;
 use crate::db::types::*;
+use crate::db::users::UserProfileSlim;
 use crate::db::Pool;
 use crate::error;
 use crate::error::PacksError;
@@ -21,7 +21,6 @@ use crate::rules::RuleContext;
 use crate::user::User;
 use chrono::NaiveDateTime;
 use chrono::Utc;
-use cis_client::AsyncCisClientTrait;
 use diesel::dsl::count;
 use diesel::prelude::*;
 use dino_park_gate::scope::ScopeAndUser;
@@ -140,7 +139,7 @@ pub async fn add(
     host: &User,
     user: &User,
     expiration: Option<i32>,
-    cis_client: Arc<impl AsyncCisClientTrait>,
+    publisher: Arc<impl ProfilePublisher>,
 ) -> Result<(), Error> {
     ONLY_ADMINS.run(&RuleContext::minimal(
         &pool.clone(),
@@ -154,10 +153,14 @@ pub async fn add(
     } else {
         expiration
     };
-    internal::member::add_to_group(&connection, &group_name, &host, &user, expiration)?;
-    let user_profile = internal::user::user_profile_by_uuid(&connection, &user.user_uuid)?;
+    let entry = connection.transaction::<_, Error, _>(|| {
+        internal::member::add_to_group(&connection, &group_name, &host, &user, expiration)?;
+        operations::outbox::enqueue_add_group(&connection, &user.user_uuid, group_name)
+    })?;
+    operations::newsletters::subscribe_member(&connection, group_name, user);
     drop(connection);
-    add_group_to_profile(cis_client, group_name.to_owned(), user_profile.profile).await
+    operations::outbox::deliver(pool, publisher, entry).await;
+    Ok(())
 }
 
 pub async fn remove_members_silent(
@@ -165,7 +168,7 @@ pub async fn remove_members_silent(
     scope_and_user: &ScopeAndUser,
     group_name: &str,
     members: &[User],
-    cis_client: Arc<impl AsyncCisClientTrait>,
+    publisher: Arc<impl ProfilePublisher>,
 ) -> Result<(), Error> {
     let connection = pool.get()?;
     let host = internal::user::user_by_id(&connection, &scope_and_user.user_id)?;
@@ -176,27 +179,40 @@ pub async fn remove_members_silent(
         &host.user_uuid,
     ))?;
     drop(connection);
+    remove_members_unchecked(pool, &host, group_name, members, publisher).await
+}
+
+/// Removes `members` from the group on behalf of `host` without checking the host's
+/// permissions and without notifying the members.
+pub async fn remove_members_unchecked(
+    pool: &Pool,
+    host: &User,
+    group_name: &str,
+    members: &[User],
+    publisher: Arc<impl ProfilePublisher>,
+) -> Result<(), Error> {
+    let group_names = [group_name];
     let v = members
         .iter()
         .map(|user| {
             let user_uuid = user.user_uuid;
             log::debug!("removing {} for {}", &group_name, user_uuid);
-            operations::members::remove_silent(
-                &pool,
-                &scope_and_user,
-                &group_name,
-                &host,
-                &user,
-                Arc::clone(&cis_client),
-            )
-            .map_ok(move |k| {
-                log::debug!("removed {} for {}", &group_name, user_uuid);
-                k
-            })
-            .map_err(move |e| {
-                log::warn!("failed to remove {} for {}: {}", &group_name, user_uuid, e);
-                e
-            })
+            let remove_groups = RemoveGroups {
+                user: *user,
+                group_names: &group_names,
+                force: true,
+                notify: false,
+                batch: true,
+            };
+            revoke_membership(pool, remove_groups, host, Arc::clone(&publisher), None)
+                .map_ok(move |k| {
+                    log::debug!("removed {} for {}", &group_name, user_uuid);
+                    k
+                })
+                .map_err(move |e| {
+                    log::warn!("failed to remove {} for {}: {}", &group_name, user_uuid, e);
+                    e
+                })
         })
         .collect::<Vec<_>>();
     log::info!("deleting {} members", v.len());
@@ -211,7 +227,7 @@ pub async fn revoke_memberships_by_trust<'a>(
     mut remove_groups: RemoveGroups<'a>,
     host: &User,
     trust: TrustType,
-    cis_client: Arc<impl AsyncCisClientTrait>,
+    publisher: Arc<impl ProfilePublisher>,
     comment: Option<Value>,
 ) -> Result<(), Error> {
     let connection = pool.get()?;
@@ -230,38 +246,88 @@ pub async fn revoke_memberships_by_trust<'a>(
             comment.clone(),
         )?;
     }
-    let all_groups = internal::group::groups_for_user(&connection, &remove_groups.user.user_uuid)?;
-    let mut revoked_groups = all_groups
+    let revoked_groups = groups_above_trust(
+        &connection,
+        &remove_groups.user,
+        trust,
+        remove_groups.group_names,
+    )?;
+    let revoked_groups = revoked_groups
         .iter()
-        .filter(|g| trust < g.trust)
-        .map(|g| g.name.as_str())
-        .chain(remove_groups.group_names.iter().copied())
+        .map(String::as_str)
         .collect::<Vec<_>>();
-    revoked_groups.dedup();
     remove_groups.group_names = &revoked_groups;
     remove_groups.force = true;
 
     drop(connection);
-    _revoke_membership(pool, remove_groups, host, cis_client, comment).await
+    _revoke_membership(pool, remove_groups, host, publisher, comment).await
+}
+
+/// All groups of `user` requiring more than `trust` together with `group_names`.
+fn groups_above_trust(
+    connection: &PgConnection,
+    user: &User,
+    trust: TrustType,
+    group_names: &[&str],
+) -> Result<Vec<String>, Error> {
+    let all_groups = internal::group::groups_for_user(connection, &user.user_uuid)?;
+    let mut revoked_groups = all_groups
+        .into_iter()
+        .filter(|g| trust < g.trust)
+        .map(|g| g.name)
+        .chain(group_names.iter().map(|g| g.to_string()))
+        .collect::<Vec<_>>();
+    revoked_groups.dedup();
+    Ok(revoked_groups)
+}
+
+/// Dropping a nda membership of a non staff user revokes all groups requiring more than
+/// authenticated trust.
+fn revokes_nda(
+    connection: &PgConnection,
+    user: &User,
+    group_names: &[&str],
+) -> Result<bool, Error> {
+    Ok(group_names
+        .iter()
+        .any(|group_name| is_nda_group(*group_name))
+        && internal::user::user_trust(connection, &user.user_uuid)? != TrustType::Staff)
+}
+
+/// The groups `revoke_membership` removes when revoking `group_names` from `user`.
+pub fn revoked_groups(
+    connection: &PgConnection,
+    user: &User,
+    group_names: &[&str],
+) -> Result<Vec<String>, Error> {
+    if revokes_nda(connection, user, group_names)? {
+        groups_above_trust(connection, user, TrustType::Authenticated, group_names)
+    } else {
+        Ok(group_names.iter().map(|g| g.to_string()).collect())
+    }
+}
+
+/// The mails sent for revoking the membership of `group_name` with `notify` set.
+pub fn revocation_notifications(
+    user_profile: &UserProfileSlim,
+    group_name: &str,
+) -> Vec<(String, Template)> {
+    vec![(
+        user_profile.email.clone(),
+        Template::DeleteMember(group_name.to_string()),
+    )]
 }
 
 pub async fn revoke_membership<'a>(
     pool: &Pool,
     remove_groups: RemoveGroups<'a>,
     host: &User,
-    cis_client: Arc<impl AsyncCisClientTrait>,
+    publisher: Arc<impl ProfilePublisher>,
     comment: Option<Value>,
 ) -> Result<(), Error> {
     let connection = pool.get()?;
-    let is_staff =
-        internal::user::user_trust(&connection, &remove_groups.user.user_uuid)? == TrustType::Staff;
     // are we dropping nda membership -> remove according groups and invitations
-    if remove_groups
-        .group_names
-        .iter()
-        .any(|group_name| is_nda_group(*group_name))
-        && !is_staff
-    {
+    if revokes_nda(&connection, &remove_groups.user, remove_groups.group_names)? {
         let comment = add_to_comment_body("trust", "nda revoked", comment);
         drop(connection);
         revoke_memberships_by_trust(
@@ -269,20 +335,20 @@ pub async fn revoke_membership<'a>(
             remove_groups,
             host,
             TrustType::Authenticated,
-            cis_client,
+            publisher,
             comment,
         )
         .await
     } else {
         drop(connection);
-        _revoke_membership(pool, remove_groups, host, cis_client, comment).await
+        _revoke_membership(pool, remove_groups, host, publisher, comment).await
     }
 }
 async fn _revoke_membership<'a>(
     pool: &Pool,
     remove_groups: RemoveGroups<'a>,
     host: &User,
-    cis_client: Arc<impl AsyncCisClientTrait>,
+    publisher: Arc<impl ProfilePublisher>,
     comment: Option<Value>,
 ) -> Result<(), Error> {
     let RemoveGroups {
@@ -290,41 +356,62 @@ async fn _revoke_membership<'a>(
         group_names,
         force,
         notify,
+        batch,
     } = remove_groups;
     if group_names.is_empty() {
         return Ok(());
     }
     let exit_on_error = group_names.len() == 1;
     let connection = pool.get()?;
-    let user_profile = internal::user::user_profile_by_uuid(&connection, &user.user_uuid)?;
-    drop(connection);
-    log::debug!("removing group from profile");
-    remove_group_from_profile(cis_client, group_names, user_profile.profile).await?;
-    log::debug!("removed group from profile");
-    let connection = pool.get()?;
-    for group_name in group_names {
-        if let Err(e) = db_leave(
-            &host.user_uuid,
-            &connection,
-            &group_name,
-            &user,
-            force,
-            comment.clone(),
-        ) {
-            if exit_on_error {
-                return Err(e);
+    let user_profile = internal::user::slim_user_profile_by_uuid(&connection, &user.user_uuid)?;
+    let (removed, entry) = connection.transaction::<_, Error, _>(|| {
+        let mut removed: Vec<String> = Vec::with_capacity(group_names.len());
+        for group_name in group_names {
+            // each group is left in a savepoint to keep successful removals on partial failure
+            if let Err(e) = connection.transaction(|| {
+                db_leave(
+                    &host.user_uuid,
+                    &connection,
+                    &group_name,
+                    &user,
+                    force,
+                    comment.clone(),
+                )
+            }) {
+                if exit_on_error {
+                    return Err(e);
+                } else {
+                    error!(
+                        "({}) failed to revoke group membership of group {} for {}",
+                        e, &group_name, user.user_uuid
+                    );
+                }
             } else {
-                error!(
-                    "({}) failed to revoke group membership of group {} for {}",
-                    e, &group_name, user.user_uuid
-                );
+                removed.push(group_name.to_string());
             }
         }
-        if notify {
-            send_email(
-                user_profile.email.clone(),
-                &Template::DeleteMember(group_name.to_string()),
-            );
+        if removed.is_empty() {
+            Ok((removed, None))
+        } else if batch {
+            operations::outbox::batch_remove_groups(&connection, &user.user_uuid, removed.clone())
+                .map(|_| (removed, None))
+        } else {
+            operations::outbox::enqueue_remove_groups(&connection, &user.user_uuid, removed.clone())
+                .map(|entry| (removed, Some(entry)))
+        }
+    })?;
+    for group_name in &removed {
+        operations::newsletters::unsubscribe_member(&connection, group_name, &user);
+    }
+    drop(connection);
+    if let Some(entry) = entry {
+        operations::outbox::deliver(pool, publisher, entry).await;
+    }
+    if notify {
+        for group_name in group_names {
+            for (email, template) in revocation_notifications(&user_profile, group_name) {
+                send_email(email, &template);
+            }
         }
     }
     Ok(())
@@ -336,7 +423,7 @@ pub async fn remove_silent(
     group_name: &str,
     host: &User,
     user: &User,
-    cis_client: Arc<impl AsyncCisClientTrait>,
+    publisher: Arc<impl ProfilePublisher>,
 ) -> Result<(), Error> {
     REMOVE_MEMBER.run(&RuleContext::minimal(
         &pool,
@@ -349,8 +436,9 @@ pub async fn remove_silent(
         group_names: &[group_name],
         force: true,
         notify: false,
+        batch: false,
     };
-    revoke_membership(pool, remove_groups, host, cis_client, None).await
+    revoke_membership(pool, remove_groups, host, publisher, None).await
 }
 
 pub async fn remove(
@@ -359,7 +447,7 @@ pub async fn remove(
     group_name: &str,
     host: &User,
     user: &User,
-    cis_client: Arc<impl AsyncCisClientTrait>,
+    publisher: Arc<impl ProfilePublisher>,
 ) -> Result<(), Error> {
     REMOVE_MEMBER.run(&RuleContext::minimal(
         &pool,
@@ -372,8 +460,9 @@ pub async fn remove(
         group_names: &[group_name],
         force: true,
         notify: true,
+        batch: false,
     };
-    revoke_membership(pool, remove_groups, host, cis_client, None).await
+    revoke_membership(pool, remove_groups, host, publisher, None).await
 }
 
 pub async fn leave(
@@ -381,7 +470,7 @@ pub async fn leave(
     scope_and_user: &ScopeAndUser,
     group_name: &str,
     force: bool,
-    cis_client: Arc<impl AsyncCisClientTrait>,
+    publisher: Arc<impl ProfilePublisher>,
 ) -> Result<(), Error> {
     let connection = pool.get()?;
     let user = internal::user::user_by_id(&connection, &scope_and_user.user_id)?;
@@ -391,17 +480,19 @@ pub async fn leave(
         group_names: &[group_name],
         force,
         notify: true,
+        batch: false,
     };
-    revoke_membership(pool, remove_groups, &host, cis_client, None).await
+    revoke_membership(pool, remove_groups, &host, publisher, None).await
 }
 
-pub fn renew(
+pub async fn renew(
     pool: &Pool,
     scope_and_user: &ScopeAndUser,
     group_name: &str,
     host: &User,
     user: &User,
     expiration: Option<i32>,
+    publisher: Arc<impl ProfilePublisher>,
 ) -> Result<(), Error> {
     RENEW_MEMBER.run(&RuleContext::minimal_with_member_uuid(
         pool,
@@ -411,7 +502,46 @@ pub fn renew(
         &user.user_uuid,
     ))?;
     let connection = pool.get()?;
-    internal::member::renew(&host.user_uuid, &connection, group_name, user, expiration)
+    internal::member::renew(&host.user_uuid, &connection, group_name, user, expiration)?;
+    let group = internal::group::get_group(&connection, group_name)?;
+    drop(connection);
+    operations::expirations::restore_access(pool, publisher, &user.user_uuid, group.id).await
+}
+
+/// Lets a member extend their own expiring membership by the group's default expiration. Only
+/// available in groups with self-renewal enabled and once per expiration.
+pub async fn self_renew(
+    pool: &Pool,
+    scope_and_user: &ScopeAndUser,
+    group_name: &str,
+    publisher: Arc<impl ProfilePublisher>,
+) -> Result<(), Error> {
+    let connection = pool.get()?;
+    let user = internal::user::user_by_id(&connection, &scope_and_user.user_id)?;
+    let group = internal::group::get_group(&connection, group_name)?;
+    if !internal::renewal::self_renewal_enabled(&connection, group.id)? {
+        return Err(PacksError::SelfRenewalNotAllowed.into());
+    }
+    let current = internal::member::get_membership(&connection, group.id, &user.user_uuid)?
+        .and_then(|m| m.expiration)
+        .ok_or(PacksError::SelfRenewalNotAllowed)?;
+    let window = internal::expiration::reminders_for_group(&connection, group.id)?
+        .iter()
+        .map(|r| r.days)
+        .max()
+        .unwrap_or_default();
+    let expiration = internal::expiration::map_expiration(None, group.group_expiration)
+        .ok_or(PacksError::SelfRenewalNotAllowed)?;
+    if current > (Utc::now() + chrono::Duration::days(window.into())).naive_utc()
+        || expiration <= current
+    {
+        return Err(PacksError::SelfRenewalNotAllowed.into());
+    }
+    connection.transaction::<_, Error, _>(|| {
+        internal::renewal::self_renew(&connection, group.id, &user.user_uuid, current, expiration)
+    })?;
+    drop(connection);
+    operations::expirations::restore_access(pool, publisher, &user.user_uuid, group.id).await
 }
 
 pub fn role_for_current(
@@ -1,10 +1,22 @@
 pub mod admins;
+pub mod broadcasts;
+pub mod calendar;
+pub mod display;
+pub mod drift;
 pub mod expirations;
 pub mod groups;
+pub mod inactive;
 pub mod invitations;
+pub mod jobs;
 pub mod logs;
 pub mod members;
 pub mod models;
+pub mod newsletters;
+pub mod notifications;
+pub mod outbox;
 pub mod requests;
+pub mod resync;
+pub mod suppressions;
 pub mod terms;
 pub mod users;
+pub mod webhooks;
@@ -1,24 +1,47 @@
+use crate::db::model::CisOutboxEntry;
+use crate::db::model::ExpirationGrace;
+use crate::db::model::ExpirationReminder;
 use crate::db::model::Group;
 use crate::db::model::GroupsList;
+use crate::db::model::JobRun;
+use crate::db::model::ResyncRun;
+use crate::db::model::SuppressedEmail;
+use crate::db::model::Webhook;
+use crate::db::model::WebhookDelivery;
 use crate::db::types::*;
+use crate::db::users::UserProfileSlim;
 use crate::error::PacksError;
+use crate::mail::templates::Template;
 use crate::user::User;
 use crate::utils::maybe_to_utc;
 use crate::utils::to_utc;
 use crate::utils::valid_group_name;
 use chrono::NaiveDateTime;
+use chrono::Utc;
 use dino_park_trust::Trust;
 use serde::Deserialize;
 use serde::Serialize;
+use serde_json::Value;
+use url::Url;
 use uuid::Uuid;
 
 const DESCRIPTION_MAX_LEN: usize = 1024;
+const BROADCAST_SUBJECT_MAX_LEN: usize = 200;
+const BROADCAST_BODY_MAX_LEN: usize = 10_000;
+const NEWSLETTER_MAX_LEN: usize = 64;
+const WEBHOOK_SECRET_MIN_LEN: usize = 16;
+const REMINDERS_MAX: usize = 5;
+const REMINDER_MAX_DAYS: i32 = 365;
+const GRACE_MAX_DAYS: i32 = 90;
+const TRUST_GRACE_MAX_DAYS: i32 = 30;
 
 pub struct RemoveGroups<'a> {
     pub user: User,
     pub group_names: &'a [&'a str],
     pub force: bool,
     pub notify: bool,
+    /// Leave the profile update to the outbox worker to combine it with other changes.
+    pub batch: bool,
 }
 
 #[derive(Clone, Debug)]
@@ -87,6 +110,11 @@ pub struct GroupUpdate {
     pub capabilities: Option<Vec<CapabilityType>>,
     #[allow(clippy::option_option)]
     pub group_expiration: Option<Option<i32>>,
+    pub reminders: Option<Vec<Reminder>>,
+    pub self_renewal: Option<bool>,
+    #[allow(clippy::option_option)]
+    pub expiration_grace: Option<Option<Grace>>,
+    pub apply_expiration: Option<ApplyExpiration>,
 }
 
 impl GroupUpdate {
@@ -96,6 +124,10 @@ impl GroupUpdate {
             self.typ.as_ref().map(|_| "typ"),
             self.capabilities.as_ref().map(|_| "capabilities"),
             self.group_expiration.as_ref().map(|_| "expiration"),
+            self.reminders.as_ref().map(|_| "reminders"),
+            self.self_renewal.as_ref().map(|_| "self_renewal"),
+            self.expiration_grace.as_ref().map(|_| "expiration_grace"),
+            self.apply_expiration.as_ref().map(|_| "apply_expiration"),
         ]
         .iter()
         .filter_map(|s| *s)
@@ -113,10 +145,119 @@ impl GroupUpdate {
         {
             return Err(PacksError::InvalidGroupData);
         }
+        if let Some(Some(grace)) = &self.expiration_grace {
+            if grace.days < 1 || grace.days > GRACE_MAX_DAYS {
+                return Err(PacksError::InvalidGroupData);
+            }
+        }
+        if let Some(reminders) = &self.reminders {
+            let mut days = reminders.iter().map(|r| r.days).collect::<Vec<_>>();
+            days.sort_unstable();
+            days.dedup();
+            if reminders.len() > REMINDERS_MAX
+                || days.len() != reminders.len()
+                || reminders.iter().any(|r| !r.valid())
+            {
+                return Err(PacksError::InvalidGroupData);
+            }
+        }
         Ok(self)
     }
 }
 
+/// How to apply the group expiration to the current members of a group.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+pub enum ApplyExpiration {
+    /// Set the expiration of members without one.
+    Missing,
+    /// Shorten expirations exceeding the group expiration.
+    Longer,
+    /// Both of the above.
+    All,
+}
+
+impl ApplyExpiration {
+    pub fn missing(self) -> bool {
+        self != ApplyExpiration::Longer
+    }
+
+    pub fn longer(self) -> bool {
+        self != ApplyExpiration::Missing
+    }
+}
+
+#[derive(Serialize)]
+pub struct GroupUpdateStatus {
+    pub affected: usize,
+}
+
+/// An expiration reminder sent `days` before a membership expires. Without a configured
+/// schedule groups fall back to [`Reminder::default_schedule`].
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Reminder {
+    pub days: i32,
+    #[serde(default)]
+    pub member: bool,
+    #[serde(default)]
+    pub host: bool,
+    #[serde(default)]
+    pub curators: bool,
+}
+
+impl Reminder {
+    pub fn default_schedule() -> Vec<Reminder> {
+        vec![
+            Reminder {
+                days: 14,
+                member: false,
+                host: true,
+                curators: false,
+            },
+            Reminder {
+                days: 7,
+                member: true,
+                host: true,
+                curators: false,
+            },
+        ]
+    }
+
+    fn valid(&self) -> bool {
+        self.days > 0
+            && self.days <= REMINDER_MAX_DAYS
+            && (self.member || self.host || self.curators)
+    }
+}
+
+impl From<ExpirationReminder> for Reminder {
+    fn from(r: ExpirationReminder) -> Self {
+        Reminder {
+            days: r.days,
+            member: r.member,
+            host: r.host,
+            curators: r.curators,
+        }
+    }
+}
+
+/// Expired memberships of groups with a grace period are kept for `days` before they are
+/// revoked. Unless `keep_access` is set they are removed from the profile right away.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Grace {
+    pub days: i32,
+    #[serde(default)]
+    pub keep_access: bool,
+}
+
+impl From<ExpirationGrace> for Grace {
+    fn from(g: ExpirationGrace) -> Self {
+        Grace {
+            days: g.days,
+            keep_access: g.keep_access,
+        }
+    }
+}
+
 #[derive(Deserialize)]
 pub struct NewGroup {
     pub name: String,
@@ -306,6 +447,7 @@ pub struct DisplayMemberAndHost {
     pub expiration: Option<NaiveDateTime>,
     pub role: RoleType,
     pub added_by: Option<DisplayHost>,
+    pub expired: bool,
 }
 
 #[derive(Serialize)]
@@ -383,12 +525,18 @@ impl DisplayMemberAndHost {
             expiration: None,
             role: m.role,
             added_by: None,
+            expired: false,
         }
     }
 }
 
 impl From<MemberAndHost> for DisplayMemberAndHost {
     fn from(m: MemberAndHost) -> Self {
+        // expired memberships are only listed while within their grace period
+        let expired = m
+            .expiration
+            .map(|e| e <= Utc::now().naive_utc())
+            .unwrap_or_default();
         DisplayMemberAndHost {
             user_uuid: m.user_uuid,
             picture: m.picture,
@@ -407,6 +555,7 @@ impl From<MemberAndHost> for DisplayMemberAndHost {
                 username: m.host_username,
                 email: m.host_email,
             }),
+            expired,
         }
     }
 }
@@ -446,6 +595,410 @@ pub struct InvitationEmail {
     pub body: Option<String>,
 }
 
+#[derive(Deserialize)]
+pub struct Broadcast {
+    pub subject: String,
+    pub body: String,
+    #[serde(default = "Broadcast::all_roles")]
+    pub roles: Vec<RoleType>,
+}
+
+impl Broadcast {
+    fn all_roles() -> Vec<RoleType> {
+        vec![RoleType::Admin, RoleType::Curator, RoleType::Member]
+    }
+
+    pub fn checked(self) -> Result<Self, PacksError> {
+        if self.subject.trim().is_empty()
+            || self.subject.len() > BROADCAST_SUBJECT_MAX_LEN
+            || self.subject.contains('\n')
+            || self.body.len() > BROADCAST_BODY_MAX_LEN
+            || self.roles.is_empty()
+        {
+            return Err(PacksError::InvalidBroadcast);
+        }
+        Ok(self)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct GroupNewsletters {
+    pub newsletters: Vec<String>,
+}
+
+impl GroupNewsletters {
+    pub fn checked(self) -> Result<Self, PacksError> {
+        if self.newsletters.iter().any(|newsletter| {
+            newsletter.is_empty()
+                || newsletter.len() > NEWSLETTER_MAX_LEN
+                || !newsletter
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        }) {
+            return Err(PacksError::InvalidNewsletter);
+        }
+        Ok(self)
+    }
+}
+
+/// Visibility of the group's membership in published profiles.
+#[derive(Serialize, Deserialize)]
+pub struct GroupDisplaySetting {
+    pub display: TrustType,
+}
+
+#[derive(Deserialize)]
+pub struct NewWebhook {
+    pub url: Url,
+    pub secret: String,
+    pub group_name: Option<String>,
+    pub events: Vec<WebhookEventType>,
+}
+
+impl NewWebhook {
+    pub fn checked(self) -> Result<Self, PacksError> {
+        if !matches!(self.url.scheme(), "http" | "https")
+            || self.secret.len() < WEBHOOK_SECRET_MIN_LEN
+            || self.events.is_empty()
+        {
+            return Err(PacksError::InvalidWebhook);
+        }
+        Ok(self)
+    }
+}
+
+#[derive(Serialize)]
+pub struct DisplayWebhook {
+    pub id: i32,
+    pub url: String,
+    pub group_name: Option<String>,
+    pub events: Vec<WebhookEventType>,
+    #[serde(serialize_with = "to_utc")]
+    pub created: NaiveDateTime,
+}
+
+impl From<(Webhook, Option<String>)> for DisplayWebhook {
+    fn from((w, group_name): (Webhook, Option<String>)) -> Self {
+        DisplayWebhook {
+            id: w.id,
+            url: w.url,
+            group_name,
+            events: w.events,
+            created: w.created,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct DisplayWebhookDelivery {
+    pub id: i32,
+    pub event: WebhookEventType,
+    pub payload: Value,
+    pub status: DeliveryStatusType,
+    pub attempts: i32,
+    pub last_error: Option<String>,
+    #[serde(serialize_with = "to_utc")]
+    pub next_attempt: NaiveDateTime,
+    #[serde(serialize_with = "to_utc")]
+    pub created: NaiveDateTime,
+    #[serde(serialize_with = "to_utc")]
+    pub updated: NaiveDateTime,
+}
+
+impl From<WebhookDelivery> for DisplayWebhookDelivery {
+    fn from(d: WebhookDelivery) -> Self {
+        DisplayWebhookDelivery {
+            id: d.id,
+            event: d.event,
+            payload: d.payload,
+            status: d.status,
+            attempts: d.attempts,
+            last_error: d.last_error,
+            next_attempt: d.next_attempt,
+            created: d.created,
+            updated: d.updated,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct UserDrift {
+    pub user_uuid: Uuid,
+    pub username: String,
+    /// Groups the user is a member of which are missing in the profile.
+    pub missing: Vec<String>,
+    /// Groups in the profile the user is not a member of.
+    pub extra: Vec<String>,
+}
+
+#[derive(Default, Serialize)]
+pub struct GroupDrift {
+    pub group_name: String,
+    pub missing: Vec<Uuid>,
+    pub extra: Vec<Uuid>,
+}
+
+#[derive(Serialize)]
+pub struct DriftReport {
+    pub users: Vec<UserDrift>,
+    pub groups: Vec<GroupDrift>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub enum ExpirationAction {
+    /// The membership is deleted.
+    Revoke,
+    /// The membership enters the grace period of its group.
+    Grace,
+}
+
+#[derive(Serialize)]
+pub struct ExpiringEntry {
+    pub user_uuid: Uuid,
+    pub username: String,
+    #[serde(serialize_with = "maybe_to_utc")]
+    pub expiration: Option<NaiveDateTime>,
+}
+
+#[derive(Serialize)]
+pub struct ExpiringMembership {
+    pub user_uuid: Uuid,
+    pub username: String,
+    #[serde(serialize_with = "maybe_to_utc")]
+    pub expiration: Option<NaiveDateTime>,
+    pub action: ExpirationAction,
+    pub keep_access: bool,
+}
+
+#[derive(Serialize)]
+pub struct PreviewNotification {
+    pub email: String,
+    pub template: Template,
+}
+
+#[derive(Default, Serialize)]
+pub struct GroupExpirationPreview {
+    pub group_name: String,
+    pub requests: Vec<ExpiringEntry>,
+    pub invitations: Vec<ExpiringEntry>,
+    pub memberships: Vec<ExpiringMembership>,
+    pub notifications: Vec<PreviewNotification>,
+}
+
+#[derive(Serialize)]
+pub struct ExpirationPreview {
+    #[serde(serialize_with = "to_utc")]
+    pub before: NaiveDateTime,
+    pub groups: Vec<GroupExpirationPreview>,
+}
+
+#[derive(Serialize)]
+pub struct TrustChangeMember {
+    pub user_uuid: Uuid,
+    pub username: String,
+    pub trust: TrustType,
+}
+
+impl From<UserProfileSlim> for TrustChangeMember {
+    fn from(p: UserProfileSlim) -> Self {
+        TrustChangeMember {
+            user_uuid: p.user_uuid,
+            username: p.username,
+            trust: p.trust,
+        }
+    }
+}
+
+/// Members who lose their membership by changing the trust of a group to `trust`. Without
+/// `effective` the change is (or would be) applied immediately.
+#[derive(Serialize)]
+pub struct TrustChangeStatus {
+    pub trust: TrustType,
+    #[serde(serialize_with = "maybe_to_utc")]
+    pub effective: Option<NaiveDateTime>,
+    pub members: Vec<TrustChangeMember>,
+}
+
+/// Days memberships are kept after the trust of a member dropped. Without `days` memberships
+/// are revoked immediately.
+#[derive(Deserialize, Serialize)]
+pub struct TrustGrace {
+    pub days: Option<i32>,
+}
+
+impl TrustGrace {
+    pub fn checked(self) -> Result<Self, PacksError> {
+        match self.days {
+            Some(days) if !(1..=TRUST_GRACE_MAX_DAYS).contains(&days) => {
+                Err(PacksError::InvalidTrustGrace)
+            }
+            _ => Ok(self),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct ReconcileReport {
+    pub dry_run: bool,
+    pub users: Vec<UserDrift>,
+    pub updated: usize,
+    pub failed: usize,
+}
+
+#[derive(Serialize)]
+pub struct DisplayOutboxEntry {
+    pub id: i32,
+    pub user_uuid: Uuid,
+    pub operation: OutboxOperationType,
+    pub group_names: Vec<String>,
+    pub status: DeliveryStatusType,
+    pub attempts: i32,
+    pub last_error: Option<String>,
+    #[serde(serialize_with = "to_utc")]
+    pub next_attempt: NaiveDateTime,
+    #[serde(serialize_with = "to_utc")]
+    pub created: NaiveDateTime,
+    #[serde(serialize_with = "to_utc")]
+    pub updated: NaiveDateTime,
+}
+
+impl From<CisOutboxEntry> for DisplayOutboxEntry {
+    fn from(e: CisOutboxEntry) -> Self {
+        DisplayOutboxEntry {
+            id: e.id,
+            user_uuid: e.user_uuid,
+            operation: e.operation,
+            group_names: e.group_names,
+            status: e.status,
+            attempts: e.attempts,
+            last_error: e.last_error,
+            next_attempt: e.next_attempt,
+            created: e.created,
+            updated: e.updated,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct MergeUsers {
+    pub from: Uuid,
+    pub to: Uuid,
+}
+
+impl MergeUsers {
+    pub fn checked(self) -> Result<Self, PacksError> {
+        if self.from == self.to {
+            return Err(PacksError::InvalidMerge);
+        }
+        Ok(self)
+    }
+}
+
+#[derive(Serialize)]
+pub struct MergeReport {
+    /// Groups the memberships were moved or merged for.
+    pub group_names: Vec<String>,
+    pub invitations: usize,
+    pub requests: usize,
+}
+
+#[derive(Serialize)]
+pub struct ResyncReport {
+    pub run: i32,
+    pub total: i32,
+    pub processed: i32,
+    pub failed: i32,
+    pub cursor: Option<Uuid>,
+    #[serde(serialize_with = "to_utc")]
+    pub started: NaiveDateTime,
+    #[serde(serialize_with = "maybe_to_utc")]
+    pub finished: Option<NaiveDateTime>,
+}
+
+impl From<ResyncRun> for ResyncReport {
+    fn from(r: ResyncRun) -> Self {
+        ResyncReport {
+            run: r.id,
+            total: r.total,
+            processed: r.processed,
+            failed: r.failed,
+            cursor: r.cursor,
+            started: r.started,
+            finished: r.finished,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct DisplayJobRun {
+    pub job: JobType,
+    #[serde(serialize_with = "to_utc")]
+    pub tick: NaiveDateTime,
+    #[serde(serialize_with = "to_utc")]
+    pub started: NaiveDateTime,
+    #[serde(serialize_with = "maybe_to_utc")]
+    pub finished: Option<NaiveDateTime>,
+    pub success: Option<bool>,
+    pub message: Option<String>,
+}
+
+impl From<JobRun> for DisplayJobRun {
+    fn from(r: JobRun) -> Self {
+        DisplayJobRun {
+            job: r.job,
+            tick: r.tick,
+            started: r.started,
+            finished: r.finished,
+            success: r.success,
+            message: r.message,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct DisplaySuppressedEmail {
+    pub email: String,
+    pub typ: SuppressionType,
+    #[serde(serialize_with = "to_utc")]
+    pub created: NaiveDateTime,
+    pub body: Option<Value>,
+}
+
+impl From<SuppressedEmail> for DisplaySuppressedEmail {
+    fn from(s: SuppressedEmail) -> Self {
+        DisplaySuppressedEmail {
+            email: s.email,
+            typ: s.typ,
+            created: s.created,
+            body: s.body,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct DisplayNotificationPreference {
+    pub category: NotificationCategoryType,
+    pub preference: NotificationPreferenceType,
+}
+
+/// Only returned once on creation, just the hash is stored.
+#[derive(Serialize)]
+pub struct CalendarToken {
+    pub token: String,
+}
+
+pub struct CalendarFeed {
+    pub group_id: i32,
+    pub group_name: String,
+    pub entries: Vec<CalendarEntry>,
+}
+
+pub struct CalendarEntry {
+    pub user_uuid: Uuid,
+    pub username: String,
+    pub expiration: NaiveDateTime,
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -457,6 +1010,10 @@ mod test {
             typ: None,
             capabilities: Some(vec![]),
             group_expiration: Some(None),
+            reminders: None,
+            self_renewal: None,
+            expiration_grace: None,
+            apply_expiration: None,
         };
         assert_eq!(
             group_update.log_comment(),
@@ -467,7 +1024,44 @@ mod test {
             typ: None,
             capabilities: None,
             group_expiration: None,
+            reminders: None,
+            self_renewal: None,
+            expiration_grace: None,
+            apply_expiration: None,
         };
         assert_eq!(group_update.log_comment(), "");
     }
+
+    #[test]
+    fn test_group_update_checked_reminders() {
+        let reminder = |days| Reminder {
+            days,
+            member: true,
+            host: false,
+            curators: false,
+        };
+        let group_update = |reminders| GroupUpdate {
+            description: None,
+            typ: None,
+            capabilities: None,
+            group_expiration: None,
+            reminders: Some(reminders),
+            self_renewal: None,
+            expiration_grace: None,
+            apply_expiration: None,
+        };
+        assert!(group_update(vec![reminder(30), reminder(14), reminder(3)])
+            .checked()
+            .is_ok());
+        assert!(group_update(vec![reminder(14), reminder(14)])
+            .checked()
+            .is_err());
+        assert!(group_update(vec![reminder(0)]).checked().is_err());
+        assert!(group_update(vec![Reminder {
+            member: false,
+            ..reminder(7)
+        }])
+        .checked()
+        .is_err());
+    }
 }
@@ -0,0 +1,131 @@
+use crate::db::internal;
+use crate::db::operations::models::GroupNewsletters;
+use crate::db::Pool;
+use crate::mail::manager::subscribe;
+use crate::mail::manager::unsubscribe;
+use crate::rules::engine::ONLY_ADMINS;
+use crate::rules::RuleContext;
+use crate::user::User;
+use diesel::PgConnection;
+use dino_park_gate::scope::ScopeAndUser;
+use failure::Error;
+use log::error;
+
+fn try_subscribe_member(
+    connection: &PgConnection,
+    group_name: &str,
+    user: &User,
+) -> Result<(), Error> {
+    let group = internal::group::get_group(connection, group_name)?;
+    let newsletters = internal::newsletter::newsletters_for_group(connection, group.id)?;
+    if !newsletters.is_empty() {
+        let profile = internal::user::slim_user_profile_by_uuid(connection, &user.user_uuid)?;
+        subscribe(profile.email, newsletters);
+    }
+    Ok(())
+}
+
+fn try_unsubscribe_member(
+    connection: &PgConnection,
+    group_name: &str,
+    user: &User,
+) -> Result<(), Error> {
+    let group = internal::group::get_group(connection, group_name)?;
+    // keep newsletters the user still receives through other groups
+    let remaining = internal::newsletter::newsletters_for_user(connection, &user.user_uuid)?;
+    let newsletters = internal::newsletter::newsletters_for_group(connection, group.id)?
+        .into_iter()
+        .filter(|newsletter| !remaining.contains(newsletter))
+        .collect::<Vec<_>>();
+    if !newsletters.is_empty() {
+        let profile = internal::user::slim_user_profile_by_uuid(connection, &user.user_uuid)?;
+        unsubscribe(profile.email, newsletters);
+    }
+    Ok(())
+}
+
+/// Subscribes a new member to all newsletters of the group. Failures are only logged to not
+/// interfere with the membership change.
+pub fn subscribe_member(connection: &PgConnection, group_name: &str, user: &User) {
+    if let Err(e) = try_subscribe_member(connection, group_name, user) {
+        error!(
+            "unable to subscribe {} to newsletters of {}: {}",
+            user.user_uuid, group_name, e
+        );
+    }
+}
+
+/// Unsubscribes a removed member from all newsletters of the group. Must be called after the
+/// removal of the membership has been committed.
+pub fn unsubscribe_member(connection: &PgConnection, group_name: &str, user: &User) {
+    if let Err(e) = try_unsubscribe_member(connection, group_name, user) {
+        error!(
+            "unable to unsubscribe {} from newsletters of {}: {}",
+            user.user_uuid, group_name, e
+        );
+    }
+}
+
+pub fn newsletters(
+    pool: &Pool,
+    scope_and_user: &ScopeAndUser,
+    host: &User,
+    group_name: &str,
+) -> Result<GroupNewsletters, Error> {
+    ONLY_ADMINS.run(&RuleContext::minimal(
+        &pool.clone(),
+        scope_and_user,
+        group_name,
+        &host.user_uuid,
+    ))?;
+    let connection = pool.get()?;
+    let group = internal::group::get_group(&connection, group_name)?;
+    let newsletters = internal::newsletter::newsletters_for_group(&connection, group.id)?;
+    Ok(GroupNewsletters { newsletters })
+}
+
+pub fn update_newsletters(
+    pool: &Pool,
+    scope_and_user: &ScopeAndUser,
+    host: &User,
+    group_name: &str,
+    newsletters: GroupNewsletters,
+) -> Result<(), Error> {
+    ONLY_ADMINS.run(&RuleContext::minimal(
+        &pool.clone(),
+        scope_and_user,
+        group_name,
+        &host.user_uuid,
+    ))?;
+    let connection = pool.get()?;
+    let group = internal::group::get_group(&connection, group_name)?;
+    internal::newsletter::set_newsletters(&connection, group.id, &newsletters.newsletters)
+}
+
+/// Subscribes all current members of a group to the group's newsletters and returns the number
+/// of subscribed members.
+pub fn reconcile_newsletters(
+    pool: &Pool,
+    scope_and_user: &ScopeAndUser,
+    host: &User,
+    group_name: &str,
+) -> Result<usize, Error> {
+    ONLY_ADMINS.run(&RuleContext::minimal(
+        &pool.clone(),
+        scope_and_user,
+        group_name,
+        &host.user_uuid,
+    ))?;
+    let connection = pool.get()?;
+    let group = internal::group::get_group(&connection, group_name)?;
+    let newsletters = internal::newsletter::newsletters_for_group(&connection, group.id)?;
+    if newsletters.is_empty() {
+        return Ok(0);
+    }
+    let emails = internal::member::get_member_emails_by_group_name(&connection, group_name)?;
+    let count = emails.len();
+    for email in emails {
+        subscribe(email, newsletters.clone());
+    }
+    Ok(count)
+}
@@ -0,0 +1,153 @@
+use crate::db::internal;
+use crate::db::model::NotificationPreference;
+use crate::db::operations::models::DisplayNotificationPreference;
+use crate::db::types::NotificationCategoryType;
+use crate::db::types::NotificationPreferenceType;
+use crate::db::Pool;
+use crate::mail::manager::deliver_email;
+use crate::mail::manager::send_email;
+use crate::mail::manager::send_emails;
+use crate::mail::templates::Template;
+use diesel::PgConnection;
+use dino_park_gate::scope::ScopeAndUser;
+use failure::Error;
+use log::error;
+use log::warn;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+const CATEGORIES: [NotificationCategoryType; 3] = [
+    NotificationCategoryType::Invitations,
+    NotificationCategoryType::Requests,
+    NotificationCategoryType::Expirations,
+];
+
+/// Queues or drops notifications according to the recipients' preferences and returns
+/// all emails which should be notified immediately.
+fn dispatch(
+    connection: &PgConnection,
+    emails: Vec<String>,
+    t: &Template,
+) -> Result<Vec<String>, Error> {
+    let category = match t.category() {
+        Some(category) => category,
+        None => return Ok(emails),
+    };
+    let preferences: HashMap<String, (Uuid, NotificationPreferenceType)> =
+        internal::notification::preferences_by_emails(connection, category, &emails)?
+            .into_iter()
+            .map(|(email, user_uuid, preference)| (email, (user_uuid, preference)))
+            .collect();
+    let mut immediate = Vec::with_capacity(emails.len());
+    for email in emails {
+        match preferences.get(&email) {
+            None | Some((_, NotificationPreferenceType::Immediate)) => immediate.push(email),
+            Some((_, NotificationPreferenceType::Off)) => {}
+            Some((user_uuid, preference)) => {
+                internal::notification::enqueue(connection, user_uuid, *preference, t)?
+            }
+        }
+    }
+    Ok(immediate)
+}
+
+pub fn notify(connection: &PgConnection, email: String, t: Template) -> Result<(), Error> {
+    for email in dispatch(connection, vec![email], &t)? {
+        send_email(email, &t);
+    }
+    Ok(())
+}
+
+pub fn notify_many(
+    connection: &PgConnection,
+    emails: Vec<String>,
+    t: Template,
+) -> Result<(), Error> {
+    let emails = dispatch(connection, emails, &t)?;
+    if !emails.is_empty() {
+        send_emails(emails, &t);
+    }
+    Ok(())
+}
+
+pub fn get_preferences(
+    pool: &Pool,
+    scope_and_user: &ScopeAndUser,
+) -> Result<Vec<DisplayNotificationPreference>, Error> {
+    let connection = pool.get()?;
+    let user = internal::user::user_by_id(&connection, &scope_and_user.user_id)?;
+    let preferences = internal::notification::get_preferences(&connection, &user.user_uuid)?;
+    Ok(CATEGORIES
+        .iter()
+        .map(|category| DisplayNotificationPreference {
+            category: *category,
+            preference: preferences
+                .iter()
+                .find(|p| p.category == *category)
+                .map(|p| p.preference)
+                .unwrap_or_default(),
+        })
+        .collect())
+}
+
+pub fn update_preference(
+    pool: &Pool,
+    scope_and_user: &ScopeAndUser,
+    update: DisplayNotificationPreference,
+) -> Result<(), Error> {
+    let connection = pool.get()?;
+    let user = internal::user::user_by_id(&connection, &scope_and_user.user_id)?;
+    let preference = NotificationPreference {
+        user_uuid: user.user_uuid,
+        category: update.category,
+        preference: update.preference,
+    };
+    internal::notification::set_preference(&connection, &preference)
+}
+
+/// Queued notifications are only removed once their digest has been sent so failed digests are
+/// retried on the next run.
+pub async fn send_digests(
+    pool: &Pool,
+    preference: NotificationPreferenceType,
+) -> Result<usize, Error> {
+    let mut digests: HashMap<Uuid, (Vec<i32>, Vec<Template>)> = HashMap::new();
+    let mut recipients = vec![];
+    {
+        let connection = pool.get()?;
+        let queued = internal::notification::queued(&connection, preference)?;
+        let mut invalid = vec![];
+        for q in queued {
+            match serde_json::from_value(q.template) {
+                Ok(t) => {
+                    let (ids, templates) = digests.entry(q.user_uuid).or_default();
+                    ids.push(q.id);
+                    templates.push(t);
+                }
+                Err(e) => {
+                    error!("invalid queued notification {}: {}", q.id, e);
+                    invalid.push(q.id);
+                }
+            }
+        }
+        internal::notification::delete_queued(&connection, &invalid)?;
+        for (user_uuid, digest) in digests {
+            match internal::user::slim_user_profile_by_uuid(&connection, &user_uuid) {
+                Ok(p) => recipients.push((p.email, digest)),
+                Err(e) => warn!("unable to send digest to {}: {}", user_uuid, e),
+            }
+        }
+    }
+    let mut count = 0;
+    for (email, (ids, templates)) in recipients {
+        match deliver_email(email, &Template::Digest(templates)).await {
+            Ok(()) => {
+                let connection = pool.get()?;
+                internal::notification::delete_queued(&connection, &ids)?;
+                count += 1;
+            }
+            Err(e) => warn!("unable to send digest: {}", e),
+        }
+    }
+    Ok(count)
+}
@@ -0,0 +1,388 @@
+use crate::cis::operations::ProfilePublisher;
+use crate::db::internal;
+use crate::db::model::CisOutboxEntry;
+use crate::db::operations::models::DisplayOutboxEntry;
+use crate::db::types::DeliveryStatusType;
+use crate::db::types::OutboxOperationType;
+use crate::db::Pool;
+use crate::error::PacksError;
+use crate::rules::engine::ONLY_ADMINS;
+use crate::rules::RuleContext;
+use crate::user::User;
+use chrono::Duration;
+use chrono::Utc;
+use cis_profile::schema::KeyValue;
+use diesel::Connection;
+use diesel::PgConnection;
+use dino_park_gate::scope::ScopeAndUser;
+use failure::Error;
+use log::info;
+use log::warn;
+use std::collections::BTreeSet;
+use std::sync::Arc;
+use uuid::Uuid;
+
+const DELIVERY_BATCH_SIZE: i64 = 100;
+const OUTBOX_HISTORY_SIZE: i64 = 100;
+/// Entries are retried with an exponential backoff (2^attempts minutes) and marked as failed
+/// after `MAX_ATTEMPTS`.
+const MAX_ATTEMPTS: i32 = 8;
+/// New entries are delivered right after their transaction commits. The worker only picks them
+/// up after this delay to not race that first attempt.
+const FIRST_ATTEMPT_GRACE_SECS: i64 = 60;
+/// Entries queued by bulk operations are held back for this window so that all changes for a
+/// user are combined into a single profile update by the worker.
+const BATCH_WINDOW_SECS: i64 = 120;
+/// Entries are claimed for this long while being delivered. Claims of crashed deliveries expire
+/// after it.
+const CLAIM_LEASE_MINUTES: i64 = 10;
+
+fn enqueue(
+    connection: &PgConnection,
+    user_uuid: &Uuid,
+    operation: OutboxOperationType,
+    group_names: Vec<String>,
+    delay: i64,
+) -> Result<CisOutboxEntry, Error> {
+    let next_attempt = Utc::now() + Duration::seconds(delay);
+    internal::outbox::enqueue(
+        connection,
+        user_uuid,
+        operation,
+        group_names,
+        next_attempt.naive_utc(),
+    )
+}
+
+/// Queues adding `group_name` to the profile of the user. Call this within the transaction of
+/// the membership change and `deliver` the entry once committed.
+pub fn enqueue_add_group(
+    connection: &PgConnection,
+    user_uuid: &Uuid,
+    group_name: &str,
+) -> Result<CisOutboxEntry, Error> {
+    enqueue_add_groups(connection, user_uuid, vec![group_name.to_owned()])
+}
+
+/// Queues adding `group_names` to the profile of the user. Call this within the transaction of
+/// the membership change and `deliver` the entry once committed.
+pub fn enqueue_add_groups(
+    connection: &PgConnection,
+    user_uuid: &Uuid,
+    group_names: Vec<String>,
+) -> Result<CisOutboxEntry, Error> {
+    enqueue(
+        connection,
+        user_uuid,
+        OutboxOperationType::AddGroups,
+        group_names,
+        FIRST_ATTEMPT_GRACE_SECS,
+    )
+}
+
+/// Queues removing `group_names` from the profile of the user. Call this within the transaction
+/// of the membership change and `deliver` the entry once committed.
+pub fn enqueue_remove_groups(
+    connection: &PgConnection,
+    user_uuid: &Uuid,
+    group_names: Vec<String>,
+) -> Result<CisOutboxEntry, Error> {
+    enqueue(
+        connection,
+        user_uuid,
+        OutboxOperationType::RemoveGroups,
+        group_names,
+        FIRST_ATTEMPT_GRACE_SECS,
+    )
+}
+
+/// Like `enqueue_add_group` but leaves delivery to the worker which batches all changes for the
+/// user. Use this for bulk operations.
+pub fn batch_add_group(
+    connection: &PgConnection,
+    user_uuid: &Uuid,
+    group_name: &str,
+) -> Result<(), Error> {
+    enqueue(
+        connection,
+        user_uuid,
+        OutboxOperationType::AddGroups,
+        vec![group_name.to_owned()],
+        BATCH_WINDOW_SECS,
+    )
+    .map(|_| ())
+}
+
+/// Like `enqueue_remove_groups` but leaves delivery to the worker which batches all changes for
+/// the user. Use this for bulk operations.
+pub fn batch_remove_groups(
+    connection: &PgConnection,
+    user_uuid: &Uuid,
+    group_names: Vec<String>,
+) -> Result<(), Error> {
+    enqueue(
+        connection,
+        user_uuid,
+        OutboxOperationType::RemoveGroups,
+        group_names,
+        BATCH_WINDOW_SECS,
+    )
+    .map(|_| ())
+}
+
+/// Folds the entries (in queue order) into the groups to add and the groups to remove.
+fn combine(entries: &[CisOutboxEntry]) -> (Vec<String>, Vec<String>) {
+    let mut add = BTreeSet::new();
+    let mut remove = BTreeSet::new();
+    for entry in entries {
+        for group_name in &entry.group_names {
+            match entry.operation {
+                OutboxOperationType::AddGroups => {
+                    remove.remove(group_name);
+                    add.insert(group_name.clone());
+                }
+                OutboxOperationType::RemoveGroups => {
+                    add.remove(group_name);
+                    remove.insert(group_name.clone());
+                }
+            }
+        }
+    }
+    (add.into_iter().collect(), remove.into_iter().collect())
+}
+
+async fn apply(
+    pool: &Pool,
+    publisher: Arc<impl ProfilePublisher>,
+    user_uuid: &Uuid,
+    entries: &[CisOutboxEntry],
+) -> Result<(), Error> {
+    let (add, remove) = combine(entries);
+    let connection = pool.get()?;
+    let user_profile = internal::user::user_profile_by_uuid(&connection, user_uuid)?;
+    let mut group_names = match user_profile.profile.access_information.mozilliansorg.values {
+        Some(KeyValue(ref values)) => values.keys().cloned().collect::<BTreeSet<_>>(),
+        None => BTreeSet::new(),
+    };
+    group_names.extend(add.iter().cloned());
+    for group_name in &remove {
+        group_names.remove(group_name);
+    }
+    let group_names = group_names.into_iter().collect::<Vec<_>>();
+    let display = internal::display::display_for_groups(&connection, &group_names)?;
+    drop(connection);
+    publisher
+        .update_groups(add, remove, display.into(), user_profile.profile)
+        .await
+}
+
+fn claim(connection: &PgConnection, ids: &[i32]) -> Result<Vec<CisOutboxEntry>, Error> {
+    let now = Utc::now();
+    internal::outbox::claim(
+        connection,
+        ids,
+        now.naive_utc(),
+        (now + Duration::minutes(CLAIM_LEASE_MINUTES)).naive_utc(),
+    )
+}
+
+/// Applies the claimed entries of a single user to CIS with one profile update and records the
+/// outcome for every entry. Returns the number of delivered entries.
+async fn try_deliver(
+    pool: &Pool,
+    publisher: Arc<impl ProfilePublisher>,
+    user_uuid: &Uuid,
+    entries: &[CisOutboxEntry],
+) -> Result<usize, Error> {
+    let result = apply(pool, publisher, user_uuid, entries).await;
+    let connection = pool.get()?;
+    let now = Utc::now();
+    match result {
+        Ok(_) => {
+            for entry in entries {
+                internal::outbox::update(
+                    &connection,
+                    entry,
+                    DeliveryStatusType::Delivered,
+                    None,
+                    now.naive_utc(),
+                )?;
+            }
+            Ok(entries.len())
+        }
+        Err(e) => {
+            for entry in entries {
+                let attempts = entry.attempts + 1;
+                let status = if attempts >= MAX_ATTEMPTS {
+                    warn!("giving up on cis outbox entry {}: {}", entry.id, e);
+                    DeliveryStatusType::Failed
+                } else {
+                    DeliveryStatusType::Pending
+                };
+                let next_attempt = now + Duration::minutes(2i64.pow(attempts as u32));
+                internal::outbox::update(
+                    &connection,
+                    entry,
+                    status,
+                    Some(e.to_string()),
+                    next_attempt.naive_utc(),
+                )?;
+            }
+            Ok(0)
+        }
+    }
+}
+
+/// Delivers a single entry unless an earlier entry of the same user has not been delivered or
+/// the entry is claimed by the worker. In that case the worker delivers them together. Returns
+/// whether the entry got delivered.
+async fn try_deliver_entry(
+    pool: &Pool,
+    publisher: Arc<impl ProfilePublisher>,
+    entry: CisOutboxEntry,
+) -> Result<bool, Error> {
+    let connection = pool.get()?;
+    if internal::outbox::has_undelivered_before(&connection, &entry.user_uuid, entry.id)? {
+        return Ok(false);
+    }
+    let claimed = claim(&connection, &[entry.id])?;
+    if claimed.is_empty() {
+        return Ok(false);
+    }
+    drop(connection);
+    try_deliver(pool, publisher, &entry.user_uuid, &claimed)
+        .await
+        .map(|delivered| delivered > 0)
+}
+
+/// Tries to deliver a freshly committed entry. Failures are left to the outbox worker and never
+/// fail the membership change itself.
+pub async fn deliver(pool: &Pool, publisher: Arc<impl ProfilePublisher>, entry: CisOutboxEntry) {
+    let id = entry.id;
+    match try_deliver_entry(pool, publisher, entry).await {
+        Ok(true) => {}
+        Ok(false) => info!("deferred cis outbox entry {}", id),
+        Err(e) => warn!("unable to deliver cis outbox entry {}: {}", id, e),
+    }
+}
+
+/// Delivers all pending outbox entries of users with entries which are due. All pending entries
+/// of a user are combined into a single profile update. Users with a failed entry are skipped
+/// until it has been retried. Returns the number of delivered entries.
+pub async fn deliver_pending(
+    pool: &Pool,
+    publisher: Arc<impl ProfilePublisher>,
+) -> Result<usize, Error> {
+    let connection = pool.get()?;
+    let due = internal::outbox::pending(&connection, Utc::now().naive_utc(), DELIVERY_BATCH_SIZE)?;
+    let mut user_uuids = due.iter().map(|entry| entry.user_uuid).collect::<Vec<_>>();
+    user_uuids.sort();
+    user_uuids.dedup();
+    drop(connection);
+    let mut delivered = 0;
+    for user_uuid in user_uuids {
+        let connection = pool.get()?;
+        let entries = internal::outbox::pending_for_user(&connection, &user_uuid)?;
+        let first = match entries.first() {
+            Some(entry) => entry.id,
+            None => continue,
+        };
+        if internal::outbox::has_undelivered_before(&connection, &user_uuid, first)? {
+            warn!(
+                "cis outbox entries of {} are blocked by a failed entry",
+                user_uuid
+            );
+            continue;
+        }
+        let ids = entries.iter().map(|entry| entry.id).collect::<Vec<_>>();
+        let claimed = claim(&connection, &ids)?;
+        drop(connection);
+        if claimed.is_empty() {
+            info!("cis outbox entries of {} are being delivered", user_uuid);
+            continue;
+        }
+        delivered += try_deliver(pool, Arc::clone(&publisher), &user_uuid, &claimed).await?;
+    }
+    info!("delivered {} cis outbox entries", delivered);
+    Ok(delivered)
+}
+
+pub fn outbox_entries(
+    pool: &Pool,
+    scope_and_user: &ScopeAndUser,
+    host: &User,
+    status: DeliveryStatusType,
+) -> Result<Vec<DisplayOutboxEntry>, Error> {
+    ONLY_ADMINS.run(&RuleContext::minimal(
+        &pool.clone(),
+        scope_and_user,
+        "",
+        &host.user_uuid,
+    ))?;
+    let connection = pool.get()?;
+    internal::outbox::entries(&connection, status, OUTBOX_HISTORY_SIZE)
+        .map(|entries| entries.into_iter().map(Into::into).collect())
+}
+
+pub async fn retry_entry(
+    pool: &Pool,
+    scope_and_user: &ScopeAndUser,
+    host: &User,
+    id: i32,
+    publisher: Arc<impl ProfilePublisher>,
+) -> Result<DisplayOutboxEntry, Error> {
+    ONLY_ADMINS.run(&RuleContext::minimal(
+        &pool.clone(),
+        scope_and_user,
+        "",
+        &host.user_uuid,
+    ))?;
+    let connection = pool.get()?;
+    let entry = connection.transaction::<_, Error, _>(|| {
+        let entry = internal::outbox::entry(&connection, id)?;
+        // a newer update of the profile must not be overwritten by an outdated one
+        if internal::outbox::has_delivered_after(&connection, &entry.user_uuid, id)? {
+            return Err(PacksError::OutdatedOutboxEntry.into());
+        }
+        internal::outbox::retry(&connection, id)
+    })?;
+    drop(connection);
+    try_deliver_entry(pool, publisher, entry).await?;
+    let connection = pool.get()?;
+    internal::outbox::entry(&connection, id).map(Into::into)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn entry(operation: OutboxOperationType, group_names: &[&str]) -> CisOutboxEntry {
+        let now = Utc::now().naive_utc();
+        CisOutboxEntry {
+            id: 0,
+            user_uuid: Uuid::default(),
+            operation,
+            group_names: group_names.iter().map(|g| g.to_string()).collect(),
+            status: DeliveryStatusType::Pending,
+            attempts: 0,
+            last_error: None,
+            next_attempt: now,
+            created: now,
+            updated: now,
+            claimed_until: None,
+        }
+    }
+
+    #[test]
+    fn test_combine() {
+        let entries = vec![
+            entry(OutboxOperationType::AddGroups, &["a"]),
+            entry(OutboxOperationType::AddGroups, &["b"]),
+            entry(OutboxOperationType::RemoveGroups, &["a", "c"]),
+            entry(OutboxOperationType::AddGroups, &["c"]),
+        ];
+        let (add, remove) = combine(&entries);
+        assert_eq!(add, vec!["b", "c"]);
+        assert_eq!(remove, vec!["a"]);
+    }
+}
@@ -1,9 +1,9 @@
 use crate::db::internal;
 use crate::db::internal::request::*;
 use crate::db::operations::models::*;
+use crate::db::operations::notifications::notify;
+use crate::db::operations::notifications::notify_many;
 use crate::db::Pool;
-use crate::mail::manager::send_email;
-use crate::mail::manager::send_emails;
 use crate::mail::templates::Template;
 use crate::rules::engine::*;
 use crate::rules::RuleContext;
@@ -48,8 +48,11 @@ pub fn reject_request(
     ))?;
     reject(&connection, group_name, &host, user)?;
     let p = internal::user::slim_user_profile_by_uuid(&connection, &user.user_uuid)?;
-    send_email(p.email, &Template::RejectRequest(group_name.to_owned()));
-    Ok(())
+    notify(
+        &connection,
+        p.email,
+        Template::RejectRequest(group_name.to_owned()),
+    )
 }
 
 pub fn cancel_request(
@@ -126,7 +129,11 @@ pub fn pending_requests_notification(pool: &Pool) -> Result<(), Error> {
     let pending = internal::request::new_pending(&connection, lower, upper)?;
     for (group_id, npr) in pending {
         let bcc = internal::member::get_curator_emails(&connection, group_id)?;
-        send_emails(bcc, &Template::PendingRequest(npr.group_name, npr.count));
+        notify_many(
+            &connection,
+            bcc,
+            Template::PendingRequest(npr.group_name, npr.count),
+        )?;
     }
     Ok(())
 }
@@ -0,0 +1,80 @@
+use crate::cis::operations::ProfilePublisher;
+use crate::db::internal;
+use crate::db::internal::lock::AdvisoryLock;
+use crate::db::operations;
+use crate::db::operations::models::ResyncReport;
+use crate::db::Pool;
+use crate::error::PacksError;
+use failure::Error;
+use log::info;
+use log::warn;
+use std::sync::Arc;
+
+/// Namespace of the advisory lock which keeps concurrent calls off the same run.
+const RESYNC_LOCK_NAMESPACE: i32 = 0x7265_7379;
+
+async fn resync_user(
+    pool: &Pool,
+    publisher: Arc<impl ProfilePublisher>,
+    user_id: &str,
+) -> Result<(), Error> {
+    let profile = Arc::clone(&publisher)
+        .fetch_profile(user_id.to_owned())
+        .await?;
+    operations::users::update_user_cache(pool, &profile, publisher).await
+}
+
+/// Refreshes up to `batch_size` cached profiles from CIS, revoking memberships the new trust
+/// level no longer allows. Progress is stored after every user so an interrupted run resumes
+/// where it stopped. Once all users are processed the next call starts a new run. Only one call
+/// can work on a run at a time, concurrent calls fail.
+pub async fn resync(
+    pool: &Pool,
+    publisher: Arc<impl ProfilePublisher>,
+    batch_size: i64,
+) -> Result<ResyncReport, Error> {
+    if batch_size < 1 {
+        return Err(PacksError::InvalidBatchSize.into());
+    }
+    let lock = match AdvisoryLock::try_acquire(pool, RESYNC_LOCK_NAMESPACE, 0)? {
+        Some(lock) => lock,
+        None => return Err(PacksError::ResyncInProgress.into()),
+    };
+    let connection = pool.get()?;
+    let run = match internal::resync::latest_run(&connection)? {
+        Some(run) if run.finished.is_none() => run,
+        _ => internal::resync::start_run(&connection)?,
+    };
+    let users = internal::resync::users_after(&connection, run.cursor, batch_size)?;
+    drop(connection);
+    let done = (users.len() as i64) < batch_size;
+    for (user_uuid, user_id) in users {
+        let failed = match resync_user(pool, Arc::clone(&publisher), &user_id).await {
+            Ok(_) => false,
+            Err(e) => {
+                warn!("unable to resync {}: {}", user_uuid, e);
+                true
+            }
+        };
+        let connection = pool.get()?;
+        internal::resync::advance(&connection, run.id, &user_uuid, failed)?;
+    }
+    let connection = pool.get()?;
+    let run = if done {
+        internal::resync::finish(&connection, run.id)?
+    } else {
+        internal::resync::run(&connection, run.id)?
+    };
+    info!(
+        "resync run {}: {}/{} users processed",
+        run.id, run.processed, run.total
+    );
+    drop(lock);
+    Ok(run.into())
+}
+
+/// Progress of the latest resync run.
+pub fn resync_status(pool: &Pool) -> Result<Option<ResyncReport>, Error> {
+    let connection = pool.get()?;
+    internal::resync::latest_run(&connection).map(|run| run.map(Into::into))
+}
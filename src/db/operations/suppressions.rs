@@ -0,0 +1,60 @@
+use crate::db::internal;
+use crate::db::model::InsertSuppressedEmail;
+use crate::db::operations::models::DisplaySuppressedEmail;
+use crate::db::Pool;
+use crate::mail::sns::SesNotification;
+use crate::rules::engine::ONLY_ADMINS;
+use crate::rules::RuleContext;
+use crate::user::User;
+use dino_park_gate::scope::ScopeAndUser;
+use failure::Error;
+use log::info;
+
+pub fn record_ses_notification(pool: &Pool, notification: SesNotification) -> Result<usize, Error> {
+    let connection = pool.get()?;
+    let suppressions = notification.suppressions();
+    for (email, typ, body) in &suppressions {
+        info!("suppressing {} ({:?})", email, typ);
+        internal::suppression::suppress(
+            &connection,
+            &InsertSuppressedEmail {
+                email: email.clone(),
+                typ: *typ,
+                body: Some(body.clone()),
+            },
+        )?;
+    }
+    Ok(suppressions.len())
+}
+
+pub fn suppressed_emails(
+    pool: &Pool,
+    scope_and_user: &ScopeAndUser,
+    host: &User,
+) -> Result<Vec<DisplaySuppressedEmail>, Error> {
+    ONLY_ADMINS.run(&RuleContext::minimal(
+        &pool.clone(),
+        scope_and_user,
+        "",
+        &host.user_uuid,
+    ))?;
+    let connection = pool.get()?;
+    internal::suppression::suppressed_emails(&connection)
+        .map(|emails| emails.into_iter().map(Into::into).collect())
+}
+
+pub fn unsuppress_email(
+    pool: &Pool,
+    scope_and_user: &ScopeAndUser,
+    host: &User,
+    email: &str,
+) -> Result<(), Error> {
+    ONLY_ADMINS.run(&RuleContext::minimal(
+        &pool.clone(),
+        scope_and_user,
+        "",
+        &host.user_uuid,
+    ))?;
+    let connection = pool.get()?;
+    internal::suppression::unsuppress(&connection, email)
+}
@@ -1,7 +1,14 @@
+use crate::cis::operations::ProfilePublisher;
 use crate::db::internal;
 use crate::db::logs::log_comment_body;
+use crate::db::model::Group;
+use crate::db::operations;
+use crate::db::operations::members::revoke_membership;
 use crate::db::operations::members::revoke_memberships_by_trust;
+use crate::db::operations::models::MergeReport;
+use crate::db::operations::models::MergeUsers;
 use crate::db::operations::models::RemoveGroups;
+use crate::db::operations::models::TrustGrace;
 use crate::db::types::TrustType;
 use crate::db::users::trust_for_profile;
 use crate::db::users::DisplayUser;
@@ -9,13 +16,22 @@ use crate::db::users::UserForGroup;
 use crate::db::users::UserProfile;
 use crate::db::Pool;
 use crate::error::PacksError;
+use crate::mail::manager::send_email;
+use crate::mail::manager::send_emails;
+use crate::mail::templates::Template;
+use crate::rules::engine::ONLY_ADMINS;
 use crate::rules::engine::SEARCH_USERS;
 use crate::rules::RuleContext;
 use crate::user::User;
-use cis_client::AsyncCisClientTrait;
+use crate::utils::to_expiration_ts;
+use chrono::Utc;
 use cis_profile::schema::Profile;
+use diesel::Connection;
 use dino_park_gate::scope::ScopeAndUser;
 use failure::Error;
+use log::error;
+use log::info;
+use std::collections::HashMap;
 use std::sync::Arc;
 use uuid::Uuid;
 
@@ -113,38 +129,225 @@ pub fn update_user_cache_unchecked(pool: &Pool, profile: &Profile) -> Result<(),
 pub async fn update_user_cache(
     pool: &Pool,
     profile: &Profile,
-    cis_client: Arc<impl AsyncCisClientTrait>,
+    publisher: Arc<impl ProfilePublisher>,
 ) -> Result<(), Error> {
     let connection = pool.get()?;
     let new_trust = trust_for_profile(&profile);
     let uuid = Uuid::parse_str(&profile.uuid.value.clone().ok_or(PacksError::NoUuid)?)?;
     let old_profile = internal::user::user_profile_by_uuid_maybe(&connection, &uuid)?;
     internal::user::update_user_cache(&connection, profile)?;
+    drop(connection);
+    operations::inactive::update_activity(pool, &uuid, profile, Arc::clone(&publisher)).await?;
 
     if let Some(old_profile) = old_profile {
         let old_trust = trust_for_profile(&old_profile.profile);
-        drop(connection);
         let remove_groups = RemoveGroups {
             user: User { user_uuid: uuid },
             group_names: &[],
             force: true,
             notify: true,
+            batch: false,
         };
+        let connection = pool.get()?;
+        internal::trust::clear_at_risk(&connection, &uuid, &new_trust)?;
+        let grace_days = internal::trust::grace_days(&connection)?;
+        drop(connection);
         if new_trust < old_trust {
-            revoke_memberships_by_trust(
-                pool,
-                remove_groups,
-                &User::default(),
-                new_trust,
-                cis_client,
-                log_comment_body("trust revoked by CIS update"),
-            )
-            .await?;
+            match grace_days {
+                Some(days) => flag_at_risk(pool, &uuid, &new_trust, days)?,
+                None => {
+                    revoke_memberships_by_trust(
+                        pool,
+                        remove_groups,
+                        &User::default(),
+                        new_trust,
+                        publisher,
+                        log_comment_body("trust revoked by CIS update"),
+                    )
+                    .await?
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Flags all memberships `trust` is not sufficient for as at risk and notifies the user and
+/// the curators about newly flagged memberships.
+fn flag_at_risk(pool: &Pool, user_uuid: &Uuid, trust: &TrustType, days: i32) -> Result<(), Error> {
+    let connection = pool.get()?;
+    let revoke_after = to_expiration_ts(days);
+    let flagged = connection.transaction::<_, Error, _>(|| {
+        let mut flagged = vec![];
+        for group in internal::group::groups_for_user(&connection, user_uuid)? {
+            if *trust < group.trust
+                && internal::trust::flag_at_risk(&connection, user_uuid, group.id, revoke_after)?
+            {
+                flagged.push(group);
+            }
         }
+        Ok(flagged)
+    })?;
+    if flagged.is_empty() {
+        return Ok(());
+    }
+    info!(
+        "flagged {} memberships of {} as at risk",
+        flagged.len(),
+        user_uuid
+    );
+    let profile = internal::user::slim_user_profile_by_uuid(&connection, user_uuid)?;
+    send_email(
+        profile.email,
+        &Template::MemberTrustDrop(flagged.iter().map(|g| g.name.clone()).collect(), days),
+    );
+    for group in flagged {
+        let bcc = internal::member::get_curator_emails(&connection, group.id)?;
+        send_emails(
+            bcc,
+            &Template::HostTrustDrop(group.name, profile.username.clone(), days),
+        );
     }
     Ok(())
 }
 
+/// Revokes at risk memberships whose grace period ended if the trust of the user is still too
+/// low. The flags of a user are only cleared once the revocation succeeded, failures are logged
+/// and retried on the next run. Returns the number of revoked memberships.
+pub async fn revoke_at_risk_memberships(
+    pool: &Pool,
+    publisher: Arc<impl ProfilePublisher>,
+) -> Result<usize, Error> {
+    let connection = pool.get()?;
+    let mut due: HashMap<Uuid, Vec<Group>> = HashMap::new();
+    for (user_uuid, group) in internal::trust::due_at_risk(&connection, Utc::now().naive_utc())? {
+        due.entry(user_uuid).or_default().push(group);
+    }
+    let mut to_revoke = vec![];
+    for (user_uuid, groups) in due {
+        let trust = internal::user::user_trust(&connection, &user_uuid)?;
+        let group_names = groups
+            .into_iter()
+            .filter(|g| trust < g.trust)
+            .map(|g| g.name)
+            .collect::<Vec<_>>();
+        to_revoke.push((User { user_uuid }, trust, group_names));
+    }
+    drop(connection);
+    let mut revoked = 0;
+    for (user, trust, group_names) in to_revoke {
+        let group_names = group_names.iter().map(String::as_str).collect::<Vec<_>>();
+        let remove_groups = RemoveGroups {
+            user,
+            group_names: &group_names,
+            force: true,
+            notify: true,
+            batch: false,
+        };
+        if let Err(e) = revoke_membership(
+            pool,
+            remove_groups,
+            &User::default(),
+            Arc::clone(&publisher),
+            log_comment_body("trust revoked by CIS update after grace period"),
+        )
+        .await
+        {
+            error!(
+                "unable to revoke at risk memberships of {}: {}",
+                user.user_uuid, e
+            );
+            continue;
+        }
+        revoked += group_names.len();
+        let connection = pool.get()?;
+        internal::trust::clear_at_risk(&connection, &user.user_uuid, &trust)?;
+    }
+    Ok(revoked)
+}
+
+pub fn trust_grace(pool: &Pool, scope_and_user: &ScopeAndUser) -> Result<TrustGrace, Error> {
+    let connection = pool.get()?;
+    let host = internal::user::user_by_id(&connection, &scope_and_user.user_id)?;
+    ONLY_ADMINS.run(&RuleContext::minimal(
+        pool,
+        scope_and_user,
+        "",
+        &host.user_uuid,
+    ))?;
+    let days = internal::trust::grace_days(&connection)?;
+    Ok(TrustGrace { days })
+}
+
+pub fn set_trust_grace(
+    pool: &Pool,
+    scope_and_user: &ScopeAndUser,
+    trust_grace: TrustGrace,
+) -> Result<(), Error> {
+    let connection = pool.get()?;
+    let host = internal::user::user_by_id(&connection, &scope_and_user.user_id)?;
+    ONLY_ADMINS.run(&RuleContext::minimal(
+        pool,
+        scope_and_user,
+        "",
+        &host.user_uuid,
+    ))?;
+    internal::trust::set_grace_days(&connection, trust_grace.days)
+}
+
+/// Moves everything attached to `merge.from` over to `merge.to` and updates both profiles in
+/// CIS accordingly.
+pub async fn merge_users(
+    pool: &Pool,
+    scope_and_user: &ScopeAndUser,
+    host: &User,
+    merge: MergeUsers,
+    publisher: Arc<impl ProfilePublisher>,
+) -> Result<MergeReport, Error> {
+    ONLY_ADMINS.run(&RuleContext::minimal(
+        pool,
+        scope_and_user,
+        "",
+        &host.user_uuid,
+    ))?;
+    let connection = pool.get()?;
+    // both identities must be known to update their profiles
+    internal::user::user_profile_by_uuid(&connection, &merge.from)?;
+    internal::user::user_profile_by_uuid(&connection, &merge.to)?;
+    let (report, entries) = connection.transaction::<_, Error, _>(|| {
+        let report =
+            internal::merge::merge_users(&connection, &host.user_uuid, &merge.from, &merge.to)?;
+        let entries = if report.group_names.is_empty() {
+            vec![]
+        } else {
+            vec![
+                operations::outbox::enqueue_remove_groups(
+                    &connection,
+                    &merge.from,
+                    report.group_names.clone(),
+                )?,
+                operations::outbox::enqueue_add_groups(
+                    &connection,
+                    &merge.to,
+                    report.group_names.clone(),
+                )?,
+            ]
+        };
+        Ok((report, entries))
+    })?;
+    drop(connection);
+    info!(
+        "merged {} into {}: {} memberships",
+        merge.from,
+        merge.to,
+        report.group_names.len()
+    );
+    for entry in entries {
+        operations::outbox::deliver(pool, Arc::clone(&publisher), entry).await;
+    }
+    Ok(report)
+}
+
 pub fn user_by_id(pool: &Pool, user_id: &str) -> Result<User, Error> {
     let connection = pool.get()?;
     internal::user::user_by_id(&connection, user_id)
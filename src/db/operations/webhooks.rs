@@ -0,0 +1,215 @@
+use crate::db::internal;
+use crate::db::model::InsertWebhook;
+use crate::db::model::Webhook;
+use crate::db::model::WebhookDelivery;
+use crate::db::operations::models::DisplayWebhook;
+use crate::db::operations::models::DisplayWebhookDelivery;
+use crate::db::operations::models::NewWebhook;
+use crate::db::types::DeliveryStatusType;
+use crate::db::Pool;
+use crate::rules::engine::ONLY_ADMINS;
+use crate::rules::RuleContext;
+use crate::user::User;
+use chrono::Duration;
+use chrono::Utc;
+use dino_park_gate::scope::ScopeAndUser;
+use failure::format_err;
+use failure::Error;
+use hmac::Hmac;
+use hmac::Mac;
+use hmac::NewMac;
+use log::info;
+use log::warn;
+use sha2::Sha256;
+
+const DELIVERY_BATCH_SIZE: i64 = 100;
+const DELIVERY_HISTORY_SIZE: i64 = 100;
+const DELIVERY_TIMEOUT_SECS: u64 = 10;
+/// Claimed deliveries are handed out again after this lease (enough for a whole batch to time
+/// out) in case the claiming run died.
+const DELIVERY_LEASE_MINUTES: i64 = 30;
+/// Deliveries are retried with an exponential backoff (2^attempts minutes) and given up
+/// after `MAX_ATTEMPTS`.
+const MAX_ATTEMPTS: i32 = 8;
+
+pub const SIGNATURE_HEADER: &str = "X-Packs-Signature";
+pub const EVENT_HEADER: &str = "X-Packs-Event";
+pub const DELIVERY_HEADER: &str = "X-Packs-Delivery";
+
+/// Hex encoded HMAC-SHA256 of `body` keyed with the webhook secret.
+pub fn sign(secret: &str, body: &[u8]) -> Result<String, Error> {
+    let mut mac = Hmac::<Sha256>::new_varkey(secret.as_bytes())
+        .map_err(|_| format_err!("invalid webhook secret"))?;
+    mac.update(body);
+    Ok(hex::encode(mac.finalize().into_bytes()))
+}
+
+async fn post_delivery(
+    client: &reqwest::Client,
+    delivery: &WebhookDelivery,
+    webhook: &Webhook,
+) -> Result<(), Error> {
+    let body = serde_json::to_vec(&delivery.payload)?;
+    let signature = sign(&webhook.secret, &body)?;
+    let res = client
+        .post(&webhook.url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .header(SIGNATURE_HEADER, format!("sha256={}", signature))
+        .header(EVENT_HEADER, serde_json::to_string(&delivery.event)?)
+        .header(DELIVERY_HEADER, delivery.id.to_string())
+        .body(body)
+        .send()
+        .await?;
+    if res.status().is_success() {
+        Ok(())
+    } else {
+        Err(format_err!("unexpected status {}", res.status()))
+    }
+}
+
+/// Delivers all pending webhook deliveries which are due and returns the number of successful
+/// deliveries.
+pub async fn deliver_pending(pool: &Pool) -> Result<usize, Error> {
+    let connection = pool.get()?;
+    let now = Utc::now();
+    let pending = internal::webhook::claim_pending_deliveries(
+        &connection,
+        now.naive_utc(),
+        (now + Duration::minutes(DELIVERY_LEASE_MINUTES)).naive_utc(),
+        DELIVERY_BATCH_SIZE,
+    )?;
+    drop(connection);
+    let client = reqwest::Client::builder()
+        .timeout(std::time::Duration::from_secs(DELIVERY_TIMEOUT_SECS))
+        .build()?;
+    let mut delivered = 0;
+    for (delivery, webhook) in pending {
+        let result = post_delivery(&client, &delivery, &webhook).await;
+        let connection = pool.get()?;
+        let now = Utc::now();
+        match result {
+            Ok(_) => {
+                delivered += 1;
+                internal::webhook::update_delivery(
+                    &connection,
+                    &delivery,
+                    DeliveryStatusType::Delivered,
+                    None,
+                    now.naive_utc(),
+                )?;
+            }
+            Err(e) => {
+                let attempts = delivery.attempts + 1;
+                let status = if attempts >= MAX_ATTEMPTS {
+                    warn!("giving up on webhook delivery {}: {}", delivery.id, e);
+                    DeliveryStatusType::Failed
+                } else {
+                    DeliveryStatusType::Pending
+                };
+                let next_attempt = now + Duration::minutes(2i64.pow(attempts as u32));
+                internal::webhook::update_delivery(
+                    &connection,
+                    &delivery,
+                    status,
+                    Some(e.to_string()),
+                    next_attempt.naive_utc(),
+                )?;
+            }
+        }
+    }
+    info!("delivered {} webhook events", delivered);
+    Ok(delivered)
+}
+
+pub fn webhooks(
+    pool: &Pool,
+    scope_and_user: &ScopeAndUser,
+    host: &User,
+) -> Result<Vec<DisplayWebhook>, Error> {
+    ONLY_ADMINS.run(&RuleContext::minimal(
+        &pool.clone(),
+        scope_and_user,
+        "",
+        &host.user_uuid,
+    ))?;
+    let connection = pool.get()?;
+    internal::webhook::webhooks(&connection)
+        .map(|webhooks| webhooks.into_iter().map(Into::into).collect())
+}
+
+pub fn create_webhook(
+    pool: &Pool,
+    scope_and_user: &ScopeAndUser,
+    host: &User,
+    new_webhook: NewWebhook,
+) -> Result<DisplayWebhook, Error> {
+    ONLY_ADMINS.run(&RuleContext::minimal(
+        &pool.clone(),
+        scope_and_user,
+        "",
+        &host.user_uuid,
+    ))?;
+    let connection = pool.get()?;
+    let group_id = match new_webhook.group_name {
+        Some(ref group_name) => Some(internal::group::get_group(&connection, group_name)?.id),
+        None => None,
+    };
+    let webhook = internal::webhook::create(
+        &connection,
+        &InsertWebhook {
+            url: new_webhook.url.to_string(),
+            secret: new_webhook.secret,
+            group_id,
+            events: new_webhook.events,
+        },
+    )?;
+    Ok(DisplayWebhook::from((webhook, new_webhook.group_name)))
+}
+
+pub fn delete_webhook(
+    pool: &Pool,
+    scope_and_user: &ScopeAndUser,
+    host: &User,
+    id: i32,
+) -> Result<(), Error> {
+    ONLY_ADMINS.run(&RuleContext::minimal(
+        &pool.clone(),
+        scope_and_user,
+        "",
+        &host.user_uuid,
+    ))?;
+    let connection = pool.get()?;
+    internal::webhook::delete(&connection, id)
+}
+
+pub fn webhook_deliveries(
+    pool: &Pool,
+    scope_and_user: &ScopeAndUser,
+    host: &User,
+    id: i32,
+) -> Result<Vec<DisplayWebhookDelivery>, Error> {
+    ONLY_ADMINS.run(&RuleContext::minimal(
+        &pool.clone(),
+        scope_and_user,
+        "",
+        &host.user_uuid,
+    ))?;
+    let connection = pool.get()?;
+    internal::webhook::deliveries(&connection, id, DELIVERY_HISTORY_SIZE)
+        .map(|deliveries| deliveries.into_iter().map(Into::into).collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_sign() -> Result<(), Error> {
+        // RFC 4231 test case 2
+        assert_eq!(
+            sign("Jefe", b"what do ya want for nothing?")?,
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+        Ok(())
+    }
+}
@@ -1,3 +1,115 @@
+table! {
+    use diesel::sql_types::*;
+    use crate::db::types::*;
+
+    at_risk_memberships (user_uuid, group_id) {
+        user_uuid -> Uuid,
+        group_id -> Int4,
+        flagged -> Timestamp,
+        revoke_after -> Timestamp,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use crate::db::types::*;
+
+    calendar_tokens (group_id, user_uuid) {
+        group_id -> Int4,
+        user_uuid -> Uuid,
+        token_hash -> Varchar,
+        created -> Timestamp,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use crate::db::types::*;
+
+    cis_outbox (id) {
+        id -> Int4,
+        user_uuid -> Uuid,
+        operation -> Outbox_operation_type,
+        group_names -> Array<Varchar>,
+        status -> Delivery_status_type,
+        attempts -> Int4,
+        last_error -> Nullable<Text>,
+        next_attempt -> Timestamp,
+        created -> Timestamp,
+        updated -> Timestamp,
+        claimed_until -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use crate::db::types::*;
+
+    expiration_grace (group_id) {
+        group_id -> Int4,
+        days -> Int4,
+        keep_access -> Bool,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use crate::db::types::*;
+
+    expiration_notifications (user_uuid, group_id, days, expiration) {
+        user_uuid -> Uuid,
+        group_id -> Int4,
+        expiration -> Timestamp,
+        sent -> Timestamp,
+        days -> Int4,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use crate::db::types::*;
+
+    expiration_reminders (group_id, days) {
+        group_id -> Int4,
+        days -> Int4,
+        member -> Bool,
+        host -> Bool,
+        curators -> Bool,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use crate::db::types::*;
+
+    expired_memberships (user_uuid, group_id) {
+        user_uuid -> Uuid,
+        group_id -> Int4,
+        expired -> Timestamp,
+        access_removed -> Bool,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use crate::db::types::*;
+
+    group_display (group_id) {
+        group_id -> Int4,
+        display -> Trust_type,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use crate::db::types::*;
+
+    group_newsletters (group_id, newsletter) {
+        group_id -> Int4,
+        newsletter -> Varchar,
+    }
+}
+
 table! {
     use diesel::sql_types::*;
     use crate::db::types::*;
@@ -26,6 +138,16 @@ table! {
     }
 }
 
+table! {
+    use diesel::sql_types::*;
+    use crate::db::types::*;
+
+    inactive_users (user_uuid) {
+        user_uuid -> Uuid,
+        since -> Timestamp,
+    }
+}
+
 table! {
     use diesel::sql_types::*;
     use crate::db::types::*;
@@ -49,6 +171,20 @@ table! {
     }
 }
 
+table! {
+    use diesel::sql_types::*;
+    use crate::db::types::*;
+
+    job_runs (job) {
+        job -> Job_type,
+        tick -> Timestamp,
+        started -> Timestamp,
+        finished -> Nullable<Timestamp>,
+        success -> Nullable<Bool>,
+        message -> Nullable<Text>,
+    }
+}
+
 table! {
     use diesel::sql_types::*;
     use crate::db::types::*;
@@ -80,6 +216,30 @@ table! {
     }
 }
 
+table! {
+    use diesel::sql_types::*;
+    use crate::db::types::*;
+
+    notification_preferences (user_uuid, category) {
+        user_uuid -> Uuid,
+        category -> Notification_category_type,
+        preference -> Notification_preference_type,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use crate::db::types::*;
+
+    notification_queue (id) {
+        id -> Int4,
+        user_uuid -> Uuid,
+        preference -> Notification_preference_type,
+        template -> Jsonb,
+        created -> Timestamp,
+    }
+}
+
 table! {
     use diesel::sql_types::*;
     use crate::db::types::*;
@@ -106,6 +266,22 @@ table! {
     }
 }
 
+table! {
+    use diesel::sql_types::*;
+    use crate::db::types::*;
+
+    resync_runs (id) {
+        id -> Int4,
+        cursor -> Nullable<Uuid>,
+        total -> Int4,
+        processed -> Int4,
+        failed -> Int4,
+        started -> Timestamp,
+        updated -> Timestamp,
+        finished -> Nullable<Timestamp>,
+    }
+}
+
 table! {
     use diesel::sql_types::*;
     use crate::db::types::*;
@@ -131,6 +307,53 @@ table! {
     }
 }
 
+table! {
+    use diesel::sql_types::*;
+    use crate::db::types::*;
+
+    self_renewal_groups (group_id) {
+        group_id -> Int4,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use crate::db::types::*;
+
+    self_renewals (user_uuid, group_id, expiration) {
+        user_uuid -> Uuid,
+        group_id -> Int4,
+        expiration -> Timestamp,
+        renewed -> Timestamp,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use crate::db::types::*;
+
+    suppressed_emails (email) {
+        email -> Varchar,
+        typ -> Suppression_type,
+        created -> Timestamp,
+        body -> Nullable<Jsonb>,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use crate::db::types::*;
+
+    suspended_memberships (user_uuid, group_id) {
+        user_uuid -> Uuid,
+        group_id -> Int4,
+        role_id -> Int4,
+        expiration -> Nullable<Timestamp>,
+        added_by -> Uuid,
+        added_ts -> Timestamp,
+    }
+}
+
 table! {
     use diesel::sql_types::*;
     use crate::db::types::*;
@@ -141,6 +364,29 @@ table! {
     }
 }
 
+table! {
+    use diesel::sql_types::*;
+    use crate::db::types::*;
+
+    trust_changes (group_id) {
+        group_id -> Int4,
+        trust -> Trust_type,
+        effective -> Timestamp,
+        created_by -> Uuid,
+        created -> Timestamp,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use crate::db::types::*;
+
+    trust_grace (id) {
+        id -> Bool,
+        days -> Int4,
+    }
+}
+
 table! {
     use diesel::sql_types::*;
     use crate::db::types::*;
@@ -226,6 +472,46 @@ table! {
     }
 }
 
+table! {
+    use diesel::sql_types::*;
+    use crate::db::types::*;
+
+    webhook_deliveries (id) {
+        id -> Int4,
+        webhook_id -> Int4,
+        event -> Webhook_event_type,
+        payload -> Jsonb,
+        status -> Delivery_status_type,
+        attempts -> Int4,
+        last_error -> Nullable<Text>,
+        next_attempt -> Timestamp,
+        created -> Timestamp,
+        updated -> Timestamp,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use crate::db::types::*;
+
+    webhooks (id) {
+        id -> Int4,
+        url -> Varchar,
+        secret -> Varchar,
+        group_id -> Nullable<Int4>,
+        events -> Array<Webhook_event_type>,
+        created -> Timestamp,
+    }
+}
+
+joinable!(at_risk_memberships -> groups (group_id));
+joinable!(calendar_tokens -> groups (group_id));
+joinable!(expiration_grace -> groups (group_id));
+joinable!(expiration_notifications -> groups (group_id));
+joinable!(expiration_reminders -> groups (group_id));
+joinable!(expired_memberships -> groups (group_id));
+joinable!(group_display -> groups (group_id));
+joinable!(group_newsletters -> groups (group_id));
 joinable!(group_rules -> groups (group_id));
 joinable!(group_rules -> rules (rule_id));
 joinable!(invitations -> groups (group_id));
@@ -234,26 +520,55 @@ joinable!(logs -> groups (group_id));
 joinable!(memberships -> groups (group_id));
 joinable!(memberships -> roles (role_id));
 joinable!(requests -> groups (group_id));
+joinable!(self_renewal_groups -> groups (group_id));
+joinable!(self_renewals -> groups (group_id));
+joinable!(suspended_memberships -> groups (group_id));
+joinable!(suspended_memberships -> inactive_users (user_uuid));
+joinable!(suspended_memberships -> roles (role_id));
 joinable!(roles -> groups (group_id));
 joinable!(terms -> groups (group_id));
+joinable!(trust_changes -> groups (group_id));
 joinable!(user_ids -> profiles (user_uuid));
+joinable!(webhook_deliveries -> webhooks (webhook_id));
+joinable!(webhooks -> groups (group_id));
 
 allow_tables_to_appear_in_same_query!(
+    at_risk_memberships,
+    calendar_tokens,
+    cis_outbox,
+    expiration_grace,
+    expiration_notifications,
+    expiration_reminders,
+    expired_memberships,
+    group_display,
+    group_newsletters,
     group_rules,
     groups,
+    inactive_users,
     invitations,
     invitationtexts,
+    job_runs,
     logs,
     memberships,
+    notification_preferences,
+    notification_queue,
     profiles,
     requests,
+    resync_runs,
     roles,
     rules,
+    self_renewal_groups,
+    self_renewals,
+    suppressed_emails,
     terms,
+    trust_changes,
+    trust_grace,
     user_ids,
     users_authenticated,
     users_ndaed,
     users_public,
     users_staff,
     users_vouched,
+    webhook_deliveries,
+    webhooks,
 );
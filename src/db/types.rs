@@ -77,6 +77,18 @@ impl TryFrom<Display> for TrustType {
     }
 }
 
+impl From<TrustType> for Display {
+    fn from(t: TrustType) -> Self {
+        match t {
+            TrustType::Staff => Display::Staff,
+            TrustType::Ndaed => Display::Ndaed,
+            TrustType::Vouched => Display::Vouched,
+            TrustType::Authenticated => Display::Authenticated,
+            TrustType::Public => Display::Public,
+        }
+    }
+}
+
 #[derive(Copy, Clone, DbEnum, Debug, Deserialize, PartialEq, Serialize)]
 #[DieselType = "Permission_type"]
 pub enum PermissionType {
@@ -87,6 +99,7 @@ pub enum PermissionType {
     DeleteGroup,
     RemoveMember,
     EditTerms,
+    EmailMembers,
 }
 
 #[derive(Copy, Clone, DbEnum, Debug, Deserialize, PartialEq, Serialize)]
@@ -151,6 +164,7 @@ pub enum LogTargetType {
     Role,
     Invitation,
     Request,
+    Email,
 }
 
 #[derive(Copy, Clone, DbEnum, Debug, Deserialize, PartialEq, Serialize)]
@@ -161,6 +175,76 @@ pub enum LogOperationType {
     Updated,
 }
 
+#[derive(Copy, Clone, DbEnum, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+#[DieselType = "Notification_category_type"]
+pub enum NotificationCategoryType {
+    Invitations,
+    Requests,
+    Expirations,
+}
+
+#[derive(Copy, Clone, DbEnum, Debug, Deserialize, PartialEq, Serialize)]
+#[DieselType = "Notification_preference_type"]
+pub enum NotificationPreferenceType {
+    Immediate,
+    Daily,
+    Weekly,
+    Off,
+}
+
+impl Default for NotificationPreferenceType {
+    fn default() -> Self {
+        Self::Immediate
+    }
+}
+
+#[derive(Copy, Clone, DbEnum, Debug, Deserialize, PartialEq, Serialize)]
+#[DieselType = "Suppression_type"]
+pub enum SuppressionType {
+    Bounce,
+    Complaint,
+}
+
+#[derive(Copy, Clone, DbEnum, Debug, Deserialize, PartialEq, Serialize)]
+#[DieselType = "Webhook_event_type"]
+pub enum WebhookEventType {
+    MembershipCreated,
+    MembershipDeleted,
+    RoleChanged,
+    GroupDeleted,
+    TrustChanged,
+    MembershipUpdated,
+}
+
+#[derive(Copy, Clone, DbEnum, Debug, Deserialize, PartialEq, Serialize)]
+#[DieselType = "Delivery_status_type"]
+pub enum DeliveryStatusType {
+    Pending,
+    Delivered,
+    Failed,
+}
+
+#[derive(Copy, Clone, DbEnum, Debug, Deserialize, PartialEq, Serialize)]
+#[DieselType = "Outbox_operation_type"]
+pub enum OutboxOperationType {
+    AddGroups,
+    RemoveGroups,
+}
+
+#[derive(Copy, Clone, DbEnum, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+#[DieselType = "Job_type"]
+#[serde(rename_all = "snake_case")]
+pub enum JobType {
+    ExpireRequests,
+    ExpireInvitations,
+    ExpireMemberships,
+    ExpirationNotification,
+    PendingRequestsNotification,
+    NotifyAnonymousMembers,
+    ApplyTrustChanges,
+    RevokeAtRiskMemberships,
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -16,4 +16,32 @@ pub enum PacksError {
     NoPrimaryEmail,
     #[fail(display = "no_uuid")]
     NoUuid,
+    #[fail(display = "invalid_broadcast")]
+    InvalidBroadcast,
+    #[fail(display = "broadcast_limit_reached")]
+    BroadcastLimitReached,
+    #[fail(display = "invalid_newsletter")]
+    InvalidNewsletter,
+    #[fail(display = "invalid_webhook")]
+    InvalidWebhook,
+    #[fail(display = "invalid_merge")]
+    InvalidMerge,
+    #[fail(display = "no_profile_source")]
+    NoProfileSource,
+    #[fail(display = "self_renewal_not_allowed")]
+    SelfRenewalNotAllowed,
+    #[fail(display = "invalid_trust_change")]
+    InvalidTrustChange,
+    #[fail(display = "invalid_trust_grace")]
+    InvalidTrustGrace,
+    #[fail(display = "outdated_outbox_entry")]
+    OutdatedOutboxEntry,
+    #[fail(display = "invalid_batch_size")]
+    InvalidBatchSize,
+    #[fail(display = "resync_in_progress")]
+    ResyncInProgress,
+    #[fail(display = "job_in_progress")]
+    JobInProgress,
+    #[fail(display = "invalid_sns_topic")]
+    InvalidSnsTopic,
 }
@@ -1,4 +1,5 @@
 use crate::api::error::ApiError;
+use crate::cis::operations::ProfilePublisher;
 use crate::db::types::TrustType;
 use crate::db::Pool;
 use crate::import::ops::*;
@@ -9,7 +10,6 @@ use actix_web::dev::HttpServiceFactory;
 use actix_web::web;
 use actix_web::web::Bytes;
 use actix_web::HttpResponse;
-use cis_client::AsyncCisClientTrait;
 use csv::ReaderBuilder;
 use futures::StreamExt;
 use futures::TryFutureExt;
@@ -29,10 +29,10 @@ struct GroupImportQuery {
     trust: Option<TrustType>,
 }
 
-async fn full_group_import<T: AsyncCisClientTrait>(
+async fn full_group_import<T: ProfilePublisher>(
     mut multipart: Multipart,
     pool: web::Data<Pool>,
-    cis_client: web::Data<T>,
+    publisher: web::Data<T>,
     query: web::Query<GroupImportQuery>,
 ) -> Result<HttpResponse, ApiError> {
     let trust = query.into_inner().trust.unwrap_or(TrustType::Ndaed);
@@ -98,14 +98,14 @@ async fn full_group_import<T: AsyncCisClientTrait>(
                 memberships,
                 trust,
             };
-            import(&pool, group_import, Arc::clone(&*cis_client)).await?
+            import(&pool, group_import, Arc::clone(&*publisher)).await?
         }
         _ => return Err(ApiError::MultipartError),
     }
     Ok(HttpResponse::Ok().finish())
 }
 
-pub fn import_app<T: AsyncCisClientTrait + 'static>() -> impl HttpServiceFactory {
+pub fn import_app<T: ProfilePublisher + 'static>() -> impl HttpServiceFactory {
     web::scope("/import")
         .app_data(web::JsonConfig::default().limit(1_048_576))
         .service(web::resource("/group/full").route(web::post().to(full_group_import::<T>)))
@@ -1,6 +1,7 @@
-use crate::cis::operations::add_group_to_profile;
+use crate::cis::operations::ProfilePublisher;
 use crate::db::internal;
 use crate::db::logs::LogContext;
+use crate::db::operations;
 use crate::db::operations::models::NewGroup;
 use crate::db::schema;
 use crate::db::types::*;
@@ -13,8 +14,6 @@ use crate::import::tsv::MozilliansGroupMembership;
 use crate::user::User;
 use chrono::DateTime;
 use chrono::Utc;
-use cis_client::getby::GetBy;
-use cis_client::AsyncCisClientTrait;
 use diesel::pg::PgConnection;
 use diesel::prelude::*;
 use failure::Error;
@@ -107,16 +106,13 @@ pub fn import_group(
 async fn get_user_profile(
     connection: &PgConnection,
     user_id: &str,
-    cis_client: Arc<impl AsyncCisClientTrait>,
+    publisher: Arc<impl ProfilePublisher>,
 ) -> Result<UserProfile, Error> {
     if let Ok(user_profile) = internal::user::user_profile_by_user_id(&connection, user_id) {
         Ok(user_profile)
     } else {
         warn!("no profile for {} → fetching", user_id);
-        let profile = cis_client
-            .clone()
-            .get_user_by(user_id, &GetBy::UserId, None)
-            .await?;
+        let profile = publisher.fetch_profile(user_id.to_owned()).await?;
         internal::user::update_user_cache(connection, &profile)?;
         profile.try_into()
     }
@@ -127,19 +123,20 @@ async fn import_curator(
     group_name: &str,
     curator: MozilliansGroupCurator,
     trust: TrustType,
-    cis_client: Arc<impl AsyncCisClientTrait>,
+    publisher: Arc<impl ProfilePublisher>,
 ) -> Result<(), Error> {
     let user_profile =
-        get_user_profile(connection, &curator.auth0_user_id, cis_client.clone()).await?;
+        get_user_profile(connection, &curator.auth0_user_id, publisher.clone()).await?;
     let user = User {
         user_uuid: user_profile.user_uuid,
     };
     if trust_for_profile(&user_profile.profile) < trust {
         return Ok(());
     }
-    internal::admin::add_admin(&connection, group_name, &User::default(), &user)?;
-    add_group_to_profile(cis_client, group_name.to_owned(), user_profile.profile).await?;
-    Ok(())
+    connection.transaction::<_, Error, _>(|| {
+        internal::admin::add_admin(&connection, group_name, &User::default(), &user)?;
+        operations::outbox::batch_add_group(&connection, &user.user_uuid, group_name)
+    })
 }
 
 pub async fn import_curators(
@@ -147,11 +144,11 @@ pub async fn import_curators(
     group_name: &str,
     moz_curators: Vec<MozilliansGroupCurator>,
     trust: TrustType,
-    cis_client: Arc<impl AsyncCisClientTrait>,
+    publisher: Arc<impl ProfilePublisher>,
 ) -> Result<(), Error> {
     for curator in moz_curators {
         let user_id = curator.auth0_user_id.clone();
-        match import_curator(connection, group_name, curator, trust, cis_client.clone()).await {
+        match import_curator(connection, group_name, curator, trust, publisher.clone()).await {
             Ok(()) => {}
             Err(e) => warn!(
                 "unable to add curator {} for group {}: {}",
@@ -167,12 +164,12 @@ pub async fn import_member(
     group_name: &str,
     member: MozilliansGroupMembership,
     trust: TrustType,
-    cis_client: Arc<impl AsyncCisClientTrait>,
+    publisher: Arc<impl ProfilePublisher>,
 ) -> Result<(), Error> {
     use schema::memberships as m;
 
     let user_profile =
-        get_user_profile(connection, &member.auth0_user_id, cis_client.clone()).await?;
+        get_user_profile(connection, &member.auth0_user_id, publisher.clone()).await?;
     if trust_for_profile(&user_profile.profile) < trust {
         return Ok(());
     }
@@ -195,29 +192,24 @@ pub async fn import_member(
     let host = if member.host.is_empty() {
         User::default()
     } else {
-        match get_user_profile(connection, &member.host, cis_client.clone()).await {
+        match get_user_profile(connection, &member.host, publisher.clone()).await {
             Ok(p) => User {
                 user_uuid: p.user_uuid,
             },
             _ => User::default(),
         }
     };
-    internal::member::add_to_group(connection, group_name, &host, &user, expiration)?;
+    connection.transaction::<_, Error, _>(|| {
+        internal::member::add_to_group(connection, group_name, &host, &user, expiration)?;
 
-    diesel::update(m::table)
-        .filter(m::user_uuid.eq(user.user_uuid))
-        .filter(m::group_id.eq(group.id))
-        .set(m::added_ts.eq(member.date_joined.naive_utc()))
-        .execute(connection)
-        .map(|_| ())?;
+        diesel::update(m::table)
+            .filter(m::user_uuid.eq(user.user_uuid))
+            .filter(m::group_id.eq(group.id))
+            .set(m::added_ts.eq(member.date_joined.naive_utc()))
+            .execute(connection)?;
 
-    add_group_to_profile(
-        cis_client.clone(),
-        group_name.to_owned(),
-        user_profile.profile,
-    )
-    .await?;
-    Ok(())
+        operations::outbox::batch_add_group(connection, &user.user_uuid, group_name)
+    })
 }
 
 pub async fn import_members(
@@ -225,7 +217,7 @@ pub async fn import_members(
     group_name: &str,
     moz_members: Vec<MozilliansGroupMembership>,
     trust: TrustType,
-    cis_client: Arc<impl AsyncCisClientTrait>,
+    publisher: Arc<impl ProfilePublisher>,
 ) -> Result<(), Error> {
     use schema::groups as g;
     let group = internal::group::get_group(connection, group_name)?;
@@ -236,7 +228,7 @@ pub async fn import_members(
             created = joined;
         }
         let user_id = member.auth0_user_id.clone();
-        match import_member(connection, group_name, member, trust, cis_client.clone()).await {
+        match import_member(connection, group_name, member, trust, publisher.clone()).await {
             Ok(()) => {}
             Err(e) => warn!(
                 "unable to add member {} for group {}: {}",
@@ -255,7 +247,7 @@ pub async fn import_members(
 pub async fn import(
     pool: &Pool,
     group_import: GroupImport,
-    cis_client: Arc<impl AsyncCisClientTrait>,
+    publisher: Arc<impl ProfilePublisher>,
 ) -> Result<(), Error> {
     let connection = pool.get()?;
     let group_name = group_import.group.name.clone();
@@ -265,7 +257,7 @@ pub async fn import(
         &group_name,
         group_import.curators,
         group_import.trust,
-        cis_client.clone(),
+        publisher.clone(),
     )
     .await?;
     import_members(
@@ -273,7 +265,7 @@ pub async fn import(
         &group_name,
         group_import.memberships,
         group_import.trust,
-        cis_client.clone(),
+        publisher.clone(),
     )
     .await?;
     Ok(())
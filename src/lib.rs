@@ -16,6 +16,7 @@ pub mod healthz;
 pub mod import;
 pub mod mail;
 pub mod rules;
+pub mod scheduler;
 pub mod settings;
 pub mod user;
 pub mod utils;
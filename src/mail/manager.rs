@@ -1,3 +1,5 @@
+use crate::db::internal;
+use crate::db::Pool;
 use crate::mail::send::EmailSender;
 #[cfg(all(not(test), not(feature = "local")))]
 use crate::mail::send::SesSender;
@@ -7,22 +9,48 @@ use crate::mail::Email;
 #[cfg(all(not(test), not(feature = "local")))]
 use crate::settings::Settings;
 use actix_rt::Arbiter;
+use actix_web::web;
 use basket::Basket;
+use failure::format_err;
+use failure::Error;
 #[cfg(all(not(test), not(feature = "local")))]
 use lazy_static::lazy_static;
 use log::error;
-
-const MOZILLIAN_NDA_LIST: &str = "mozillians-nda";
+use log::info;
+use std::future::Future;
+use std::pin::Pin;
+#[cfg(all(not(test), not(feature = "local")))]
+use std::sync::Mutex;
 
 #[cfg(all(not(test), not(feature = "local")))]
 lazy_static! {
+    static ref POOL: Mutex<Option<Pool>> = Mutex::new(None);
     static ref MAIL_MAN: MailMan<SesSender> = {
         let s = Settings::new().expect("invalid settings");
         let basket = s.basket.map(|b| Basket::new(b.api_key, b.basket_url));
-        MailMan::<SesSender>::new(s.packs.domain, s.packs.catcher, basket)
+        // without the pool suppressed recipients would silently receive emails again
+        let pool = POOL
+            .lock()
+            .ok()
+            .and_then(|mut pool| pool.take())
+            .expect("mail manager used before mail::manager::init");
+        MailMan::<SesSender>::new(s.packs.domain, s.packs.catcher, basket, Some(pool))
     };
 }
 
+/// Sets up the mail manager with the application's pool which is used to skip suppressed
+/// recipients. Must be called before any email is sent.
+#[cfg(all(not(test), not(feature = "local")))]
+pub fn init(pool: Pool) {
+    if let Ok(mut p) = POOL.lock() {
+        *p = Some(pool);
+    }
+    lazy_static::initialize(&MAIL_MAN);
+}
+
+#[cfg(any(test, feature = "local"))]
+pub fn init(_: Pool) {}
+
 #[cfg(all(not(test), not(feature = "local")))]
 pub fn send_email(to: String, t: &Template) {
     let message = MAIL_MAN.template_man.render(t);
@@ -35,6 +63,23 @@ pub fn send_emails(to: Vec<String>, t: &Template) {
     MAIL_MAN.send(Email::with_many(to, &MAIL_MAN.template_man.domain, message));
 }
 
+#[cfg(all(not(test), not(feature = "local")))]
+pub fn send_emails_with_reply_to(to: Vec<String>, reply_to: String, t: &Template) {
+    let message = MAIL_MAN.template_man.render(t);
+    let mut email = Email::with_many(to, &MAIL_MAN.template_man.domain, message);
+    email.reply_to = Some(reply_to);
+    MAIL_MAN.send(email);
+}
+
+/// Like `send_email` but waits for the email to be sent.
+#[cfg(all(not(test), not(feature = "local")))]
+pub async fn deliver_email(to: String, t: &Template) -> Result<(), Error> {
+    let message = MAIL_MAN.template_man.render(t);
+    MAIL_MAN
+        .deliver(Email::with(to, &MAIL_MAN.template_man.domain, message))
+        .await
+}
+
 #[cfg(all(not(test), not(feature = "local")))]
 pub fn send_email_raw(mut email: Email) {
     email.from = format!("no-reply@{}", &MAIL_MAN.template_man.domain);
@@ -47,24 +92,32 @@ pub fn send_email(_: String, _: &Template) {}
 #[cfg(any(test, feature = "local"))]
 pub fn send_emails(_: Vec<String>, _: &Template) {}
 
+#[cfg(any(test, feature = "local"))]
+pub async fn deliver_email(_: String, _: &Template) -> Result<(), Error> {
+    Ok(())
+}
+
+#[cfg(any(test, feature = "local"))]
+pub fn send_emails_with_reply_to(_: Vec<String>, _: String, _: &Template) {}
+
 #[cfg(any(test, feature = "local"))]
 pub fn send_email_raw(_: Email) {}
 
 #[cfg(all(not(test), not(feature = "local")))]
-pub fn subscribe_nda(email: String) {
-    MAIL_MAN.subscribe_nda(email);
+pub fn subscribe(email: String, newsletters: Vec<String>) {
+    MAIL_MAN.subscribe(email, newsletters);
 }
 
 #[cfg(all(not(test), not(feature = "local")))]
-pub fn unsubscribe_nda(email: String) {
-    MAIL_MAN.unsubscribe_nda(email);
+pub fn unsubscribe(email: String, newsletters: Vec<String>) {
+    MAIL_MAN.unsubscribe(email, newsletters);
 }
 
 #[cfg(any(test, feature = "local"))]
-pub fn subscribe_nda(_: String) {}
+pub fn subscribe(_: String, _: Vec<String>) {}
 
 #[cfg(any(test, feature = "local"))]
-pub fn unsubscribe_nda(_: String) {}
+pub fn unsubscribe(_: String, _: Vec<String>) {}
 
 #[derive(Clone)]
 pub struct MailMan<T: EmailSender> {
@@ -73,58 +126,121 @@ pub struct MailMan<T: EmailSender> {
     pub template_man: TemplateManager,
     pub catcher: Option<String>,
     pub basket: Option<Basket>,
+    pub pool: Option<Pool>,
 }
 
 impl<T: EmailSender> MailMan<T> {
-    pub fn new(domain: String, catcher: Option<String>, basket: Option<Basket>) -> Self {
+    pub fn new(
+        domain: String,
+        catcher: Option<String>,
+        basket: Option<Basket>,
+        pool: Option<Pool>,
+    ) -> Self {
         MailMan {
             arbiter: Arbiter::default(),
             sender: T::default(),
             template_man: TemplateManager::new(domain),
             catcher,
             basket,
+            pool,
         }
     }
 }
 
+/// Removes all suppressed recipients. Returns `None` if no recipient is left.
+fn skip_suppressed(pool: &Pool, mut e: Email) -> Option<Email> {
+    let recipients =
+        e.to.iter()
+            .chain(e.bcc.iter().flatten())
+            .cloned()
+            .collect::<Vec<String>>();
+    let suppressed =
+        match pool.get().map_err(Into::into).and_then(|connection| {
+            internal::suppression::suppressed_among(&connection, &recipients)
+        }) {
+            Ok(suppressed) => suppressed,
+            Err(e) => {
+                error!("Unable to check for suppressed emails: {}", e);
+                vec![]
+            }
+        };
+    if suppressed.is_empty() {
+        return Some(e);
+    }
+    info!("Skipping suppressed emails: {}", suppressed.join(", "));
+    e.to = e.to.filter(|to| !suppressed.contains(to));
+    e.bcc = e
+        .bcc
+        .map(|bcc| {
+            bcc.into_iter()
+                .filter(|b| !suppressed.contains(b))
+                .collect::<Vec<String>>()
+        })
+        .filter(|bcc| !bcc.is_empty());
+    if e.to.is_none() && e.bcc.is_none() {
+        None
+    } else {
+        Some(e)
+    }
+}
+
 impl<T: EmailSender> MailMan<T> {
-    pub fn send(&self, mut e: Email) {
-        if let Some(ref catcher) = self.catcher {
-            if let Some(to) = e.to {
-                e.message.body = format!("[to: caught for {}]\n\n{}", to, e.message.body);
-                e.to = Some(catcher.to_owned());
-            };
-            if let Some(bcc) = e.bcc {
-                e.message.body =
-                    format!("[bcc: caught for {}]\n\n{}", bcc.join(", "), e.message.body);
-                e.to = Some(catcher.to_owned());
-                e.bcc = None;
-            };
-        }
-        let s = self.sender.clone();
-        let f = Box::pin(async move {
-            if let Err(e) = s.send_email(e).await {
+    pub fn send(&self, e: Email) {
+        let f = self.deliver(e);
+        self.arbiter.send(Box::pin(async move {
+            if let Err(e) = f.await {
                 error!("Error sending email: {}", e);
             }
-        });
-        self.arbiter.send(f)
+        }))
+    }
+
+    /// Sends the email and resolves once it has been handed over to the sender.
+    pub fn deliver(&self, e: Email) -> Pin<Box<dyn Future<Output = Result<(), Error>> + Send>> {
+        let sender = self.sender.clone();
+        let catcher = self.catcher.clone();
+        let pool = self.pool.clone();
+        Box::pin(async move {
+            let mut e = match pool {
+                Some(pool) => {
+                    match web::block(move || Ok::<_, Error>(skip_suppressed(&pool, e)))
+                        .await
+                        .map_err(|e| format_err!("{}", e))?
+                    {
+                        Some(e) => e,
+                        None => return Ok(()),
+                    }
+                }
+                None => e,
+            };
+            if let Some(catcher) = catcher {
+                if let Some(to) = e.to {
+                    e.message.body = format!("[to: caught for {}]\n\n{}", to, e.message.body);
+                    e.to = Some(catcher.clone());
+                };
+                if let Some(bcc) = e.bcc {
+                    e.message.body =
+                        format!("[bcc: caught for {}]\n\n{}", bcc.join(", "), e.message.body);
+                    e.to = Some(catcher);
+                    e.bcc = None;
+                };
+            }
+            sender.send_email(e).await
+        })
     }
 
-    pub fn subscribe_nda(&self, email: String) {
+    pub fn subscribe(&self, email: String, newsletters: Vec<String>) {
         if let Some(basket) = self.basket.clone() {
             let f = Box::pin(async move {
-                if let Err(e) = basket
-                    .subscribe_private(&email, vec![MOZILLIAN_NDA_LIST.into()], None)
-                    .await
-                {
-                    error!("Error subscribing {} to nda-list: {}", email, e);
+                let lists = newsletters.join(", ");
+                if let Err(e) = basket.subscribe_private(&email, newsletters, None).await {
+                    error!("Error subscribing {} to {}: {}", email, lists, e);
                 }
             });
             self.arbiter.send(f)
         }
     }
 
-    pub fn unsubscribe_nda(&self, email: String) {
+    pub fn unsubscribe(&self, email: String, newsletters: Vec<String>) {
         if let Some(basket) = self.basket.clone() {
             let f = Box::pin(async move {
                 let token = match basket.lookup_user(&email).await {
@@ -140,11 +256,9 @@ impl<T: EmailSender> MailMan<T> {
                         return;
                     }
                 };
-                if let Err(e) = basket
-                    .unsubscribe(&token, vec![MOZILLIAN_NDA_LIST.into()], false)
-                    .await
-                {
-                    error!("Error unsubscribing {} to nda-list: {}", &email, e);
+                let lists = newsletters.join(", ");
+                if let Err(e) = basket.unsubscribe(&token, newsletters, false).await {
+                    error!("Error unsubscribing {} from {}: {}", &email, lists, e);
                 }
             });
             self.arbiter.send(f)
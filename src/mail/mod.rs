@@ -1,5 +1,6 @@
 pub mod manager;
 pub mod send;
+pub mod sns;
 pub mod templates;
 
 use rusoto_ses::Body;
@@ -35,6 +36,7 @@ pub struct Email {
     pub to: Option<String>,
     pub bcc: Option<Vec<String>>,
     pub from: String,
+    pub reply_to: Option<String>,
     pub message: Message,
 }
 
@@ -44,6 +46,7 @@ impl Email {
             to: Some(to),
             bcc: None,
             from: format!("no-reply@{}", domain),
+            reply_to: None,
             message,
         }
     }
@@ -52,6 +55,7 @@ impl Email {
             to: None,
             bcc: Some(bcc),
             from: format!("no-reply@{}", domain),
+            reply_to: None,
             message,
         }
     }
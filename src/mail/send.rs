@@ -36,6 +36,7 @@ impl Into<SendEmailRequest> for Email {
             destination,
             message,
             source: self.from,
+            reply_to_addresses: self.reply_to.map(|r| vec![r]),
             ..Default::default()
         }
     }
@@ -57,6 +58,7 @@ impl EmailSender for SesSender {
                     to: None,
                     bcc: Some(bcc),
                     from: email.from.clone(),
+                    reply_to: email.reply_to.clone(),
                     message: email.message.clone(),
                 };
 
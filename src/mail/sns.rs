@@ -0,0 +1,156 @@
+use crate::db::types::SuppressionType;
+use serde::Deserialize;
+use serde_json::json;
+use serde_json::Value;
+
+/// Envelope of messages delivered by an SNS http(s) subscription.
+#[derive(Deserialize)]
+#[serde(tag = "Type")]
+pub enum SnsMessage {
+    Notification {
+        #[serde(rename = "TopicArn")]
+        topic_arn: String,
+        #[serde(rename = "Message")]
+        message: String,
+    },
+    SubscriptionConfirmation {
+        #[serde(rename = "TopicArn")]
+        topic_arn: String,
+        #[serde(rename = "SubscribeURL")]
+        subscribe_url: String,
+    },
+    UnsubscribeConfirmation {
+        #[serde(rename = "TopicArn")]
+        topic_arn: String,
+    },
+}
+
+impl SnsMessage {
+    pub fn topic_arn(&self) -> &str {
+        match self {
+            SnsMessage::Notification { topic_arn, .. }
+            | SnsMessage::SubscriptionConfirmation { topic_arn, .. }
+            | SnsMessage::UnsubscribeConfirmation { topic_arn } => topic_arn,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Recipient {
+    pub email_address: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Bounce {
+    pub bounce_type: String,
+    #[serde(default)]
+    pub bounce_sub_type: Option<String>,
+    pub bounced_recipients: Vec<Recipient>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Complaint {
+    #[serde(default)]
+    pub complaint_feedback_type: Option<String>,
+    pub complained_recipients: Vec<Recipient>,
+}
+
+/// SES bounce, complaint and delivery notifications.
+#[derive(Deserialize)]
+#[serde(tag = "notificationType")]
+pub enum SesNotification {
+    Bounce {
+        bounce: Bounce,
+    },
+    Complaint {
+        complaint: Complaint,
+    },
+    #[serde(other)]
+    Other,
+}
+
+impl SesNotification {
+    /// Only permanent bounces and complaints lead to suppressed addresses.
+    pub fn suppressions(self) -> Vec<(String, SuppressionType, Value)> {
+        match self {
+            SesNotification::Bounce { bounce } if bounce.bounce_type == "Permanent" => {
+                let body = json!({
+                    "bounce_type": bounce.bounce_type,
+                    "bounce_sub_type": bounce.bounce_sub_type,
+                });
+                bounce
+                    .bounced_recipients
+                    .into_iter()
+                    .map(|r| (r.email_address, SuppressionType::Bounce, body.clone()))
+                    .collect()
+            }
+            SesNotification::Complaint { complaint } => {
+                let body = json!({ "complaint_feedback_type": complaint.complaint_feedback_type });
+                complaint
+                    .complained_recipients
+                    .into_iter()
+                    .map(|r| (r.email_address, SuppressionType::Complaint, body.clone()))
+                    .collect()
+            }
+            _ => vec![],
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use failure::Error;
+
+    #[test]
+    fn test_permanent_bounce() -> Result<(), Error> {
+        let sns = json!({
+            "Type": "Notification",
+            "MessageId": "22b80b92-fdea-4c2c-8f9d-bdfb0c7bf324",
+            "TopicArn": "arn:aws:sns:us-west-2:123456789012:ses-notifications",
+            "Message": json!({
+                "notificationType": "Bounce",
+                "bounce": {
+                    "bounceType": "Permanent",
+                    "bounceSubType": "General",
+                    "bouncedRecipients": [{ "emailAddress": "bounce@example.com" }],
+                },
+                "mail": {},
+            }).to_string(),
+        });
+        let sns = serde_json::from_value::<SnsMessage>(sns)?;
+        assert_eq!(
+            sns.topic_arn(),
+            "arn:aws:sns:us-west-2:123456789012:ses-notifications"
+        );
+        let message = match sns {
+            SnsMessage::Notification { message, .. } => message,
+            _ => panic!("not a notification"),
+        };
+        let suppressions = serde_json::from_str::<SesNotification>(&message)?.suppressions();
+        assert_eq!(suppressions.len(), 1);
+        assert_eq!(suppressions[0].0, "bounce@example.com");
+        assert_eq!(suppressions[0].1, SuppressionType::Bounce);
+        Ok(())
+    }
+
+    #[test]
+    fn test_transient_bounce_and_delivery() -> Result<(), Error> {
+        let transient = json!({
+            "notificationType": "Bounce",
+            "bounce": {
+                "bounceType": "Transient",
+                "bouncedRecipients": [{ "emailAddress": "full@example.com" }],
+            },
+        });
+        let suppressions = serde_json::from_value::<SesNotification>(transient)?.suppressions();
+        assert!(suppressions.is_empty());
+        let delivery = json!({ "notificationType": "Delivery", "delivery": {} });
+        let suppressions = serde_json::from_value::<SesNotification>(delivery)?.suppressions();
+        assert!(suppressions.is_empty());
+        Ok(())
+    }
+}
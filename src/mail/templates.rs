@@ -1,4 +1,9 @@
+use crate::db::types::NotificationCategoryType;
 use crate::mail::Message;
+use serde::Deserialize;
+use serde::Serialize;
+
+const SIGNATURE: &str = "\n\nCheers,\nThe Mozilla IAM Team";
 
 fn invitation(group_name: &str, domain: &str) -> Message {
     Message {
@@ -131,7 +136,14 @@ The Mozilla IAM Team",
     }
 }
 
-fn first_host_expiration(group_name: &str, user: &str, domain: &str) -> Message {
+fn in_days(days: i32) -> String {
+    match days {
+        1 => String::from("1 day"),
+        d => format!("{} days", d),
+    }
+}
+
+fn host_expiration(group_name: &str, user: &str, days: i32, domain: &str) -> Message {
     Message {
         subject: format!(
             "[{domain}] {user}'s membership of the '{group_name}' group is about to expire",
@@ -142,9 +154,9 @@ fn first_host_expiration(group_name: &str, user: &str, domain: &str) -> Message
         body: format!(
             "\
 Dear Curator,
-{user}'s membership of the '{group_name}' group will expire in 14 days.
+{user}'s membership of the '{group_name}' group will expire in {days}.
 
-Please visit https://{domain}/a/{group_name}//edit?section=members to renew the \
+Please visit https://{domain}/a/{group_name}/edit?section=members to renew the \
 membership if applicable.
 
 Or visit {user}'s profile first: https://{domain}/p/{user}
@@ -153,39 +165,40 @@ Cheers,
 The Mozilla IAM Team",
             group_name = group_name,
             user = user,
+            days = in_days(days),
             domain = domain
         ),
     }
 }
 
-fn second_host_expiration(group_name: &str, user: &str, domain: &str) -> Message {
+fn member_expiration(group_name: &str, days: i32, domain: &str) -> Message {
     Message {
         subject: format!(
-            "[{domain}] {user}'s membership of the '{group_name}' group is about to expire",
+            "[{domain}] Your membership of the '{group_name}' group is about to expire",
             group_name = group_name,
-            user = user,
             domain = domain
         ),
         body: format!(
             "\
-Dear Curator,
-{user}'s membership of the '{group_name}' group will expire in 7 days.
+Dear Mozillian,
+As per the terms of your membership to group '{group_name} your membership will expire in {days} \
+unless you are renewed by your group’s curators.
 
-Please visit https://{domain}/a/{group_name}/edit?section=members to renew the \
-membership if applicable.
+Your inviter has also been sent a notice for your renewal and will approve or reject your \
+membership renewal in the next {days}.
 
-Or visit {user}'s profile first: https://{domain}/p/{user}
+For more information visit the group page: https://{domain}/a/{group_name}
 
 Cheers,
 The Mozilla IAM Team",
             group_name = group_name,
-            user = user,
+            days = in_days(days),
             domain = domain
         ),
     }
 }
 
-fn member_expiration(group_name: &str, domain: &str) -> Message {
+fn member_self_renewal(group_name: &str, days: i32, domain: &str) -> Message {
     Message {
         subject: format!(
             "[{domain}] Your membership of the '{group_name}' group is about to expire",
@@ -195,17 +208,164 @@ fn member_expiration(group_name: &str, domain: &str) -> Message {
         body: format!(
             "\
 Dear Mozillian,
-As per the terms of your membership to group '{group_name} your membership will expire in 7 days \
-unless you are renewed by your group’s curators.
+your membership of the '{group_name}' group will expire in {days}.
 
-Your inviter has also been sent a notice for your renewal and will approve or reject your \
-membership renewal in the next 7 days.
+The curators of '{group_name}' allow members to renew their own membership. If you still need \
+access please visit https://{domain}/a/{group_name}?renew to renew your membership.
 
-For more information visit the group page: https://{domain}/a/{group_name}
+Cheers,
+The Mozilla IAM Team",
+            group_name = group_name,
+            days = in_days(days),
+            domain = domain
+        ),
+    }
+}
+
+fn member_trust_change(group_name: &str, days: i32, domain: &str) -> Message {
+    Message {
+        subject: format!(
+            "[{domain}] Your membership of the '{group_name}' group will be revoked",
+            group_name = group_name,
+            domain = domain
+        ),
+        body: format!(
+            "\
+Dear Mozillian,
+the '{group_name}' group is changing its access requirements and your profile does not meet \
+them anymore. Your membership will be revoked in {days}.
+
+If you have any questions please contact the curators of the group: https://{domain}/a/{group_name}
+
+Cheers,
+The Mozilla IAM Team",
+            group_name = group_name,
+            days = in_days(days),
+            domain = domain
+        ),
+    }
+}
+
+fn host_trust_change(group_name: &str, count: usize, days: i32, domain: &str) -> Message {
+    let members = match count {
+        1 => String::from("1 member does"),
+        c => format!("{} members do", c),
+    };
+    Message {
+        subject: format!(
+            "[{domain}] The access requirements of the '{group_name}' group are changing",
+            group_name = group_name,
+            domain = domain
+        ),
+        body: format!(
+            "\
+Dear Curator,
+the access requirements of the '{group_name}' group are changing and {members} not meet them \
+anymore. Their memberships will be revoked in {days} unless the change is cancelled by an admin.
+
+Please visit https://{domain}/a/{group_name}/edit?section=members to review the members.
+
+Cheers,
+The Mozilla IAM Team",
+            group_name = group_name,
+            members = members,
+            days = in_days(days),
+            domain = domain
+        ),
+    }
+}
+
+fn member_trust_change_cancelled(group_name: &str, domain: &str) -> Message {
+    Message {
+        subject: format!(
+            "[{domain}] Your membership of the '{group_name}' group will be kept",
+            group_name = group_name,
+            domain = domain
+        ),
+        body: format!(
+            "\
+Dear Mozillian,
+the change of the access requirements of the '{group_name}' group has been cancelled. Your \
+membership will not be revoked.
 
 Cheers,
 The Mozilla IAM Team",
             group_name = group_name,
+        ),
+    }
+}
+
+fn host_trust_change_cancelled(group_name: &str, domain: &str) -> Message {
+    Message {
+        subject: format!(
+            "[{domain}] The access requirements of the '{group_name}' group are not changing",
+            group_name = group_name,
+            domain = domain
+        ),
+        body: format!(
+            "\
+Dear Curator,
+the change of the access requirements of the '{group_name}' group has been cancelled by an \
+admin. No memberships will be revoked.
+
+Cheers,
+The Mozilla IAM Team",
+            group_name = group_name,
+        ),
+    }
+}
+
+fn member_trust_drop(group_names: &[String], days: i32, domain: &str) -> Message {
+    let groups = group_names
+        .iter()
+        .map(|group_name| format!("- https://{}/a/{}", domain, group_name))
+        .collect::<Vec<_>>()
+        .join("\n");
+    Message {
+        subject: format!(
+            "[{domain}] Your group memberships are about to be revoked",
+            domain = domain
+        ),
+        body: format!(
+            "\
+Dear Mozillian,
+your profile does not meet the access requirements of the following groups anymore:
+
+{groups}
+
+Your memberships will be revoked in {days} unless your profile meets the requirements again.
+If you think this is a mistake please check your profile at https://{domain}
+
+Cheers,
+The Mozilla IAM Team",
+            groups = groups,
+            days = in_days(days),
+            domain = domain
+        ),
+    }
+}
+
+fn host_trust_drop(group_name: &str, user: &str, days: i32, domain: &str) -> Message {
+    Message {
+        subject: format!(
+            "[{domain}] {user}'s membership of the '{group_name}' group is about to be revoked",
+            group_name = group_name,
+            user = user,
+            domain = domain
+        ),
+        body: format!(
+            "\
+Dear Curator,
+{user}'s profile does not meet the access requirements of the '{group_name}' group anymore.
+The membership will be revoked in {days} unless the profile meets the requirements again.
+
+You can visit {user}'s profile here: https://{domain}/p/{user}
+
+Cheers,
+The Mozilla IAM Team",
+            group_name = group_name,
+            user = user,
+            days = in_days(days),
             domain = domain
         ),
     }
@@ -316,6 +476,67 @@ The Mozilla IAM Team",
     }
 }
 
+fn broadcast(group_name: &str, user: &str, subject: &str, body: &str, domain: &str) -> Message {
+    Message {
+        subject: format!(
+            "[{domain}] [{group_name}] {subject}",
+            group_name = group_name,
+            subject = subject,
+            domain = domain
+        ),
+        body: format!(
+            "\
+{body}
+
+---
+You are receiving this message as a member of the '{group_name}' access group.
+It was sent by https://{domain}/p/{user} and replies will go directly to them.
+For more information visit the group page: https://{domain}/a/{group_name}",
+            group_name = group_name,
+            user = user,
+            body = body,
+            domain = domain
+        ),
+    }
+}
+
+fn digest_entry(message: Message) -> String {
+    let body = message.body.trim_end_matches(SIGNATURE);
+    // skip the salutation
+    let body = body.split_once('\n').map(|(_, b)| b).unwrap_or(body);
+    format!("{}\n{}", message.subject, body)
+}
+
+fn digest(messages: Vec<Message>, domain: &str) -> Message {
+    let count = messages.len();
+    let entries = messages
+        .into_iter()
+        .map(digest_entry)
+        .collect::<Vec<_>>()
+        .join("\n\n---\n\n");
+    Message {
+        subject: format!(
+            "[{domain}] Your notification digest ({count} updates)",
+            count = count,
+            domain = domain
+        ),
+        body: format!(
+            "\
+Dear Mozillian,
+here is a summary of your group notifications since the last digest:
+
+{entries}
+
+You can change how often you receive these notifications in your settings at https://{domain}
+
+Cheers,
+The Mozilla IAM Team",
+            entries = entries,
+            domain = domain
+        ),
+    }
+}
+
 #[derive(Clone)]
 pub struct TemplateManager {
     pub domain: String,
@@ -338,14 +559,32 @@ impl TemplateManager {
             }
             Template::DemoteCurator(ref group_name) => demote_curator(group_name, &self.domain),
             Template::DeleteMember(ref group_name) => delete_member(group_name, &self.domain),
-            Template::MemberExpiration(ref group_name) => {
-                member_expiration(group_name, &self.domain)
+            Template::MemberExpiration(ref group_name, days) => {
+                member_expiration(group_name, *days, &self.domain)
+            }
+            Template::MemberSelfRenewal(ref group_name, days) => {
+                member_self_renewal(group_name, *days, &self.domain)
+            }
+            Template::HostExpiration(ref group_name, ref user, days) => {
+                host_expiration(group_name, user, *days, &self.domain)
+            }
+            Template::MemberTrustChange(ref group_name, days) => {
+                member_trust_change(group_name, *days, &self.domain)
+            }
+            Template::HostTrustChange(ref group_name, count, days) => {
+                host_trust_change(group_name, *count, *days, &self.domain)
+            }
+            Template::MemberTrustChangeCancelled(ref group_name) => {
+                member_trust_change_cancelled(group_name, &self.domain)
+            }
+            Template::HostTrustChangeCancelled(ref group_name) => {
+                host_trust_change_cancelled(group_name, &self.domain)
             }
-            Template::FirstHostExpiration(ref group_name, ref user) => {
-                first_host_expiration(group_name, user, &self.domain)
+            Template::MemberTrustDrop(ref group_names, days) => {
+                member_trust_drop(group_names, *days, &self.domain)
             }
-            Template::SecondHostExpiration(ref group_name, ref user) => {
-                second_host_expiration(group_name, user, &self.domain)
+            Template::HostTrustDrop(ref group_name, ref user, days) => {
+                host_trust_drop(group_name, user, *days, &self.domain)
             }
             Template::PendingRequest(ref group_name, count) => {
                 pending_request(group_name, *count, &self.domain)
@@ -354,10 +593,18 @@ impl TemplateManager {
                 group_deleted(group_name, user, &self.domain)
             }
             Template::AnonymousMember => anonymous_member(&self.domain),
+            Template::Broadcast(ref group_name, ref user, ref subject, ref body) => {
+                broadcast(group_name, user, subject, body, &self.domain)
+            }
+            Template::Digest(ref templates) => digest(
+                templates.iter().map(|t| self.render(t)).collect(),
+                &self.domain,
+            ),
         }
     }
 }
 
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub enum Template {
     Invitation(String),
     CustomInvitation(String, String),
@@ -365,10 +612,72 @@ pub enum Template {
     DeleteInvitation(String),
     DemoteCurator(String),
     DeleteMember(String),
-    MemberExpiration(String),
-    FirstHostExpiration(String, String),
-    SecondHostExpiration(String, String),
+    MemberExpiration(String, i32),
+    MemberSelfRenewal(String, i32),
+    HostExpiration(String, String, i32),
+    MemberTrustChange(String, i32),
+    HostTrustChange(String, usize, i32),
+    MemberTrustChangeCancelled(String),
+    HostTrustChangeCancelled(String),
+    MemberTrustDrop(Vec<String>, i32),
+    HostTrustDrop(String, String, i32),
     PendingRequest(String, usize),
     GroupDeleted(String, String),
     AnonymousMember,
+    Broadcast(String, String, String, String),
+    Digest(Vec<Template>),
+}
+
+impl Template {
+    /// Templates without a category are mandatory and always sent immediately.
+    pub fn category(&self) -> Option<NotificationCategoryType> {
+        match self {
+            Template::Invitation(_) | Template::CustomInvitation(_, _) => {
+                Some(NotificationCategoryType::Invitations)
+            }
+            Template::RejectRequest(_) | Template::PendingRequest(_, _) => {
+                Some(NotificationCategoryType::Requests)
+            }
+            Template::MemberExpiration(_, _)
+            | Template::MemberSelfRenewal(_, _)
+            | Template::HostExpiration(_, _, _) => Some(NotificationCategoryType::Expirations),
+            Template::DeleteInvitation(_)
+            | Template::DemoteCurator(_)
+            | Template::DeleteMember(_)
+            | Template::MemberTrustChange(_, _)
+            | Template::HostTrustChange(_, _, _)
+            | Template::MemberTrustChangeCancelled(_)
+            | Template::HostTrustChangeCancelled(_)
+            | Template::MemberTrustDrop(_, _)
+            | Template::HostTrustDrop(_, _, _)
+            | Template::GroupDeleted(_, _)
+            | Template::AnonymousMember
+            | Template::Broadcast(_, _, _, _)
+            | Template::Digest(_) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_digest_strips_salutation_and_signature() {
+        let template_man = TemplateManager::new(String::from("example.com"));
+        let message = template_man.render(&Template::Digest(vec![
+            Template::Invitation(String::from("foo")),
+            Template::PendingRequest(String::from("bar"), 2),
+        ]));
+        assert_eq!(
+            message.subject,
+            "[example.com] Your notification digest (2 updates)"
+        );
+        assert_eq!(message.body.matches("Dear").count(), 1);
+        assert_eq!(message.body.matches("Cheers,").count(), 1);
+        assert!(message
+            .body
+            .contains("you've been invited to join the access group 'foo'."));
+        assert!(message.body.contains("are 2 pending requests"));
+    }
 }
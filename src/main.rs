@@ -8,13 +8,21 @@ use actix_web::middleware::Logger;
 use actix_web::web;
 use actix_web::App;
 use actix_web::HttpServer;
+use cis::operations::LocalPublisher;
+use cis::operations::LocalTarget;
+use cis::operations::NoopPublisher;
+use cis::operations::ProfilePublisher;
 use cis_client::CisClient;
+use db::Pool;
 use dino_park_gate::provider::Provider;
 use dino_park_gate::scope::ScopeAndUserAuth;
 use log::debug;
 use log::info;
+use settings::Publisher;
+use settings::Schedules;
 use std::io::Error;
 use std::io::ErrorKind;
+use std::sync::Arc;
 
 embed_migrations!();
 
@@ -22,48 +30,123 @@ fn map_io_err(e: impl Into<failure::Error>) -> Error {
     Error::new(ErrorKind::Other, e.into())
 }
 
-#[actix_rt::main]
-async fn main() -> std::io::Result<()> {
-    if std::env::var("RUST_LOG").is_err() {
-        std::env::set_var("RUST_LOG", "info");
-    }
-    env_logger::init();
-    info!("starting dino-park-packs");
-    debug!("DEBUG logging enabled");
-
-    let s = settings::Settings::new().map_err(map_io_err)?;
-    let cis_client = CisClient::from_settings(&s.cis).await.map_err(map_io_err)?;
-
-    let pool = db::establish_connection(&s.packs.postgres_url);
-    embedded_migrations::run_with_output(&pool.get().map_err(map_io_err)?, &mut std::io::stdout())
-        .map_err(map_io_err)?;
-
-    let provider = Provider::from_issuer(&s.auth).await.map_err(map_io_err)?;
+async fn serve<T: ProfilePublisher + Clone + Send + 'static>(
+    publisher: T,
+    pool: Pool,
+    provider: Provider,
+    domain: String,
+    suspension_grace_days: i64,
+    sns_topic_arn: Option<String>,
+    schedules: &Schedules,
+) -> std::io::Result<()> {
+    scheduler::start(schedules, &pool, Arc::new(publisher.clone())).map_err(map_io_err)?;
     HttpServer::new(move || {
         let scope_middleware = ScopeAndUserAuth::new(provider.clone());
         App::new()
-            .data(cis_client.clone())
+            .data(publisher.clone())
             .data(pool.clone())
             .wrap(Logger::default().exclude("/healthz"))
             .service(healthz::healthz_app())
-            .service(api::internal::internal_app::<CisClient>())
-            .service(import::api::import_app::<CisClient>())
+            .service(api::internal::internal_app::<T>(
+                suspension_grace_days,
+                sns_topic_arn.clone(),
+            ))
+            .service(import::api::import_app::<T>())
+            .service(api::calendar::calendar_app(domain.clone()))
             .service(
                 web::scope("/groups/api/v1/")
                     .wrap(scope_middleware)
-                    .service(api::groups::groups_app::<CisClient>())
-                    .service(api::members::members_app::<CisClient>())
-                    .service(api::current::current_app::<CisClient>())
+                    .service(api::groups::groups_app::<T>())
+                    .service(api::members::members_app::<T>())
+                    .service(api::current::current_app::<T>())
                     .service(api::invitations::invitations_app())
                     .service(api::terms::terms_app())
                     .service(api::users::users_app())
-                    .service(api::admins::admins_app::<CisClient>())
+                    .service(api::admins::admins_app::<T>())
                     .service(api::requests::requests_app())
-                    .service(api::sudo::sudo_app::<CisClient>())
-                    .service(api::forms::forms_app::<CisClient>()),
+                    .service(api::sudo::sudo_app::<T>())
+                    .service(api::forms::forms_app::<T>()),
             )
     })
     .bind("0.0.0.0:8085")?
     .run()
     .await
 }
+
+#[actix_rt::main]
+async fn main() -> std::io::Result<()> {
+    if std::env::var("RUST_LOG").is_err() {
+        std::env::set_var("RUST_LOG", "info");
+    }
+    env_logger::init();
+    info!("starting dino-park-packs");
+    debug!("DEBUG logging enabled");
+
+    let s = settings::Settings::new().map_err(map_io_err)?;
+
+    let pool = db::establish_connection(&s.packs.postgres_url);
+    embedded_migrations::run_with_output(&pool.get().map_err(map_io_err)?, &mut std::io::stdout())
+        .map_err(map_io_err)?;
+    mail::manager::init(pool.clone());
+
+    let provider = Provider::from_issuer(&s.auth).await.map_err(map_io_err)?;
+    let domain = s.packs.domain.clone();
+    let suspension_grace_days = s.packs.suspension_grace_days;
+    let sns_topic_arn = s.packs.sns_topic_arn.clone();
+    info!("publishing memberships to: {:?}", s.publisher);
+    match s.publisher {
+        Publisher::Cis => {
+            let cis = s
+                .cis
+                .as_ref()
+                .ok_or_else(|| Error::new(ErrorKind::Other, "missing cis settings"))?;
+            let cis_client = CisClient::from_settings(cis).await.map_err(map_io_err)?;
+            serve(
+                cis_client,
+                pool,
+                provider,
+                domain,
+                suspension_grace_days,
+                sns_topic_arn,
+                &s.schedules,
+            )
+            .await
+        }
+        Publisher::None => {
+            serve(
+                NoopPublisher,
+                pool,
+                provider,
+                domain,
+                suspension_grace_days,
+                sns_topic_arn,
+                &s.schedules,
+            )
+            .await
+        }
+        Publisher::File { path } => {
+            serve(
+                LocalPublisher::new(LocalTarget::File(path)),
+                pool,
+                provider,
+                domain,
+                suspension_grace_days,
+                sns_topic_arn,
+                &s.schedules,
+            )
+            .await
+        }
+        Publisher::Http { url } => {
+            serve(
+                LocalPublisher::new(LocalTarget::Http(url)),
+                pool,
+                provider,
+                domain,
+                suspension_grace_days,
+                sns_topic_arn,
+                &s.schedules,
+            )
+            .await
+        }
+    }
+}
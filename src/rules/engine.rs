@@ -40,6 +40,10 @@ pub const EDIT_TERMS: Engine = Engine {
     rules: &[&rule_host_can_edit_terms],
 };
 
+pub const EMAIL_MEMBERS: Engine = Engine {
+    rules: &[&rule_host_can_email_members],
+};
+
 pub const CAN_ADD_CURATOR: Engine = Engine {
     rules: &[&rule_host_is_curator, &member_is_ndaed],
 };
@@ -22,6 +22,8 @@ pub enum RuleError {
     NotAMember,
     #[fail(display = "rule_not_allowed_to_edit_terms")]
     NotAllowedToEditTerms,
+    #[fail(display = "rule_not_allowed_to_email_members")]
+    NotAllowedToEmailMembers,
     #[fail(display = "rule_never_allowed")]
     NeverAllowed,
     #[fail(display = "rule_invalid_context")]
@@ -165,6 +165,21 @@ pub fn rule_host_can_edit_terms(ctx: &RuleContext) -> Result<(), RuleError> {
     }
 }
 
+/// Check if the host is either `RoleType::Admin` or has `EmailMembers` permissions for the given
+/// group.
+pub fn rule_host_can_email_members(ctx: &RuleContext) -> Result<(), RuleError> {
+    let connection = ctx.pool.get().map_err(|_| RuleError::PoolError)?;
+    match internal::member::role_for(&connection, ctx.host_uuid, ctx.group) {
+        Ok(Some(role))
+            if role.typ == RoleType::Admin
+                || role.permissions.contains(&PermissionType::EmailMembers) =>
+        {
+            Ok(())
+        }
+        _ => Err(RuleError::NotAllowedToEmailMembers),
+    }
+}
+
 /// Check if the group name is in a valid format
 pub fn rule_valid_group_name(ctx: &RuleContext) -> Result<(), RuleError> {
     if valid_group_name(ctx.group) {
@@ -0,0 +1,192 @@
+use crate::cis::operations::ProfilePublisher;
+use crate::db::internal;
+use crate::db::operations;
+use crate::db::operations::models::DisplayJobRun;
+use crate::db::types::JobType;
+use crate::db::Pool;
+use crate::error::PacksError;
+use crate::settings::Schedules;
+use actix_rt::time::delay_for;
+use chrono::DateTime;
+use chrono::NaiveDateTime;
+use chrono::Timelike;
+use chrono::Utc;
+use cron::Schedule;
+use diesel::PgConnection;
+use failure::format_err;
+use failure::Error;
+use log::error;
+use log::info;
+use log::warn;
+use std::str::FromStr;
+use std::sync::Arc;
+
+async fn execute(
+    pool: &Pool,
+    publisher: Arc<impl ProfilePublisher>,
+    job: JobType,
+) -> Result<Option<String>, Error> {
+    match job {
+        JobType::ExpireRequests => operations::expirations::expire_requests(pool).map(|_| None),
+        JobType::ExpireInvitations => {
+            operations::expirations::expire_invitations(pool).map(|_| None)
+        }
+        JobType::ExpireMemberships => operations::expirations::expire_memberships(pool, publisher)
+            .await
+            .map(|_| None),
+        JobType::ExpirationNotification => {
+            let sent = operations::expirations::expiration_notification(pool)?;
+            Ok(Some(format!("{} reminders", sent)))
+        }
+        JobType::PendingRequestsNotification => {
+            operations::requests::pending_requests_notification(pool).map(|_| None)
+        }
+        JobType::NotifyAnonymousMembers => {
+            operations::members::notify_anonymous_members(pool).map(|_| None)
+        }
+        JobType::ApplyTrustChanges => {
+            let applied = operations::groups::apply_staged_trust_changes(pool, publisher).await?;
+            Ok(Some(format!("{} trust changes applied", applied)))
+        }
+        JobType::RevokeAtRiskMemberships => {
+            let revoked = operations::users::revoke_at_risk_memberships(pool, publisher).await?;
+            Ok(Some(format!("{} at risk memberships revoked", revoked)))
+        }
+    }
+}
+
+async fn run_locked(
+    pool: &Pool,
+    connection: &PgConnection,
+    publisher: Arc<impl ProfilePublisher>,
+    job: JobType,
+    tick: NaiveDateTime,
+) -> Result<bool, Error> {
+    if let Some(run) = internal::job::last_run(connection, job)? {
+        if run.tick >= tick {
+            return Ok(false);
+        }
+    }
+    internal::job::start(connection, job, tick)?;
+    match execute(pool, publisher, job).await {
+        Ok(message) => {
+            info!("job {:?} finished", job);
+            internal::job::finish(connection, job, true, message)?;
+        }
+        Err(e) => {
+            warn!("job {:?} failed: {}", job, e);
+            internal::job::finish(connection, job, false, Some(e.to_string()))?;
+        }
+    }
+    Ok(true)
+}
+
+/// Runs `job` for the scheduled `tick` unless another replica is running it or already ran it
+/// for this `tick`. Returns whether the job ran. Failures of the job itself are recorded with
+/// the run.
+pub async fn run_scheduled(
+    pool: &Pool,
+    publisher: Arc<impl ProfilePublisher>,
+    job: JobType,
+    tick: DateTime<Utc>,
+) -> Result<bool, Error> {
+    // the advisory lock belongs to the session of its connection which is kept for the whole run
+    let lock = match internal::job::try_lock(pool, job)? {
+        Some(lock) => lock,
+        None => {
+            info!("job {:?} is running on another replica", job);
+            return Ok(false);
+        }
+    };
+    run_locked(pool, &lock, publisher, job, whole_seconds(tick)).await
+}
+
+/// Runs `job` right away under the same lock as scheduled runs and records it as the latest run.
+/// Fails if another replica is running the job or the job itself failed.
+pub async fn run_now(
+    pool: &Pool,
+    publisher: Arc<impl ProfilePublisher>,
+    job: JobType,
+) -> Result<DisplayJobRun, Error> {
+    let lock = match internal::job::try_lock(pool, job)? {
+        Some(lock) => lock,
+        None => return Err(PacksError::JobInProgress.into()),
+    };
+    run_locked(pool, &lock, publisher, job, whole_seconds(Utc::now())).await?;
+    let run = internal::job::last_run(&lock, job)?
+        .ok_or_else(|| format_err!("no run of job {:?} recorded", job))?;
+    match run.success {
+        Some(false) => Err(format_err!(
+            "job {:?} failed: {}",
+            job,
+            run.message.unwrap_or_default()
+        )),
+        _ => Ok(run.into()),
+    }
+}
+
+/// Cron ticks are whole seconds, this also keeps them intact within postgres' precision.
+fn whole_seconds(tick: DateTime<Utc>) -> NaiveDateTime {
+    tick.naive_utc()
+        .with_nanosecond(0)
+        .unwrap_or_else(|| tick.naive_utc())
+}
+
+fn schedules(schedules: &Schedules) -> Vec<(JobType, &Option<String>)> {
+    vec![
+        (JobType::ExpireRequests, &schedules.expire_requests),
+        (JobType::ExpireInvitations, &schedules.expire_invitations),
+        (JobType::ExpireMemberships, &schedules.expire_memberships),
+        (
+            JobType::ExpirationNotification,
+            &schedules.expiration_notification,
+        ),
+        (
+            JobType::PendingRequestsNotification,
+            &schedules.pending_requests_notification,
+        ),
+        (
+            JobType::NotifyAnonymousMembers,
+            &schedules.notify_anonymous_members,
+        ),
+        (JobType::ApplyTrustChanges, &schedules.apply_trust_changes),
+        (
+            JobType::RevokeAtRiskMemberships,
+            &schedules.revoke_at_risk_memberships,
+        ),
+    ]
+}
+
+fn spawn_job<P: ProfilePublisher + 'static>(
+    pool: Pool,
+    publisher: Arc<P>,
+    job: JobType,
+    schedule: Schedule,
+) {
+    actix_rt::spawn(async move {
+        while let Some(tick) = schedule.upcoming(Utc).next() {
+            let wait = (tick - Utc::now()).to_std().unwrap_or_default();
+            delay_for(wait).await;
+            if let Err(e) = run_scheduled(&pool, Arc::clone(&publisher), job, tick).await {
+                error!("unable to run job {:?}: {}", job, e);
+            }
+        }
+    });
+}
+
+/// Spawns a task for every job with a schedule. Must be called from within the actix runtime.
+pub fn start<P: ProfilePublisher + 'static>(
+    settings: &Schedules,
+    pool: &Pool,
+    publisher: Arc<P>,
+) -> Result<(), Error> {
+    for (job, expression) in schedules(settings) {
+        if let Some(expression) = expression {
+            let schedule = Schedule::from_str(expression)
+                .map_err(|e| format_err!("invalid schedule for {:?}: {}", job, e))?;
+            info!("scheduling job {:?} at {}", job, expression);
+            spawn_job(pool.clone(), Arc::clone(&publisher), job, schedule);
+        }
+    }
+    Ok(())
+}
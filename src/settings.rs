@@ -2,6 +2,7 @@ use cis_client::settings::CisSettings;
 use config::{Config, ConfigError, Environment, File};
 use serde::Deserialize;
 use std::env;
+use std::path::PathBuf;
 use url::Url;
 
 #[derive(Debug, Deserialize)]
@@ -9,6 +10,16 @@ pub struct Packs {
     pub postgres_url: String,
     pub domain: String,
     pub catcher: Option<String>,
+    /// Days after which suspended memberships of inactive users are revoked.
+    #[serde(default = "default_suspension_grace_days")]
+    pub suspension_grace_days: i64,
+    /// ARN of the SNS topic delivering SES bounces and complaints. SNS messages of other topics
+    /// are rejected.
+    pub sns_topic_arn: Option<String>,
+}
+
+fn default_suspension_grace_days() -> i64 {
+    30
 }
 
 #[derive(Debug, Deserialize)]
@@ -17,12 +28,45 @@ pub struct Basket {
     pub basket_url: Url,
 }
 
+/// Where group memberships get published to. Everything but `cis` runs packs as a standalone
+/// group manager.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "mode", rename_all = "lowercase")]
+pub enum Publisher {
+    Cis,
+    None,
+    File { path: PathBuf },
+    Http { url: Url },
+}
+
+fn default_publisher() -> Publisher {
+    Publisher::Cis
+}
+
+/// Cron expressions including seconds (e.g. `0 0 3 * * *`) for the jobs of the built-in
+/// scheduler. Jobs without a schedule are not run in-process.
+#[derive(Debug, Default, Deserialize)]
+pub struct Schedules {
+    pub expire_requests: Option<String>,
+    pub expire_invitations: Option<String>,
+    pub expire_memberships: Option<String>,
+    pub expiration_notification: Option<String>,
+    pub pending_requests_notification: Option<String>,
+    pub notify_anonymous_members: Option<String>,
+    pub apply_trust_changes: Option<String>,
+    pub revoke_at_risk_memberships: Option<String>,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct Settings {
     pub auth: String,
-    pub cis: CisSettings,
+    pub cis: Option<CisSettings>,
     pub packs: Packs,
     pub basket: Option<Basket>,
+    #[serde(default = "default_publisher")]
+    pub publisher: Publisher,
+    #[serde(default)]
+    pub schedules: Schedules,
 }
 
 impl Settings {
@@ -0,0 +1,103 @@
+use crate::helpers::api::*;
+use crate::helpers::db::reset;
+use crate::helpers::misc::read_json;
+use crate::helpers::misc::test_app;
+use crate::helpers::misc::Soa;
+use crate::helpers::sudo::add_to_group;
+use crate::helpers::users::basic_user;
+use crate::helpers::users::user_uuid;
+use actix_web::test;
+use actix_web::App;
+use failure::Error;
+use serde_json::json;
+
+#[actix_rt::test]
+async fn broadcast() -> Result<(), Error> {
+    reset()?;
+    let app = App::new().service(test_app().await);
+    let mut app = test::init_service(app).await;
+
+    let host_user = basic_user(1, true);
+    let member_user = basic_user(2, true);
+    let curator_user = basic_user(3, true);
+    let host = Soa::from(&host_user).aal_medium();
+    let member = Soa::from(&member_user).aal_medium();
+    let curator = Soa::from(&curator_user).aal_medium();
+
+    let res = post(
+        &mut app,
+        "/groups/api/v1/groups",
+        json!({ "name": "broadcast-test", "description": "a group" }),
+        &host.clone().creator(),
+    )
+    .await;
+    assert!(res.status().is_success());
+    add_to_group(
+        &mut app,
+        &host.clone().admin(),
+        &member_user,
+        "broadcast-test",
+    )
+    .await;
+    let res = post(
+        &mut app,
+        "/groups/api/v1/curators/broadcast-test",
+        json!({ "member_uuid": user_uuid(&curator_user) }),
+        &host,
+    )
+    .await;
+    assert!(res.status().is_success());
+
+    let res = post(
+        &mut app,
+        "/groups/api/v1/members/broadcast-test/email",
+        json!({ "subject": "", "body": "hello" }),
+        &host,
+    )
+    .await;
+    assert_eq!(res.status().as_u16(), 400);
+    assert_eq!(read_json(res).await["error"], "invalid_broadcast");
+
+    let res = post(
+        &mut app,
+        "/groups/api/v1/members/broadcast-test/email",
+        json!({ "subject": "hello", "body": "hello members" }),
+        &member,
+    )
+    .await;
+    assert!(!res.status().is_success());
+
+    let res = post(
+        &mut app,
+        "/groups/api/v1/members/broadcast-test/email",
+        json!({ "subject": "hello", "body": "hello members", "roles": ["Member"] }),
+        &host,
+    )
+    .await;
+    assert!(res.status().is_success());
+    assert_eq!(read_json(res).await, json!({ "recipients": 1 }));
+
+    for soa in &[&curator, &host] {
+        let res = post(
+            &mut app,
+            "/groups/api/v1/members/broadcast-test/email",
+            json!({ "subject": "hello", "body": "hello everyone" }),
+            soa,
+        )
+        .await;
+        assert!(res.status().is_success());
+        assert_eq!(read_json(res).await, json!({ "recipients": 3 }));
+    }
+
+    let res = post(
+        &mut app,
+        "/groups/api/v1/members/broadcast-test/email",
+        json!({ "subject": "hello", "body": "hello again" }),
+        &host,
+    )
+    .await;
+    assert_eq!(res.status().as_u16(), 400);
+    assert_eq!(read_json(res).await["error"], "broadcast_limit_reached");
+
+    Ok(())
+}
@@ -1,14 +1,22 @@
 use crate::helpers::api::*;
+use crate::helpers::db::get_pool;
 use crate::helpers::db::reset;
+use crate::helpers::misc::nobody_soa;
 use crate::helpers::misc::read_json;
 use crate::helpers::misc::test_app;
+use crate::helpers::misc::test_app_and_cis;
 use crate::helpers::misc::Soa;
 use crate::helpers::users::basic_user;
 use crate::helpers::users::user_uuid;
 use actix_web::test;
 use actix_web::App;
+use cis_profile::schema::KeyValue;
+use diesel::RunQueryDsl;
+use dino_park_packs::db::operations::expirations::expiration_notification;
+use dino_park_packs::db::operations::expirations::expire_memberships;
 use failure::Error;
 use serde_json::json;
+use std::sync::Arc;
 
 #[actix_rt::test]
 async fn revoke_nda() -> Result<(), Error> {
@@ -65,3 +73,625 @@ async fn revoke_nda() -> Result<(), Error> {
 
     Ok(())
 }
+
+#[actix_rt::test]
+async fn expiration_reminders_are_sent_once() -> Result<(), Error> {
+    reset()?;
+    let service = test_app().await;
+    let app = App::new().service(service);
+    let mut app = test::init_service(app).await;
+    let pool = get_pool();
+
+    let host_user = basic_user(1, true);
+    let normal_user_1 = basic_user(11, false);
+    let host = Soa::from(&host_user).aal_medium();
+
+    let res = post(
+        &mut app,
+        "/groups/api/v1/groups",
+        json!({ "name": "exp-notify", "description": "a group", "trust": "Authenticated" }),
+        &host.clone().creator(),
+    )
+    .await;
+    assert!(res.status().is_success());
+
+    let res = post(
+        &mut app,
+        "/groups/api/v1/sudo/member/exp-notify",
+        json!({ "user_uuid": user_uuid(&normal_user_1), "group_expiration": 10 }),
+        &host.clone().admin(),
+    )
+    .await;
+    assert!(res.status().is_success());
+
+    assert_eq!(expiration_notification(&pool)?, 1);
+    assert_eq!(expiration_notification(&pool)?, 0);
+
+    let res = post(
+        &mut app,
+        &format!(
+            "/groups/api/v1/members/exp-notify/{}/renew",
+            user_uuid(&normal_user_1)
+        ),
+        json!({ "group_expiration": 3 }),
+        &host,
+    )
+    .await;
+    assert!(res.status().is_success());
+
+    assert_eq!(expiration_notification(&pool)?, 1);
+    assert_eq!(expiration_notification(&pool)?, 0);
+
+    Ok(())
+}
+
+#[actix_rt::test]
+async fn custom_reminder_schedule() -> Result<(), Error> {
+    reset()?;
+    let service = test_app().await;
+    let app = App::new().service(service);
+    let mut app = test::init_service(app).await;
+    let pool = get_pool();
+
+    let host_user = basic_user(1, true);
+    let normal_user_1 = basic_user(11, false);
+    let host = Soa::from(&host_user).aal_medium();
+
+    let res = post(
+        &mut app,
+        "/groups/api/v1/groups",
+        json!({ "name": "exp-schedule", "description": "a group", "trust": "Authenticated" }),
+        &host.clone().creator(),
+    )
+    .await;
+    assert!(res.status().is_success());
+
+    let res = get(
+        &mut app,
+        "/groups/api/v1/groups/exp-schedule/details",
+        &host,
+    )
+    .await;
+    assert!(res.status().is_success());
+    let details = read_json(res).await;
+    assert_eq!(details["group"]["reminders"][0]["days"], 14);
+
+    let res = put(
+        &mut app,
+        "/groups/api/v1/groups/exp-schedule",
+        json!({ "reminders": [
+            { "days": 30, "member": true },
+            { "days": 3, "host": true, "curators": true },
+        ] }),
+        &host,
+    )
+    .await;
+    assert!(res.status().is_success());
+
+    let res = get(
+        &mut app,
+        "/groups/api/v1/groups/exp-schedule/details",
+        &host,
+    )
+    .await;
+    assert!(res.status().is_success());
+    let details = read_json(res).await;
+    assert_eq!(details["group"]["reminders"][0]["days"], 30);
+    assert_eq!(details["group"]["reminders"][1]["days"], 3);
+
+    let res = post(
+        &mut app,
+        "/groups/api/v1/sudo/member/exp-schedule",
+        json!({ "user_uuid": user_uuid(&normal_user_1), "group_expiration": 20 }),
+        &host.clone().admin(),
+    )
+    .await;
+    assert!(res.status().is_success());
+
+    assert_eq!(expiration_notification(&pool)?, 1);
+    assert_eq!(expiration_notification(&pool)?, 0);
+
+    let res = put(
+        &mut app,
+        "/groups/api/v1/groups/exp-schedule",
+        json!({ "reminders": [{ "days": 7 }] }),
+        &host,
+    )
+    .await;
+    assert!(res.status().is_client_error());
+
+    Ok(())
+}
+
+#[actix_rt::test]
+async fn self_renewal() -> Result<(), Error> {
+    reset()?;
+    let service = test_app().await;
+    let app = App::new().service(service);
+    let mut app = test::init_service(app).await;
+
+    let host_user = basic_user(1, true);
+    let normal_user_1 = basic_user(11, false);
+    let host = Soa::from(&host_user).aal_medium();
+    let member = Soa::from(&normal_user_1);
+
+    let res = post(
+        &mut app,
+        "/groups/api/v1/groups",
+        json!({ "name": "exp-self", "description": "a group", "trust": "Authenticated" }),
+        &host.clone().creator(),
+    )
+    .await;
+    assert!(res.status().is_success());
+
+    let res = put(
+        &mut app,
+        "/groups/api/v1/groups/exp-self",
+        json!({ "group_expiration": 30 }),
+        &host,
+    )
+    .await;
+    assert!(res.status().is_success());
+
+    let res = post(
+        &mut app,
+        "/groups/api/v1/sudo/member/exp-self",
+        json!({ "user_uuid": user_uuid(&normal_user_1), "group_expiration": 10 }),
+        &host.clone().admin(),
+    )
+    .await;
+    assert!(res.status().is_success());
+
+    let res = post(
+        &mut app,
+        "/groups/api/v1/self/exp-self/renew",
+        json!({}),
+        &member,
+    )
+    .await;
+    assert!(res.status().is_client_error());
+
+    let res = put(
+        &mut app,
+        "/groups/api/v1/groups/exp-self",
+        json!({ "self_renewal": true }),
+        &host,
+    )
+    .await;
+    assert!(res.status().is_success());
+
+    let res = get(&mut app, "/groups/api/v1/groups/exp-self/details", &member).await;
+    assert!(res.status().is_success());
+    let details = read_json(res).await;
+    assert_eq!(details["group"]["self_renewal"], true);
+
+    let res = post(
+        &mut app,
+        "/groups/api/v1/self/exp-self/renew",
+        json!({}),
+        &member,
+    )
+    .await;
+    assert!(res.status().is_success());
+
+    let res = post(
+        &mut app,
+        "/groups/api/v1/self/exp-self/renew",
+        json!({}),
+        &member,
+    )
+    .await;
+    assert!(res.status().is_client_error());
+
+    let res = get(&mut app, "/groups/api/v1/members/exp-self?r=Member", &host).await;
+    assert!(res.status().is_success());
+    let members = read_json(res).await;
+    let expiration = members["members"][0]["expiration"]
+        .as_str()
+        .unwrap_or_default();
+    assert!(
+        expiration
+            > (chrono::Utc::now() + chrono::Duration::days(20))
+                .to_rfc3339()
+                .as_str()
+    );
+
+    Ok(())
+}
+
+fn expire_in_db(group_name: &str, user_uuid: &str, days_ago: i32) -> Result<(), Error> {
+    let connection = get_pool().get()?;
+    diesel::sql_query(format!(
+        "UPDATE memberships SET expiration = NOW() - INTERVAL '{} days' \
+         WHERE user_uuid = '{}' AND group_id = (SELECT group_id FROM groups WHERE name = '{}')",
+        days_ago, user_uuid, group_name
+    ))
+    .execute(&connection)?;
+    Ok(())
+}
+
+#[actix_rt::test]
+async fn grace_period() -> Result<(), Error> {
+    reset()?;
+    let (service, cis_client) = test_app_and_cis().await;
+    let app = App::new().service(service);
+    let mut app = test::init_service(app).await;
+    let pool = get_pool();
+
+    let host_user = basic_user(1, true);
+    let normal_user_1 = basic_user(11, false);
+    let host = Soa::from(&host_user).aal_medium();
+    let user_id = normal_user_1.user_id.value.clone().unwrap();
+    let has_group = |cis_client: &dino_park_packs::cis::fake::CisFakeClient| {
+        let updates = cis_client.updates();
+        let (_, profile) = updates.iter().rev().find(|(id, _)| id == &user_id).unwrap();
+        match profile.access_information.mozilliansorg.values {
+            Some(KeyValue(ref groups)) => groups.contains_key("exp-grace"),
+            None => false,
+        }
+    };
+
+    let res = post(
+        &mut app,
+        "/groups/api/v1/groups",
+        json!({ "name": "exp-grace", "description": "a group", "trust": "Authenticated" }),
+        &host.clone().creator(),
+    )
+    .await;
+    assert!(res.status().is_success());
+
+    let res = put(
+        &mut app,
+        "/groups/api/v1/groups/exp-grace",
+        json!({ "expiration_grace": { "days": 7 } }),
+        &host,
+    )
+    .await;
+    assert!(res.status().is_success());
+
+    let res = post(
+        &mut app,
+        "/groups/api/v1/sudo/member/exp-grace",
+        json!({ "user_uuid": user_uuid(&normal_user_1), "group_expiration": 1 }),
+        &host.clone().admin(),
+    )
+    .await;
+    assert!(res.status().is_success());
+    assert!(has_group(&cis_client));
+
+    expire_in_db("exp-grace", &user_uuid(&normal_user_1), 1)?;
+    expire_memberships(&pool, Arc::new(cis_client.clone())).await?;
+    assert!(!has_group(&cis_client));
+
+    let res = get(&mut app, "/groups/api/v1/members/exp-grace?r=Member", &host).await;
+    assert!(res.status().is_success());
+    let members = read_json(res).await;
+    assert_eq!(members["members"].as_array().map(|a| a.len()), Some(1));
+    assert_eq!(members["members"][0]["expired"], true);
+
+    let res = post(
+        &mut app,
+        &format!(
+            "/groups/api/v1/members/exp-grace/{}/renew",
+            user_uuid(&normal_user_1)
+        ),
+        json!({ "group_expiration": 30 }),
+        &host,
+    )
+    .await;
+    assert!(res.status().is_success());
+    assert!(has_group(&cis_client));
+
+    let res = get(&mut app, "/groups/api/v1/members/exp-grace?r=Member", &host).await;
+    assert!(res.status().is_success());
+    let members = read_json(res).await;
+    assert_eq!(members["members"][0]["expired"], false);
+
+    expire_in_db("exp-grace", &user_uuid(&normal_user_1), 8)?;
+    expire_memberships(&pool, Arc::new(cis_client.clone())).await?;
+    assert!(!has_group(&cis_client));
+
+    let res = get(&mut app, "/groups/api/v1/members/exp-grace?r=Member", &host).await;
+    assert!(res.status().is_success());
+    let members = read_json(res).await;
+    assert_eq!(members["members"].as_array().map(|a| a.len()), Some(0));
+
+    Ok(())
+}
+
+#[actix_rt::test]
+async fn expiration_preview() -> Result<(), Error> {
+    reset()?;
+    let service = test_app().await;
+    let app = App::new().service(service);
+    let mut app = test::init_service(app).await;
+
+    let host_user = basic_user(1, true);
+    let normal_user_1 = basic_user(11, false);
+    let normal_user_2 = basic_user(12, false);
+    let host = Soa::from(&host_user).aal_medium();
+    let admin = host.clone().admin();
+
+    let res = post(
+        &mut app,
+        "/groups/api/v1/groups",
+        json!({ "name": "exp-preview", "description": "a group", "trust": "Authenticated" }),
+        &host.clone().creator(),
+    )
+    .await;
+    assert!(res.status().is_success());
+
+    let res = post(
+        &mut app,
+        "/groups/api/v1/sudo/member/exp-preview",
+        json!({ "user_uuid": user_uuid(&normal_user_1), "group_expiration": 1 }),
+        &admin,
+    )
+    .await;
+    assert!(res.status().is_success());
+    expire_in_db("exp-preview", &user_uuid(&normal_user_1), 1)?;
+
+    let res = post(
+        &mut app,
+        "/groups/api/v1/invitations/exp-preview",
+        json!({ "user_uuid": user_uuid(&normal_user_2), "invitation_expiration": 2 }),
+        &host,
+    )
+    .await;
+    assert!(res.status().is_success());
+
+    let res = post(
+        &mut app,
+        "/groups/api/v1/groups",
+        json!({ "name": "exp-preview-trust", "description": "a group", "trust": "Authenticated" }),
+        &host.clone().creator(),
+    )
+    .await;
+    assert!(res.status().is_success());
+
+    let res = post(
+        &mut app,
+        "/groups/api/v1/sudo/member/exp-preview-trust",
+        json!({ "user_uuid": user_uuid(&normal_user_2) }),
+        &admin,
+    )
+    .await;
+    assert!(res.status().is_success());
+
+    let res = put(
+        &mut app,
+        "/groups/api/v1/sudo/trust/groups/exp-preview-trust",
+        json!({ "trust": "Ndaed", "stage_days": 1 }),
+        &admin,
+    )
+    .await;
+    assert!(res.status().is_success());
+
+    let before = (chrono::Utc::now() + chrono::Duration::days(3)).format("%Y-%m-%dT%H:%M:%SZ");
+    let url = format!("/groups/api/v1/sudo/expirations/preview?before={}", before);
+
+    let res = get(&mut app, &url, &host).await;
+    assert!(!res.status().is_success());
+
+    let res = get(&mut app, &url, &admin).await;
+    assert!(res.status().is_success());
+    let preview = read_json(res).await;
+    // staged trust changes are applied by their own job
+    assert_eq!(preview["groups"].as_array().map(|a| a.len()), Some(1));
+    let group = &preview["groups"][0];
+    assert_eq!(group["group_name"], "exp-preview");
+    assert_eq!(
+        group["invitations"][0]["user_uuid"],
+        user_uuid(&normal_user_2)
+    );
+    assert_eq!(
+        group["memberships"][0]["user_uuid"],
+        user_uuid(&normal_user_1)
+    );
+    assert_eq!(group["memberships"][0]["action"], "Revoke");
+    assert_eq!(
+        group["notifications"][0]["template"],
+        json!({ "DeleteMember": "exp-preview" })
+    );
+
+    let res = get(&mut app, "/groups/api/v1/sudo/expirations/preview", &admin).await;
+    assert!(res.status().is_success());
+    let preview = read_json(res).await;
+    assert_eq!(preview["groups"][0]["invitations"], json!([]));
+
+    let res = get(
+        &mut app,
+        "/groups/api/v1/members/exp-preview?r=Member",
+        &host,
+    )
+    .await;
+    assert!(res.status().is_success());
+    let members = read_json(res).await;
+    assert_eq!(members["members"].as_array().map(|a| a.len()), Some(1));
+
+    Ok(())
+}
+
+#[actix_rt::test]
+async fn apply_group_expiration() -> Result<(), Error> {
+    reset()?;
+    let service = test_app().await;
+    let app = App::new().service(service);
+    let mut app = test::init_service(app).await;
+
+    let host_user = basic_user(1, true);
+    let host = Soa::from(&host_user).aal_medium();
+    let admin = host.clone().admin();
+
+    let res = post(
+        &mut app,
+        "/groups/api/v1/groups",
+        json!({ "name": "exp-apply", "description": "a group", "trust": "Authenticated" }),
+        &host.clone().creator(),
+    )
+    .await;
+    assert!(res.status().is_success());
+
+    for (n, group_expiration) in &[(11, 0), (12, 60), (13, 10)] {
+        let res = post(
+            &mut app,
+            "/groups/api/v1/sudo/member/exp-apply",
+            json!({
+                "user_uuid": user_uuid(&basic_user(*n, false)),
+                "group_expiration": group_expiration
+            }),
+            &admin,
+        )
+        .await;
+        assert!(res.status().is_success());
+    }
+
+    let res = put(
+        &mut app,
+        "/groups/api/v1/groups/exp-apply?preview=true",
+        json!({ "group_expiration": 30, "apply_expiration": "Missing" }),
+        &host,
+    )
+    .await;
+    assert!(res.status().is_success());
+    assert_eq!(read_json(res).await, json!({ "affected": 1 }));
+
+    let res = put(
+        &mut app,
+        "/groups/api/v1/groups/exp-apply?preview=true",
+        json!({ "group_expiration": 30, "apply_expiration": "All" }),
+        &host,
+    )
+    .await;
+    assert!(res.status().is_success());
+    assert_eq!(read_json(res).await, json!({ "affected": 2 }));
+
+    let res = get(&mut app, "/groups/api/v1/groups/exp-apply/details", &host).await;
+    assert!(res.status().is_success());
+    assert_eq!(read_json(res).await["group"]["expiration"], 0);
+
+    let res = put(
+        &mut app,
+        "/groups/api/v1/groups/exp-apply",
+        json!({ "group_expiration": 30, "apply_expiration": "All" }),
+        &host,
+    )
+    .await;
+    assert!(res.status().is_success());
+    assert_eq!(read_json(res).await, json!({ "affected": 2 }));
+
+    let res = get(&mut app, "/groups/api/v1/groups/exp-apply/details", &host).await;
+    assert!(res.status().is_success());
+    assert_eq!(read_json(res).await["group"]["expiration"], 30);
+
+    let limit = (chrono::Utc::now() + chrono::Duration::days(31)).to_rfc3339();
+    let res = get(&mut app, "/groups/api/v1/members/exp-apply?r=Member", &host).await;
+    assert!(res.status().is_success());
+    let members = read_json(res).await;
+    assert_eq!(members["members"].as_array().map(|a| a.len()), Some(3));
+    for member in members["members"].as_array().unwrap() {
+        let expiration = member["expiration"].as_str().unwrap();
+        assert!(expiration < limit.as_str());
+    }
+
+    Ok(())
+}
+
+#[actix_rt::test]
+async fn calendar_feed() -> Result<(), Error> {
+    reset()?;
+    let service = test_app().await;
+    let app = App::new().service(service);
+    let mut app = test::init_service(app).await;
+
+    let host_user = basic_user(1, true);
+    let normal_user_1 = basic_user(11, false);
+    let host = Soa::from(&host_user).aal_medium();
+    let member = Soa::from(&normal_user_1).aal_medium();
+
+    let res = post(
+        &mut app,
+        "/groups/api/v1/groups",
+        json!({ "name": "exp-calendar", "description": "a group", "trust": "Authenticated" }),
+        &host.clone().creator(),
+    )
+    .await;
+    assert!(res.status().is_success());
+
+    let res = post(
+        &mut app,
+        "/groups/api/v1/sudo/member/exp-calendar",
+        json!({ "user_uuid": user_uuid(&normal_user_1), "group_expiration": 10 }),
+        &host.clone().admin(),
+    )
+    .await;
+    assert!(res.status().is_success());
+
+    let res = post(
+        &mut app,
+        "/groups/api/v1/members/exp-calendar/calendar",
+        json!({}),
+        &member,
+    )
+    .await;
+    assert!(res.status().is_client_error());
+
+    let res = post(
+        &mut app,
+        "/groups/api/v1/members/exp-calendar/calendar",
+        json!({}),
+        &host,
+    )
+    .await;
+    assert!(res.status().is_success());
+    let token = read_json(res).await["token"].as_str().unwrap().to_owned();
+
+    let res = get(
+        &mut app,
+        &format!("/groups/calendar/{}", token),
+        &nobody_soa(),
+    )
+    .await;
+    assert!(res.status().is_success());
+    let body = test::read_body(res).await;
+    let feed = String::from_utf8_lossy(&body);
+    assert!(feed.starts_with("BEGIN:VCALENDAR\r\n"));
+    assert_eq!(feed.matches("BEGIN:VEVENT").count(), 1);
+    assert!(feed.contains("SUMMARY:Hans11 expires from exp-calendar"));
+    assert!(feed.contains("URL:https://localhost/a/exp-calendar/edit?section=members"));
+
+    let res = post(
+        &mut app,
+        "/groups/api/v1/members/exp-calendar/calendar",
+        json!({}),
+        &host,
+    )
+    .await;
+    assert!(res.status().is_success());
+    let rotated = read_json(res).await["token"].as_str().unwrap().to_owned();
+
+    let res = get(
+        &mut app,
+        &format!("/groups/calendar/{}", token),
+        &nobody_soa(),
+    )
+    .await;
+    assert_eq!(res.status().as_u16(), 404);
+
+    let res = delete(
+        &mut app,
+        "/groups/api/v1/members/exp-calendar/calendar",
+        &host,
+    )
+    .await;
+    assert!(res.status().is_success());
+
+    let res = get(
+        &mut app,
+        &format!("/groups/calendar/{}", rotated),
+        &nobody_soa(),
+    )
+    .await;
+    assert_eq!(res.status().as_u16(), 404);
+    Ok(())
+}
@@ -1,5 +1,6 @@
 mod anonmail;
 mod basics;
+mod broadcast;
 mod create;
 mod delete;
 mod details;
@@ -9,7 +10,10 @@ mod groups;
 mod import;
 mod invitations;
 mod join;
+mod notifications;
+mod outbox;
 mod requests;
 mod revoke;
 mod sudo;
 mod upgrade;
+mod webhooks;
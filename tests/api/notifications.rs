@@ -0,0 +1,110 @@
+use crate::helpers::api::*;
+use crate::helpers::db::reset;
+use crate::helpers::misc::nobody_soa;
+use crate::helpers::misc::read_json;
+use crate::helpers::misc::test_app;
+use crate::helpers::misc::Soa;
+use crate::helpers::users::basic_user;
+use crate::helpers::users::user_uuid;
+use actix_web::test;
+use actix_web::App;
+use failure::Error;
+use serde_json::json;
+
+#[actix_rt::test]
+async fn digest() -> Result<(), Error> {
+    reset()?;
+    let app = App::new().service(test_app().await);
+    let mut app = test::init_service(app).await;
+
+    let host_user = basic_user(1, true);
+    let daily_user = basic_user(2, true);
+    let off_user = basic_user(3, true);
+    let host = Soa::from(&host_user).creator().aal_medium();
+    let daily = Soa::from(&daily_user);
+    let off = Soa::from(&off_user);
+
+    let res = get(&mut app, "/groups/api/v1/self/notifications", &daily).await;
+    assert!(res.status().is_success());
+    assert_eq!(
+        read_json(res).await,
+        json!([
+            { "category": "Invitations", "preference": "Immediate" },
+            { "category": "Requests", "preference": "Immediate" },
+            { "category": "Expirations", "preference": "Immediate" },
+        ])
+    );
+
+    let res = put(
+        &mut app,
+        "/groups/api/v1/self/notifications",
+        json!({ "category": "Invitations", "preference": "Daily" }),
+        &daily,
+    )
+    .await;
+    assert!(res.status().is_success());
+    let res = put(
+        &mut app,
+        "/groups/api/v1/self/notifications",
+        json!({ "category": "Invitations", "preference": "Off" }),
+        &off,
+    )
+    .await;
+    assert!(res.status().is_success());
+
+    let res = get(&mut app, "/groups/api/v1/self/notifications", &daily).await;
+    assert!(res.status().is_success());
+    assert_eq!(read_json(res).await[0]["preference"], "Daily");
+
+    let res = post(
+        &mut app,
+        "/groups/api/v1/groups",
+        json!({ "name": "digest-test", "description": "a group" }),
+        &host,
+    )
+    .await;
+    assert!(res.status().is_success());
+
+    for user in &[&daily_user, &off_user] {
+        let res = post(
+            &mut app,
+            "/groups/api/v1/invitations/digest-test",
+            json!({ "user_uuid": user_uuid(user) }),
+            &host,
+        )
+        .await;
+        assert!(res.status().is_success());
+    }
+
+    let res = post(
+        &mut app,
+        "/internal/notify/digest/weekly",
+        json!({}),
+        &nobody_soa(),
+    )
+    .await;
+    assert!(res.status().is_success());
+    assert_eq!(read_json(res).await, json!({ "sent": 0 }));
+
+    let res = post(
+        &mut app,
+        "/internal/notify/digest/daily",
+        json!({}),
+        &nobody_soa(),
+    )
+    .await;
+    assert!(res.status().is_success());
+    assert_eq!(read_json(res).await, json!({ "sent": 1 }));
+
+    let res = post(
+        &mut app,
+        "/internal/notify/digest/daily",
+        json!({}),
+        &nobody_soa(),
+    )
+    .await;
+    assert!(res.status().is_success());
+    assert_eq!(read_json(res).await, json!({ "sent": 0 }));
+
+    Ok(())
+}
@@ -0,0 +1,214 @@
+use crate::helpers::api::*;
+use crate::helpers::db::get_pool;
+use crate::helpers::db::reset;
+use crate::helpers::misc::nobody_soa;
+use crate::helpers::misc::read_json;
+use crate::helpers::misc::test_app;
+use crate::helpers::misc::test_app_and_cis;
+use crate::helpers::misc::Soa;
+use crate::helpers::sudo::add_to_group;
+use crate::helpers::users::basic_user;
+use crate::helpers::users::user_uuid;
+use actix_web::test;
+use actix_web::App;
+use cis_profile::schema::KeyValue;
+use diesel::RunQueryDsl;
+use failure::Error;
+use serde_json::json;
+
+#[actix_rt::test]
+async fn outbox() -> Result<(), Error> {
+    reset()?;
+    let app = App::new().service(test_app().await);
+    let mut app = test::init_service(app).await;
+
+    let host_user = basic_user(1, true);
+    let host = Soa::from(&host_user).aal_medium();
+    let admin = host.clone().admin();
+    let member_user = basic_user(2, true);
+
+    let res = post(
+        &mut app,
+        "/groups/api/v1/groups",
+        json!({ "name": "outbox-test", "description": "a group" }),
+        &host.clone().creator(),
+    )
+    .await;
+    assert!(res.status().is_success());
+
+    add_to_group(&mut app, &host, &member_user, "outbox-test").await;
+
+    let res = delete(
+        &mut app,
+        &format!(
+            "/groups/api/v1/members/outbox-test/{}",
+            user_uuid(&member_user)
+        ),
+        &host,
+    )
+    .await;
+    assert!(res.status().is_success());
+
+    let res = get(
+        &mut app,
+        "/groups/api/v1/sudo/outbox?status=Delivered",
+        &admin,
+    )
+    .await;
+    assert!(res.status().is_success());
+    let entries = read_json(res).await;
+    assert_eq!(entries.as_array().map(|e| e.len()), Some(3));
+    assert_eq!(entries[0]["operation"], "RemoveGroups");
+    assert_eq!(entries[0]["user_uuid"], user_uuid(&member_user));
+    assert_eq!(entries[0]["group_names"], json!(["outbox-test"]));
+    assert_eq!(entries[1]["operation"], "AddGroups");
+    assert_eq!(entries[1]["user_uuid"], user_uuid(&member_user));
+    assert_eq!(entries[2]["user_uuid"], user_uuid(&host_user));
+    assert_eq!(entries[2]["attempts"], 1);
+
+    let res = get(&mut app, "/groups/api/v1/sudo/outbox", &admin).await;
+    assert!(res.status().is_success());
+    assert_eq!(read_json(res).await, json!([]));
+
+    let res = get(&mut app, "/groups/api/v1/sudo/outbox", &host).await;
+    assert!(!res.status().is_success());
+
+    let retry_url = format!(
+        "/groups/api/v1/sudo/outbox/{}/retry",
+        entries[0]["id"].as_i64().unwrap()
+    );
+    let res = post(&mut app, &retry_url, json!({}), &admin).await;
+    assert_eq!(res.status().as_u16(), 400);
+
+    // retrying must not revert the newer removal which has already been delivered
+    let connection = get_pool().get()?;
+    diesel::sql_query(format!(
+        "UPDATE cis_outbox SET status = 'failed' WHERE id = {}",
+        entries[1]["id"].as_i64().unwrap()
+    ))
+    .execute(&connection)?;
+    let retry_url = format!(
+        "/groups/api/v1/sudo/outbox/{}/retry",
+        entries[1]["id"].as_i64().unwrap()
+    );
+    let res = post(&mut app, &retry_url, json!({}), &admin).await;
+    assert_eq!(res.status().as_u16(), 400);
+    assert_eq!(read_json(res).await["error"], "outdated_outbox_entry");
+
+    let res = post(
+        &mut app,
+        "/internal/outbox/deliver",
+        json!({}),
+        &nobody_soa(),
+    )
+    .await;
+    assert!(res.status().is_success());
+    assert_eq!(read_json(res).await, json!({ "delivered": 0 }));
+
+    Ok(())
+}
+
+#[actix_rt::test]
+async fn outbox_batches_bulk_removals() -> Result<(), Error> {
+    reset()?;
+    let app = App::new().service(test_app().await);
+    let mut app = test::init_service(app).await;
+
+    let host_user = basic_user(1, true);
+    let host = Soa::from(&host_user).aal_medium();
+    let admin = host.clone().admin();
+    let member_user_1 = basic_user(2, true);
+    let member_user_2 = basic_user(3, true);
+
+    for group_name in &["batch-test-1", "batch-test-2"] {
+        let res = post(
+            &mut app,
+            "/groups/api/v1/groups",
+            json!({ "name": group_name, "description": "a group" }),
+            &host.clone().creator(),
+        )
+        .await;
+        assert!(res.status().is_success());
+        add_to_group(&mut app, &host, &member_user_1, group_name).await;
+        add_to_group(&mut app, &host, &member_user_2, group_name).await;
+    }
+
+    for group_name in &["batch-test-1", "batch-test-2"] {
+        let res = delete(
+            &mut app,
+            &format!("/groups/api/v1/groups/{}", group_name),
+            &host.clone().creator(),
+        )
+        .await;
+        assert!(res.status().is_success());
+    }
+
+    // the removals of the members are left to the worker
+    let res = get(
+        &mut app,
+        "/groups/api/v1/sudo/outbox?status=Pending",
+        &admin,
+    )
+    .await;
+    assert!(res.status().is_success());
+    let entries = read_json(res).await;
+    assert_eq!(entries.as_array().map(|e| e.len()), Some(4));
+    for entry in entries.as_array().unwrap() {
+        assert_eq!(entry["operation"], "RemoveGroups");
+        assert_eq!(entry["attempts"], 0);
+        assert_ne!(entry["user_uuid"], user_uuid(&host_user));
+    }
+
+    Ok(())
+}
+
+#[actix_rt::test]
+async fn outbox_keeps_failed_updates() -> Result<(), Error> {
+    reset()?;
+    let (service, cis_client) = test_app_and_cis().await;
+    let app = App::new().service(service);
+    let mut app = test::init_service(app).await;
+
+    let host_user = basic_user(1, true);
+    let host = Soa::from(&host_user).aal_medium();
+    let admin = host.clone().admin();
+    let failing_user = basic_user(2, true);
+    let member_user = basic_user(3, true);
+
+    let res = post(
+        &mut app,
+        "/groups/api/v1/groups",
+        json!({ "name": "outbox-failure-test", "description": "a group" }),
+        &host.clone().creator(),
+    )
+    .await;
+    assert!(res.status().is_success());
+    assert_eq!(cis_client.updates().len(), 1);
+
+    cis_client.fail_updates(1);
+    add_to_group(&mut app, &host, &failing_user, "outbox-failure-test").await;
+    add_to_group(&mut app, &host, &member_user, "outbox-failure-test").await;
+
+    let res = get(
+        &mut app,
+        "/groups/api/v1/sudo/outbox?status=Pending",
+        &admin,
+    )
+    .await;
+    assert!(res.status().is_success());
+    let entries = read_json(res).await;
+    assert_eq!(entries.as_array().map(|e| e.len()), Some(1));
+    assert_eq!(entries[0]["user_uuid"], user_uuid(&failing_user));
+    assert_eq!(entries[0]["attempts"], 1);
+    assert_eq!(entries[0]["last_error"], "injected failure");
+
+    let updates = cis_client.updates();
+    assert_eq!(updates.len(), 2);
+    assert_eq!(updates[1].0, member_user.user_id.value.clone().unwrap());
+    match updates[1].1.access_information.mozilliansorg.values {
+        Some(KeyValue(ref groups)) => assert!(groups.contains_key("outbox-failure-test")),
+        None => panic!("no groups published"),
+    }
+
+    Ok(())
+}
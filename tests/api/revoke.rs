@@ -2,6 +2,7 @@ use crate::helpers::api::*;
 use crate::helpers::db::get_pool;
 use crate::helpers::db::reset;
 use crate::helpers::misc::create_nda;
+use crate::helpers::misc::nobody_soa;
 use crate::helpers::misc::read_json;
 use crate::helpers::misc::test_app_and_cis;
 use crate::helpers::misc::Soa;
@@ -10,6 +11,8 @@ use crate::helpers::users::basic_user;
 use crate::helpers::users::user_uuid;
 use actix_web::test;
 use actix_web::App;
+use diesel::RunQueryDsl;
+use dino_park_packs::db::operations::users::revoke_at_risk_memberships;
 use dino_park_packs::db::operations::users::update_user_cache;
 use failure::Error;
 use serde_json::json;
@@ -144,3 +147,282 @@ async fn revoke_staff() -> Result<(), Error> {
 
     Ok(())
 }
+
+#[actix_rt::test]
+async fn resync() -> Result<(), Error> {
+    reset()?;
+    let (service, cis_client) = test_app_and_cis().await;
+    let app = App::new().service(service);
+    let mut app = test::init_service(app).await;
+
+    let host_user = basic_user(1, true);
+    let staff_user = basic_user(2, true);
+    let host = Soa::from(&host_user).aal_medium();
+
+    let res = post(
+        &mut app,
+        "/groups/api/v1/groups",
+        json!({ "name": "resync-test", "description": "a group", "trust": "Staff" }),
+        &host.clone().creator(),
+    )
+    .await;
+    assert!(res.status().is_success());
+    add_to_group(&mut app, &host, &staff_user, "resync-test").await;
+
+    // change the profile in CIS without notifying packs
+    cis_client
+        .store
+        .write()
+        .unwrap()
+        .get_mut(&staff_user.user_id.value.clone().unwrap())
+        .unwrap()
+        .staff_information
+        .staff
+        .value = Some(false);
+
+    let res = get(&mut app, "/internal/resync/users", &nobody_soa()).await;
+    assert!(res.status().is_success());
+    assert_eq!(read_json(res).await, json!(null));
+
+    let res = post(
+        &mut app,
+        "/internal/resync/users?batch_size=0",
+        json!({}),
+        &nobody_soa(),
+    )
+    .await;
+    assert_eq!(res.status().as_u16(), 400);
+    assert_eq!(read_json(res).await["error"], "invalid_batch_size");
+
+    // another call is working on the run
+    let connection = get_pool().get()?;
+    diesel::sql_query("SELECT pg_advisory_lock(1919251321, 0)").execute(&connection)?;
+    let res = post(
+        &mut app,
+        "/internal/resync/users?batch_size=15",
+        json!({}),
+        &nobody_soa(),
+    )
+    .await;
+    assert_eq!(res.status().as_u16(), 400);
+    assert_eq!(read_json(res).await["error"], "resync_in_progress");
+    diesel::sql_query("SELECT pg_advisory_unlock(1919251321, 0)").execute(&connection)?;
+
+    let res = post(
+        &mut app,
+        "/internal/resync/users?batch_size=15",
+        json!({}),
+        &nobody_soa(),
+    )
+    .await;
+    assert!(res.status().is_success());
+    let report = read_json(res).await;
+    assert_eq!(report["total"], 20);
+    assert_eq!(report["processed"], 15);
+    assert_eq!(report["finished"], json!(null));
+
+    let res = post(
+        &mut app,
+        "/internal/resync/users?batch_size=15",
+        json!({}),
+        &nobody_soa(),
+    )
+    .await;
+    assert!(res.status().is_success());
+    let report = read_json(res).await;
+    assert_eq!(report["processed"], 20);
+    assert_eq!(report["failed"], 0);
+    assert!(report["finished"].is_string());
+
+    let res = get(&mut app, "/groups/api/v1/members/resync-test", &host).await;
+    assert!(res.status().is_success());
+    let members = read_json(res).await;
+    assert_eq!(members["members"].as_array().map(|a| a.len()), Some(1));
+
+    Ok(())
+}
+
+#[actix_rt::test]
+async fn suspend_inactive() -> Result<(), Error> {
+    reset()?;
+    let (service, cis_client) = test_app_and_cis().await;
+    let cis_client = Arc::new(cis_client);
+    let app = App::new().service(service);
+    let mut app = test::init_service(app).await;
+
+    let host_user = basic_user(1, true);
+    let mut staff_user = basic_user(2, true);
+    let host = Soa::from(&host_user).aal_medium();
+
+    let res = post(
+        &mut app,
+        "/groups/api/v1/groups",
+        json!({ "name": "inactive-test", "description": "a group", "trust": "Staff" }),
+        &host.clone().creator(),
+    )
+    .await;
+    assert!(res.status().is_success());
+    add_to_group(&mut app, &host, &staff_user, "inactive-test").await;
+
+    let search = "/groups/api/v1/users?g=inactive-test&q=Hans2&a=true";
+    let res = get(&mut app, search, &host).await;
+    assert!(res.status().is_success());
+    assert_eq!(read_json(res).await.as_array().map(|a| a.len()), Some(1));
+
+    let pool = get_pool();
+    staff_user.active.value = Some(false);
+    update_user_cache(&pool, &staff_user, Arc::clone(&cis_client)).await?;
+
+    let res = get(&mut app, "/groups/api/v1/members/inactive-test", &host).await;
+    assert!(res.status().is_success());
+    let members = read_json(res).await;
+    assert_eq!(members["members"].as_array().map(|a| a.len()), Some(1));
+
+    let res = get(&mut app, search, &host).await;
+    assert!(res.status().is_success());
+    assert_eq!(read_json(res).await.as_array().map(|a| a.len()), Some(0));
+
+    // reactivation within the grace period restores the membership
+    staff_user.active.value = Some(true);
+    update_user_cache(&pool, &staff_user, Arc::clone(&cis_client)).await?;
+
+    let res = get(&mut app, "/groups/api/v1/members/inactive-test", &host).await;
+    assert!(res.status().is_success());
+    let members = read_json(res).await;
+    assert_eq!(members["members"].as_array().map(|a| a.len()), Some(2));
+
+    staff_user.active.value = Some(false);
+    update_user_cache(&pool, &staff_user, Arc::clone(&cis_client)).await?;
+
+    let res = post(
+        &mut app,
+        "/internal/suspensions/revoke",
+        json!({}),
+        &nobody_soa(),
+    )
+    .await;
+    assert!(res.status().is_success());
+    assert_eq!(read_json(res).await["revoked"], 1);
+
+    staff_user.active.value = Some(true);
+    update_user_cache(&pool, &staff_user, Arc::clone(&cis_client)).await?;
+
+    let res = get(&mut app, "/groups/api/v1/members/inactive-test", &host).await;
+    assert!(res.status().is_success());
+    let members = read_json(res).await;
+    assert_eq!(members["members"].as_array().map(|a| a.len()), Some(1));
+
+    Ok(())
+}
+
+#[actix_rt::test]
+async fn trust_drop_grace() -> Result<(), Error> {
+    reset()?;
+    let (service, cis_client) = test_app_and_cis().await;
+    let cis_client = Arc::new(cis_client);
+    let app = App::new().service(service);
+    let mut app = test::init_service(app).await;
+
+    let host_user = basic_user(1, true);
+    let mut staff_user_1 = basic_user(2, true);
+    let mut staff_user_2 = basic_user(3, true);
+    let host = Soa::from(&host_user).aal_medium();
+
+    let res = post(
+        &mut app,
+        "/groups/api/v1/groups",
+        json!({ "name": "grace-test", "description": "a group", "trust": "Staff" }),
+        &host.clone().creator(),
+    )
+    .await;
+    assert!(res.status().is_success());
+
+    add_to_group(&mut app, &host, &staff_user_1, "grace-test").await;
+    add_to_group(&mut app, &host, &staff_user_2, "grace-test").await;
+
+    let res = put(
+        &mut app,
+        "/groups/api/v1/sudo/trust/grace",
+        json!({ "days": 0 }),
+        &host.clone().admin(),
+    )
+    .await;
+    assert!(res.status().is_client_error());
+
+    let res = put(
+        &mut app,
+        "/groups/api/v1/sudo/trust/grace",
+        json!({ "days": 7 }),
+        &host.clone().admin(),
+    )
+    .await;
+    assert!(res.status().is_success());
+
+    let res = get(
+        &mut app,
+        "/groups/api/v1/sudo/trust/grace",
+        &host.clone().admin(),
+    )
+    .await;
+    assert!(res.status().is_success());
+    assert_eq!(read_json(res).await["days"], 7);
+
+    let pool = get_pool();
+    staff_user_1.staff_information.staff.value = Some(false);
+    update_user_cache(&pool, &staff_user_1, Arc::clone(&cis_client)).await?;
+    staff_user_2.staff_information.staff.value = Some(false);
+    update_user_cache(&pool, &staff_user_2, Arc::clone(&cis_client)).await?;
+
+    let res = get(&mut app, "/groups/api/v1/members/grace-test", &host).await;
+    assert!(res.status().is_success());
+    let members = read_json(res).await;
+    assert_eq!(members["members"].as_array().map(|a| a.len()), Some(3));
+
+    assert_eq!(
+        revoke_at_risk_memberships(&pool, Arc::clone(&cis_client)).await?,
+        0
+    );
+
+    // restored trust clears the flag
+    staff_user_1.staff_information.staff.value = Some(true);
+    update_user_cache(&pool, &staff_user_1, Arc::clone(&cis_client)).await?;
+
+    let connection = pool.get()?;
+    diesel::sql_query("UPDATE at_risk_memberships SET revoke_after = NOW() - INTERVAL '1 day'")
+        .execute(&connection)?;
+    assert_eq!(
+        revoke_at_risk_memberships(&pool, Arc::clone(&cis_client)).await?,
+        1
+    );
+
+    let res = get(&mut app, "/groups/api/v1/members/grace-test", &host).await;
+    assert!(res.status().is_success());
+    let members = read_json(res).await;
+    assert_eq!(members["members"].as_array().map(|a| a.len()), Some(2));
+
+    // nothing is left to revoke
+    let res = post(&mut app, "/internal/trust/revoke", json!({}), &nobody_soa()).await;
+    assert!(res.status().is_success());
+    let run = read_json(res).await;
+    assert_eq!(run["job"], "revoke_at_risk_memberships");
+    assert_eq!(run["message"], "0 at risk memberships revoked");
+
+    let res = put(
+        &mut app,
+        "/groups/api/v1/sudo/trust/grace",
+        json!({ "days": null }),
+        &host.clone().admin(),
+    )
+    .await;
+    assert!(res.status().is_success());
+
+    staff_user_1.staff_information.staff.value = Some(false);
+    update_user_cache(&pool, &staff_user_1, Arc::clone(&cis_client)).await?;
+
+    let res = get(&mut app, "/groups/api/v1/members/grace-test", &host).await;
+    assert!(res.status().is_success());
+    let members = read_json(res).await;
+    assert_eq!(members["members"].as_array().map(|a| a.len()), Some(1));
+
+    Ok(())
+}
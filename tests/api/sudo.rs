@@ -1,15 +1,26 @@
 use crate::helpers::api::*;
+use crate::helpers::db::get_pool;
 use crate::helpers::db::reset;
+use crate::helpers::misc::nobody_soa;
 use crate::helpers::misc::read_json;
 use crate::helpers::misc::test_app;
+use crate::helpers::misc::test_app_and_cis;
 use crate::helpers::misc::Soa;
+use crate::helpers::misc::SNS_TOPIC_ARN;
+use crate::helpers::sudo::add_to_group;
 use crate::helpers::users::basic_user;
 use crate::helpers::users::user_email;
 use crate::helpers::users::user_uuid;
 use actix_web::test;
 use actix_web::App;
+use cis_client::getby::GetBy;
+use cis_client::AsyncCisClientTrait;
+use cis_profile::schema::Display;
+use cis_profile::schema::KeyValue;
+use diesel::RunQueryDsl;
 use failure::Error;
 use serde_json::json;
+use std::collections::BTreeMap;
 
 #[actix_rt::test]
 async fn curator_emails() -> Result<(), Error> {
@@ -184,3 +195,585 @@ async fn add_remove() -> Result<(), Error> {
 
     Ok(())
 }
+
+#[actix_rt::test]
+async fn suppressed_emails() -> Result<(), Error> {
+    reset()?;
+    let app = App::new().service(test_app().await);
+    let mut app = test::init_service(app).await;
+
+    let admin = Soa::from(&basic_user(1, true)).admin().aal_medium();
+    let bouncing_user = basic_user(2, true);
+
+    let res = post(
+        &mut app,
+        "/internal/mail/sns",
+        json!({
+            "Type": "SubscriptionConfirmation",
+            "TopicArn": SNS_TOPIC_ARN,
+            "SubscribeURL": "https://sns.us-west-2.amazonaws.com/?Action=ConfirmSubscription",
+        }),
+        &nobody_soa(),
+    )
+    .await;
+    assert!(res.status().is_success());
+    assert_eq!(read_json(res).await, json!({ "suppressed": 0 }));
+
+    let bounce = json!({
+        "notificationType": "Bounce",
+        "bounce": {
+            "bounceType": "Permanent",
+            "bounceSubType": "General",
+            "bouncedRecipients": [{ "emailAddress": user_email(&bouncing_user) }],
+        },
+    });
+    let res = post(
+        &mut app,
+        "/internal/mail/sns",
+        json!({
+            "Type": "Notification",
+            "TopicArn": "arn:aws:sns:us-west-2:123456789012:someone-else",
+            "Message": bounce.to_string(),
+        }),
+        &nobody_soa(),
+    )
+    .await;
+    assert_eq!(res.status().as_u16(), 400);
+    assert_eq!(read_json(res).await["error"], "invalid_sns_topic");
+
+    let res = post(
+        &mut app,
+        "/internal/mail/sns",
+        json!({
+            "Type": "Notification",
+            "TopicArn": SNS_TOPIC_ARN,
+            "Message": bounce.to_string(),
+        }),
+        &nobody_soa(),
+    )
+    .await;
+    assert!(res.status().is_success());
+    assert_eq!(read_json(res).await, json!({ "suppressed": 1 }));
+
+    let res = get(&mut app, "/groups/api/v1/sudo/mail/suppressed", &admin).await;
+    assert!(res.status().is_success());
+    let suppressed = read_json(res).await;
+    assert_eq!(suppressed[0]["email"], user_email(&bouncing_user));
+    assert_eq!(suppressed[0]["typ"], "Bounce");
+
+    let res = delete(
+        &mut app,
+        &format!(
+            "/groups/api/v1/sudo/mail/suppressed/{}",
+            user_email(&bouncing_user)
+        ),
+        &admin,
+    )
+    .await;
+    assert!(res.status().is_success());
+
+    let res = get(&mut app, "/groups/api/v1/sudo/mail/suppressed", &admin).await;
+    assert!(res.status().is_success());
+    assert_eq!(read_json(res).await, json!([]));
+
+    Ok(())
+}
+
+#[actix_rt::test]
+async fn newsletters() -> Result<(), Error> {
+    reset()?;
+    let app = App::new().service(test_app().await);
+    let mut app = test::init_service(app).await;
+
+    let host = Soa::from(&basic_user(1, true)).aal_medium();
+    let admin = host.clone().admin();
+    let member_user = basic_user(2, true);
+
+    let res = post(
+        &mut app,
+        "/groups/api/v1/groups",
+        json!({ "name": "newsletter-test", "description": "a group" }),
+        &host.clone().creator(),
+    )
+    .await;
+    assert!(res.status().is_success());
+
+    let res = post(
+        &mut app,
+        "/groups/api/v1/sudo/member/newsletter-test",
+        json!({ "user_uuid": user_uuid(&member_user) }),
+        &admin,
+    )
+    .await;
+    assert!(res.status().is_success());
+
+    let res = get(
+        &mut app,
+        "/groups/api/v1/sudo/newsletters/newsletter-test",
+        &admin,
+    )
+    .await;
+    assert!(res.status().is_success());
+    assert_eq!(read_json(res).await, json!({ "newsletters": [] }));
+
+    let res = post(
+        &mut app,
+        "/groups/api/v1/sudo/newsletters/newsletter-test/reconcile",
+        json!({}),
+        &admin,
+    )
+    .await;
+    assert!(res.status().is_success());
+    assert_eq!(read_json(res).await, json!({ "subscribed": 0 }));
+
+    let res = put(
+        &mut app,
+        "/groups/api/v1/sudo/newsletters/newsletter-test",
+        json!({ "newsletters": ["not a newsletter"] }),
+        &admin,
+    )
+    .await;
+    assert_eq!(res.status().as_u16(), 400);
+    assert_eq!(read_json(res).await["error"], "invalid_newsletter");
+
+    let res = put(
+        &mut app,
+        "/groups/api/v1/sudo/newsletters/newsletter-test",
+        json!({ "newsletters": ["mozilla-foo", "mozilla-bar"] }),
+        &host,
+    )
+    .await;
+    assert!(!res.status().is_success());
+
+    let res = put(
+        &mut app,
+        "/groups/api/v1/sudo/newsletters/newsletter-test",
+        json!({ "newsletters": ["mozilla-foo", "mozilla-bar"] }),
+        &admin,
+    )
+    .await;
+    assert!(res.status().is_success());
+
+    let res = get(
+        &mut app,
+        "/groups/api/v1/sudo/newsletters/newsletter-test",
+        &admin,
+    )
+    .await;
+    assert!(res.status().is_success());
+    assert_eq!(
+        read_json(res).await,
+        json!({ "newsletters": ["mozilla-bar", "mozilla-foo"] })
+    );
+
+    let res = post(
+        &mut app,
+        "/groups/api/v1/sudo/newsletters/newsletter-test/reconcile",
+        json!({}),
+        &admin,
+    )
+    .await;
+    assert!(res.status().is_success());
+    assert_eq!(read_json(res).await, json!({ "subscribed": 2 }));
+
+    let res = delete(
+        &mut app,
+        &format!(
+            "/groups/api/v1/sudo/member/newsletter-test/{}",
+            user_uuid(&member_user)
+        ),
+        &admin,
+    )
+    .await;
+    assert!(res.status().is_success());
+
+    Ok(())
+}
+
+#[actix_rt::test]
+async fn drift() -> Result<(), Error> {
+    reset()?;
+    let (service, cis_client) = test_app_and_cis().await;
+    let app = App::new().service(service);
+    let mut app = test::init_service(app).await;
+
+    let host_user = basic_user(1, true);
+    let host = Soa::from(&host_user).aal_medium();
+    let admin = host.clone().admin();
+    let member_user = basic_user(2, true);
+    let other_user = basic_user(3, true);
+
+    let res = post(
+        &mut app,
+        "/groups/api/v1/groups",
+        json!({ "name": "drift-test", "description": "a group" }),
+        &host.clone().creator(),
+    )
+    .await;
+    assert!(res.status().is_success());
+
+    let res = post(
+        &mut app,
+        "/groups/api/v1/sudo/member/drift-test",
+        json!({ "user_uuid": user_uuid(&member_user) }),
+        &admin,
+    )
+    .await;
+    assert!(res.status().is_success());
+
+    let res = get(&mut app, "/groups/api/v1/sudo/drift", &admin).await;
+    assert!(res.status().is_success());
+    assert_eq!(read_json(res).await, json!({ "users": [], "groups": [] }));
+
+    // change the profiles without updating the memberships
+    let member_id = member_user.user_id.value.clone().unwrap();
+    cis_client
+        .update_user(&member_id, member_user.clone())
+        .await?;
+    let mut other_profile = other_user.clone();
+    let mut groups = BTreeMap::new();
+    groups.insert(String::from("drift-test"), Some(String::default()));
+    other_profile.access_information.mozilliansorg.values = Some(KeyValue(groups));
+    let other_id = other_user.user_id.value.clone().unwrap();
+    cis_client.update_user(&other_id, other_profile).await?;
+
+    let res = get(&mut app, "/groups/api/v1/sudo/drift", &host).await;
+    assert!(!res.status().is_success());
+
+    let res = get(&mut app, "/groups/api/v1/sudo/drift", &admin).await;
+    assert!(res.status().is_success());
+    let report = read_json(res).await;
+    let users = report["users"].as_array().unwrap();
+    assert_eq!(users.len(), 2);
+    let member = users
+        .iter()
+        .find(|u| u["user_uuid"] == user_uuid(&member_user))
+        .unwrap();
+    assert_eq!(member["missing"], json!(["drift-test"]));
+    assert_eq!(member["extra"], json!([]));
+    let other = users
+        .iter()
+        .find(|u| u["user_uuid"] == user_uuid(&other_user))
+        .unwrap();
+    assert_eq!(other["missing"], json!([]));
+    assert_eq!(other["extra"], json!(["drift-test"]));
+    assert_eq!(
+        report["groups"],
+        json!([{
+            "group_name": "drift-test",
+            "missing": [user_uuid(&member_user)],
+            "extra": [user_uuid(&other_user)],
+        }])
+    );
+
+    Ok(())
+}
+
+#[actix_rt::test]
+async fn reconcile() -> Result<(), Error> {
+    reset()?;
+    let (service, cis_client) = test_app_and_cis().await;
+    let app = App::new().service(service);
+    let mut app = test::init_service(app).await;
+
+    let host = Soa::from(&basic_user(1, true)).aal_medium();
+    let admin = host.clone().admin();
+    let member_user = basic_user(2, true);
+    let other_user = basic_user(3, true);
+
+    let res = post(
+        &mut app,
+        "/groups/api/v1/groups",
+        json!({ "name": "reconcile-test", "description": "a group" }),
+        &host.clone().creator(),
+    )
+    .await;
+    assert!(res.status().is_success());
+
+    let res = post(
+        &mut app,
+        "/groups/api/v1/sudo/member/reconcile-test",
+        json!({ "user_uuid": user_uuid(&member_user) }),
+        &admin,
+    )
+    .await;
+    assert!(res.status().is_success());
+
+    let member_id = member_user.user_id.value.clone().unwrap();
+    cis_client
+        .update_user(&member_id, member_user.clone())
+        .await?;
+    let mut other_profile = other_user.clone();
+    let mut groups = BTreeMap::new();
+    groups.insert(String::from("reconcile-test"), Some(String::default()));
+    other_profile.access_information.mozilliansorg.values = Some(KeyValue(groups));
+    let other_id = other_user.user_id.value.clone().unwrap();
+    cis_client.update_user(&other_id, other_profile).await?;
+
+    let res = post(
+        &mut app,
+        "/internal/reconcile/groups?dry_run=true",
+        json!({}),
+        &nobody_soa(),
+    )
+    .await;
+    assert!(res.status().is_success());
+    let report = read_json(res).await;
+    assert_eq!(report["dry_run"], true);
+    assert_eq!(report["users"].as_array().map(|u| u.len()), Some(2));
+    assert_eq!(report["updated"], 0);
+
+    let res = get(&mut app, "/groups/api/v1/sudo/drift", &admin).await;
+    assert!(res.status().is_success());
+    assert_eq!(
+        read_json(res).await["users"].as_array().map(|u| u.len()),
+        Some(2)
+    );
+
+    let res = post(
+        &mut app,
+        "/internal/reconcile/groups",
+        json!({}),
+        &nobody_soa(),
+    )
+    .await;
+    assert!(res.status().is_success());
+    let report = read_json(res).await;
+    assert_eq!(report["dry_run"], false);
+    assert_eq!(report["updated"], 2);
+    assert_eq!(report["failed"], 0);
+
+    let res = get(&mut app, "/groups/api/v1/sudo/drift", &admin).await;
+    assert!(res.status().is_success());
+    assert_eq!(read_json(res).await, json!({ "users": [], "groups": [] }));
+
+    Ok(())
+}
+
+#[actix_rt::test]
+async fn display() -> Result<(), Error> {
+    reset()?;
+    let (service, cis_client) = test_app_and_cis().await;
+    let app = App::new().service(service);
+    let mut app = test::init_service(app).await;
+
+    let host = Soa::from(&basic_user(1, true)).aal_medium();
+    let admin = host.clone().admin();
+    let member_user = basic_user(2, true);
+
+    let res = post(
+        &mut app,
+        "/groups/api/v1/groups",
+        json!({ "name": "display-test", "description": "a group" }),
+        &host.clone().creator(),
+    )
+    .await;
+    assert!(res.status().is_success());
+
+    let res = get(
+        &mut app,
+        "/groups/api/v1/sudo/display/groups/display-test",
+        &admin,
+    )
+    .await;
+    assert!(res.status().is_success());
+    assert_eq!(read_json(res).await, json!({ "display": "Staff" }));
+
+    let res = put(
+        &mut app,
+        "/groups/api/v1/sudo/display/groups/display-test",
+        json!({ "display": "Public" }),
+        &host,
+    )
+    .await;
+    assert!(!res.status().is_success());
+
+    let res = put(
+        &mut app,
+        "/groups/api/v1/sudo/display/groups/display-test",
+        json!({ "display": "Public" }),
+        &admin,
+    )
+    .await;
+    assert!(res.status().is_success());
+
+    let res = post(
+        &mut app,
+        "/groups/api/v1/sudo/member/display-test",
+        json!({ "user_uuid": user_uuid(&member_user) }),
+        &admin,
+    )
+    .await;
+    assert!(res.status().is_success());
+
+    let member_id = member_user.user_id.value.clone().unwrap();
+    let profile = cis_client
+        .get_user_by(&member_id, &GetBy::UserId, None)
+        .await?;
+    assert_eq!(
+        profile.access_information.mozilliansorg.metadata.display,
+        Some(Display::Public)
+    );
+
+    let res = post(
+        &mut app,
+        "/groups/api/v1/sudo/display/groups/display-test/republish",
+        json!({}),
+        &admin,
+    )
+    .await;
+    assert!(res.status().is_success());
+    assert_eq!(read_json(res).await, json!({ "queued": 2 }));
+
+    Ok(())
+}
+
+#[actix_rt::test]
+async fn merge_users() -> Result<(), Error> {
+    reset()?;
+    let (service, cis_client) = test_app_and_cis().await;
+    let app = App::new().service(service);
+    let mut app = test::init_service(app).await;
+
+    let host = Soa::from(&basic_user(1, true)).aal_medium();
+    let admin = host.clone().admin();
+    let old_user = basic_user(2, true);
+    let new_user = basic_user(3, true);
+
+    for group_name in &["merge-test-1", "merge-test-2"] {
+        let res = post(
+            &mut app,
+            "/groups/api/v1/groups",
+            json!({ "name": group_name, "description": "a group" }),
+            &host.clone().creator(),
+        )
+        .await;
+        assert!(res.status().is_success());
+        add_to_group(&mut app, &host, &old_user, group_name).await;
+    }
+    let res = post(
+        &mut app,
+        "/groups/api/v1/sudo/curators/merge-test-1",
+        json!({ "user_uuid": user_uuid(&new_user) }),
+        &admin,
+    )
+    .await;
+    assert!(res.status().is_success());
+
+    // a sent reminder and a calendar token which must follow the membership
+    let connection = get_pool().get()?;
+    diesel::sql_query(format!(
+        "INSERT INTO expiration_notifications (user_uuid, group_id, expiration, days) \
+         SELECT '{}', group_id, NOW() + INTERVAL '7 days', 7 FROM groups WHERE name = 'merge-test-2'",
+        user_uuid(&old_user)
+    ))
+    .execute(&connection)?;
+    diesel::sql_query(format!(
+        "INSERT INTO calendar_tokens (group_id, user_uuid, token_hash) \
+         SELECT group_id, '{}', 'merge-test-token' FROM groups WHERE name = 'merge-test-2'",
+        user_uuid(&old_user)
+    ))
+    .execute(&connection)?;
+
+    let res = post(
+        &mut app,
+        "/groups/api/v1/sudo/users/merge",
+        json!({ "from": user_uuid(&old_user), "to": user_uuid(&old_user) }),
+        &admin,
+    )
+    .await;
+    assert_eq!(res.status().as_u16(), 400);
+    assert_eq!(read_json(res).await["error"], "invalid_merge");
+
+    let res = post(
+        &mut app,
+        "/groups/api/v1/sudo/users/merge",
+        json!({ "from": user_uuid(&old_user), "to": user_uuid(&new_user) }),
+        &host,
+    )
+    .await;
+    assert!(!res.status().is_success());
+
+    let res = post(
+        &mut app,
+        "/groups/api/v1/sudo/users/merge",
+        json!({ "from": user_uuid(&old_user), "to": user_uuid(&new_user) }),
+        &admin,
+    )
+    .await;
+    assert!(res.status().is_success());
+    let mut report = read_json(res).await;
+    report["group_names"]
+        .as_array_mut()
+        .unwrap()
+        .sort_by_key(|g| g.to_string());
+    assert_eq!(
+        report,
+        json!({
+            "group_names": ["merge-test-1", "merge-test-2"],
+            "invitations": 0,
+            "requests": 0,
+        })
+    );
+
+    for table in &["expiration_notifications", "calendar_tokens"] {
+        for (user, count) in &[(&old_user, 0), (&new_user, 1)] {
+            let rows = diesel::sql_query(format!(
+                "UPDATE {} SET user_uuid = user_uuid WHERE user_uuid = '{}'",
+                table,
+                user_uuid(user)
+            ))
+            .execute(&connection)?;
+            assert_eq!(rows, *count);
+        }
+    }
+
+    // the curator role is kept
+    let res = get(
+        &mut app,
+        "/groups/api/v1/sudo/curators/merge-test-1",
+        &admin,
+    )
+    .await;
+    assert!(res.status().is_success());
+    assert_eq!(read_json(res).await.as_array().map(|c| c.len()), Some(2));
+
+    let res = get(&mut app, "/groups/api/v1/members/merge-test-2", &host).await;
+    assert!(res.status().is_success());
+    let members = read_json(res).await;
+    assert_eq!(members["members"].as_array().map(|m| m.len()), Some(2));
+    assert!(members["members"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .any(|m| m["user_uuid"] == user_uuid(&new_user)));
+
+    let old_profile = cis_client
+        .get_user_by(
+            &old_user.user_id.value.clone().unwrap(),
+            &GetBy::UserId,
+            None,
+        )
+        .await?;
+    assert_eq!(
+        old_profile.access_information.mozilliansorg.values,
+        Some(KeyValue(BTreeMap::new()))
+    );
+    let new_profile = cis_client
+        .get_user_by(
+            &new_user.user_id.value.clone().unwrap(),
+            &GetBy::UserId,
+            None,
+        )
+        .await?;
+    let groups = new_profile
+        .access_information
+        .mozilliansorg
+        .values
+        .unwrap()
+        .0;
+    assert!(groups.contains_key("merge-test-1"));
+    assert!(groups.contains_key("merge-test-2"));
+
+    Ok(())
+}
@@ -1,14 +1,19 @@
 use crate::helpers::api::*;
+use crate::helpers::db::get_pool;
 use crate::helpers::db::reset;
 use crate::helpers::misc::read_json;
 use crate::helpers::misc::test_app;
+use crate::helpers::misc::test_app_and_cis;
 use crate::helpers::misc::Soa;
 use crate::helpers::sudo::add_to_group;
 use crate::helpers::users::basic_user;
 use actix_web::test;
 use actix_web::App;
+use diesel::RunQueryDsl;
+use dino_park_packs::db::operations::groups::apply_staged_trust_changes;
 use failure::Error;
 use serde_json::json;
+use std::sync::Arc;
 
 #[actix_rt::test]
 async fn upgrade_group_trust() -> Result<(), Error> {
@@ -116,3 +121,142 @@ async fn upgrade_group_trust() -> Result<(), Error> {
     assert_eq!(group["group"]["trust"], "Staff");
     Ok(())
 }
+
+#[actix_rt::test]
+async fn staged_group_trust() -> Result<(), Error> {
+    reset()?;
+    let (service, cis_client) = test_app_and_cis().await;
+    let app = App::new().service(service);
+    let mut app = test::init_service(app).await;
+    let pool = get_pool();
+
+    let host_user = basic_user(1, true);
+    let nda_user_1 = basic_user(11, false);
+    let normal_user_1 = basic_user(13, false);
+    let normal_user_2 = basic_user(14, false);
+    let host = Soa::from(&host_user).aal_medium();
+
+    let res = post(
+        &mut app,
+        "/groups/api/v1/groups",
+        json!({ "name": "nda", "description": "the nda group" }),
+        &host.clone().creator(),
+    )
+    .await;
+    assert!(res.status().is_success());
+
+    let res = post(
+        &mut app,
+        "/groups/api/v1/groups",
+        json!({ "name": "staged-test", "description": "a group", "trust": "Authenticated" }),
+        &host.clone().creator(),
+    )
+    .await;
+    assert!(res.status().is_success());
+
+    add_to_group(&mut app, &host, &nda_user_1, "nda").await;
+    add_to_group(&mut app, &host, &nda_user_1, "staged-test").await;
+    add_to_group(&mut app, &host, &normal_user_1, "staged-test").await;
+    add_to_group(&mut app, &host, &normal_user_2, "staged-test").await;
+
+    let res = put(
+        &mut app,
+        "/groups/api/v1/sudo/trust/groups/staged-test?preview=true",
+        json!({ "trust": "Ndaed" }),
+        &host.clone().admin(),
+    )
+    .await;
+    assert!(res.status().is_success());
+    let preview = read_json(res).await;
+    assert_eq!(preview["members"].as_array().map(|a| a.len()), Some(2));
+    assert_eq!(preview["members"][0]["username"], "Hans13");
+    assert!(preview["effective"].is_null());
+
+    let res = put(
+        &mut app,
+        "/groups/api/v1/sudo/trust/groups/staged-test",
+        json!({ "trust": "Ndaed", "stage_days": 0 }),
+        &host.clone().admin(),
+    )
+    .await;
+    assert!(res.status().is_client_error());
+
+    let res = put(
+        &mut app,
+        "/groups/api/v1/sudo/trust/groups/staged-test",
+        json!({ "trust": "Ndaed", "stage_days": 7 }),
+        &host.clone().admin(),
+    )
+    .await;
+    assert!(res.status().is_success());
+    let staged = read_json(res).await;
+    assert_eq!(staged["members"].as_array().map(|a| a.len()), Some(2));
+    assert!(staged["effective"].is_string());
+
+    let res = get(
+        &mut app,
+        "/groups/api/v1/sudo/trust/groups/staged-test",
+        &host.clone().admin(),
+    )
+    .await;
+    assert!(res.status().is_success());
+    assert_eq!(read_json(res).await["trust"], "Ndaed");
+
+    let res = get(&mut app, "/groups/api/v1/members/staged-test", &host).await;
+    let members = read_json(res).await;
+    assert_eq!(members["members"].as_array().map(|a| a.len()), Some(4));
+
+    let res = delete(
+        &mut app,
+        "/groups/api/v1/sudo/trust/groups/staged-test",
+        &host.clone().admin(),
+    )
+    .await;
+    assert!(res.status().is_success());
+
+    let res = get(
+        &mut app,
+        "/groups/api/v1/sudo/trust/groups/staged-test",
+        &host.clone().admin(),
+    )
+    .await;
+    assert!(read_json(res).await.is_null());
+
+    let res = put(
+        &mut app,
+        "/groups/api/v1/sudo/trust/groups/staged-test",
+        json!({ "trust": "Ndaed", "stage_days": 7 }),
+        &host.clone().admin(),
+    )
+    .await;
+    assert!(res.status().is_success());
+
+    assert_eq!(
+        apply_staged_trust_changes(&pool, Arc::new(cis_client.clone())).await?,
+        0
+    );
+    let connection = pool.get()?;
+    diesel::sql_query("UPDATE trust_changes SET effective = NOW() - INTERVAL '1 day'")
+        .execute(&connection)?;
+    assert_eq!(
+        apply_staged_trust_changes(&pool, Arc::new(cis_client.clone())).await?,
+        1
+    );
+
+    let res = get(&mut app, "/groups/api/v1/members/staged-test", &host).await;
+    let members = read_json(res).await;
+    assert_eq!(members["members"].as_array().map(|a| a.len()), Some(2));
+
+    let res = get(&mut app, "/groups/api/v1/groups/staged-test/details", &host).await;
+    let group = read_json(res).await;
+    assert_eq!(group["group"]["trust"], "Ndaed");
+
+    let res = get(
+        &mut app,
+        "/groups/api/v1/sudo/trust/groups/staged-test",
+        &host.clone().admin(),
+    )
+    .await;
+    assert!(read_json(res).await.is_null());
+    Ok(())
+}
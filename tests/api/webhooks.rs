@@ -0,0 +1,163 @@
+use crate::helpers::api::*;
+use crate::helpers::db::reset;
+use crate::helpers::misc::nobody_soa;
+use crate::helpers::misc::read_json;
+use crate::helpers::misc::test_app;
+use crate::helpers::misc::Soa;
+use crate::helpers::sudo::add_to_group;
+use crate::helpers::users::basic_user;
+use crate::helpers::users::user_uuid;
+use actix_web::test;
+use actix_web::App;
+use failure::Error;
+use serde_json::json;
+
+#[actix_rt::test]
+async fn webhooks() -> Result<(), Error> {
+    reset()?;
+    let app = App::new().service(test_app().await);
+    let mut app = test::init_service(app).await;
+
+    let host = Soa::from(&basic_user(1, true)).aal_medium();
+    let admin = host.clone().admin();
+    let member_user = basic_user(2, true);
+
+    let res = post(
+        &mut app,
+        "/groups/api/v1/groups",
+        json!({ "name": "webhook-test", "description": "a group" }),
+        &host.clone().creator(),
+    )
+    .await;
+    assert!(res.status().is_success());
+
+    let res = post(
+        &mut app,
+        "/groups/api/v1/sudo/webhooks",
+        json!({
+            "url": "http://127.0.0.1:1/hook",
+            "secret": "too short",
+            "events": ["MembershipCreated"],
+        }),
+        &admin,
+    )
+    .await;
+    assert_eq!(res.status().as_u16(), 400);
+    assert_eq!(read_json(res).await["error"], "invalid_webhook");
+
+    let res = post(
+        &mut app,
+        "/groups/api/v1/sudo/webhooks",
+        json!({
+            "url": "http://127.0.0.1:1/hook",
+            "secret": "a very secret secret",
+            "group_name": "webhook-test",
+            "events": ["MembershipCreated", "MembershipDeleted", "MembershipUpdated"],
+        }),
+        &admin,
+    )
+    .await;
+    assert!(res.status().is_success());
+    let webhook = read_json(res).await;
+    assert_eq!(webhook["group_name"], "webhook-test");
+    assert!(webhook.get("secret").is_none());
+    let deliveries_url = format!(
+        "/groups/api/v1/sudo/webhooks/{}/deliveries",
+        webhook["id"].as_i64().unwrap()
+    );
+
+    add_to_group(&mut app, &host, &member_user, "webhook-test").await;
+
+    let res = get(&mut app, &deliveries_url, &admin).await;
+    assert!(res.status().is_success());
+    let deliveries = read_json(res).await;
+    assert_eq!(deliveries.as_array().map(|d| d.len()), Some(1));
+    assert_eq!(deliveries[0]["event"], "MembershipCreated");
+    assert_eq!(deliveries[0]["status"], "Pending");
+    assert_eq!(deliveries[0]["attempts"], 0);
+    assert_eq!(deliveries[0]["payload"]["group_name"], "webhook-test");
+    assert_eq!(
+        deliveries[0]["payload"]["user_uuid"],
+        user_uuid(&member_user)
+    );
+
+    let res = post(
+        &mut app,
+        "/internal/webhooks/deliver",
+        json!({}),
+        &nobody_soa(),
+    )
+    .await;
+    assert!(res.status().is_success());
+    assert_eq!(read_json(res).await, json!({ "delivered": 0 }));
+
+    let res = get(&mut app, &deliveries_url, &admin).await;
+    assert!(res.status().is_success());
+    let deliveries = read_json(res).await;
+    assert_eq!(deliveries[0]["status"], "Pending");
+    assert_eq!(deliveries[0]["attempts"], 1);
+    assert!(deliveries[0]["last_error"].is_string());
+
+    let admin_user = basic_user(3, true);
+    let res = post(
+        &mut app,
+        "/groups/api/v1/sudo/curators/webhook-test",
+        json!({ "user_uuid": user_uuid(&admin_user) }),
+        &admin,
+    )
+    .await;
+    assert!(res.status().is_success());
+
+    let res = get(&mut app, &deliveries_url, &admin).await;
+    assert!(res.status().is_success());
+    let deliveries = read_json(res).await;
+    assert_eq!(deliveries.as_array().map(|d| d.len()), Some(2));
+    assert_eq!(deliveries[0]["event"], "MembershipCreated");
+    assert_eq!(
+        deliveries[0]["payload"]["user_uuid"],
+        user_uuid(&admin_user)
+    );
+    assert_eq!(deliveries[0]["payload"]["body"], json!({ "role": "Admin" }));
+
+    let res = post(
+        &mut app,
+        &format!(
+            "/groups/api/v1/members/webhook-test/{}/renew",
+            user_uuid(&member_user)
+        ),
+        json!({ "group_expiration": 30 }),
+        &host,
+    )
+    .await;
+    assert!(res.status().is_success());
+
+    let res = get(&mut app, &deliveries_url, &admin).await;
+    assert!(res.status().is_success());
+    let deliveries = read_json(res).await;
+    assert_eq!(deliveries.as_array().map(|d| d.len()), Some(3));
+    assert_eq!(deliveries[0]["event"], "MembershipUpdated");
+    assert_eq!(
+        deliveries[0]["payload"]["user_uuid"],
+        user_uuid(&member_user)
+    );
+
+    let res = get(&mut app, "/groups/api/v1/sudo/webhooks", &host).await;
+    assert!(!res.status().is_success());
+
+    let res = delete(
+        &mut app,
+        &format!(
+            "/groups/api/v1/sudo/webhooks/{}",
+            webhook["id"].as_i64().unwrap()
+        ),
+        &admin,
+    )
+    .await;
+    assert!(res.status().is_success());
+
+    let res = get(&mut app, "/groups/api/v1/sudo/webhooks", &admin).await;
+    assert!(res.status().is_success());
+    assert_eq!(read_json(res).await, json!([]));
+
+    Ok(())
+}
@@ -1,4 +1,3 @@
-use crate::helpers::cis::CisFakeClient;
 use crate::helpers::db::get_pool;
 use crate::helpers::users::basic_user;
 use actix_web::dev::*;
@@ -11,6 +10,8 @@ use base64::encode;
 use cis_client::AsyncCisClientTrait;
 use cis_profile::schema::Profile;
 use dino_park_gate::scope::ScopeAndUser;
+use dino_park_packs::cis::fake::CisFakeClient;
+use dino_park_packs::cis::operations::ProfilePublisher;
 use dino_park_trust::AALevel;
 use dino_park_trust::GroupsTrust;
 use dino_park_trust::Trust;
@@ -126,6 +127,9 @@ pub async fn populate(cis_client: &CisFakeClient) {
     }
 }
 
+/// The SNS topic the test app accepts SES notifications from.
+pub const SNS_TOPIC_ARN: &str = "arn:aws:sns:us-west-2:123456789012:ses-notifications";
+
 pub async fn read_json<B: MessageBody>(res: ServiceResponse<B>) -> Value {
     serde_json::from_slice(test::read_body(res).await.as_ref()).unwrap()
 }
@@ -138,13 +142,19 @@ pub async fn test_app_and_cis() -> (impl HttpServiceFactory, CisFakeClient) {
     let pool = get_pool();
     let cis_client = CisFakeClient::new(pool.clone());
     populate(&cis_client).await;
+    cis_client.clear_updates();
     (
         web::scope("")
             .data(cis_client.clone())
             .data(pool.clone())
             .service(healthz::healthz_app())
-            .service(api::internal::internal_app::<CisFakeClient>())
+            // suspended memberships are revoked right away
+            .service(api::internal::internal_app::<CisFakeClient>(
+                0,
+                Some(String::from(SNS_TOPIC_ARN)),
+            ))
             .service(import::api::import_app::<CisFakeClient>())
+            .service(api::calendar::calendar_app(String::from("localhost")))
             .service(
                 web::scope("/groups/api/v1/")
                     .wrap_fn(|req, srv| {
@@ -166,7 +176,7 @@ pub async fn test_app_and_cis() -> (impl HttpServiceFactory, CisFakeClient) {
     )
 }
 
-pub async fn create_nda(cis_client: Arc<impl AsyncCisClientTrait>) -> Result<(), Error> {
+pub async fn create_nda(cis_client: Arc<impl ProfilePublisher>) -> Result<(), Error> {
     let pool = get_pool();
     let host = Soa::from(&basic_user(1, true)).admin().aal_medium();
     let nda_group = db::operations::models::NewGroup {
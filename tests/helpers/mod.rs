@@ -1,5 +1,4 @@
 pub mod api;
-pub mod cis;
 pub mod db;
 pub mod misc;
 pub mod sudo;
@@ -0,0 +1,77 @@
+use crate::helpers::db::get_pool;
+use crate::helpers::db::reset;
+use diesel::RunQueryDsl;
+use dino_park_packs::mail::manager::MailMan;
+use dino_park_packs::mail::send::EmailSender;
+use dino_park_packs::mail::Email;
+use dino_park_packs::mail::Message;
+use failure::Error;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+#[derive(Clone, Default)]
+struct RecordingSender {
+    sent: Arc<Mutex<Vec<Email>>>,
+}
+
+impl EmailSender for RecordingSender {
+    fn send_email(&self, email: Email) -> Pin<Box<dyn Future<Output = Result<(), Error>> + Send>> {
+        self.sent.lock().unwrap().push(email);
+        Box::pin(async { Ok(()) })
+    }
+}
+
+#[actix_rt::test]
+async fn suppressed_recipients_are_skipped() -> Result<(), Error> {
+    reset()?;
+    let pool = get_pool();
+    let connection = pool.get()?;
+    diesel::sql_query(
+        "INSERT INTO suppressed_emails (email, typ) VALUES ('bounced@example.com', 'bounce')",
+    )
+    .execute(&connection)?;
+
+    let mail_man =
+        MailMan::<RecordingSender>::new(String::from("localhost"), None, None, Some(pool));
+    let sent = Arc::clone(&mail_man.sender.sent);
+
+    mail_man
+        .deliver(Email::with(
+            String::from("bounced@example.com"),
+            "localhost",
+            Message::default(),
+        ))
+        .await?;
+    assert!(sent.lock().unwrap().is_empty());
+
+    mail_man
+        .deliver(Email::with_many(
+            vec![
+                String::from("bounced@example.com"),
+                String::from("fine@example.com"),
+            ],
+            "localhost",
+            Message::default(),
+        ))
+        .await?;
+    {
+        let sent = sent.lock().unwrap();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].to, None);
+        assert_eq!(sent[0].bcc, Some(vec![String::from("fine@example.com")]));
+    }
+
+    mail_man
+        .deliver(Email::with(
+            String::from("fine@example.com"),
+            "localhost",
+            Message::default(),
+        ))
+        .await?;
+    let sent = sent.lock().unwrap();
+    assert_eq!(sent.len(), 2);
+    assert_eq!(sent[1].to, Some(String::from("fine@example.com")));
+    Ok(())
+}
@@ -1 +1,3 @@
+mod mail;
 mod rules;
+mod scheduler;
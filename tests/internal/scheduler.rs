@@ -0,0 +1,81 @@
+use crate::helpers::api::*;
+use crate::helpers::db::get_pool;
+use crate::helpers::db::reset;
+use crate::helpers::misc::nobody_soa;
+use crate::helpers::misc::read_json;
+use crate::helpers::misc::test_app_and_cis;
+use actix_web::test;
+use actix_web::App;
+use chrono::Duration;
+use chrono::Utc;
+use diesel::RunQueryDsl;
+use dino_park_packs::db::types::JobType;
+use dino_park_packs::scheduler::run_scheduled;
+use failure::Error;
+use serde_json::json;
+use std::sync::Arc;
+
+#[actix_rt::test]
+async fn scheduled_jobs_run_once_per_tick() -> Result<(), Error> {
+    reset()?;
+    let (service, cis_client) = test_app_and_cis().await;
+    let cis_client = Arc::new(cis_client);
+    let app = App::new().service(service);
+    let mut app = test::init_service(app).await;
+    let pool = get_pool();
+
+    let res = get(&mut app, "/internal/jobs", &nobody_soa()).await;
+    assert!(res.status().is_success());
+    assert_eq!(read_json(res).await, json!([]));
+
+    let tick = Utc::now();
+    let job = JobType::ExpireRequests;
+    assert!(run_scheduled(&pool, Arc::clone(&cis_client), job, tick).await?);
+    // another replica must not run the same tick again
+    assert!(!run_scheduled(&pool, Arc::clone(&cis_client), job, tick).await?);
+
+    // nor while the job is locked by someone else
+    // the lock of expire_requests in the scheduler's lock namespace
+    let connection = pool.get()?;
+    diesel::sql_query("SELECT pg_advisory_lock(1885430635, 0)").execute(&connection)?;
+    let next_tick = tick + Duration::hours(1);
+    assert!(!run_scheduled(&pool, Arc::clone(&cis_client), job, next_tick).await?);
+    diesel::sql_query("SELECT pg_advisory_unlock(1885430635, 0)").execute(&connection)?;
+    assert!(run_scheduled(&pool, Arc::clone(&cis_client), job, next_tick).await?);
+
+    let res = get(&mut app, "/internal/jobs", &nobody_soa()).await;
+    assert!(res.status().is_success());
+    let runs = read_json(res).await;
+    assert_eq!(runs.as_array().map(|r| r.len()), Some(1));
+    assert_eq!(runs[0]["job"], "expire_requests");
+    assert_eq!(runs[0]["success"], true);
+    assert!(runs[0]["finished"].is_string());
+
+    let job = JobType::ApplyTrustChanges;
+    assert!(run_scheduled(&pool, Arc::clone(&cis_client), job, tick).await?);
+    let res = get(&mut app, "/internal/jobs", &nobody_soa()).await;
+    assert!(res.status().is_success());
+    let runs = read_json(res).await;
+    assert_eq!(runs.as_array().map(|r| r.len()), Some(2));
+    assert_eq!(runs[1]["job"], "apply_trust_changes");
+    assert_eq!(runs[1]["success"], true);
+    assert_eq!(runs[1]["message"], "0 trust changes applied");
+
+    // manual runs take the same lock and are recorded as well
+    diesel::sql_query("SELECT pg_advisory_lock(1885430635, 7)").execute(&connection)?;
+    let res = post(&mut app, "/internal/trust/revoke", json!({}), &nobody_soa()).await;
+    assert_eq!(res.status().as_u16(), 400);
+    assert_eq!(read_json(res).await["error"], "job_in_progress");
+    diesel::sql_query("SELECT pg_advisory_unlock(1885430635, 7)").execute(&connection)?;
+    let res = post(&mut app, "/internal/trust/revoke", json!({}), &nobody_soa()).await;
+    assert!(res.status().is_success());
+    assert_eq!(read_json(res).await["success"], true);
+
+    let res = get(&mut app, "/internal/jobs", &nobody_soa()).await;
+    assert!(res.status().is_success());
+    let runs = read_json(res).await;
+    assert_eq!(runs.as_array().map(|r| r.len()), Some(3));
+    assert_eq!(runs[2]["job"], "revoke_at_risk_memberships");
+
+    Ok(())
+}